@@ -18,6 +18,25 @@ export const SongHeaderSchema = v.object({
 
 export type SongHeader = v.InferOutput<typeof SongHeaderSchema>;
 
+export const SongPageSchema = v.object({
+	songs: v.array(SongHeaderSchema),
+	total: v.number(),
+	next_cursor: v.nullable(v.string())
+});
+
+export type SongPage = v.InferOutput<typeof SongPageSchema>;
+
+export type SongListQuery = {
+	cursor?: string | null;
+	limit?: string | null;
+	sort?: 'title' | 'author' | 'updated' | null;
+	order?: 'asc' | 'desc' | null;
+	author?: string | null;
+	owner?: string | null;
+	tag?: string | null;
+	key?: string | null;
+};
+
 export const SongSchema = v.object({
 	header: SongHeaderSchema,
 	contents: v.string(),
@@ -33,17 +52,22 @@ export const SongSchema = v.object({
 	),
 	fingerings: v.record(v.string(), v.string()),
 	original: v.string(),
-	instrument: v.string()
+	instrument: v.string(),
+	note_locale: v.string()
 });
 
 export type Song = v.InferOutput<typeof SongSchema>;
 
 export type SongQuery = {
 	instrument?: string | null;
+	note_locale?: string | null;
 };
 
-export function loadSongs(fetch: FetchApi): Promise<FetchResult<SongHeader[]>> {
-	return apiCall(fetch, '/api/songs', v.array(SongHeaderSchema));
+export function loadSongs(
+	fetch: FetchApi,
+	query: SongListQuery = {}
+): Promise<FetchResult<SongPage>> {
+	return apiCall(fetch, `/api/songs?${encodeQueryString(query)}`, SongPageSchema);
 }
 
 export function getSongUrl(songId: string, query?: SongQuery) {
@@ -3,8 +3,10 @@ import { loadSongs } from '$lib/api/song';
 import type { PageLoad } from './$types';
 
 export const load: PageLoad = async ({ fetch }) => {
-	const songs = unpackOrRedirect(await loadSongs(fetch));
+	const page = unpackOrRedirect(await loadSongs(fetch));
 	return {
-		songs: songs
+		songs: page.songs,
+		total: page.total,
+		nextCursor: page.next_cursor
 	};
 };
@@ -5,7 +5,8 @@ import type { PageLoad } from './$types';
 
 export const load: PageLoad = async ({ fetch, params, url }) => {
 	const queryString = {
-		instrument: url.searchParams.get('instrument')
+		instrument: url.searchParams.get('instrument'),
+		note_locale: url.searchParams.get('note_locale')
 	};
 	const song = unpackOrRedirect(await loadSong(fetch, params.id, queryString));
 	const instruments = unpackOrThrow(await fetchInstruments(fetch));
@@ -17,6 +18,7 @@ export const load: PageLoad = async ({ fetch, params, url }) => {
 		fingerings: song.fingerings,
 		original: song.original,
 		instrument: song.instrument,
+		noteLocale: song.note_locale,
 		instruments: instruments
 	};
 };
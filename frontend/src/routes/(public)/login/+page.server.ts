@@ -22,7 +22,7 @@ export const load: PageServerLoad = async ({ parent, url }) => {
 
 	const form = await superValidate(valibot(schema));
 
-	return { form, googleClientId: env.GOOGLE_CLIENT_ID };
+	return { form, googleClientId: env.GOOGLE_CLIENT_ID, oidcProvider: env.OIDC_PROVIDER_NAME };
 };
 
 export const actions = {
@@ -2,6 +2,20 @@ pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_song_and_user_tables;
 mod m20240709_020038_create_sessions_table;
+mod m20261019_000001_add_note_locales;
+mod m20261019_000002_add_song_metadata;
+mod m20261019_000003_create_song_search;
+mod m20261019_000004_create_song_chord_table;
+mod m20261019_000005_add_song_tags_and_updated_at;
+mod m20261019_000006_create_song_revision_table;
+mod m20261019_000007_add_song_version;
+mod m20261019_000008_create_song_sharing_tables;
+mod m20261019_000009_create_band_tables;
+mod m20261019_000010_create_setlist_tables;
+mod m20261019_000011_create_account_tables;
+mod m20261019_000012_add_session_indexes;
+mod m20261019_000013_create_api_token_table;
+mod m20261019_000014_create_unaccent_search_configuration;
 
 pub struct Migrator;
 
@@ -11,6 +25,20 @@ impl MigratorTrait for Migrator {
         vec![
             Box::new(m20220101_000001_create_song_and_user_tables::Migration),
             Box::new(m20240709_020038_create_sessions_table::Migration),
+            Box::new(m20261019_000001_add_note_locales::Migration),
+            Box::new(m20261019_000002_add_song_metadata::Migration),
+            Box::new(m20261019_000003_create_song_search::Migration),
+            Box::new(m20261019_000004_create_song_chord_table::Migration),
+            Box::new(m20261019_000005_add_song_tags_and_updated_at::Migration),
+            Box::new(m20261019_000006_create_song_revision_table::Migration),
+            Box::new(m20261019_000007_add_song_version::Migration),
+            Box::new(m20261019_000008_create_song_sharing_tables::Migration),
+            Box::new(m20261019_000009_create_band_tables::Migration),
+            Box::new(m20261019_000010_create_setlist_tables::Migration),
+            Box::new(m20261019_000011_create_account_tables::Migration),
+            Box::new(m20261019_000012_add_session_indexes::Migration),
+            Box::new(m20261019_000013_create_api_token_table::Migration),
+            Box::new(m20261019_000014_create_unaccent_search_configuration::Migration),
         ]
     }
 }
@@ -0,0 +1,61 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite only supports a single change per ALTER TABLE statement
+        for column in [
+            string_null(Song::Key),
+            integer_null(Song::Capo),
+            integer_null(Song::Tempo),
+            string_null(Song::TimeSignature),
+            string_null(Song::Tuning),
+            string_null(Song::Instrument),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Song::Table)
+                        .add_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            Song::Instrument,
+            Song::Tuning,
+            Song::TimeSignature,
+            Song::Tempo,
+            Song::Capo,
+            Song::Key,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Song::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Song {
+    Table,
+    Key,
+    Capo,
+    Tempo,
+    TimeSignature,
+    Tuning,
+    Instrument,
+}
@@ -0,0 +1,106 @@
+use sea_orm_migration::{prelude::*, sea_orm::DatabaseBackend};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// External content FTS5 index over the song table, kept in sync by triggers. `remove_diacritics`
+// makes both the indexed text and the queries accent-insensitive.
+const SQLITE_UP: &str = r#"
+CREATE VIRTUAL TABLE song_search USING fts5(
+    title,
+    author,
+    tablature,
+    content = 'song',
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+CREATE TRIGGER song_search_insert AFTER INSERT ON song BEGIN
+    INSERT INTO song_search(rowid, title, author, tablature)
+    VALUES (new.rowid, new.title, new.author, new.tablature);
+END;
+
+CREATE TRIGGER song_search_delete AFTER DELETE ON song BEGIN
+    INSERT INTO song_search(song_search, rowid, title, author, tablature)
+    VALUES ('delete', old.rowid, old.title, old.author, old.tablature);
+END;
+
+CREATE TRIGGER song_search_update AFTER UPDATE ON song BEGIN
+    INSERT INTO song_search(song_search, rowid, title, author, tablature)
+    VALUES ('delete', old.rowid, old.title, old.author, old.tablature);
+    INSERT INTO song_search(rowid, title, author, tablature)
+    VALUES (new.rowid, new.title, new.author, new.tablature);
+END;
+
+INSERT INTO song_search(song_search) VALUES ('rebuild');
+"#;
+
+const SQLITE_DOWN: &str = r#"
+DROP TRIGGER song_search_update;
+DROP TRIGGER song_search_delete;
+DROP TRIGGER song_search_insert;
+DROP TABLE song_search;
+"#;
+
+// Postgres keeps a weighted `tsvector` per song in a side table, kept in sync by a trigger.
+// Title, author and tablature get the weights A, B and D, which searches map to the same
+// relevance as the FTS5 ranking. `unaccent` plays the role of `remove_diacritics`.
+const POSTGRES_UP: &str = r#"
+CREATE EXTENSION IF NOT EXISTS unaccent;
+
+CREATE TABLE song_search (
+    song_id VARCHAR NOT NULL PRIMARY KEY REFERENCES song (id) ON DELETE CASCADE,
+    document TSVECTOR NOT NULL
+);
+
+CREATE INDEX idx_song_search_document ON song_search USING GIN (document);
+
+CREATE FUNCTION song_search_document(title TEXT, author TEXT, tablature TEXT)
+RETURNS TSVECTOR AS $$
+    SELECT setweight(to_tsvector('simple', unaccent(title)), 'A')
+        || setweight(to_tsvector('simple', unaccent(author)), 'B')
+        || setweight(to_tsvector('simple', unaccent(tablature)), 'D')
+$$ LANGUAGE SQL STABLE;
+
+CREATE FUNCTION song_search_update() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO song_search (song_id, document)
+    VALUES (new.id, song_search_document(new.title, new.author, new.tablature))
+    ON CONFLICT (song_id) DO UPDATE SET document = excluded.document;
+    RETURN new;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER song_search_update AFTER INSERT OR UPDATE OF title, author, tablature ON song
+FOR EACH ROW EXECUTE FUNCTION song_search_update();
+
+INSERT INTO song_search (song_id, document)
+SELECT id, song_search_document(title, author, tablature) FROM song;
+"#;
+
+const POSTGRES_DOWN: &str = r#"
+DROP TRIGGER song_search_update ON song;
+DROP FUNCTION song_search_update;
+DROP FUNCTION song_search_document;
+DROP TABLE song_search;
+"#;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let sql = match manager.get_database_backend() {
+            DatabaseBackend::Postgres => POSTGRES_UP,
+            _ => SQLITE_UP,
+        };
+        manager.get_connection().execute_unprepared(sql).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let sql = match manager.get_database_backend() {
+            DatabaseBackend::Postgres => POSTGRES_DOWN,
+            _ => SQLITE_DOWN,
+        };
+        manager.get_connection().execute_unprepared(sql).await?;
+        Ok(())
+    }
+}
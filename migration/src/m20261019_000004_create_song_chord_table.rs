@@ -0,0 +1,54 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(SongChord::Table)
+                    .col(string(SongChord::SongId))
+                    .col(string(SongChord::Chord))
+                    .primary_key(Index::create().col(SongChord::SongId).col(SongChord::Chord))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(SongChord::Table, SongChord::SongId)
+                            .to(Song::Table, Song::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_song_chord_chord")
+                    .table(SongChord::Table)
+                    .col(SongChord::Chord)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(SongChord::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum SongChord {
+    Table,
+    SongId,
+    Chord,
+}
+
+#[derive(DeriveIden)]
+enum Song {
+    Table,
+    Id,
+}
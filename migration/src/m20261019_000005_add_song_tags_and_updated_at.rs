@@ -0,0 +1,99 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite can not add a column with a non constant default, so existing songs are
+        // updated afterwards
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Song::Table)
+                    .add_column(date_time(Song::UpdatedAt).default("1970-01-01 00:00:00"))
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(Song::Table)
+                    .value(Song::UpdatedAt, Expr::current_timestamp())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_song_updated_at")
+                    .table(Song::Table)
+                    .col(Song::UpdatedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(SongTag::Table)
+                    .col(string(SongTag::SongId))
+                    .col(string(SongTag::Tag))
+                    .primary_key(Index::create().col(SongTag::SongId).col(SongTag::Tag))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(SongTag::Table, SongTag::SongId)
+                            .to(Song::Table, Song::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_song_tag_tag")
+                    .table(SongTag::Table)
+                    .col(SongTag::Tag)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(SongTag::Table).to_owned())
+            .await?;
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_song_updated_at")
+                    .table(Song::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Song::Table)
+                    .drop_column(Song::UpdatedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Song {
+    Table,
+    Id,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum SongTag {
+    Table,
+    SongId,
+    Tag,
+}
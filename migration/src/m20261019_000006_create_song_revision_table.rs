@@ -0,0 +1,101 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(SongRevision::Table)
+                    .col(pk_auto(SongRevision::Id))
+                    .col(string(SongRevision::SongId))
+                    .col(string(SongRevision::EditorId))
+                    .col(date_time(SongRevision::CreatedAt))
+                    .col(string_null(SongRevision::Note))
+                    .col(string(SongRevision::Author))
+                    .col(string(SongRevision::Title))
+                    .col(string(SongRevision::Tablature))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(SongRevision::Table, SongRevision::SongId)
+                            .to(Song::Table, Song::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_song_revision_song_id")
+                    .table(SongRevision::Table)
+                    .col(SongRevision::SongId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // The current state of every song becomes its first revision
+        manager
+            .exec_stmt(
+                Query::insert()
+                    .into_table(SongRevision::Table)
+                    .columns([
+                        SongRevision::SongId,
+                        SongRevision::EditorId,
+                        SongRevision::CreatedAt,
+                        SongRevision::Author,
+                        SongRevision::Title,
+                        SongRevision::Tablature,
+                    ])
+                    .select_from(
+                        Query::select()
+                            .columns([
+                                Song::Id,
+                                Song::Owner,
+                                Song::UpdatedAt,
+                                Song::Author,
+                                Song::Title,
+                                Song::Tablature,
+                            ])
+                            .from(Song::Table)
+                            .to_owned(),
+                    )
+                    .map_err(|err| DbErr::Migration(err.to_string()))?
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(SongRevision::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum SongRevision {
+    Table,
+    Id,
+    SongId,
+    EditorId,
+    CreatedAt,
+    Note,
+    Author,
+    Title,
+    Tablature,
+}
+
+#[derive(DeriveIden)]
+enum Song {
+    Table,
+    Id,
+    Owner,
+    UpdatedAt,
+    Author,
+    Title,
+    Tablature,
+}
@@ -0,0 +1,99 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(SongGrant::Table)
+                    .col(string(SongGrant::SongId))
+                    .col(string(SongGrant::UserId))
+                    .col(string(SongGrant::Role))
+                    .primary_key(
+                        Index::create()
+                            .col(SongGrant::SongId)
+                            .col(SongGrant::UserId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(SongGrant::Table, SongGrant::SongId)
+                            .to(Song::Table, Song::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_song_grant_user_id")
+                    .table(SongGrant::Table)
+                    .col(SongGrant::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(SongShareLink::Table)
+                    .col(string(SongShareLink::Token).primary_key())
+                    .col(string(SongShareLink::SongId))
+                    .col(string(SongShareLink::CreatedBy))
+                    .col(date_time(SongShareLink::CreatedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(SongShareLink::Table, SongShareLink::SongId)
+                            .to(Song::Table, Song::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_song_share_link_song_id")
+                    .table(SongShareLink::Table)
+                    .col(SongShareLink::SongId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(SongShareLink::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(SongGrant::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum SongGrant {
+    Table,
+    SongId,
+    UserId,
+    Role,
+}
+
+#[derive(DeriveIden)]
+enum SongShareLink {
+    Table,
+    Token,
+    SongId,
+    CreatedBy,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Song {
+    Table,
+    Id,
+}
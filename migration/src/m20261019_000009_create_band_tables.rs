@@ -0,0 +1,161 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Band::Table)
+                    .col(string(Band::Id).primary_key())
+                    .col(string(Band::Name))
+                    .col(date_time(Band::CreatedAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(BandMember::Table)
+                    .col(string(BandMember::BandId))
+                    .col(string(BandMember::UserId))
+                    .col(string(BandMember::Role))
+                    .primary_key(
+                        Index::create()
+                            .col(BandMember::BandId)
+                            .col(BandMember::UserId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(BandMember::Table, BandMember::BandId)
+                            .to(Band::Table, Band::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(BandMember::Table, BandMember::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_band_member_user_id")
+                    .table(BandMember::Table)
+                    .col(BandMember::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(SongBandGrant::Table)
+                    .col(string(SongBandGrant::SongId))
+                    .col(string(SongBandGrant::BandId))
+                    .col(string(SongBandGrant::Role))
+                    .primary_key(
+                        Index::create()
+                            .col(SongBandGrant::SongId)
+                            .col(SongBandGrant::BandId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(SongBandGrant::Table, SongBandGrant::SongId)
+                            .to(Song::Table, Song::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(SongBandGrant::Table, SongBandGrant::BandId)
+                            .to(Band::Table, Band::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_song_band_grant_band_id")
+                    .table(SongBandGrant::Table)
+                    .col(SongBandGrant::BandId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Existing songs are all owned by users
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Song::Table)
+                    .add_column(string(Song::OwnerKind).default("user"))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Song::Table)
+                    .drop_column(Song::OwnerKind)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(SongBandGrant::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(BandMember::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Band::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Band {
+    Table,
+    Id,
+    Name,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum BandMember {
+    Table,
+    BandId,
+    UserId,
+    Role,
+}
+
+#[derive(DeriveIden)]
+enum SongBandGrant {
+    Table,
+    SongId,
+    BandId,
+    Role,
+}
+
+#[derive(DeriveIden)]
+enum Song {
+    Table,
+    Id,
+    OwnerKind,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
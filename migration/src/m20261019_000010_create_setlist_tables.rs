@@ -0,0 +1,111 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Setlist::Table)
+                    .col(string(Setlist::Id).primary_key())
+                    .col(string(Setlist::Name))
+                    .col(string(Setlist::Owner))
+                    .col(string(Setlist::OwnerKind).default("user"))
+                    .col(date_time(Setlist::CreatedAt))
+                    .col(date_time(Setlist::UpdatedAt))
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_setlist_owner")
+                    .table(Setlist::Table)
+                    .col(Setlist::Owner)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(SetlistEntry::Table)
+                    .col(string(SetlistEntry::SetlistId))
+                    .col(integer(SetlistEntry::Position))
+                    .col(string(SetlistEntry::SongId))
+                    .col(integer_null(SetlistEntry::Transpose))
+                    .col(integer_null(SetlistEntry::Capo))
+                    .col(string_null(SetlistEntry::Instrument))
+                    .col(string_null(SetlistEntry::Notes))
+                    .primary_key(
+                        Index::create()
+                            .col(SetlistEntry::SetlistId)
+                            .col(SetlistEntry::Position),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(SetlistEntry::Table, SetlistEntry::SetlistId)
+                            .to(Setlist::Table, Setlist::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(SetlistEntry::Table, SetlistEntry::SongId)
+                            .to(Song::Table, Song::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_setlist_entry_song_id")
+                    .table(SetlistEntry::Table)
+                    .col(SetlistEntry::SongId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(SetlistEntry::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Setlist::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Setlist {
+    Table,
+    Id,
+    Name,
+    Owner,
+    OwnerKind,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum SetlistEntry {
+    Table,
+    SetlistId,
+    Position,
+    SongId,
+    Transpose,
+    Capo,
+    Instrument,
+    Notes,
+}
+
+#[derive(DeriveIden)]
+enum Song {
+    Table,
+    Id,
+}
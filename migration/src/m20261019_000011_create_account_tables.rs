@@ -0,0 +1,118 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Users created before registration existed were added by an admin
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(boolean(User::EmailVerified).default(true))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(AccountToken::Table)
+                    .col(string(AccountToken::TokenHash).primary_key())
+                    .col(string(AccountToken::UserId))
+                    .col(string(AccountToken::Purpose))
+                    .col(date_time(AccountToken::CreatedAt))
+                    .col(date_time(AccountToken::ExpiresAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(AccountToken::Table, AccountToken::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_account_token_user_id")
+                    .table(AccountToken::Table)
+                    .col(AccountToken::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Invite::Table)
+                    .col(string(Invite::Code).primary_key())
+                    .col(string(Invite::CreatedBy))
+                    .col(date_time(Invite::CreatedAt))
+                    .col(date_time_null(Invite::ExpiresAt))
+                    .col(string_null(Invite::UsedBy))
+                    .col(date_time_null(Invite::UsedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Invite::Table, Invite::CreatedBy)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Invite::Table, Invite::UsedBy)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Invite::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(AccountToken::Table).to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::EmailVerified)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+    EmailVerified,
+}
+
+#[derive(DeriveIden)]
+enum AccountToken {
+    Table,
+    TokenHash,
+    UserId,
+    Purpose,
+    CreatedAt,
+    ExpiresAt,
+}
+
+#[derive(DeriveIden)]
+enum Invite {
+    Table,
+    Code,
+    CreatedBy,
+    CreatedAt,
+    ExpiresAt,
+    UsedBy,
+    UsedAt,
+}
@@ -0,0 +1,41 @@
+use sea_orm_migration::{prelude::*, sea_orm::DatabaseBackend};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// Text search configuration that reads words like `simple`, but without accents. Highlighting the
+// matches with it marks the words that matched regardless of their accents, like FTS5 does.
+const POSTGRES_UP: &str = r#"
+CREATE TEXT SEARCH CONFIGURATION simple_unaccent (COPY = simple);
+
+ALTER TEXT SEARCH CONFIGURATION simple_unaccent
+    ALTER MAPPING FOR asciiword, asciihword, hword_asciipart, word, hword, hword_part
+    WITH unaccent, simple;
+"#;
+
+const POSTGRES_DOWN: &str = r#"
+DROP TEXT SEARCH CONFIGURATION simple_unaccent;
+"#;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.get_database_backend() == DatabaseBackend::Postgres {
+            manager
+                .get_connection()
+                .execute_unprepared(POSTGRES_UP)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.get_database_backend() == DatabaseBackend::Postgres {
+            manager
+                .get_connection()
+                .execute_unprepared(POSTGRES_DOWN)
+                .await?;
+        }
+        Ok(())
+    }
+}
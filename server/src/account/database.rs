@@ -0,0 +1,298 @@
+use axum::async_trait;
+use chrono::NaiveDateTime;
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, Condition, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder,
+};
+use uuid::Uuid;
+
+use crate::{
+    entities::{
+        account_token, invite,
+        prelude::{AccountToken as AccountTokenEntity, Invite as InviteEntity},
+    },
+    error::{ChordDbError, ChordDbResult},
+};
+
+use super::{AccountToken, Accounts, Invite, TokenPurpose};
+
+pub struct SeaOrmAccounts {
+    db: DatabaseConnection,
+}
+
+impl SeaOrmAccounts {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+}
+
+fn parse_uuid(id: &str) -> ChordDbResult<Uuid> {
+    Uuid::parse_str(id)
+        .map_err(|err| ChordDbError::InvalidData(format!("Invalid uuid: '{}'. Err: {}", id, err)))
+}
+
+fn build_token(model: account_token::Model) -> ChordDbResult<AccountToken> {
+    Ok(AccountToken {
+        user_id: parse_uuid(&model.user_id)?,
+        purpose: TokenPurpose::parse(&model.purpose).ok_or_else(|| {
+            ChordDbError::InvalidData(format!("Invalid token purpose: '{}'", model.purpose))
+        })?,
+        token_hash: model.token_hash,
+        created_at: model.created_at,
+        expires_at: model.expires_at,
+    })
+}
+
+fn build_invite(model: invite::Model) -> ChordDbResult<Invite> {
+    Ok(Invite {
+        created_by: parse_uuid(&model.created_by)?,
+        used_by: model.used_by.as_deref().map(parse_uuid).transpose()?,
+        code: model.code,
+        created_at: model.created_at,
+        expires_at: model.expires_at,
+        used_at: model.used_at,
+    })
+}
+
+#[async_trait]
+impl Accounts for SeaOrmAccounts {
+    async fn insert_token(&self, token: &AccountToken) -> ChordDbResult<()> {
+        AccountTokenEntity::insert(account_token::ActiveModel::from(account_token::Model {
+            token_hash: token.token_hash.clone(),
+            user_id: token.user_id.to_string(),
+            purpose: token.purpose.id().to_string(),
+            created_at: token.created_at,
+            expires_at: token.expires_at,
+        }))
+        .exec(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn take_token(
+        &self,
+        token_hash: &str,
+        purpose: TokenPurpose,
+    ) -> ChordDbResult<Option<AccountToken>> {
+        let Some(model) = AccountTokenEntity::find_by_id(token_hash)
+            .filter(account_token::Column::Purpose.eq(purpose.id()))
+            .one(&self.db)
+            .await?
+        else {
+            return Ok(None);
+        };
+        // Only the request that deletes the token gets to use it
+        let deleted = AccountTokenEntity::delete_by_id(token_hash)
+            .exec(&self.db)
+            .await?;
+        if deleted.rows_affected == 0 {
+            return Ok(None);
+        }
+        build_token(model).map(Some)
+    }
+
+    async fn delete_user_tokens(
+        &self,
+        user_id: &Uuid,
+        purpose: TokenPurpose,
+    ) -> ChordDbResult<u64> {
+        let result = AccountTokenEntity::delete_many()
+            .filter(account_token::Column::UserId.eq(user_id.to_string()))
+            .filter(account_token::Column::Purpose.eq(purpose.id()))
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected)
+    }
+
+    async fn get_invite(&self, code: &str) -> ChordDbResult<Option<Invite>> {
+        InviteEntity::find_by_id(code)
+            .one(&self.db)
+            .await?
+            .map(build_invite)
+            .transpose()
+    }
+
+    async fn list_invites(&self) -> ChordDbResult<Vec<Invite>> {
+        InviteEntity::find()
+            .order_by_desc(invite::Column::CreatedAt)
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(build_invite)
+            .collect()
+    }
+
+    async fn insert_invite(&self, invite: &Invite) -> ChordDbResult<()> {
+        InviteEntity::insert(invite::ActiveModel::from(invite::Model {
+            code: invite.code.clone(),
+            created_by: invite.created_by.to_string(),
+            created_at: invite.created_at,
+            expires_at: invite.expires_at,
+            used_by: invite.used_by.map(|id| id.to_string()),
+            used_at: invite.used_at,
+        }))
+        .exec(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_invite(&self, code: &str) -> ChordDbResult<bool> {
+        let result = InviteEntity::delete_by_id(code).exec(&self.db).await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    async fn redeem_invite(
+        &self,
+        code: &str,
+        user_id: &Uuid,
+        now: NaiveDateTime,
+    ) -> ChordDbResult<bool> {
+        // A single conditional update, so two registrations can not use the same invite
+        let result = InviteEntity::update_many()
+            .col_expr(invite::Column::UsedBy, Expr::value(user_id.to_string()))
+            .col_expr(invite::Column::UsedAt, Expr::value(now))
+            .filter(invite::Column::Code.eq(code))
+            .filter(invite::Column::UsedBy.is_null())
+            .filter(
+                Condition::any()
+                    .add(invite::Column::ExpiresAt.is_null())
+                    .add(invite::Column::ExpiresAt.gt(now)),
+            )
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    async fn release_invite(&self, code: &str, user_id: &Uuid) -> ChordDbResult<()> {
+        InviteEntity::update_many()
+            .col_expr(invite::Column::UsedBy, Expr::value(Option::<String>::None))
+            .col_expr(
+                invite::Column::UsedAt,
+                Expr::value(Option::<NaiveDateTime>::None),
+            )
+            .filter(invite::Column::Code.eq(code))
+            .filter(invite::Column::UsedBy.eq(user_id.to_string()))
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use sea_orm::DatabaseConnection;
+
+    use crate::{
+        account::{hash_token, issue_token, redeem_token},
+        testing::{test_databases, user},
+        user::{SeaOrmUsers, Users},
+    };
+
+    use super::*;
+    use test_log::test;
+
+    async fn insert_user(db: &DatabaseConnection, name: &str) -> Uuid {
+        let user = user(name);
+        SeaOrmUsers::new(db.clone())
+            .upsert_user(&user)
+            .await
+            .unwrap();
+        user.id
+    }
+
+    #[test(tokio::test)]
+    async fn test_tokens() {
+        for database in test_databases().await {
+            let accounts = SeaOrmAccounts::new(database.db.clone());
+            let user_id = insert_user(&database.db, "user").await;
+            let token = issue_token(&accounts, &user_id, TokenPurpose::VerifyEmail)
+                .await
+                .unwrap();
+            let stored = accounts
+                .take_token(&hash_token(&token), TokenPurpose::ResetPassword)
+                .await
+                .unwrap();
+            assert_eq!(stored, None, "{}", database.name);
+            assert_eq!(
+                redeem_token(&accounts, &token, TokenPurpose::VerifyEmail)
+                    .await
+                    .unwrap(),
+                Some(user_id)
+            );
+            assert_eq!(
+                redeem_token(&accounts, &token, TokenPurpose::VerifyEmail)
+                    .await
+                    .unwrap(),
+                None
+            );
+
+            issue_token(&accounts, &user_id, TokenPurpose::ResetPassword)
+                .await
+                .unwrap();
+            assert_eq!(
+                accounts
+                    .delete_user_tokens(&user_id, TokenPurpose::ResetPassword)
+                    .await
+                    .unwrap(),
+                1
+            );
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_invites() {
+        for database in test_databases().await {
+            let accounts = SeaOrmAccounts::new(database.db.clone());
+            let admin = insert_user(&database.db, "admin").await;
+            let user = insert_user(&database.db, "user").await;
+            let now = Utc::now().naive_utc();
+            let invite = Invite::new(admin, Some(now + Duration::days(1)));
+            let unlimited = Invite::new(admin, None);
+            accounts.insert_invite(&invite).await.unwrap();
+            accounts.insert_invite(&unlimited).await.unwrap();
+            assert_eq!(accounts.list_invites().await.unwrap().len(), 2);
+
+            let later = now + Duration::days(2);
+            assert!(!accounts
+                .redeem_invite(&invite.code, &user, later)
+                .await
+                .unwrap());
+            assert!(
+                accounts
+                    .redeem_invite(&invite.code, &user, now)
+                    .await
+                    .unwrap(),
+                "{}",
+                database.name
+            );
+            assert!(!accounts
+                .redeem_invite(&invite.code, &user, now)
+                .await
+                .unwrap());
+            let used = accounts.get_invite(&invite.code).await.unwrap().unwrap();
+            assert_eq!(used.used_by, Some(user));
+            assert!(accounts
+                .redeem_invite(&unlimited.code, &user, later)
+                .await
+                .unwrap());
+
+            accounts
+                .release_invite(&invite.code, &Uuid::new_v4())
+                .await
+                .unwrap();
+            assert!(!accounts
+                .redeem_invite(&invite.code, &user, now)
+                .await
+                .unwrap());
+            accounts.release_invite(&invite.code, &user).await.unwrap();
+            assert!(accounts
+                .redeem_invite(&invite.code, &user, now)
+                .await
+                .unwrap());
+
+            assert!(accounts.delete_invite(&invite.code).await.unwrap());
+            assert!(!accounts.delete_invite(&invite.code).await.unwrap());
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use axum::async_trait;
+use chrono::NaiveDateTime;
+use dashmap::DashMap;
+use uuid::Uuid;
+
+use crate::error::ChordDbResult;
+
+use super::{AccountToken, Accounts, Invite, TokenPurpose};
+
+/// Tokens and invites kept in memory, for servers running without a database
+#[derive(Default)]
+pub struct MemoryAccounts {
+    /// Tokens by hash
+    tokens: DashMap<String, AccountToken>,
+    invites: DashMap<String, Invite>,
+}
+
+impl MemoryAccounts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Accounts for MemoryAccounts {
+    async fn insert_token(&self, token: &AccountToken) -> ChordDbResult<()> {
+        self.tokens.insert(token.token_hash.clone(), token.clone());
+        Ok(())
+    }
+
+    async fn take_token(
+        &self,
+        token_hash: &str,
+        purpose: TokenPurpose,
+    ) -> ChordDbResult<Option<AccountToken>> {
+        Ok(self
+            .tokens
+            .remove_if(token_hash, |_, token| token.purpose == purpose)
+            .map(|(_, token)| token))
+    }
+
+    async fn delete_user_tokens(
+        &self,
+        user_id: &Uuid,
+        purpose: TokenPurpose,
+    ) -> ChordDbResult<u64> {
+        let mut deleted = 0;
+        self.tokens.retain(|_, token| {
+            let keep = token.user_id != *user_id || token.purpose != purpose;
+            if !keep {
+                deleted += 1;
+            }
+            keep
+        });
+        Ok(deleted)
+    }
+
+    async fn get_invite(&self, code: &str) -> ChordDbResult<Option<Invite>> {
+        Ok(self.invites.get(code).map(|invite| invite.value().clone()))
+    }
+
+    async fn list_invites(&self) -> ChordDbResult<Vec<Invite>> {
+        let mut invites: Vec<Invite> = self
+            .invites
+            .iter()
+            .map(|invite| invite.value().clone())
+            .collect();
+        invites.sort_by_key(|invite| std::cmp::Reverse(invite.created_at));
+        Ok(invites)
+    }
+
+    async fn insert_invite(&self, invite: &Invite) -> ChordDbResult<()> {
+        self.invites.insert(invite.code.clone(), invite.clone());
+        Ok(())
+    }
+
+    async fn delete_invite(&self, code: &str) -> ChordDbResult<bool> {
+        Ok(self.invites.remove(code).is_some())
+    }
+
+    async fn redeem_invite(
+        &self,
+        code: &str,
+        user_id: &Uuid,
+        now: NaiveDateTime,
+    ) -> ChordDbResult<bool> {
+        let Some(mut invite) = self.invites.get_mut(code) else {
+            return Ok(false);
+        };
+        if !invite.is_usable(now) {
+            return Ok(false);
+        }
+        invite.used_by = Some(*user_id);
+        invite.used_at = Some(now);
+        Ok(true)
+    }
+
+    async fn release_invite(&self, code: &str, user_id: &Uuid) -> ChordDbResult<()> {
+        if let Some(mut invite) = self.invites.get_mut(code) {
+            if invite.used_by == Some(*user_id) {
+                invite.used_by = None;
+                invite.used_at = None;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,256 @@
+//! Self-service accounts: registration settings, invites and the single-use tokens sent by email
+//! to verify addresses and reset passwords
+
+use axum::async_trait;
+use chrono::{Duration, NaiveDateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{error::ChordDbResult, song::new_share_token};
+
+mod database;
+mod memory;
+
+pub use database::SeaOrmAccounts;
+pub use memory::MemoryAccounts;
+
+/// Who can create an account through the API
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Registration {
+    /// Anyone
+    Open,
+    /// Only people with an invite code from an admin
+    Invite,
+    /// Nobody, users are added with the `user` command
+    Disabled,
+}
+
+#[derive(Debug, Clone)]
+pub struct AccountSettings {
+    pub registration: Registration,
+    /// Address of the web app, for the links sent by email
+    pub public_url: String,
+}
+
+impl AccountSettings {
+    pub fn link(&self, path: &str) -> String {
+        format!("{}{}", self.public_url.trim_end_matches('/'), path)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenPurpose {
+    VerifyEmail,
+    ResetPassword,
+}
+
+impl TokenPurpose {
+    pub fn parse(source: &str) -> Option<TokenPurpose> {
+        match source {
+            "verify_email" => Some(TokenPurpose::VerifyEmail),
+            "reset_password" => Some(TokenPurpose::ResetPassword),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            TokenPurpose::VerifyEmail => "verify_email",
+            TokenPurpose::ResetPassword => "reset_password",
+        }
+    }
+
+    /// How long the tokens can be used
+    pub fn lifetime(&self) -> Duration {
+        match self {
+            TokenPurpose::VerifyEmail => Duration::days(2),
+            TokenPurpose::ResetPassword => Duration::hours(1),
+        }
+    }
+}
+
+/// A token sent to a user. Only its hash is stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountToken {
+    pub token_hash: String,
+    pub user_id: Uuid,
+    pub purpose: TokenPurpose,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
+
+/// Lets someone register while registration is invite-only
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Invite {
+    pub code: String,
+    pub created_by: Uuid,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+    pub used_by: Option<Uuid>,
+    pub used_at: Option<NaiveDateTime>,
+}
+
+impl Invite {
+    pub fn new(created_by: Uuid, expires_at: Option<NaiveDateTime>) -> Self {
+        Invite {
+            code: Uuid::new_v4().simple().to_string(),
+            created_by,
+            created_at: Utc::now().naive_utc(),
+            expires_at,
+            used_by: None,
+            used_at: None,
+        }
+    }
+
+    pub fn is_usable(&self, now: NaiveDateTime) -> bool {
+        self.used_by.is_none() && self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+}
+
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[async_trait]
+pub trait Accounts: Send + Sync {
+    async fn insert_token(&self, token: &AccountToken) -> ChordDbResult<()>;
+    /// Deletes the token and returns it, so it can only be used once. Expired tokens are
+    /// returned too, callers check the expiration.
+    async fn take_token(
+        &self,
+        token_hash: &str,
+        purpose: TokenPurpose,
+    ) -> ChordDbResult<Option<AccountToken>>;
+    /// Deletes the tokens of a user for the purpose. Returns the number of tokens deleted
+    async fn delete_user_tokens(&self, user_id: &Uuid, purpose: TokenPurpose)
+        -> ChordDbResult<u64>;
+
+    async fn get_invite(&self, code: &str) -> ChordDbResult<Option<Invite>>;
+    /// Every invite, newest first
+    async fn list_invites(&self) -> ChordDbResult<Vec<Invite>>;
+    async fn insert_invite(&self, invite: &Invite) -> ChordDbResult<()>;
+    async fn delete_invite(&self, code: &str) -> ChordDbResult<bool>;
+    /// Marks the invite as used by the user, if it is still usable at `now`
+    async fn redeem_invite(
+        &self,
+        code: &str,
+        user_id: &Uuid,
+        now: NaiveDateTime,
+    ) -> ChordDbResult<bool>;
+    /// Makes an invite redeemed by the user usable again
+    async fn release_invite(&self, code: &str, user_id: &Uuid) -> ChordDbResult<()>;
+}
+
+/// Creates a token for the user, replacing the previous ones for the same purpose. Returns the
+/// token to send to the user.
+pub async fn issue_token(
+    accounts: &dyn Accounts,
+    user_id: &Uuid,
+    purpose: TokenPurpose,
+) -> ChordDbResult<String> {
+    accounts.delete_user_tokens(user_id, purpose).await?;
+    let token = new_share_token();
+    let now = Utc::now().naive_utc();
+    accounts
+        .insert_token(&AccountToken {
+            token_hash: hash_token(&token),
+            user_id: *user_id,
+            purpose,
+            created_at: now,
+            expires_at: now + purpose.lifetime(),
+        })
+        .await?;
+    Ok(token)
+}
+
+/// Consumes a token, returning the user it was issued to if it was still valid
+pub async fn redeem_token(
+    accounts: &dyn Accounts,
+    token: &str,
+    purpose: TokenPurpose,
+) -> ChordDbResult<Option<Uuid>> {
+    let token = accounts.take_token(&hash_token(token), purpose).await?;
+    Ok(token
+        .filter(|token| token.expires_at > Utc::now().naive_utc())
+        .map(|token| token.user_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_hash_token() {
+        assert_eq!(
+            hash_token("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_ne!(hash_token("abc"), hash_token("abd"));
+    }
+
+    #[test(tokio::test)]
+    async fn test_tokens() {
+        let accounts = MemoryAccounts::new();
+        let user_id = Uuid::new_v4();
+        let old = issue_token(&accounts, &user_id, TokenPurpose::ResetPassword)
+            .await
+            .unwrap();
+        let token = issue_token(&accounts, &user_id, TokenPurpose::ResetPassword)
+            .await
+            .unwrap();
+        let verify = issue_token(&accounts, &user_id, TokenPurpose::VerifyEmail)
+            .await
+            .unwrap();
+
+        // Issuing a token replaces the previous ones
+        let redeem = |token: String, purpose| {
+            let accounts = &accounts;
+            async move { redeem_token(accounts, &token, purpose).await.unwrap() }
+        };
+        assert_eq!(redeem(old, TokenPurpose::ResetPassword).await, None);
+        assert_eq!(redeem(token.clone(), TokenPurpose::VerifyEmail).await, None);
+        assert_eq!(
+            redeem(token.clone(), TokenPurpose::ResetPassword).await,
+            Some(user_id)
+        );
+        assert_eq!(redeem(token, TokenPurpose::ResetPassword).await, None);
+        assert_eq!(
+            redeem(verify, TokenPurpose::VerifyEmail).await,
+            Some(user_id)
+        );
+
+        let now = Utc::now().naive_utc();
+        accounts
+            .insert_token(&AccountToken {
+                token_hash: hash_token("expired"),
+                user_id,
+                purpose: TokenPurpose::VerifyEmail,
+                created_at: now - Duration::days(3),
+                expires_at: now - Duration::days(1),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            redeem("expired".to_string(), TokenPurpose::VerifyEmail).await,
+            None
+        );
+    }
+
+    #[test]
+    fn test_invite_is_usable() {
+        let now = Utc::now().naive_utc();
+        let mut invite = Invite::new(Uuid::new_v4(), Some(now + Duration::days(1)));
+        assert!(invite.is_usable(now));
+        assert!(!invite.is_usable(now + Duration::days(2)));
+        invite.expires_at = None;
+        assert!(invite.is_usable(now + Duration::days(2)));
+        invite.used_by = Some(Uuid::new_v4());
+        assert!(!invite.is_usable(now));
+    }
+}
@@ -0,0 +1,151 @@
+use axum::async_trait;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use uuid::Uuid;
+
+use crate::{
+    entities::{api_token, prelude::ApiToken as ApiTokenEntity},
+    error::{ChordDbError, ChordDbResult},
+};
+
+use super::{ApiToken, ApiTokens, TokenScope};
+
+pub struct SeaOrmApiTokens {
+    db: DatabaseConnection,
+}
+
+impl SeaOrmApiTokens {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+}
+
+fn parse_uuid(id: &str) -> ChordDbResult<Uuid> {
+    Uuid::parse_str(id)
+        .map_err(|err| ChordDbError::InvalidData(format!("Invalid uuid: '{}'. Err: {}", id, err)))
+}
+
+fn build_token(model: api_token::Model) -> ChordDbResult<ApiToken> {
+    Ok(ApiToken {
+        id: parse_uuid(&model.id)?,
+        user_id: parse_uuid(&model.user_id)?,
+        scope: TokenScope::parse(&model.scope).ok_or_else(|| {
+            ChordDbError::InvalidData(format!("Invalid token scope: '{}'", model.scope))
+        })?,
+        name: model.name,
+        token_hash: model.token_hash,
+        created_at: model.created_at,
+        expires_at: model.expires_at,
+    })
+}
+
+#[async_trait]
+impl ApiTokens for SeaOrmApiTokens {
+    async fn insert_token(&self, token: &ApiToken) -> ChordDbResult<()> {
+        ApiTokenEntity::insert(api_token::ActiveModel::from(api_token::Model {
+            id: token.id.to_string(),
+            user_id: token.user_id.to_string(),
+            name: token.name.clone(),
+            token_hash: token.token_hash.clone(),
+            scope: token.scope.id().to_string(),
+            created_at: token.created_at,
+            expires_at: token.expires_at,
+        }))
+        .exec(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_token_by_hash(&self, token_hash: &str) -> ChordDbResult<Option<ApiToken>> {
+        ApiTokenEntity::find()
+            .filter(api_token::Column::TokenHash.eq(token_hash))
+            .one(&self.db)
+            .await?
+            .map(build_token)
+            .transpose()
+    }
+
+    async fn user_tokens(&self, user_id: &Uuid) -> ChordDbResult<Vec<ApiToken>> {
+        ApiTokenEntity::find()
+            .filter(api_token::Column::UserId.eq(user_id.to_string()))
+            .order_by_desc(api_token::Column::CreatedAt)
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(build_token)
+            .collect()
+    }
+
+    async fn delete_token(&self, user_id: &Uuid, id: &Uuid) -> ChordDbResult<bool> {
+        let result = ApiTokenEntity::delete_many()
+            .filter(api_token::Column::Id.eq(id.to_string()))
+            .filter(api_token::Column::UserId.eq(user_id.to_string()))
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    async fn delete_user_tokens(&self, user_id: &Uuid) -> ChordDbResult<u64> {
+        let result = ApiTokenEntity::delete_many()
+            .filter(api_token::Column::UserId.eq(user_id.to_string()))
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use crate::{
+        testing::{test_databases, user},
+        user::{SeaOrmUsers, Users},
+    };
+
+    use super::*;
+    use test_log::test;
+
+    #[test(tokio::test)]
+    async fn test_api_tokens() {
+        for database in test_databases().await {
+            let tokens = SeaOrmApiTokens::new(database.db.clone());
+            let user = user("user");
+            let user_id = user.id;
+            SeaOrmUsers::new(database.db.clone())
+                .upsert_user(&user)
+                .await
+                .unwrap();
+
+            let (token, _) =
+                ApiToken::generate(user_id, "display", TokenScope::Read, TimeDelta::days(30));
+            tokens.insert_token(&token).await.unwrap();
+            let stored = tokens
+                .get_token_by_hash(&token.token_hash)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(stored.name, "display", "{}", database.name);
+            assert_eq!(stored.scope, TokenScope::Read);
+            assert_eq!(tokens.user_tokens(&user_id).await.unwrap().len(), 1);
+
+            assert!(!tokens
+                .delete_token(&Uuid::new_v4(), &token.id)
+                .await
+                .unwrap());
+            assert!(tokens.delete_token(&user_id, &token.id).await.unwrap());
+            assert!(tokens
+                .get_token_by_hash(&token.token_hash)
+                .await
+                .unwrap()
+                .is_none());
+
+            for name in ["first", "second"] {
+                let (token, _) =
+                    ApiToken::generate(user_id, name, TokenScope::Write, TimeDelta::days(30));
+                tokens.insert_token(&token).await.unwrap();
+            }
+            assert_eq!(tokens.delete_user_tokens(&user_id).await.unwrap(), 2);
+            assert!(tokens.user_tokens(&user_id).await.unwrap().is_empty());
+        }
+    }
+}
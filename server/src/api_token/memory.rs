@@ -0,0 +1,59 @@
+use axum::async_trait;
+use dashmap::DashMap;
+use uuid::Uuid;
+
+use crate::error::ChordDbResult;
+
+use super::{ApiToken, ApiTokens};
+
+/// Tokens kept in memory, for servers running without a database
+#[derive(Default)]
+pub struct MemoryApiTokens {
+    tokens: DashMap<Uuid, ApiToken>,
+}
+
+impl MemoryApiTokens {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ApiTokens for MemoryApiTokens {
+    async fn insert_token(&self, token: &ApiToken) -> ChordDbResult<()> {
+        self.tokens.insert(token.id, token.clone());
+        Ok(())
+    }
+
+    async fn get_token_by_hash(&self, token_hash: &str) -> ChordDbResult<Option<ApiToken>> {
+        Ok(self
+            .tokens
+            .iter()
+            .find(|token| token.token_hash == token_hash)
+            .map(|token| token.value().clone()))
+    }
+
+    async fn user_tokens(&self, user_id: &Uuid) -> ChordDbResult<Vec<ApiToken>> {
+        let mut tokens: Vec<ApiToken> = self
+            .tokens
+            .iter()
+            .filter(|token| token.user_id == *user_id)
+            .map(|token| token.value().clone())
+            .collect();
+        tokens.sort_by_key(|token| std::cmp::Reverse(token.created_at));
+        Ok(tokens)
+    }
+
+    async fn delete_token(&self, user_id: &Uuid, id: &Uuid) -> ChordDbResult<bool> {
+        Ok(self
+            .tokens
+            .remove_if(id, |_, token| token.user_id == *user_id)
+            .is_some())
+    }
+
+    async fn delete_user_tokens(&self, user_id: &Uuid) -> ChordDbResult<u64> {
+        let before = self.tokens.len();
+        self.tokens.retain(|_, token| token.user_id != *user_id);
+        Ok((before - self.tokens.len()) as u64)
+    }
+}
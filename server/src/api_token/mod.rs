@@ -0,0 +1,139 @@
+//! Personal access tokens, for scripts and devices calling the API without a browser session
+
+use axum::async_trait;
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{account::hash_token, error::ChordDbResult, song::new_share_token};
+
+mod database;
+mod memory;
+
+pub use database::SeaOrmApiTokens;
+pub use memory::MemoryApiTokens;
+
+/// Prefix of every token, so leaked ones are easy to recognize
+const TOKEN_PREFIX: &str = "chorddb_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenScope {
+    /// Only reads
+    Read,
+    /// Reads and changes anything the user can
+    Write,
+}
+
+impl TokenScope {
+    pub fn parse(source: &str) -> Option<TokenScope> {
+        match source {
+            "read" => Some(TokenScope::Read),
+            "write" => Some(TokenScope::Write),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Write => "write",
+        }
+    }
+}
+
+/// A personal access token. Only the hash of the token is stored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    #[serde(skip)]
+    pub token_hash: String,
+    pub scope: TokenScope,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
+
+impl ApiToken {
+    /// Creates a token for the user. Returns it along with the secret to hand to the user, which
+    /// can not be recovered later.
+    pub fn generate(
+        user_id: Uuid,
+        name: &str,
+        scope: TokenScope,
+        lifetime: TimeDelta,
+    ) -> (ApiToken, String) {
+        let secret = format!("{}{}", TOKEN_PREFIX, new_share_token());
+        let now = Utc::now().naive_utc();
+        let token = ApiToken {
+            id: Uuid::new_v4(),
+            user_id,
+            name: name.to_string(),
+            token_hash: hash_token(&secret),
+            scope,
+            created_at: now,
+            expires_at: now + lifetime,
+        };
+        (token, secret)
+    }
+
+    pub fn is_expired(&self, now: NaiveDateTime) -> bool {
+        self.expires_at <= now
+    }
+}
+
+#[async_trait]
+pub trait ApiTokens: Send + Sync {
+    async fn insert_token(&self, token: &ApiToken) -> ChordDbResult<()>;
+    async fn get_token_by_hash(&self, token_hash: &str) -> ChordDbResult<Option<ApiToken>>;
+    /// Tokens of the user, newest first
+    async fn user_tokens(&self, user_id: &Uuid) -> ChordDbResult<Vec<ApiToken>>;
+    /// Revokes a token of the user
+    async fn delete_token(&self, user_id: &Uuid, id: &Uuid) -> ChordDbResult<bool>;
+    /// Revokes every token of the user. Returns the number of tokens revoked
+    async fn delete_user_tokens(&self, user_id: &Uuid) -> ChordDbResult<u64>;
+}
+
+/// Finds the token with the secret sent by a client, if it has not expired
+pub async fn find_token(tokens: &dyn ApiTokens, secret: &str) -> ChordDbResult<Option<ApiToken>> {
+    if !secret.starts_with(TOKEN_PREFIX) {
+        return Ok(None);
+    }
+    let token = tokens.get_token_by_hash(&hash_token(secret)).await?;
+    Ok(token.filter(|token| !token.is_expired(Utc::now().naive_utc())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_scopes() {
+        assert!(TokenScope::Read < TokenScope::Write);
+        for scope in [TokenScope::Read, TokenScope::Write] {
+            assert_eq!(TokenScope::parse(scope.id()), Some(scope));
+        }
+        assert_eq!(TokenScope::parse("admin"), None);
+    }
+
+    #[test(tokio::test)]
+    async fn test_find_token() {
+        let tokens = MemoryApiTokens::new();
+        let user_id = Uuid::new_v4();
+        let (token, secret) =
+            ApiToken::generate(user_id, "script", TokenScope::Read, TimeDelta::days(1));
+        assert!(secret.starts_with(TOKEN_PREFIX));
+        assert_ne!(token.token_hash, secret);
+        tokens.insert_token(&token).await.unwrap();
+        let (expired, expired_secret) =
+            ApiToken::generate(user_id, "old", TokenScope::Write, TimeDelta::seconds(-1));
+        tokens.insert_token(&expired).await.unwrap();
+
+        assert_eq!(find_token(&tokens, &secret).await.unwrap(), Some(token));
+        assert_eq!(find_token(&tokens, &expired_secret).await.unwrap(), None);
+        assert_eq!(find_token(&tokens, "chorddb_unknown").await.unwrap(), None);
+        assert_eq!(find_token(&tokens, "").await.unwrap(), None);
+    }
+}
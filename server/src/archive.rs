@@ -0,0 +1,406 @@
+use std::{collections::BTreeMap, io::Read};
+
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    error::{ChordDbError, ChordDbResult},
+    export::{build_sheet, render_chordpro, SheetOptions},
+    importer::{import_song, SourceFormat},
+    parser::parse_metadata,
+    song::{OwnerKind, Song},
+    user::User,
+};
+
+/// Version of the archive layout. Archives written by newer versions are rejected
+pub const ARCHIVE_VERSION: u32 = 1;
+
+const MANIFEST_PATH: &str = "manifest.json";
+const SONGS_DIR: &str = "songs/";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub exported_at: NaiveDateTime,
+    pub exported_by: String,
+    pub songs: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub id: Uuid,
+    pub title: String,
+    pub author: String,
+    /// Path of the song, with all its metadata, in the archive
+    pub json: String,
+    /// Path of a ChordPro copy of the song, for other apps
+    pub chordpro: String,
+}
+
+fn archive_error<E: std::fmt::Display>(err: E) -> ChordDbError {
+    ChordDbError::BadRequest(format!("Invalid archive: {}", err))
+}
+
+fn append(builder: &mut tar::Builder<Vec<u8>>, path: &str, data: &[u8]) -> ChordDbResult<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Utc::now().timestamp().max(0) as u64);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, path, data)
+        .map_err(|err| ChordDbError::Generic(Box::new(err)))
+}
+
+/// Writes the songs as a tar archive, each one as JSON and as ChordPro, plus a manifest
+pub fn write_archive(songs: &[Song], exported_by: &str) -> ChordDbResult<Vec<u8>> {
+    let mut builder = tar::Builder::new(vec![]);
+    let mut entries = vec![];
+    for song in songs {
+        let entry = ManifestEntry {
+            id: *song.id(),
+            title: song.title().to_owned(),
+            author: song.author().to_owned(),
+            json: format!("{}{}.json", SONGS_DIR, song.id()),
+            chordpro: format!("{}{}.cho", SONGS_DIR, song.id()),
+        };
+        let json =
+            serde_json::to_vec_pretty(song).map_err(|err| ChordDbError::Generic(Box::new(err)))?;
+        append(&mut builder, &entry.json, &json)?;
+        let sheet = build_sheet(song, &SheetOptions::default(), |_| None);
+        append(
+            &mut builder,
+            &entry.chordpro,
+            render_chordpro(&sheet).as_bytes(),
+        )?;
+        entries.push(entry);
+    }
+
+    let manifest = Manifest {
+        version: ARCHIVE_VERSION,
+        exported_at: Utc::now().naive_utc(),
+        exported_by: exported_by.to_owned(),
+        songs: entries,
+    };
+    let manifest =
+        serde_json::to_vec_pretty(&manifest).map_err(|err| ChordDbError::Generic(Box::new(err)))?;
+    append(&mut builder, MANIFEST_PATH, &manifest)?;
+    builder
+        .into_inner()
+        .map_err(|err| ChordDbError::Generic(Box::new(err)))
+}
+
+#[derive(Default)]
+pub struct ArchiveContents {
+    pub songs: Vec<Song>,
+    /// Files that could not be read
+    pub warnings: Vec<String>,
+}
+
+/// Reads the songs in an archive written by `write_archive`, as new songs of `owner`. ChordPro
+/// files are only read for songs without a JSON copy, so archives from other apps can be
+/// imported too.
+pub fn read_archive(data: &[u8], owner: &User) -> ChordDbResult<ArchiveContents> {
+    let mut files = BTreeMap::new();
+    let mut archive = tar::Archive::new(data);
+    for entry in archive.entries().map_err(archive_error)? {
+        let mut entry = entry.map_err(archive_error)?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path().map_err(archive_error)?.display().to_string();
+        // Archives repacked with `tar -cf archive.tar .` have paths like `./songs/...`
+        let path = path.trim_start_matches("./").to_owned();
+        let mut contents = String::new();
+        entry
+            .read_to_string(&mut contents)
+            .map_err(|err| archive_error(format!("{}: {}", path, err)))?;
+        files.insert(path, contents);
+    }
+
+    if let Some(manifest) = files.get(MANIFEST_PATH) {
+        let manifest: Manifest = serde_json::from_str(manifest).map_err(archive_error)?;
+        if manifest.version > ARCHIVE_VERSION {
+            return Err(ChordDbError::BadRequest(format!(
+                "Archive version {} is newer than the supported version {}",
+                manifest.version, ARCHIVE_VERSION
+            )));
+        }
+    }
+
+    let mut contents = ArchiveContents::default();
+    for (path, data) in &files {
+        if path == MANIFEST_PATH {
+            continue;
+        }
+        let Some(name) = path.strip_prefix(SONGS_DIR) else {
+            contents
+                .warnings
+                .push(format!("{}: not a song, ignored", path));
+            continue;
+        };
+        if name.ends_with(".json") {
+            match serde_json::from_str::<Song>(data) {
+                Ok(mut song) => {
+                    song.header.owner_id = owner.id;
+                    song.header.owner_kind = OwnerKind::User;
+                    song.header.version = 0;
+                    contents.songs.push(song);
+                }
+                Err(err) => contents.warnings.push(format!("{}: {}", path, err)),
+            }
+        } else if let Some(stem) = name.strip_suffix(".cho") {
+            if files.contains_key(&format!("{}{}.json", SONGS_DIR, stem)) {
+                continue;
+            }
+            let imported = import_song(data, Some(SourceFormat::ChordPro));
+            let id = Uuid::parse_str(stem).unwrap_or_else(|_| Uuid::new_v4());
+            let mut song = Song::new(
+                id,
+                imported.author.unwrap_or_default(),
+                imported.title.unwrap_or_else(|| stem.to_owned()),
+                imported.contents,
+                owner,
+            );
+            song.header.note_locale = owner.note_locale.unwrap_or_default();
+            song.header.metadata = parse_metadata(song.contents(), song.note_locale());
+            contents.songs.push(song);
+            contents.warnings.extend(
+                imported
+                    .warnings
+                    .into_iter()
+                    .map(|warning| format!("{}: {}", path, warning)),
+            );
+        } else {
+            contents
+                .warnings
+                .push(format!("{}: not a song, ignored", path));
+        }
+    }
+    Ok(contents)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportStatus {
+    /// New song, added to the library
+    Created,
+    /// Same song as one already in the library, skipped
+    Duplicate,
+    /// Different song with the id or title and author of one in the library, skipped
+    Conflict,
+    /// New song that could not be saved
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportItem {
+    pub id: Uuid,
+    pub title: String,
+    pub author: String,
+    pub status: ImportStatus,
+    /// Song in the library the imported song matches
+    pub existing: Option<Uuid>,
+}
+
+fn same_name(a: &Song, b: &Song) -> bool {
+    a.title().trim().to_lowercase() == b.title().trim().to_lowercase()
+        && a.author().trim().to_lowercase() == b.author().trim().to_lowercase()
+}
+
+fn same_song(a: &Song, b: &Song) -> bool {
+    same_name(a, b) && a.contents().trim_end() == b.contents().trim_end()
+}
+
+/// Decides what to do with each song of an archive, matching them with the `library` by id and
+/// then by title and author. Repeated songs in the archive are matched with their first copy.
+pub fn plan_import(songs: &[Song], library: &[Song]) -> Vec<ImportItem> {
+    let mut created: Vec<&Song> = vec![];
+    songs
+        .iter()
+        .map(|song| {
+            let existing = library
+                .iter()
+                .chain(created.iter().copied())
+                .find(|other| other.id() == song.id())
+                .or_else(|| {
+                    library
+                        .iter()
+                        .chain(created.iter().copied())
+                        .find(|other| same_name(other, song))
+                });
+            let status = match existing {
+                None => {
+                    created.push(song);
+                    ImportStatus::Created
+                }
+                Some(existing) if same_song(existing, song) => ImportStatus::Duplicate,
+                Some(_) => ImportStatus::Conflict,
+            };
+            ImportItem {
+                id: *song.id(),
+                title: song.title().to_owned(),
+                author: song.author().to_owned(),
+                status,
+                existing: existing.map(|existing| *existing.id()),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::user;
+
+    use super::*;
+    use test_log::test;
+
+    fn song(title: &str, contents: &str, owner: &User) -> Song {
+        let mut song = Song::new(
+            Uuid::new_v4(),
+            "Author".to_owned(),
+            title.to_owned(),
+            contents.to_owned(),
+            owner,
+        );
+        song.header.metadata = parse_metadata(contents, song.note_locale());
+        song.header.version = 3;
+        song
+    }
+
+    #[test]
+    fn test_archive_round_trip() {
+        let owner = user("owner");
+        let songs = vec![
+            song("First", "{key: Am}\nAm    C\nHello world", &owner),
+            song("Second", "G\nla la la", &owner),
+        ];
+        let archive = write_archive(&songs, &owner.name).unwrap();
+
+        let importer = user("importer");
+        let contents = read_archive(&archive, &importer).unwrap();
+        assert!(contents.warnings.is_empty());
+        assert_eq!(contents.songs.len(), 2);
+        for song in &songs {
+            let read = contents
+                .songs
+                .iter()
+                .find(|read| read.id() == song.id())
+                .unwrap();
+            assert_eq!(read.title(), song.title());
+            assert_eq!(read.contents(), song.contents());
+            assert_eq!(read.metadata(), song.metadata());
+            assert_eq!(read.owner_id(), &importer.id);
+            assert_eq!(read.header.version, 0);
+        }
+    }
+
+    #[test]
+    fn test_read_chordpro_archive() {
+        let mut builder = tar::Builder::new(vec![]);
+        append(
+            &mut builder,
+            "songs/song.cho",
+            b"{title: Song}\n{artist: Someone}\n[Am]Hello [C]world\n",
+        )
+        .unwrap();
+        let archive = builder.into_inner().unwrap();
+
+        let contents = read_archive(&archive, &user("user")).unwrap();
+        assert_eq!(contents.songs.len(), 1);
+        let song = &contents.songs[0];
+        assert_eq!(song.title(), "Song");
+        assert_eq!(song.author(), "Someone");
+        assert_eq!(song.contents(), "Am    C\nHello world");
+    }
+
+    #[test]
+    fn test_read_repacked_archive() {
+        let owner = user("owner");
+        let mut builder = tar::Builder::new(vec![]);
+        let song = song("Song", "G\nla", &owner);
+        let json = serde_json::to_vec(&song).unwrap();
+        append(&mut builder, "./songs/song.json", &json).unwrap();
+        append(&mut builder, "./notes.txt", b"notes").unwrap();
+        append(&mut builder, "./songs/cover.png", b"png").unwrap();
+        let archive = builder.into_inner().unwrap();
+
+        let contents = read_archive(&archive, &owner).unwrap();
+        assert_eq!(contents.songs.len(), 1);
+        assert_eq!(contents.songs[0].title(), "Song");
+        assert_eq!(
+            contents.warnings,
+            vec![
+                "notes.txt: not a song, ignored".to_owned(),
+                "songs/cover.png: not a song, ignored".to_owned()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_newer_archive() {
+        let manifest = Manifest {
+            version: ARCHIVE_VERSION + 1,
+            exported_at: Utc::now().naive_utc(),
+            exported_by: "user".to_owned(),
+            songs: vec![],
+        };
+        let mut builder = tar::Builder::new(vec![]);
+        append(
+            &mut builder,
+            MANIFEST_PATH,
+            &serde_json::to_vec(&manifest).unwrap(),
+        )
+        .unwrap();
+        let archive = builder.into_inner().unwrap();
+        assert!(matches!(
+            read_archive(&archive, &user("user")),
+            Err(ChordDbError::BadRequest(_))
+        ));
+        assert!(read_archive(b"not an archive", &user("user")).is_err());
+    }
+
+    #[test]
+    fn test_plan_import() {
+        let owner = user("owner");
+        let existing = song("Existing", "Am\nla la", &owner);
+        let library = vec![existing.clone()];
+
+        let mut same_id = existing.clone();
+        same_id.contents = "C\nla la".to_owned();
+        let mut same_name = song("existing ", "Am\nla la", &owner);
+        same_name.header.id = Uuid::new_v4();
+        let mut renamed = song("Existing", "G\nother", &owner);
+        renamed.header.author = "Someone else".to_owned();
+        let new = song("New", "G\nla", &owner);
+        let repeated = new.clone();
+
+        let plan = plan_import(
+            &[
+                existing.clone(),
+                same_id,
+                same_name,
+                renamed.clone(),
+                new.clone(),
+                repeated,
+            ],
+            &library,
+        );
+        let statuses: Vec<(ImportStatus, Option<Uuid>)> = plan
+            .iter()
+            .map(|item| (item.status, item.existing))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                (ImportStatus::Duplicate, Some(*existing.id())),
+                (ImportStatus::Conflict, Some(*existing.id())),
+                (ImportStatus::Duplicate, Some(*existing.id())),
+                (ImportStatus::Created, None),
+                (ImportStatus::Created, None),
+                (ImportStatus::Duplicate, Some(*new.id())),
+            ]
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+
+use axum::async_trait;
+use chrono::Utc;
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+    TransactionTrait,
+};
+use uuid::Uuid;
+
+use crate::{
+    entities::{
+        band, band_member,
+        prelude::{Band as BandEntity, BandMember as BandMemberEntity},
+    },
+    error::{ChordDbError, ChordDbResult},
+};
+
+use super::{Band, BandMember, BandRole, Bands, Membership};
+
+pub struct SeaOrmBands {
+    db: DatabaseConnection,
+}
+
+impl SeaOrmBands {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+}
+
+fn parse_uuid(id: &str) -> ChordDbResult<Uuid> {
+    Uuid::parse_str(id)
+        .map_err(|err| ChordDbError::InvalidData(format!("Invalid uuid: '{}'. Err: {}", id, err)))
+}
+
+fn parse_role(role: &str) -> ChordDbResult<BandRole> {
+    BandRole::parse(role)
+        .ok_or_else(|| ChordDbError::InvalidData(format!("Invalid band role: '{}'", role)))
+}
+
+fn build_band(model: band::Model) -> ChordDbResult<Band> {
+    Ok(Band {
+        id: parse_uuid(&model.id)?,
+        name: model.name,
+        created_at: model.created_at,
+    })
+}
+
+fn build_member(model: band_member::Model) -> ChordDbResult<BandMember> {
+    Ok(BandMember {
+        band_id: parse_uuid(&model.band_id)?,
+        user_id: parse_uuid(&model.user_id)?,
+        role: parse_role(&model.role)?,
+    })
+}
+
+#[async_trait]
+impl Bands for SeaOrmBands {
+    async fn get_band(&self, id: &Uuid) -> ChordDbResult<Option<Band>> {
+        BandEntity::find_by_id(id.to_string())
+            .one(&self.db)
+            .await?
+            .map(build_band)
+            .transpose()
+    }
+
+    async fn user_bands(&self, user_id: &Uuid) -> ChordDbResult<Vec<Membership>> {
+        let roles: HashMap<String, String> = BandMemberEntity::find()
+            .filter(band_member::Column::UserId.eq(user_id.to_string()))
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|member| (member.band_id, member.role))
+            .collect();
+        BandEntity::find()
+            .filter(band::Column::Id.is_in(roles.keys()))
+            .order_by_asc(band::Column::Name)
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|band| {
+                let role = parse_role(&roles[&band.id])?;
+                Ok(Membership {
+                    band: build_band(band)?,
+                    role,
+                })
+            })
+            .collect()
+    }
+
+    async fn create_band(&self, name: &str, leader: &Uuid) -> ChordDbResult<Band> {
+        let model = band::Model {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            created_at: Utc::now().naive_utc(),
+        };
+        let txn = self.db.begin().await?;
+        BandEntity::insert(band::ActiveModel::from(model.clone()))
+            .exec(&txn)
+            .await?;
+        BandMemberEntity::insert(band_member::ActiveModel::from(band_member::Model {
+            band_id: model.id.clone(),
+            user_id: leader.to_string(),
+            role: BandRole::Leader.id().to_string(),
+        }))
+        .exec(&txn)
+        .await?;
+        txn.commit().await?;
+        build_band(model)
+    }
+
+    async fn delete_band(&self, id: &Uuid) -> ChordDbResult<bool> {
+        let result = BandEntity::delete_by_id(id.to_string())
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    async fn members(&self, band_id: &Uuid) -> ChordDbResult<Vec<BandMember>> {
+        BandMemberEntity::find()
+            .filter(band_member::Column::BandId.eq(band_id.to_string()))
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(build_member)
+            .collect()
+    }
+
+    async fn member_role(&self, band_id: &Uuid, user_id: &Uuid) -> ChordDbResult<Option<BandRole>> {
+        BandMemberEntity::find_by_id((band_id.to_string(), user_id.to_string()))
+            .one(&self.db)
+            .await?
+            .map(|model| parse_role(&model.role))
+            .transpose()
+    }
+
+    async fn upsert_member(&self, member: &BandMember) -> ChordDbResult<()> {
+        BandMemberEntity::insert(band_member::ActiveModel::from(band_member::Model {
+            band_id: member.band_id.to_string(),
+            user_id: member.user_id.to_string(),
+            role: member.role.id().to_string(),
+        }))
+        .on_conflict(
+            OnConflict::columns([band_member::Column::BandId, band_member::Column::UserId])
+                .update_column(band_member::Column::Role)
+                .to_owned(),
+        )
+        .exec(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_member(&self, band_id: &Uuid, user_id: &Uuid) -> ChordDbResult<bool> {
+        let result = BandMemberEntity::delete_by_id((band_id.to_string(), user_id.to_string()))
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+}
@@ -0,0 +1,91 @@
+use axum::async_trait;
+use chrono::Utc;
+use dashmap::DashMap;
+use uuid::Uuid;
+
+use crate::error::ChordDbResult;
+
+use super::{Band, BandMember, BandRole, Bands, Membership};
+
+/// Bands kept in memory, for servers running without a database
+#[derive(Default)]
+pub struct MemoryBands {
+    bands: DashMap<Uuid, Band>,
+    /// Role of each member, by band and user id
+    members: DashMap<(Uuid, Uuid), BandRole>,
+}
+
+impl MemoryBands {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Bands for MemoryBands {
+    async fn get_band(&self, id: &Uuid) -> ChordDbResult<Option<Band>> {
+        Ok(self.bands.get(id).map(|band| band.value().clone()))
+    }
+
+    async fn user_bands(&self, user_id: &Uuid) -> ChordDbResult<Vec<Membership>> {
+        let mut bands: Vec<Membership> = self
+            .members
+            .iter()
+            .filter(|member| member.key().1 == *user_id)
+            .filter_map(|member| {
+                self.bands.get(&member.key().0).map(|band| Membership {
+                    band: band.value().clone(),
+                    role: *member.value(),
+                })
+            })
+            .collect();
+        bands.sort_by(|a, b| a.band.name.cmp(&b.band.name));
+        Ok(bands)
+    }
+
+    async fn create_band(&self, name: &str, leader: &Uuid) -> ChordDbResult<Band> {
+        let band = Band {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            created_at: Utc::now().naive_utc(),
+        };
+        self.bands.insert(band.id, band.clone());
+        self.members.insert((band.id, *leader), BandRole::Leader);
+        Ok(band)
+    }
+
+    async fn delete_band(&self, id: &Uuid) -> ChordDbResult<bool> {
+        self.members.retain(|(band_id, _), _| band_id != id);
+        Ok(self.bands.remove(id).is_some())
+    }
+
+    async fn members(&self, band_id: &Uuid) -> ChordDbResult<Vec<BandMember>> {
+        Ok(self
+            .members
+            .iter()
+            .filter(|member| member.key().0 == *band_id)
+            .map(|member| BandMember {
+                band_id: *band_id,
+                user_id: member.key().1,
+                role: *member.value(),
+            })
+            .collect())
+    }
+
+    async fn member_role(&self, band_id: &Uuid, user_id: &Uuid) -> ChordDbResult<Option<BandRole>> {
+        Ok(self
+            .members
+            .get(&(*band_id, *user_id))
+            .map(|role| *role.value()))
+    }
+
+    async fn upsert_member(&self, member: &BandMember) -> ChordDbResult<()> {
+        self.members
+            .insert((member.band_id, member.user_id), member.role);
+        Ok(())
+    }
+
+    async fn delete_member(&self, band_id: &Uuid, user_id: &Uuid) -> ChordDbResult<bool> {
+        Ok(self.members.remove(&(*band_id, *user_id)).is_some())
+    }
+}
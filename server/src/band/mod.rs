@@ -0,0 +1,99 @@
+use axum::async_trait;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{error::ChordDbResult, song::SongRole};
+
+mod database;
+mod memory;
+
+pub use database::SeaOrmBands;
+pub use memory::MemoryBands;
+
+/// A group of users sharing a song library
+#[derive(Debug, Clone, Serialize)]
+pub struct Band {
+    pub id: Uuid,
+    pub name: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BandRole {
+    /// Reads and edits the band songs
+    Member,
+    /// Also deletes and shares the band songs and manages the members
+    Leader,
+}
+
+impl BandRole {
+    pub fn parse(source: &str) -> Option<BandRole> {
+        match source {
+            "member" => Some(BandRole::Member),
+            "leader" => Some(BandRole::Leader),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            BandRole::Member => "member",
+            BandRole::Leader => "leader",
+        }
+    }
+
+    /// Role on the songs owned by the band
+    pub fn song_role(&self) -> SongRole {
+        match self {
+            BandRole::Member => SongRole::Editor,
+            BandRole::Leader => SongRole::Owner,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BandMember {
+    pub band_id: Uuid,
+    pub user_id: Uuid,
+    pub role: BandRole,
+}
+
+/// A band as seen by one of its members
+#[derive(Debug, Clone, Serialize)]
+pub struct Membership {
+    #[serde(flatten)]
+    pub band: Band,
+    pub role: BandRole,
+}
+
+#[async_trait]
+pub trait Bands: Send + Sync {
+    async fn get_band(&self, id: &Uuid) -> ChordDbResult<Option<Band>>;
+    async fn user_bands(&self, user_id: &Uuid) -> ChordDbResult<Vec<Membership>>;
+    /// Creates a band led by `leader`
+    async fn create_band(&self, name: &str, leader: &Uuid) -> ChordDbResult<Band>;
+    async fn delete_band(&self, id: &Uuid) -> ChordDbResult<bool>;
+    async fn members(&self, band_id: &Uuid) -> ChordDbResult<Vec<BandMember>>;
+    async fn member_role(&self, band_id: &Uuid, user_id: &Uuid) -> ChordDbResult<Option<BandRole>>;
+    async fn upsert_member(&self, member: &BandMember) -> ChordDbResult<()>;
+    async fn delete_member(&self, band_id: &Uuid, user_id: &Uuid) -> ChordDbResult<bool>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_band_roles() {
+        for role in [BandRole::Member, BandRole::Leader] {
+            assert_eq!(BandRole::parse(role.id()), Some(role));
+        }
+        assert_eq!(BandRole::parse("drummer"), None);
+        assert!(BandRole::Leader > BandRole::Member);
+        assert_eq!(BandRole::Member.song_role(), SongRole::Editor);
+        assert_eq!(BandRole::Leader.song_role(), SongRole::Owner);
+    }
+}
@@ -23,6 +23,14 @@ impl Corda {
     pub fn new(note: Note, frets: usize) -> Self {
         Corda { note, frets }
     }
+
+    pub fn note(&self) -> Note {
+        self.note
+    }
+
+    pub fn frets(&self) -> usize {
+        self.frets
+    }
 }
 
 #[derive(Clone, PartialEq, Eq)]
@@ -88,6 +96,10 @@ impl StringInstrument {
     pub fn description(&self) -> &str {
         &self.description
     }
+
+    pub fn strings(&self) -> &[Corda] {
+        &self.strings
+    }
 }
 
 lazy_static! {
@@ -120,6 +132,8 @@ lazy_static! {
         m.insert(Variant::MinorSeventh, Variant::Minor);
         m.insert(Variant::Seventh, Variant::Major);
         m.insert(Variant::AddNinth, Variant::Major);
+        m.insert(Variant::MajorSeventh, Variant::Major);
+        m.insert(Variant::HalfDiminished, Variant::Diminished);
         m
     };
 }
@@ -145,6 +159,22 @@ impl Fingering {
     pub fn placements(&self) -> &[Option<usize>] {
         &self.placements
     }
+
+    /// Rough measure of how hard the fingering is to play: fretted strings, hand stretch and
+    /// position on the neck
+    pub fn difficulty(&self) -> usize {
+        let fretted: Vec<usize> = self
+            .placements
+            .iter()
+            .flatten()
+            .copied()
+            .filter(|fret| *fret > 0)
+            .collect();
+        let (Some(lowest), Some(highest)) = (fretted.iter().min(), fretted.iter().max()) else {
+            return 0;
+        };
+        fretted.len() + 2 * (highest - lowest) + 2 * (lowest - 1)
+    }
 }
 
 impl Debug for Fingering {
@@ -506,4 +536,19 @@ mod tests {
             &[None, Some(0), Some(4), Some(4), Some(4), Some(2)]
         );
     }
+
+    #[test]
+    fn fingering_difficulty() {
+        let fingering = |placements: Vec<Option<usize>>| Fingering {
+            instrument_id: "guitar".to_owned(),
+            placements,
+        };
+        let open = fingering(vec![Some(0), Some(2), Some(2), Some(0), Some(0), Some(0)]);
+        let barre = fingering(vec![Some(1), Some(3), Some(3), Some(2), Some(1), Some(1)]);
+        let high = fingering(vec![None, Some(2), Some(4), Some(4), Some(3), Some(2)]);
+        assert_eq!(open.difficulty(), 4);
+        assert!(open.difficulty() < barre.difficulty());
+        assert!(barre.difficulty() < high.difficulty());
+        assert_eq!(fingering(vec![Some(0), None]).difficulty(), 0);
+    }
 }
@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+use strum::EnumIter;
+
+use super::Key;
+
+/// Naming convention used to write notes, both when parsing and when rendering chords
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, EnumIter, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteLocale {
+    /// C D E F G A B
+    #[default]
+    English,
+    /// Do Re Mi Fa Sol La Si
+    Solfege,
+    /// C D E F G A H, where B is B flat
+    German,
+}
+
+impl NoteLocale {
+    pub fn parse(source: &str) -> Option<NoteLocale> {
+        match source.to_lowercase().as_str() {
+            "english" | "en" | "letters" => Some(NoteLocale::English),
+            "solfege" | "solfège" | "es" | "it" | "fr" => Some(NoteLocale::Solfege),
+            "german" | "de" => Some(NoteLocale::German),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            NoteLocale::English => "english",
+            NoteLocale::Solfege => "solfege",
+            NoteLocale::German => "german",
+        }
+    }
+
+    pub fn key_text(&self, key: &Key) -> &'static str {
+        match self {
+            NoteLocale::English => key.text(),
+            NoteLocale::Solfege => match key {
+                Key::C => "Do",
+                Key::Db => "Reb",
+                Key::D => "Re",
+                Key::Eb => "Mib",
+                Key::E => "Mi",
+                Key::F => "Fa",
+                Key::Gb => "Solb",
+                Key::G => "Sol",
+                Key::Ab => "Lab",
+                Key::A => "La",
+                Key::Bb => "Sib",
+                Key::B => "Si",
+            },
+            NoteLocale::German => match key {
+                Key::Bb => "B",
+                Key::B => "H",
+                _ => key.text(),
+            },
+        }
+    }
+
+    pub fn key_names(&self, key: &Key) -> Vec<&'static str> {
+        match self {
+            NoteLocale::English => key.valid_names(),
+            NoteLocale::Solfege => match key {
+                Key::C => vec!["Do"],
+                Key::Db => vec!["Do#", "Reb", "Réb"],
+                Key::D => vec!["Re", "Ré"],
+                Key::Eb => vec!["Re#", "Ré#", "Mib"],
+                Key::E => vec!["Mi"],
+                Key::F => vec!["Fa"],
+                Key::Gb => vec!["Fa#", "Solb"],
+                Key::G => vec!["Sol"],
+                Key::Ab => vec!["Sol#", "Lab"],
+                Key::A => vec!["La"],
+                Key::Bb => vec!["La#", "Sib"],
+                Key::B => vec!["Si"],
+            },
+            NoteLocale::German => match key {
+                Key::C => vec!["C"],
+                Key::Db => vec!["C#", "Db", "Cis", "Des"],
+                Key::D => vec!["D"],
+                Key::Eb => vec!["D#", "Eb", "Dis", "Es"],
+                Key::E => vec!["E"],
+                Key::F => vec!["F"],
+                Key::Gb => vec!["F#", "Gb", "Fis", "Ges"],
+                Key::G => vec!["G"],
+                Key::Ab => vec!["G#", "Ab", "Gis", "As"],
+                Key::A => vec!["A"],
+                Key::Bb => vec!["A#", "B", "Ais"],
+                Key::B => vec!["H"],
+            },
+        }
+    }
+}
@@ -10,6 +10,9 @@ use sea_orm::Iterable;
 use strum::EnumIter;
 
 pub mod finder;
+mod locale;
+
+pub use locale::NoteLocale;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, EnumIter)]
 pub enum Key {
@@ -29,18 +32,23 @@ pub enum Key {
 
 lazy_static! {
     static ref ALL_KEYS: Vec<Key> = Key::iter().collect();
-    static ref KEYS_BY_NAME: HashMap<&'static str, Key> = Key::iter()
-        .flat_map(|k| k
-            .valid_names()
-            .into_iter()
-            .map(|n| (n, k))
-            .collect::<Vec<_>>())
+    static ref KEYS_BY_NAME: HashMap<NoteLocale, HashMap<&'static str, Key>> = NoteLocale::iter()
+        .map(|locale| (
+            locale,
+            Key::iter()
+                .flat_map(|k| locale
+                    .key_names(&k)
+                    .into_iter()
+                    .map(|n| (n, k))
+                    .collect::<Vec<_>>())
+                .collect()
+        ))
         .collect();
 }
 
 impl Key {
-    fn parse(source: &str) -> Option<&'static Key> {
-        KEYS_BY_NAME.get(source)
+    fn parse_in(source: &str, locale: NoteLocale) -> Option<&'static Key> {
+        KEYS_BY_NAME.get(&locale)?.get(source)
     }
 
     fn ordinal(&self) -> usize {
@@ -77,6 +85,14 @@ impl Key {
         }
     }
 
+    pub fn text_in(&self, locale: NoteLocale) -> &'static str {
+        locale.key_text(self)
+    }
+
+    pub fn transpose(&self, semitones: i32) -> Key {
+        ALL_KEYS[(self.ordinal() as i32 + semitones).rem_euclid(12) as usize]
+    }
+
     pub fn valid_names(&self) -> Vec<&'static str> {
         match self {
             Key::C => vec!["C"],
@@ -163,15 +179,22 @@ pub enum Variant {
     Seventh,
     MinorSeventh,
     MinorSixth,
+    MajorSeventh,
     SuspendedSecond,
     AddNinth,
     Diminished,
+    HalfDiminished,
     Augmented,
 }
 
 lazy_static! {
-    static ref VARIANTS_BY_TEXT: HashMap<&'static str, Variant> =
-        Variant::iter().map(|v| (v.text(), v)).collect();
+    static ref VARIANTS_BY_TEXT: HashMap<&'static str, Variant> = Variant::iter()
+        .flat_map(|v| v
+            .valid_names()
+            .into_iter()
+            .map(|n| (n, v))
+            .collect::<Vec<_>>())
+        .collect();
 }
 
 impl Variant {
@@ -186,13 +209,32 @@ impl Variant {
             Variant::Seventh => "7",
             Variant::MinorSeventh => "m7",
             Variant::MinorSixth => "m6",
+            Variant::MajorSeventh => "maj7",
             Variant::SuspendedSecond => "sus2",
             Variant::AddNinth => "add9",
             Variant::Diminished => "dim",
+            Variant::HalfDiminished => "m7b5",
             Variant::Augmented => "aug",
         }
     }
 
+    /// All the spellings accepted when parsing, including `text()`
+    pub fn valid_names(&self) -> Vec<&'static str> {
+        match self {
+            Variant::Major => vec!["", "maj"],
+            Variant::Minor => vec!["m", "min", "-"],
+            Variant::Seventh => vec!["7"],
+            Variant::MinorSeventh => vec!["m7", "min7", "-7"],
+            Variant::MinorSixth => vec!["m6", "min6", "-6"],
+            Variant::MajorSeventh => vec!["maj7", "M7", "Δ", "Δ7"],
+            Variant::SuspendedSecond => vec!["sus2"],
+            Variant::AddNinth => vec!["add9"],
+            Variant::Diminished => vec!["dim", "°"],
+            Variant::HalfDiminished => vec!["m7b5", "ø", "ø7", "-7b5"],
+            Variant::Augmented => vec!["aug", "+"],
+        }
+    }
+
     pub fn intervals(&self) -> Vec<usize> {
         match self {
             Variant::Major => vec![0, 4, 7],
@@ -200,23 +242,35 @@ impl Variant {
             Variant::Seventh => vec![0, 4, 7, 10],
             Variant::MinorSeventh => vec![0, 3, 7, 10],
             Variant::MinorSixth => vec![0, 3, 7, 9],
+            Variant::MajorSeventh => vec![0, 4, 7, 11],
             Variant::SuspendedSecond => vec![0, 2, 7],
             Variant::AddNinth => vec![0, 4, 7, 14],
             Variant::Diminished => vec![0, 3, 6],
+            Variant::HalfDiminished => vec![0, 3, 6, 10],
             Variant::Augmented => vec![0, 4, 8],
         }
     }
 }
 
 lazy_static! {
-    static ref KEY_PATTERN: String = format!("(?:{})", KEYS_BY_NAME.keys().join("|"));
-    static ref VARIANT_PATTERN: String =
-        format!("(?:{})", Variant::iter().map(|v| v.text()).join("|"));
-    static ref CHORD_PATTERN: String = format!(
-        "^(?<root>{})(?<variant>{})(?:/(?<bass>{}))?$",
-        *KEY_PATTERN, *VARIANT_PATTERN, *KEY_PATTERN
+    static ref VARIANT_PATTERN: String = format!(
+        "(?:{})",
+        VARIANTS_BY_TEXT
+            .keys()
+            .map(|name| regex::escape(name))
+            .join("|")
     );
-    static ref CHORD_REGEX: Regex = Regex::new(&CHORD_PATTERN).unwrap();
+    static ref CHORD_REGEXES: HashMap<NoteLocale, Regex> = KEYS_BY_NAME
+        .iter()
+        .map(|(locale, keys)| {
+            let key_pattern = format!("(?:{})", keys.keys().join("|"));
+            let chord_pattern = format!(
+                "^(?<root>{})(?<variant>{})(?:/(?<bass>{}))?$",
+                key_pattern, *VARIANT_PATTERN, key_pattern
+            );
+            (*locale, Regex::new(&chord_pattern).unwrap())
+        })
+        .collect();
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -240,29 +294,51 @@ impl Chord {
     }
 
     pub fn text(&self) -> String {
+        self.text_in(NoteLocale::English)
+    }
+
+    pub fn text_in(&self, locale: NoteLocale) -> String {
         let bass = if self.root == self.bass {
             "".to_owned()
         } else {
-            format!("/{}", self.bass.text())
+            format!("/{}", self.bass.text_in(locale))
         };
-        format!("{}{}{}", self.root.text(), self.variant.text(), bass)
+        format!(
+            "{}{}{}",
+            self.root.text_in(locale),
+            self.variant.text(),
+            bass
+        )
     }
 
     pub fn parse<S: AsRef<str>>(source: S) -> Option<Chord> {
-        log::trace!("Parsing chord '{}'", source.as_ref());
-        let caps = CHORD_REGEX.captures(source.as_ref())?;
-        let root = Key::parse(&caps["root"]).copied()?;
+        Chord::parse_in(source, NoteLocale::English)
+    }
+
+    pub fn parse_in<S: AsRef<str>>(source: S, locale: NoteLocale) -> Option<Chord> {
+        log::trace!("Parsing chord '{}' ({})", source.as_ref(), locale.id());
+        let normalized = source.as_ref().replace('♯', "#").replace('♭', "b");
+        let caps = CHORD_REGEXES.get(&locale)?.captures(&normalized)?;
+        let root = Key::parse_in(&caps["root"], locale).copied()?;
         Some(Chord {
             root,
             variant: Variant::parse(&caps["variant"]).copied()?,
             bass: caps
                 .name("bass")
-                .and_then(|s| Key::parse(s.as_str()))
+                .and_then(|s| Key::parse_in(s.as_str(), locale))
                 .cloned()
                 .unwrap_or(root),
         })
     }
 
+    pub fn transpose(&self, semitones: i32) -> Chord {
+        Chord::new(
+            self.root.transpose(semitones),
+            self.variant,
+            self.bass.transpose(semitones),
+        )
+    }
+
     pub fn keys(&self) -> HashSet<Key> {
         let root_ordinal = self.root.ordinal();
         self.variant
@@ -311,7 +387,10 @@ mod tests {
     #[test]
     fn validate_key_parsing() {
         for key in Key::iter() {
-            assert_eq!(key, *Key::parse(key.text()).unwrap());
+            assert_eq!(
+                key,
+                *Key::parse_in(key.text(), NoteLocale::English).unwrap()
+            );
         }
     }
 
@@ -341,4 +420,79 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn validate_transposition() {
+        assert_eq!(Key::B.transpose(1), Key::C);
+        assert_eq!(Key::C.transpose(-1), Key::B);
+        assert_eq!(Key::G.transpose(14), Key::A);
+        assert_eq!(
+            Chord::parse("Am/G").unwrap().transpose(2).text(),
+            "Bm/A".to_owned()
+        );
+    }
+
+    #[test]
+    fn validate_alternative_symbols() {
+        let cases = [
+            ("C♯", Chord::simple(Key::Db, Variant::Major)),
+            ("B♭m", Chord::simple(Key::Bb, Variant::Minor)),
+            ("CΔ", Chord::simple(Key::C, Variant::MajorSeventh)),
+            ("Cmaj7", Chord::simple(Key::C, Variant::MajorSeventh)),
+            ("C°", Chord::simple(Key::C, Variant::Diminished)),
+            ("Cø", Chord::simple(Key::C, Variant::HalfDiminished)),
+            ("C+", Chord::simple(Key::C, Variant::Augmented)),
+            ("C-7", Chord::simple(Key::C, Variant::MinorSeventh)),
+            ("E♭/B♭", Chord::new(Key::Eb, Variant::Major, Key::Bb)),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(Chord::parse(text), Some(expected), "Parsing '{}'", text);
+        }
+    }
+
+    #[test]
+    fn validate_localized_chords_round_trip() {
+        for locale in NoteLocale::iter() {
+            for root in Key::iter() {
+                for variant in Variant::iter() {
+                    let chord = Chord::new(root, variant, Key::E);
+                    assert_eq!(
+                        Chord::parse_in(chord.text_in(locale), locale),
+                        Some(chord),
+                        "{} did not round trip in {:?}",
+                        chord,
+                        locale
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn validate_localized_parsing() {
+        let solfege = NoteLocale::Solfege;
+        assert_eq!(
+            Chord::parse_in("Rem", solfege),
+            Some(Chord::simple(Key::D, Variant::Minor))
+        );
+        assert_eq!(
+            Chord::parse_in("La/Do#", solfege),
+            Some(Chord::new(Key::A, Variant::Major, Key::Db))
+        );
+        assert_eq!(Chord::parse_in("Am", solfege), None);
+
+        let german = NoteLocale::German;
+        assert_eq!(
+            Chord::parse_in("H7", german),
+            Some(Chord::simple(Key::B, Variant::Seventh))
+        );
+        assert_eq!(
+            Chord::parse_in("B", german),
+            Some(Chord::simple(Key::Bb, Variant::Major))
+        );
+        assert_eq!(
+            Chord::simple(Key::Bb, Variant::Minor).text_in(german),
+            "Bm".to_string()
+        );
+    }
 }
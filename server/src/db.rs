@@ -0,0 +1,211 @@
+//! Database connection and schema migrations, with the migrations of the `migration` crate
+//! built into the server
+
+use std::{fs::File, path::Path};
+
+use migration::{Migrator, MigratorTrait};
+use sea_orm::{Database, DatabaseConnection};
+
+use crate::error::{ChordDbError, ChordDbResult};
+
+const SQLITE_PREFIX: &str = "sqlite://";
+
+fn create_sqlite_db_if_missing(url: &str) -> ChordDbResult<()> {
+    if !url.starts_with(SQLITE_PREFIX) {
+        return Ok(());
+    }
+    // Options like `?mode=rwc` are not part of the path
+    let path = url[SQLITE_PREFIX.len()..]
+        .split('?')
+        .next()
+        .unwrap_or_default();
+    let path = Path::new(path);
+    if !path.exists() {
+        log::info!("Creating empty database @{}", url);
+        File::create(path).map_err(|err| ChordDbError::Generic(Box::new(err)))?;
+    }
+    Ok(())
+}
+
+/// Connects to the database at `url`, creating SQLite databases that do not exist yet
+pub async fn connect(url: &str) -> ChordDbResult<DatabaseConnection> {
+    create_sqlite_db_if_missing(url)?;
+    Ok(Database::connect(url).await?)
+}
+
+/// Migrations of the database, by name
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SchemaStatus {
+    pub applied: Vec<String>,
+    pub pending: Vec<String>,
+    /// Applied migrations this server does not know about, from a newer version
+    pub unknown: Vec<String>,
+}
+
+impl SchemaStatus {
+    /// Compares the migrations the server `known`s with the ones `applied` to the database
+    pub fn new(known: &[String], applied: &[String]) -> Self {
+        let (applied_known, pending) = known
+            .iter()
+            .cloned()
+            .partition(|name| applied.contains(name));
+        SchemaStatus {
+            applied: applied_known,
+            pending,
+            unknown: applied
+                .iter()
+                .filter(|name| !known.contains(name))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    pub fn is_newer(&self) -> bool {
+        !self.unknown.is_empty()
+    }
+}
+
+pub async fn schema_status(db: &DatabaseConnection) -> ChordDbResult<SchemaStatus> {
+    let known: Vec<String> = Migrator::migrations()
+        .iter()
+        .map(|migration| migration.name().to_string())
+        .collect();
+    let applied: Vec<String> = Migrator::get_migration_models(db)
+        .await?
+        .into_iter()
+        .map(|model| model.version)
+        .collect();
+    Ok(SchemaStatus::new(&known, &applied))
+}
+
+/// Checks the schema before serving. Pending migrations are applied if `migrate` is set, and
+/// databases migrated by a newer version of the server are rejected.
+pub async fn prepare_schema(db: &DatabaseConnection, migrate: bool) -> ChordDbResult<()> {
+    let status = schema_status(db).await?;
+    if status.is_newer() {
+        return Err(ChordDbError::InvalidData(format!(
+            "The database schema is newer than this server, it has unknown migrations: {}",
+            status.unknown.join(", ")
+        )));
+    }
+    if status.pending.is_empty() {
+        return Ok(());
+    }
+    if migrate {
+        log::info!("Applying {} migrations", status.pending.len());
+        Migrator::up(db, None).await?;
+    } else {
+        log::warn!(
+            "The database has {} pending migrations, run `chorddb db up` to apply them",
+            status.pending.len()
+        );
+    }
+    Ok(())
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum DbCommand {
+    /// Show the applied and pending migrations
+    Status,
+    /// Apply pending migrations
+    Up {
+        /// number of migrations to apply, all of them by default
+        #[clap(short = 'n', long = "steps")]
+        steps: Option<u32>,
+    },
+    /// Roll back applied migrations
+    Down {
+        /// number of migrations to roll back
+        #[clap(short = 'n', long = "steps", default_value = "1")]
+        steps: u32,
+    },
+}
+
+pub async fn run_command(db: &DatabaseConnection, command: &DbCommand) -> ChordDbResult<()> {
+    match command {
+        DbCommand::Status => {
+            let status = schema_status(db).await?;
+            for name in &status.applied {
+                println!("Applied  {}", name);
+            }
+            for name in &status.pending {
+                println!("Pending  {}", name);
+            }
+            for name in &status.unknown {
+                println!("Unknown  {}", name);
+            }
+            if status.is_newer() {
+                println!("The database schema is newer than this server");
+            }
+        }
+        DbCommand::Up { steps } => {
+            if schema_status(db).await?.is_newer() {
+                return Err(ChordDbError::InvalidData(
+                    "The database schema is newer than this server".to_string(),
+                ));
+            }
+            Migrator::up(db, *steps).await?
+        }
+        DbCommand::Down { steps } => Migrator::down(db, Some(*steps)).await?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{ConnectionTrait, Statement};
+
+    use crate::testing::test_databases;
+
+    use super::*;
+    use test_log::test;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_schema_status() {
+        let known = names(&["m1", "m2", "m3"]);
+        assert_eq!(
+            SchemaStatus::new(&known, &names(&["m1"])),
+            SchemaStatus {
+                applied: names(&["m1"]),
+                pending: names(&["m2", "m3"]),
+                unknown: vec![],
+            }
+        );
+        let status = SchemaStatus::new(&known, &names(&["m1", "m2", "m3", "m4"]));
+        assert!(status.pending.is_empty());
+        assert_eq!(status.unknown, names(&["m4"]));
+        assert!(status.is_newer());
+    }
+
+    #[test(tokio::test)]
+    async fn test_prepare_schema() {
+        for database in test_databases().await {
+            let db = &database.db;
+            Migrator::down(db, Some(2)).await.unwrap();
+            assert_eq!(schema_status(db).await.unwrap().pending.len(), 2);
+            prepare_schema(db, false).await.unwrap();
+            assert_eq!(schema_status(db).await.unwrap().pending.len(), 2);
+            prepare_schema(db, true).await.unwrap();
+            assert!(schema_status(db).await.unwrap().pending.is_empty());
+
+            db.execute(Statement::from_string(
+                db.get_database_backend(),
+                "INSERT INTO seaql_migrations (version, applied_at) VALUES ('m29990101_000001_future', 0)",
+            ))
+            .await
+            .unwrap();
+            assert!(
+                matches!(
+                    prepare_schema(db, true).await,
+                    Err(ChordDbError::InvalidData(_))
+                ),
+                "{}",
+                database.name
+            );
+        }
+    }
+}
@@ -2,6 +2,19 @@
 
 pub mod prelude;
 
+pub mod account_token;
+pub mod api_token;
+pub mod band;
+pub mod band_member;
+pub mod invite;
 pub mod session;
+pub mod setlist;
+pub mod setlist_entry;
 pub mod song;
+pub mod song_band_grant;
+pub mod song_chord;
+pub mod song_grant;
+pub mod song_revision;
+pub mod song_share_link;
+pub mod song_tag;
 pub mod user;
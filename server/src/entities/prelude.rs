@@ -1,5 +1,18 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.0.0-rc.7
 
+pub use super::account_token::Entity as AccountToken;
+pub use super::api_token::Entity as ApiToken;
+pub use super::band::Entity as Band;
+pub use super::band_member::Entity as BandMember;
+pub use super::invite::Entity as Invite;
 pub use super::session::Entity as Session;
+pub use super::setlist::Entity as Setlist;
+pub use super::setlist_entry::Entity as SetlistEntry;
 pub use super::song::Entity as Song;
+pub use super::song_band_grant::Entity as SongBandGrant;
+pub use super::song_chord::Entity as SongChord;
+pub use super::song_grant::Entity as SongGrant;
+pub use super::song_revision::Entity as SongRevision;
+pub use super::song_share_link::Entity as SongShareLink;
+pub use super::song_tag::Entity as SongTag;
 pub use super::user::Entity as User;
@@ -8,9 +8,19 @@ pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub id: String,
     pub owner: String,
+    pub owner_kind: String,
     pub author: String,
     pub title: String,
     pub tablature: String,
+    pub note_locale: String,
+    pub key: Option<String>,
+    pub capo: Option<i32>,
+    pub tempo: Option<i32>,
+    pub time_signature: Option<String>,
+    pub tuning: Option<String>,
+    pub instrument: Option<String>,
+    pub updated_at: DateTime,
+    pub version: i32,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
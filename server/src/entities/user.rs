@@ -11,6 +11,8 @@ pub struct Model {
     pub email: String,
     pub password: String,
     pub is_admin: bool,
+    pub email_verified: bool,
+    pub note_locale: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -11,6 +11,10 @@ pub enum ChordDbError {
     InvalidData(String),
     BadRequest(String),
     Forbidden,
+    /// The client changed an outdated version of a resource. Carries the current one
+    Conflict(serde_json::Value),
+    /// An `If-Match` precondition failed. Carries the current version of the resource
+    PreconditionFailed(serde_json::Value),
     Generic(Box<dyn Error>),
 }
 
@@ -22,6 +26,8 @@ impl Display for ChordDbError {
             ChordDbError::InvalidData(msg) => format!("InvalidData: {}", msg),
             ChordDbError::BadRequest(msg) => format!("BadRequest: {}", msg),
             ChordDbError::Forbidden => "Forbidden".to_string(),
+            ChordDbError::Conflict(_) => "Conflict".to_string(),
+            ChordDbError::PreconditionFailed(_) => "PreconditionFailed".to_string(),
             ChordDbError::Generic(e) => format!("Generic: {}", e),
         };
         f.write_fmt(format_args!("ChordDbError::{}", message))
@@ -57,6 +63,12 @@ impl SimpleError {
     }
 }
 
+#[derive(Debug, Serialize)]
+struct ConflictError {
+    message: String,
+    current: serde_json::Value,
+}
+
 impl IntoResponse for ChordDbError {
     fn into_response(self) -> axum::response::Response {
         match self {
@@ -69,6 +81,22 @@ impl IntoResponse for ChordDbError {
                 (StatusCode::BAD_REQUEST, Json(SimpleError::new(message))).into_response()
             }
             ChordDbError::Forbidden => (StatusCode::FORBIDDEN, "Forbidden").into_response(),
+            ChordDbError::Conflict(current) => (
+                StatusCode::CONFLICT,
+                Json(ConflictError {
+                    message: "The resource was modified by someone else".to_string(),
+                    current,
+                }),
+            )
+                .into_response(),
+            ChordDbError::PreconditionFailed(current) => (
+                StatusCode::PRECONDITION_FAILED,
+                Json(ConflictError {
+                    message: "The resource does not match If-Match".to_string(),
+                    current,
+                }),
+            )
+                .into_response(),
             _ => {
                 log::warn!("Request failed! {}", self);
                 (StatusCode::INTERNAL_SERVER_ERROR, "Something went wrong :(").into_response()
@@ -0,0 +1,134 @@
+use super::sheet::{SheetLine, SongSheet};
+
+/// Moves the chords of the row above into the lyrics, as `[Am]` at the column they are played
+fn inline_chords(chords: &str, lyrics: &str) -> String {
+    let mut placed = vec![];
+    let mut start = None;
+    for (column, c) in chords.chars().chain(std::iter::once(' ')).enumerate() {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some(column),
+            (true, Some(begin)) => {
+                let chord: String = chords.chars().skip(begin).take(column - begin).collect();
+                placed.push((begin, chord));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    let mut line: Vec<String> = lyrics.chars().map(String::from).collect();
+    if let Some((last, _)) = placed.last() {
+        line.resize(line.len().max(*last), " ".to_owned());
+    }
+    for (column, chord) in placed.into_iter().rev() {
+        line.insert(column, format!("[{}]", chord));
+    }
+    line.concat().trim_end().to_owned()
+}
+
+/// Prints a song in the ChordPro format, with its metadata as directives
+pub fn render_chordpro(sheet: &SongSheet) -> String {
+    let mut output = vec![format!("{{title: {}}}", sheet.title)];
+    if !sheet.author.is_empty() {
+        output.push(format!("{{artist: {}}}", sheet.author));
+    }
+    let metadata = &sheet.metadata;
+    let directives = [
+        ("key", metadata.key.clone()),
+        ("capo", metadata.capo.map(|capo| capo.to_string())),
+        ("tempo", metadata.tempo.map(|tempo| tempo.to_string())),
+        ("time", metadata.time_signature.clone()),
+        ("tuning", metadata.tuning.clone()),
+        ("instrument", metadata.instrument.clone()),
+    ];
+    for (name, value) in directives {
+        if let Some(value) = value {
+            output.push(format!("{{{}: {}}}", name, value));
+        }
+    }
+
+    for section in &sheet.sections {
+        output.push(String::new());
+        for line in &section.lines {
+            match line {
+                SheetLine::Lyrics { chords, lyrics } => output.push(inline_chords(chords, lyrics)),
+                SheetLine::Tab(staves) => {
+                    output.push("{start_of_tab}".to_owned());
+                    output.extend(staves.iter().cloned());
+                    output.push("{end_of_tab}".to_owned());
+                }
+            }
+        }
+    }
+    output.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        export::Section,
+        importer::{import_song, SourceFormat},
+        parser::SongMetadata,
+    };
+
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_inline_chords() {
+        assert_eq!(
+            inline_chords("Am    C", "Hello world"),
+            "[Am]Hello [C]world"
+        );
+        assert_eq!(inline_chords("G", ""), "[G]");
+        assert_eq!(inline_chords("A     (E)", "la"), "[A]la    [(E)]");
+        assert_eq!(inline_chords("", "la la"), "la la");
+    }
+
+    #[test]
+    fn test_render_chordpro() {
+        let sheet = SongSheet {
+            title: "Title".to_owned(),
+            author: "Author".to_owned(),
+            metadata: SongMetadata {
+                key: Some("Am".to_owned()),
+                ..Default::default()
+            },
+            sections: vec![
+                Section {
+                    lines: vec![
+                        SheetLine::Lyrics {
+                            chords: "Am    C".to_owned(),
+                            lyrics: "Hello world".to_owned(),
+                        },
+                        SheetLine::Lyrics {
+                            chords: "G     E7".to_owned(),
+                            lyrics: String::new(),
+                        },
+                    ],
+                },
+                Section {
+                    lines: vec![SheetLine::Lyrics {
+                        chords: String::new(),
+                        lyrics: "la la la".to_owned(),
+                    }],
+                },
+            ],
+            diagrams: vec![],
+        };
+        let chordpro = render_chordpro(&sheet);
+        assert_eq!(
+            chordpro,
+            "{title: Title}\n{artist: Author}\n{key: Am}\n\n[Am]Hello [C]world\n[G]      [E7]\n\nla la la\n"
+        );
+
+        let imported = import_song(&chordpro, None);
+        assert_eq!(imported.format, SourceFormat::ChordPro);
+        assert_eq!(imported.title.as_deref(), Some("Title"));
+        assert_eq!(imported.author.as_deref(), Some("Author"));
+        assert_eq!(
+            imported.contents,
+            "{key: Am}\n\nAm    C\nHello world\nG     E7\n\nla la la"
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use std::fmt::Write;
+
+use super::sheet::{ChordDiagram, SheetLine, SongSheet};
+
+const STYLE: &str =
+    "body{font-family:sans-serif;margin:0 auto;max-width:60em;padding:1em;color:#222}\
+h1{font-size:1.5em;margin:0}\
+header p{margin:.2em 0;color:#555}\
+nav ol{padding-left:1.5em}\
+nav a{color:inherit}\
+article{margin-bottom:3em}\
+.diagrams{display:flex;flex-wrap:wrap;gap:.5em;margin:1em 0}\
+.sheet{font-family:monospace;font-size:clamp(.7em,2.5vw,1em);white-space:pre;overflow-x:auto}\
+.sheet section{margin-bottom:1em;break-inside:avoid}\
+.chords{font-weight:bold;color:#a11}\
+@media print{nav+article,article+article{break-before:page}}";
+
+const DIAGRAM_FRETS: usize = 4;
+const GRID_LEFT: usize = 12;
+const GRID_WIDTH: usize = 50;
+const GRID_TOP: usize = 26;
+const FRET_HEIGHT: usize = 14;
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Draws a chord diagram as an inline SVG
+fn diagram(output: &mut String, diagram: &ChordDiagram) {
+    let strings = diagram.placements.len();
+    let grid_bottom = GRID_TOP + DIAGRAM_FRETS * FRET_HEIGHT;
+    let base = diagram.base_fret(DIAGRAM_FRETS);
+    let _ = write!(
+        output,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"80\" height=\"{}\" viewBox=\"0 0 80 {}\" role=\"img\"><title>{} {}</title>",
+        grid_bottom + 4,
+        grid_bottom + 4,
+        escape(&diagram.name),
+        diagram.fingering(),
+    );
+    let _ = write!(
+        output,
+        "<text x=\"{}\" y=\"12\" text-anchor=\"middle\" font-family=\"sans-serif\" font-size=\"12\" font-weight=\"bold\">{}</text>",
+        GRID_LEFT + GRID_WIDTH / 2,
+        escape(&diagram.name),
+    );
+    if strings < 2 {
+        output.push_str("</svg>");
+        return;
+    }
+    let spacing = GRID_WIDTH as f32 / (strings - 1) as f32;
+    output.push_str("<g stroke=\"#222\">");
+    for fret in 0..=DIAGRAM_FRETS {
+        let width = if fret == 0 && base == 1 { 3 } else { 1 };
+        let y = GRID_TOP + fret * FRET_HEIGHT;
+        let _ = write!(
+            output,
+            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke-width=\"{}\"/>",
+            GRID_LEFT,
+            y,
+            GRID_LEFT + GRID_WIDTH,
+            y,
+            width
+        );
+    }
+    for string in 0..strings {
+        let x = GRID_LEFT as f32 + string as f32 * spacing;
+        let _ = write!(
+            output,
+            "<line x1=\"{:.1}\" y1=\"{}\" x2=\"{:.1}\" y2=\"{}\"/>",
+            x, GRID_TOP, x, grid_bottom
+        );
+    }
+    output.push_str("</g>");
+
+    for (string, placement) in diagram.placements.iter().enumerate() {
+        let x = GRID_LEFT as f32 + string as f32 * spacing;
+        match placement {
+            None | Some(0) => {
+                let _ = write!(
+                    output,
+                    "<text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\" font-family=\"sans-serif\" font-size=\"8\">{}</text>",
+                    x,
+                    GRID_TOP - 3,
+                    if placement.is_none() { "x" } else { "o" },
+                );
+            }
+            Some(fret) => {
+                let y = GRID_TOP as f32 + ((fret + 1 - base) as f32 - 0.5) * FRET_HEIGHT as f32;
+                let _ = write!(
+                    output,
+                    "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"4\" fill=\"#222\"/>",
+                    x, y
+                );
+            }
+        }
+    }
+    if base > 1 {
+        let _ = write!(
+            output,
+            "<text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"8\">{}fr</text>",
+            GRID_LEFT + GRID_WIDTH + 3,
+            GRID_TOP + FRET_HEIGHT - 4,
+            base
+        );
+    }
+    output.push_str("</svg>");
+}
+
+fn song(output: &mut String, index: usize, sheet: &SongSheet) {
+    let _ = write!(
+        output,
+        "<article id=\"song-{}\"><header><h1>{}</h1>",
+        index + 1,
+        escape(&sheet.title)
+    );
+    if !sheet.author.is_empty() {
+        let _ = write!(output, "<p>{}</p>", escape(&sheet.author));
+    }
+    let summary = sheet.summary();
+    if !summary.is_empty() {
+        let _ = write!(output, "<p>{}</p>", escape(&summary));
+    }
+    output.push_str("</header>");
+
+    if !sheet.diagrams.is_empty() {
+        output.push_str("<div class=\"diagrams\">");
+        for chord in &sheet.diagrams {
+            diagram(output, chord);
+        }
+        output.push_str("</div>");
+    }
+
+    output.push_str("<div class=\"sheet\">");
+    for section in &sheet.sections {
+        output.push_str("<section>");
+        for line in &section.lines {
+            match line {
+                SheetLine::Lyrics { chords, lyrics } => {
+                    if !chords.is_empty() {
+                        let _ = write!(output, "<div class=\"chords\">{}</div>", escape(chords));
+                    }
+                    if !lyrics.is_empty() {
+                        let _ = write!(output, "<div>{}</div>", escape(lyrics));
+                    }
+                }
+                SheetLine::Tab(staves) => {
+                    for staff in staves {
+                        let _ = write!(output, "<div>{}</div>", escape(staff));
+                    }
+                }
+            }
+        }
+        output.push_str("</section>");
+    }
+    output.push_str("</div></article>");
+}
+
+/// Prints the songs as a single HTML page, with no external resources. Pages with several songs
+/// start with a table of contents.
+pub fn render_html(sheets: &[SongSheet], title: &str) -> String {
+    let mut output = String::new();
+    let _ = write!(
+        output,
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta name=\"viewport\" content=\"width=device-width, initial-scale=1\"><title>{}</title><style>{}</style></head><body>",
+        escape(title),
+        STYLE
+    );
+    if sheets.len() > 1 {
+        let _ = write!(output, "<nav><h1>{}</h1><ol>", escape(title));
+        for (index, sheet) in sheets.iter().enumerate() {
+            let name = if sheet.author.is_empty() {
+                sheet.title.clone()
+            } else {
+                format!("{} - {}", sheet.title, sheet.author)
+            };
+            let _ = write!(
+                output,
+                "<li><a href=\"#song-{}\">{}</a></li>",
+                index + 1,
+                escape(&name)
+            );
+        }
+        output.push_str("</ol></nav>");
+    }
+    for (index, sheet) in sheets.iter().enumerate() {
+        song(&mut output, index, sheet);
+    }
+    output.push_str("</body></html>\n");
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{export::Section, parser::SongMetadata};
+
+    use super::*;
+    use test_log::test;
+
+    fn sheet(title: &str) -> SongSheet {
+        SongSheet {
+            title: title.to_owned(),
+            author: "Simon & Garfunkel".to_owned(),
+            metadata: SongMetadata::default(),
+            sections: vec![Section {
+                lines: vec![SheetLine::Lyrics {
+                    chords: "Am    C".to_owned(),
+                    lyrics: "Hello <darkness>".to_owned(),
+                }],
+            }],
+            diagrams: vec![ChordDiagram {
+                name: "Am".to_owned(),
+                placements: vec![None, Some(0), Some(2), Some(2), Some(1), Some(0)],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_render_html() {
+        let html = render_html(&[sheet("Title")], "Title");
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(!html.contains("<nav>"));
+        assert!(html.contains("<p>Simon &amp; Garfunkel</p>"));
+        assert!(
+            html.contains("<div class=\"chords\">Am    C</div><div>Hello &lt;darkness&gt;</div>")
+        );
+        assert!(html.contains("<title>Am X02210</title>"));
+        assert_eq!(html.matches("<circle").count(), 3);
+        assert!(!html.contains("src=") && !html.contains("href=\"http"));
+    }
+
+    #[test]
+    fn test_render_html_contents() {
+        let html = render_html(&[sheet("First"), sheet("Second")], "Setlist");
+        assert!(html.contains("<li><a href=\"#song-2\">Second - Simon &amp; Garfunkel</a></li>"));
+        assert!(html.contains("<article id=\"song-2\">"));
+    }
+}
@@ -0,0 +1,11 @@
+mod chordpro;
+mod html;
+mod pdf;
+mod sheet;
+mod text;
+
+pub use chordpro::render_chordpro;
+pub use html::render_html;
+pub use pdf::{render_pdf, PdfOptions};
+pub use sheet::{build_sheet, ChordDiagram, Section, SheetLine, SheetOptions, SongSheet};
+pub use text::render_text;
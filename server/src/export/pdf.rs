@@ -0,0 +1,603 @@
+use printpdf::{
+    calculate_points_for_circle,
+    path::{PaintMode, WindingOrder},
+    Line, Mm, PdfDocument, Point, Polygon,
+};
+use ttf_parser::Face;
+
+use crate::error::{ChordDbError, ChordDbResult};
+
+use super::sheet::{ChordDiagram, SheetLine, SongSheet};
+
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 15.0;
+const COLUMN_GAP: f32 = 8.0;
+const CONTENT_TOP: f32 = PAGE_HEIGHT - MARGIN;
+/// Leaves room for the page numbers
+const CONTENT_BOTTOM: f32 = MARGIN + 6.0;
+
+const MM_PER_PT: f32 = 0.3528;
+const MAX_BODY_SIZE: f32 = 10.0;
+const MIN_BODY_SIZE: f32 = 6.0;
+const LINE_SPACING: f32 = 1.2;
+
+const DIAGRAM_WIDTH: f32 = 18.0;
+const DIAGRAM_HEIGHT: f32 = 26.0;
+const DIAGRAM_GRID_WIDTH: f32 = 11.0;
+const DIAGRAM_FRETS: usize = 4;
+const DIAGRAM_FRET_HEIGHT: f32 = 3.5;
+
+const TOC_LINE_HEIGHT: f32 = 6.0;
+
+/// Embedded so songs print the same everywhere, in any script the font covers (Latin, Greek,
+/// Cyrillic and most symbols), which the builtin PDF fonts don't
+const REGULAR_FONT: &[u8] = include_bytes!("fonts/DejaVuSansMono.ttf");
+const BOLD_FONT: &[u8] = include_bytes!("fonts/DejaVuSansMono-Bold.ttf");
+
+lazy_static! {
+    static ref REGULAR_FACE: Face<'static> =
+        Face::parse(REGULAR_FONT, 0).expect("The regular font is valid");
+    static ref BOLD_FACE: Face<'static> =
+        Face::parse(BOLD_FONT, 0).expect("The bold font is valid");
+}
+
+/// How to print a songbook
+#[derive(Debug, Clone)]
+pub struct PdfOptions {
+    pub title: String,
+    /// Text columns per page, 1 or 2
+    pub columns: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Font {
+    Lyrics,
+    Chords,
+    Heading,
+    Text,
+}
+
+impl Font {
+    fn face(self) -> &'static Face<'static> {
+        match self {
+            Font::Lyrics | Font::Text => &REGULAR_FACE,
+            Font::Chords | Font::Heading => &BOLD_FACE,
+        }
+    }
+}
+
+/// Drawing operation, with coordinates in millimeters from the bottom left corner
+#[derive(Debug, Clone, PartialEq)]
+enum Op {
+    Text {
+        x: f32,
+        y: f32,
+        size: f32,
+        font: Font,
+        text: String,
+    },
+    Line {
+        points: Vec<(f32, f32)>,
+        thickness: f32,
+    },
+    Dot {
+        x: f32,
+        y: f32,
+        radius: f32,
+    },
+}
+
+#[derive(Debug, Default)]
+struct Page {
+    ops: Vec<Op>,
+    bookmark: Option<String>,
+}
+
+/// Width of the text in millimeters, with the advances of the glyphs of the font. Characters
+/// the font does not have are printed as its missing glyph.
+fn text_width(text: &str, size: f32, font: Font) -> f32 {
+    let face = font.face();
+    let missing = ttf_parser::GlyphId(0);
+    let units: u32 = text
+        .chars()
+        .map(|c| {
+            let glyph = face.glyph_index(c).unwrap_or(missing);
+            face.glyph_hor_advance(glyph).unwrap_or_default() as u32
+        })
+        .sum();
+    units as f32 / face.units_per_em() as f32 * size * MM_PER_PT
+}
+
+fn line_height(size: f32) -> f32 {
+    size * LINE_SPACING * MM_PER_PT
+}
+
+/// Cursor over the pages of the songbook
+struct Layout {
+    pages: Vec<Page>,
+    columns: usize,
+    column: usize,
+    /// Where the columns of the current page start, below the song header
+    column_top: f32,
+    y: f32,
+}
+
+impl Layout {
+    fn new(columns: usize) -> Self {
+        Layout {
+            pages: vec![],
+            columns: columns.clamp(1, 2),
+            column: 0,
+            column_top: CONTENT_TOP,
+            y: CONTENT_TOP,
+        }
+    }
+
+    fn column_width(&self) -> f32 {
+        let columns = self.columns as f32;
+        (PAGE_WIDTH - 2.0 * MARGIN - (columns - 1.0) * COLUMN_GAP) / columns
+    }
+
+    fn column_x(&self) -> f32 {
+        MARGIN + self.column as f32 * (self.column_width() + COLUMN_GAP)
+    }
+
+    fn new_page(&mut self) {
+        self.pages.push(Page::default());
+        self.column = 0;
+        self.column_top = CONTENT_TOP;
+        self.y = CONTENT_TOP;
+    }
+
+    fn next_column(&mut self) {
+        if self.column + 1 < self.columns {
+            self.column += 1;
+            self.y = self.column_top;
+        } else {
+            self.new_page();
+        }
+    }
+
+    /// Moves to the next column if there is not enough room for `height`
+    fn ensure(&mut self, height: f32) {
+        if self.y - height < CONTENT_BOTTOM && self.y < self.column_top {
+            self.next_column();
+        }
+    }
+
+    fn push(&mut self, op: Op) {
+        self.pages
+            .last_mut()
+            .expect("Layout without pages")
+            .ops
+            .push(op);
+    }
+
+    fn text(&mut self, x: f32, y: f32, size: f32, font: Font, text: &str) {
+        self.push(Op::Text {
+            x,
+            y,
+            size,
+            font,
+            text: text.to_string(),
+        });
+    }
+}
+
+/// Font size that fits the widest line of the song in a column
+fn body_size(sheet: &SongSheet, column_width: f32) -> f32 {
+    let width = sheet.width().max(1) as f32;
+    // Every glyph of the monospaced font has the same advance
+    let advance = text_width(" ", 1.0, Font::Lyrics);
+    (column_width / (width * advance)).clamp(MIN_BODY_SIZE, MAX_BODY_SIZE)
+}
+
+/// Draws a chord diagram with its top left corner at `x`, `y`
+fn diagram(layout: &mut Layout, x: f32, y: f32, diagram: &ChordDiagram) {
+    layout.text(x, y - 3.5, 9.0, Font::Heading, &diagram.name);
+    let strings = diagram.placements.len();
+    if strings < 2 {
+        return;
+    }
+    let spacing = DIAGRAM_GRID_WIDTH / (strings - 1) as f32;
+    let grid_top = y - 8.0;
+    let grid_bottom = grid_top - DIAGRAM_FRETS as f32 * DIAGRAM_FRET_HEIGHT;
+
+    let base = diagram.base_fret(DIAGRAM_FRETS);
+
+    for string in 0..strings {
+        let string_x = x + string as f32 * spacing;
+        layout.push(Op::Line {
+            points: vec![(string_x, grid_top), (string_x, grid_bottom)],
+            thickness: 0.5,
+        });
+        match diagram.placements[string] {
+            None => layout.text(string_x - 0.8, grid_top + 0.8, 6.0, Font::Text, "x"),
+            Some(0) => layout.text(string_x - 0.8, grid_top + 0.8, 6.0, Font::Text, "o"),
+            Some(fret) => {
+                let row = (fret + 1 - base) as f32;
+                layout.push(Op::Dot {
+                    x: string_x,
+                    y: grid_top - (row - 0.5) * DIAGRAM_FRET_HEIGHT,
+                    radius: 1.0,
+                });
+            }
+        }
+    }
+    for fret in 0..=DIAGRAM_FRETS {
+        let fret_y = grid_top - fret as f32 * DIAGRAM_FRET_HEIGHT;
+        layout.push(Op::Line {
+            points: vec![(x, fret_y), (x + DIAGRAM_GRID_WIDTH, fret_y)],
+            thickness: if fret == 0 && base == 1 { 1.5 } else { 0.5 },
+        });
+    }
+    if base > 1 {
+        let label = format!("{}fr", base);
+        layout.text(
+            x + DIAGRAM_GRID_WIDTH + 0.8,
+            grid_top - DIAGRAM_FRET_HEIGHT + 0.8,
+            6.0,
+            Font::Text,
+            &label,
+        );
+    }
+}
+
+/// Lays out a song starting on a new page. Returns the number of its first page.
+fn song(layout: &mut Layout, sheet: &SongSheet) -> usize {
+    layout.new_page();
+    let first_page = layout.pages.len();
+    if let Some(page) = layout.pages.last_mut() {
+        page.bookmark = Some(sheet.title.clone());
+    }
+
+    layout.y -= 6.0;
+    layout.text(MARGIN, layout.y, 16.0, Font::Heading, &sheet.title);
+    if !sheet.author.is_empty() {
+        layout.y -= 6.0;
+        layout.text(MARGIN, layout.y, 11.0, Font::Text, &sheet.author);
+    }
+    let metadata = sheet.summary();
+    if !metadata.is_empty() {
+        layout.y -= 5.0;
+        layout.text(MARGIN, layout.y, 9.0, Font::Text, &metadata);
+    }
+    layout.y -= 4.0;
+
+    let per_row = ((PAGE_WIDTH - 2.0 * MARGIN) / DIAGRAM_WIDTH) as usize;
+    for row in sheet.diagrams.chunks(per_row.max(1)) {
+        for (index, chord) in row.iter().enumerate() {
+            let x = MARGIN + index as f32 * DIAGRAM_WIDTH;
+            let y = layout.y;
+            diagram(layout, x, y, chord);
+        }
+        layout.y -= DIAGRAM_HEIGHT;
+    }
+    layout.column_top = layout.y;
+
+    let size = body_size(sheet, layout.column_width());
+    let row_height = line_height(size);
+    let full_column = CONTENT_TOP - CONTENT_BOTTOM;
+    for section in &sheet.sections {
+        let height = section.rows() as f32 * row_height;
+        // Sections that fit in a column are never split
+        if layout.y - height < CONTENT_BOTTOM && height <= full_column {
+            layout.next_column();
+        }
+        for line in &section.lines {
+            let rows: Vec<(&str, Font)> = match line {
+                SheetLine::Lyrics { chords, lyrics } => {
+                    let mut rows = vec![];
+                    if !chords.is_empty() {
+                        rows.push((chords.as_str(), Font::Chords));
+                    }
+                    if !lyrics.is_empty() {
+                        rows.push((lyrics.as_str(), Font::Lyrics));
+                    }
+                    rows
+                }
+                SheetLine::Tab(staves) => staves
+                    .iter()
+                    .map(|staff| (staff.as_str(), Font::Lyrics))
+                    .collect(),
+            };
+            for (text, font) in rows {
+                layout.ensure(row_height);
+                layout.y -= row_height;
+                let x = layout.column_x();
+                let y = layout.y;
+                layout.text(x, y, size, font, text);
+            }
+        }
+        layout.y -= row_height / 2.0;
+    }
+    first_page
+}
+
+fn table_of_contents(pages: &mut [Page], sheets: &[SongSheet], first_pages: &[usize]) {
+    let per_page = table_of_contents_entries();
+    for (index, (sheet, first_page)) in sheets.iter().zip(first_pages).enumerate() {
+        let page = &mut pages[index / per_page];
+        let mut y = CONTENT_TOP - 10.0;
+        if index % per_page == 0 {
+            page.ops.push(Op::Text {
+                x: MARGIN,
+                y,
+                size: 16.0,
+                font: Font::Heading,
+                text: "Contents".to_string(),
+            });
+        }
+        y -= 10.0 + (index % per_page) as f32 * TOC_LINE_HEIGHT;
+        let title = if sheet.author.is_empty() {
+            sheet.title.clone()
+        } else {
+            format!("{} - {}", sheet.title, sheet.author)
+        };
+        page.ops.push(Op::Text {
+            x: MARGIN,
+            y,
+            size: 11.0,
+            font: Font::Text,
+            text: title,
+        });
+        let number = first_page.to_string();
+        page.ops.push(Op::Text {
+            x: PAGE_WIDTH - MARGIN - text_width(&number, 11.0, Font::Lyrics),
+            y,
+            size: 11.0,
+            font: Font::Lyrics,
+            text: number,
+        });
+    }
+}
+
+fn table_of_contents_entries() -> usize {
+    ((CONTENT_TOP - CONTENT_BOTTOM - 20.0) / TOC_LINE_HEIGHT) as usize
+}
+
+/// Lays out the songbook. Songbooks with several songs start with a table of contents.
+fn layout(sheets: &[SongSheet], options: &PdfOptions) -> Vec<Page> {
+    let mut layout = Layout::new(options.columns);
+    let toc_pages = if sheets.len() > 1 {
+        sheets.len().div_ceil(table_of_contents_entries())
+    } else {
+        0
+    };
+    for _ in 0..toc_pages {
+        layout.new_page();
+    }
+
+    let first_pages: Vec<usize> = sheets
+        .iter()
+        .map(|sheet| song(&mut layout, sheet))
+        .collect();
+    if layout.pages.is_empty() {
+        layout.new_page();
+    }
+    table_of_contents(&mut layout.pages, sheets, &first_pages);
+
+    let total = layout.pages.len();
+    for (index, page) in layout.pages.iter_mut().enumerate() {
+        let number = format!("{}/{}", index + 1, total);
+        page.ops.push(Op::Text {
+            x: PAGE_WIDTH - MARGIN - text_width(&number, 8.0, Font::Lyrics),
+            y: MARGIN,
+            size: 8.0,
+            font: Font::Lyrics,
+            text: number,
+        });
+    }
+    layout.pages
+}
+
+fn pdf_error(err: printpdf::Error) -> ChordDbError {
+    ChordDbError::Generic(Box::new(err))
+}
+
+/// Prints the songs as a PDF songbook
+pub fn render_pdf(sheets: &[SongSheet], options: &PdfOptions) -> ChordDbResult<Vec<u8>> {
+    let pages = layout(sheets, options);
+    let (doc, first_page, first_layer) =
+        PdfDocument::new(&options.title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Songs");
+    let regular = doc.add_external_font(REGULAR_FONT).map_err(pdf_error)?;
+    let bold = doc.add_external_font(BOLD_FONT).map_err(pdf_error)?;
+    let font_ref = |font: Font| match font {
+        Font::Lyrics | Font::Text => &regular,
+        Font::Chords | Font::Heading => &bold,
+    };
+
+    for (index, page) in pages.iter().enumerate() {
+        let (page_index, layer_index) = if index == 0 {
+            (first_page, first_layer)
+        } else {
+            doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Songs")
+        };
+        if let Some(bookmark) = &page.bookmark {
+            doc.add_bookmark(bookmark.clone(), page_index);
+        }
+        let layer = doc.get_page(page_index).get_layer(layer_index);
+        for op in &page.ops {
+            match op {
+                Op::Text {
+                    x,
+                    y,
+                    size,
+                    font,
+                    text,
+                } => layer.use_text(text.clone(), *size, Mm(*x), Mm(*y), font_ref(*font)),
+                Op::Line { points, thickness } => {
+                    layer.set_outline_thickness(*thickness);
+                    layer.add_line(Line {
+                        points: points
+                            .iter()
+                            .map(|(x, y)| (Point::new(Mm(*x), Mm(*y)), false))
+                            .collect(),
+                        is_closed: false,
+                    });
+                }
+                Op::Dot { x, y, radius } => layer.add_polygon(Polygon {
+                    rings: vec![calculate_points_for_circle(Mm(*radius), Mm(*x), Mm(*y))],
+                    mode: PaintMode::Fill,
+                    winding_order: WindingOrder::NonZero,
+                }),
+            }
+        }
+    }
+
+    doc.save_to_bytes().map_err(pdf_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{export::Section, parser::SongMetadata};
+
+    use super::*;
+    use test_log::test;
+
+    fn sheet(title: &str, sections: usize, rows: usize) -> SongSheet {
+        SongSheet {
+            title: title.to_string(),
+            author: "Author".to_string(),
+            metadata: SongMetadata::default(),
+            sections: (0..sections)
+                .map(|section| Section {
+                    lines: (0..rows)
+                        .map(|row| SheetLine::Lyrics {
+                            chords: String::new(),
+                            lyrics: format!("{} {}", section, row),
+                        })
+                        .collect(),
+                })
+                .collect(),
+            diagrams: vec![ChordDiagram {
+                name: "Am".to_string(),
+                placements: vec![None, Some(0), Some(2), Some(2), Some(1), Some(0)],
+            }],
+        }
+    }
+
+    /// Page and column of every line of lyrics, by section
+    fn positions(pages: &[Page], sections: usize) -> Vec<Vec<(usize, i32)>> {
+        (0..sections)
+            .map(|section| {
+                let prefix = format!("{} ", section);
+                let prefix = prefix.as_str();
+                pages
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(index, page)| {
+                        page.ops.iter().filter_map(move |op| match op {
+                            Op::Text {
+                                x,
+                                text,
+                                font: Font::Lyrics,
+                                ..
+                            } if text.starts_with(prefix) => Some((index, *x as i32)),
+                            _ => None,
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_layout_keeps_sections_together() {
+        for columns in [1, 2] {
+            let options = PdfOptions {
+                title: "Songbook".to_string(),
+                columns,
+            };
+            let pages = layout(&[sheet("Song", 20, 7)], &options);
+            assert!(pages.len() > 1);
+            for section in positions(&pages, 20) {
+                assert_eq!(section.len(), 7);
+                assert!(section.iter().all(|position| *position == section[0]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_layout_two_columns() {
+        let options = PdfOptions {
+            title: "Songbook".to_string(),
+            columns: 2,
+        };
+        let pages = layout(&[sheet("Song", 20, 7)], &options);
+        let columns: Vec<i32> = positions(&pages, 20)
+            .iter()
+            .filter(|section| section[0].0 == 0)
+            .map(|section| section[0].1)
+            .collect();
+        assert_eq!(columns.first(), Some(&(MARGIN as i32)));
+        assert!(columns.iter().any(|x| *x > (PAGE_WIDTH / 2.0) as i32));
+    }
+
+    #[test]
+    fn test_table_of_contents() {
+        let options = PdfOptions {
+            title: "Songbook".to_string(),
+            columns: 1,
+        };
+        let pages = layout(&[sheet("First", 1, 3), sheet("Second", 1, 3)], &options);
+        assert_eq!(pages.len(), 3);
+        assert_eq!(pages[1].bookmark.as_deref(), Some("First"));
+        let toc: Vec<&str> = pages[0]
+            .ops
+            .iter()
+            .filter_map(|op| match op {
+                Op::Text { text, .. } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            toc,
+            vec![
+                "Contents",
+                "First - Author",
+                "2",
+                "Second - Author",
+                "3",
+                "1/3"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_pdf() {
+        let options = PdfOptions {
+            title: "Songbook".to_string(),
+            columns: 2,
+        };
+        let pdf = render_pdf(&[sheet("Song", 3, 4)], &options).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+
+    #[test]
+    fn test_render_non_latin_lyrics() {
+        let lyrics = "Калинка, калинка моя… Σαν πας στην Ιθάκη F♯ B♭ ♪";
+        for font in [Font::Lyrics, Font::Chords] {
+            for c in lyrics.chars() {
+                assert!(font.face().glyph_index(c).is_some(), "{:?} {}", font, c);
+            }
+        }
+        assert_eq!(
+            text_width("Калинка", 10.0, Font::Lyrics),
+            text_width("Kalinka", 10.0, Font::Lyrics)
+        );
+
+        let mut sheet = sheet("Калинка", 1, 0);
+        sheet.sections[0].lines.push(SheetLine::Lyrics {
+            chords: "Am   E7".to_string(),
+            lyrics: lyrics.to_string(),
+        });
+        let options = PdfOptions {
+            title: "Песни".to_string(),
+            columns: 1,
+        };
+        let pdf = render_pdf(&[sheet], &options).unwrap();
+        assert!(pdf.windows(14).any(|bytes| bytes == b"DejaVuSansMono"));
+    }
+}
@@ -0,0 +1,400 @@
+use crate::{
+    chord::{finder::Fingering, Chord, NoteLocale},
+    parser::{parse_tablature_in, transpose_tablature, Comp, LineBit, SongMetadata},
+    song::Song,
+};
+
+/// How to lay out a song
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SheetOptions {
+    /// Locale of the chords in the sheet. Defaults to the locale of the song
+    pub note_locale: Option<NoteLocale>,
+    /// Semitones to transpose the song
+    pub transpose: i32,
+    /// Capo position to play the song with, instead of the written one
+    pub capo: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SheetLine {
+    /// Chords aligned above the lyrics they are played on. Either row may be empty
+    Lyrics { chords: String, lyrics: String },
+    /// Staves of a tablature block
+    Tab(Vec<String>),
+}
+
+impl SheetLine {
+    /// Rows of text the line takes when printed
+    pub fn rows(&self) -> usize {
+        match self {
+            SheetLine::Lyrics { chords, lyrics } => {
+                (!chords.is_empty() as usize + !lyrics.is_empty() as usize).max(1)
+            }
+            SheetLine::Tab(staves) => staves.len(),
+        }
+    }
+
+    /// Width of the line in characters
+    pub fn width(&self) -> usize {
+        match self {
+            SheetLine::Lyrics { chords, lyrics } => {
+                chords.chars().count().max(lyrics.chars().count())
+            }
+            SheetLine::Tab(staves) => staves
+                .iter()
+                .map(|staff| staff.chars().count())
+                .max()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Lines of a song between blank lines, which should be kept together when printing
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Section {
+    pub lines: Vec<SheetLine>,
+}
+
+impl Section {
+    pub fn rows(&self) -> usize {
+        self.lines.iter().map(SheetLine::rows).sum()
+    }
+}
+
+/// Fingering used for a chord in the song
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChordDiagram {
+    pub name: String,
+    /// Fret of each string, lowest string first. `None` for muted strings
+    pub placements: Vec<Option<usize>>,
+}
+
+impl ChordDiagram {
+    /// Fingering as written in chord charts, like `X02210`
+    pub fn fingering(&self) -> String {
+        let separator = if self.placements.iter().flatten().any(|fret| *fret > 9) {
+            ","
+        } else {
+            ""
+        };
+        self.placements
+            .iter()
+            .map(|fret| fret.map(|n| n.to_string()).unwrap_or("X".to_owned()))
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+
+    /// First fret of a diagram showing `frets` frets. Chords played up the neck start at
+    /// their lowest fretted note
+    pub fn base_fret(&self, frets: usize) -> usize {
+        let fretted = self.placements.iter().flatten().filter(|fret| **fret > 0);
+        if fretted
+            .clone()
+            .max()
+            .is_some_and(|highest| *highest > frets)
+        {
+            fretted.min().copied().unwrap_or(1)
+        } else {
+            1
+        }
+    }
+}
+
+/// A song laid out for export, independent of the output format
+#[derive(Debug, Clone)]
+pub struct SongSheet {
+    pub title: String,
+    pub author: String,
+    pub metadata: SongMetadata,
+    pub sections: Vec<Section>,
+    pub diagrams: Vec<ChordDiagram>,
+}
+
+impl SongSheet {
+    /// Width of the widest line in characters
+    pub fn width(&self) -> usize {
+        self.sections
+            .iter()
+            .flat_map(|section| &section.lines)
+            .map(SheetLine::width)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Key, capo and the rest of the metadata in one line
+    pub fn summary(&self) -> String {
+        let metadata = &self.metadata;
+        let mut parts = vec![];
+        if let Some(key) = &metadata.key {
+            parts.push(format!("Key: {}", key));
+        }
+        if let Some(capo) = metadata.capo.filter(|capo| *capo > 0) {
+            parts.push(format!("Capo: {}", capo));
+        }
+        if let Some(tempo) = metadata.tempo {
+            parts.push(format!("Tempo: {}", tempo));
+        }
+        if let Some(time_signature) = &metadata.time_signature {
+            parts.push(format!("Time: {}", time_signature));
+        }
+        if let Some(tuning) = &metadata.tuning {
+            parts.push(format!("Tuning: {}", tuning));
+        }
+        parts.join("   ")
+    }
+}
+
+/// Writes `text` in `row` starting at the `position` column, keeping at least a space after
+/// the previous text
+fn place(row: &mut String, position: usize, text: &str) {
+    let length = row.chars().count();
+    if length < position {
+        row.extend(std::iter::repeat_n(' ', position - length));
+    } else if length > 0 {
+        row.push(' ');
+    }
+    row.push_str(text);
+}
+
+/// Lays out a song, transposing its chords and using `fingering` to pick the diagram of each
+/// chord
+pub fn build_sheet<F>(song: &Song, options: &SheetOptions, fingering: F) -> SongSheet
+where
+    F: Fn(&Chord) -> Option<Fingering>,
+{
+    let input = song.note_locale();
+    let output = options.note_locale.unwrap_or(input);
+    let mut tablature = parse_tablature_in(song.contents(), input);
+    let shift = song.metadata().shape_shift(options.transpose, options.capo);
+    transpose_tablature(&mut tablature, shift, input);
+
+    let chord_text = |bit: &LineBit, chord: &Chord, original_text: &str| {
+        if input == output {
+            original_text.to_owned()
+        } else {
+            bit.decorations.apply(&chord.text_in(output))
+        }
+    };
+
+    let mut sections = vec![];
+    let mut current = Section::default();
+    let mut pending_chords: Option<String> = None;
+    let mut chords: Vec<Chord> = vec![];
+    for line in &tablature {
+        if line.is_empty() {
+            if let Some(pending) = pending_chords.take() {
+                current.lines.push(SheetLine::Lyrics {
+                    chords: pending,
+                    lyrics: String::new(),
+                });
+            }
+            if !current.lines.is_empty() {
+                sections.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+
+        let mut chord_row = String::new();
+        let mut lyrics_row = String::new();
+        for bit in line {
+            match &bit.comp {
+                Comp::Chord {
+                    chord,
+                    original_text,
+                } => {
+                    place(
+                        &mut chord_row,
+                        bit.position,
+                        &chord_text(bit, chord, original_text),
+                    );
+                    if !chords.contains(chord) {
+                        chords.push(*chord);
+                    }
+                }
+                Comp::Text(text) => place(&mut lyrics_row, bit.position, text),
+                Comp::Tab(tab) => {
+                    if let Some(pending) = pending_chords.take() {
+                        current.lines.push(SheetLine::Lyrics {
+                            chords: pending,
+                            lyrics: String::new(),
+                        });
+                    }
+                    current.lines.push(SheetLine::Tab(
+                        tab.staves.iter().map(|staff| staff.text.clone()).collect(),
+                    ));
+                }
+                // Shown in the header of the sheet
+                Comp::Directive { .. } => {}
+            }
+        }
+
+        if lyrics_row.is_empty() {
+            if chord_row.is_empty() {
+                continue;
+            }
+            // A line of chords goes above the next line of lyrics
+            if let Some(pending) = pending_chords.replace(chord_row) {
+                current.lines.push(SheetLine::Lyrics {
+                    chords: pending,
+                    lyrics: String::new(),
+                });
+            }
+        } else if chord_row.is_empty() {
+            current.lines.push(SheetLine::Lyrics {
+                chords: pending_chords.take().unwrap_or_default(),
+                lyrics: lyrics_row,
+            });
+        } else {
+            if let Some(pending) = pending_chords.take() {
+                current.lines.push(SheetLine::Lyrics {
+                    chords: pending,
+                    lyrics: String::new(),
+                });
+            }
+            current.lines.push(SheetLine::Lyrics {
+                chords: chord_row,
+                lyrics: lyrics_row,
+            });
+        }
+    }
+    if let Some(pending) = pending_chords {
+        current.lines.push(SheetLine::Lyrics {
+            chords: pending,
+            lyrics: String::new(),
+        });
+    }
+    if !current.lines.is_empty() {
+        sections.push(current);
+    }
+
+    let diagrams = chords
+        .iter()
+        .filter_map(|chord| {
+            fingering(chord).map(|fingering| ChordDiagram {
+                name: chord.text_in(output),
+                placements: fingering.placements().to_vec(),
+            })
+        })
+        .collect();
+
+    SongSheet {
+        title: song.title().to_owned(),
+        author: song.author().to_owned(),
+        metadata: song.metadata().transposed(options.transpose, options.capo),
+        sections,
+        diagrams,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::{
+        chord::finder::GUITAR_STANDARD,
+        parser::parse_metadata,
+        song::{ChordRepository, FingeringCalculator},
+        testing::user,
+    };
+
+    use super::*;
+    use test_log::test;
+
+    fn song(contents: &str) -> Song {
+        let owner = user("user");
+        let mut song = Song::new(
+            Uuid::new_v4(),
+            "Author".to_string(),
+            "Title".to_string(),
+            contents.to_string(),
+            &owner,
+        );
+        song.header.metadata = parse_metadata(contents, song.note_locale());
+        song
+    }
+
+    fn lyrics(chords: &str, lyrics: &str) -> SheetLine {
+        SheetLine::Lyrics {
+            chords: chords.to_owned(),
+            lyrics: lyrics.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_build_sheet() {
+        let song = song("{key: Am}\nAm    C\nHello world\nG\n\n\nla la la\nE7");
+        let sheet = build_sheet(&song, &SheetOptions::default(), |_| None);
+        assert_eq!(
+            sheet.sections,
+            vec![
+                Section {
+                    lines: vec![lyrics("Am    C", "Hello world"), lyrics("G", "")],
+                },
+                Section {
+                    lines: vec![lyrics("", "la la la"), lyrics("E7", "")],
+                },
+            ]
+        );
+        assert_eq!(sheet.metadata.key, Some("Am".to_owned()));
+        assert_eq!(sheet.width(), 11);
+        assert_eq!(sheet.sections[0].rows(), 3);
+    }
+
+    #[test]
+    fn test_build_sheet_transposed() {
+        let song = song("A B  (E)\nla la la");
+        let options = SheetOptions {
+            transpose: 1,
+            ..Default::default()
+        };
+        let sheet = build_sheet(&song, &options, |_| None);
+        assert_eq!(
+            sheet.sections[0].lines,
+            vec![lyrics("Bb C (F)", "la la la")]
+        );
+
+        let options = SheetOptions {
+            note_locale: Some(NoteLocale::Solfege),
+            capo: Some(2),
+            ..Default::default()
+        };
+        let sheet = build_sheet(&song, &options, |_| None);
+        assert_eq!(
+            sheet.sections[0].lines,
+            vec![lyrics("Sol La (Re)", "la la la")]
+        );
+        assert_eq!(sheet.metadata.capo, Some(2));
+    }
+
+    #[test]
+    fn test_build_sheet_diagrams() {
+        let song = song("Am C Am\nG C");
+        let calculator = FingeringCalculator {};
+        let sheet = build_sheet(&song, &SheetOptions::default(), |chord| {
+            calculator
+                .get_fingerings(&GUITAR_STANDARD, chord)
+                .into_iter()
+                .next()
+        });
+        let names: Vec<&str> = sheet.diagrams.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, vec!["Am", "C", "G"]);
+        assert!(sheet.diagrams.iter().all(|d| d.placements.len() == 6));
+    }
+
+    #[test]
+    fn test_chord_diagram() {
+        let diagram = ChordDiagram {
+            name: "Am".to_owned(),
+            placements: vec![None, Some(0), Some(2), Some(2), Some(1), Some(0)],
+        };
+        assert_eq!(diagram.fingering(), "X02210");
+        assert_eq!(diagram.base_fret(4), 1);
+
+        let diagram = ChordDiagram {
+            name: "D".to_owned(),
+            placements: vec![Some(10), Some(12), Some(12), Some(11), Some(10), Some(10)],
+        };
+        assert_eq!(diagram.fingering(), "10,12,12,11,10,10");
+        assert_eq!(diagram.base_fret(4), 10);
+    }
+}
@@ -0,0 +1,114 @@
+use super::sheet::{SheetLine, SongSheet};
+
+fn song(output: &mut Vec<String>, sheet: &SongSheet) {
+    output.push(sheet.title.clone());
+    if !sheet.author.is_empty() {
+        output.push(sheet.author.clone());
+    }
+    let summary = sheet.summary();
+    if !summary.is_empty() {
+        output.push(summary);
+    }
+    if !sheet.diagrams.is_empty() {
+        output.push(String::new());
+        let width = sheet
+            .diagrams
+            .iter()
+            .map(|diagram| diagram.name.chars().count())
+            .max()
+            .unwrap_or_default();
+        for diagram in &sheet.diagrams {
+            output.push(format!(
+                "{:width$}  {}",
+                diagram.name,
+                diagram.fingering(),
+                width = width
+            ));
+        }
+    }
+
+    for section in &sheet.sections {
+        output.push(String::new());
+        for line in &section.lines {
+            match line {
+                SheetLine::Lyrics { chords, lyrics } => {
+                    if !chords.is_empty() {
+                        output.push(chords.clone());
+                    }
+                    if !lyrics.is_empty() {
+                        output.push(lyrics.clone());
+                    }
+                }
+                SheetLine::Tab(staves) => output.extend(staves.iter().cloned()),
+            }
+        }
+    }
+}
+
+/// Prints the songs as plain text, with the chords aligned above the lyrics
+pub fn render_text(sheets: &[SongSheet]) -> String {
+    let mut output = vec![];
+    for (index, sheet) in sheets.iter().enumerate() {
+        if index > 0 {
+            output.push(String::new());
+            output.push(String::new());
+        }
+        song(&mut output, sheet);
+    }
+    output
+        .iter()
+        .map(|line| format!("{}\n", line.trim_end()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        export::{ChordDiagram, Section},
+        parser::SongMetadata,
+    };
+
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_render_text() {
+        let sheet = SongSheet {
+            title: "Title".to_owned(),
+            author: "Author".to_owned(),
+            metadata: SongMetadata {
+                key: Some("Am".to_owned()),
+                capo: Some(2),
+                ..Default::default()
+            },
+            sections: vec![
+                Section {
+                    lines: vec![
+                        SheetLine::Lyrics {
+                            chords: "Am    C".to_owned(),
+                            lyrics: "Hello world".to_owned(),
+                        },
+                        SheetLine::Lyrics {
+                            chords: "G".to_owned(),
+                            lyrics: String::new(),
+                        },
+                    ],
+                },
+                Section {
+                    lines: vec![SheetLine::Tab(vec![
+                        "e|--0--|".to_owned(),
+                        "B|--1--|".to_owned(),
+                    ])],
+                },
+            ],
+            diagrams: vec![ChordDiagram {
+                name: "Am".to_owned(),
+                placements: vec![None, Some(0), Some(2), Some(2), Some(1), Some(0)],
+            }],
+        };
+        assert_eq!(
+            render_text(&[sheet]),
+            "Title\nAuthor\nKey: Am   Capo: 2\n\nAm  X02210\n\nAm    C\nHello world\nG\n\ne|--0--|\nB|--1--|\n"
+        );
+    }
+}
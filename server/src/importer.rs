@@ -0,0 +1,363 @@
+use regex::Regex;
+
+use crate::{
+    chord::Chord,
+    error::{ChordDbError, ChordDbResult},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceFormat {
+    /// Chords over lyrics, as consumed by `parse_tablature`
+    Plain,
+    /// `[ch]Am[/ch]` and `[tab]...[/tab]` markup, as copied from Ultimate-Guitar and similar sites
+    UltimateGuitar,
+    /// Inline `[Am]` chords and `{title: ...}` directives
+    ChordPro,
+    /// Inline `[Am]` chords with a `Title:`/`Artist:` metadata header
+    OnSong,
+}
+
+impl SourceFormat {
+    pub fn parse(source: &str) -> Option<SourceFormat> {
+        match source.to_lowercase().as_str() {
+            "plain" | "text" => Some(SourceFormat::Plain),
+            "ultimate-guitar" | "ultimate_guitar" | "ug" => Some(SourceFormat::UltimateGuitar),
+            "chordpro" | "cho" => Some(SourceFormat::ChordPro),
+            "onsong" => Some(SourceFormat::OnSong),
+            _ => None,
+        }
+    }
+
+    pub fn text(&self) -> &'static str {
+        match self {
+            SourceFormat::Plain => "plain",
+            SourceFormat::UltimateGuitar => "ultimate-guitar",
+            SourceFormat::ChordPro => "chordpro",
+            SourceFormat::OnSong => "onsong",
+        }
+    }
+
+    pub fn detect(source: &str) -> SourceFormat {
+        if UG_TAG_REGEX.is_match(source) {
+            SourceFormat::UltimateGuitar
+        } else if !has_inline_chords(source) {
+            SourceFormat::Plain
+        } else if source.lines().any(|l| CHORDPRO_DIRECTIVE_REGEX.is_match(l)) {
+            SourceFormat::ChordPro
+        } else if source
+            .lines()
+            .take(ONSONG_HEADER_LINES)
+            .any(|l| ONSONG_METADATA_REGEX.is_match(l))
+        {
+            SourceFormat::OnSong
+        } else {
+            SourceFormat::ChordPro
+        }
+    }
+}
+
+fn has_inline_chords(source: &str) -> bool {
+    INLINE_CHORD_REGEX
+        .captures_iter(source)
+        .any(|caps| Chord::parse(&caps["chord"]).is_some())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedSong {
+    pub format: SourceFormat,
+    pub contents: String,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// Anything in the source that could not be interpreted
+    pub warnings: Vec<String>,
+}
+
+impl ImportedSong {
+    /// The source as it is, without detecting its format or converting it
+    pub fn unchanged(source: &str) -> Self {
+        ImportedSong {
+            format: SourceFormat::Plain,
+            contents: source.to_owned(),
+            title: None,
+            author: None,
+            warnings: vec![],
+        }
+    }
+}
+
+lazy_static! {
+    static ref UG_TAG_REGEX: Regex = Regex::new(r"\[/?(?:ch|tab)\]").unwrap();
+    static ref UG_CHORD_REGEX: Regex = Regex::new(r"\[ch\](?<chord>.*?)\[/ch\]").unwrap();
+    static ref INLINE_CHORD_REGEX: Regex = Regex::new(r"\[(?<chord>[^\]\s]+)\]").unwrap();
+    static ref CHORDPRO_DIRECTIVE_REGEX: Regex =
+        Regex::new(r"^\s*\{(?<name>[a-zA-Z_]+)(?:\s*[:\s]\s*(?<value>.*?))?\s*\}\s*$").unwrap();
+    static ref ONSONG_METADATA_REGEX: Regex = Regex::new(
+        r"^\s*(?<name>Title|Artist|Key|Capo|Tempo|Time|Flow|CCLI|Copyright)\s*:\s*(?<value>.*?)\s*$"
+    )
+    .unwrap();
+}
+
+const ONSONG_HEADER_LINES: usize = 10;
+const TAB_WIDTH: usize = 8;
+
+/// Replaces the typographic characters introduced by word processors with their plain
+/// text equivalents, so chords and lyrics stay aligned.
+fn clean_text(source: &str) -> String {
+    let mut cleaned = String::with_capacity(source.len());
+    for line in source.lines() {
+        let mut column = 0;
+        for char in line.chars() {
+            match char {
+                '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{2032}' => cleaned.push('\''),
+                '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{2033}' => cleaned.push('"'),
+                '\u{2013}' | '\u{2014}' => cleaned.push('-'),
+                '\u{00A0}' | '\u{2007}' | '\u{202F}' => cleaned.push(' '),
+                '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{FEFF}' => continue,
+                '\u{2026}' => {
+                    cleaned.push_str("...");
+                    column += 2;
+                }
+                '\t' => {
+                    let spaces = TAB_WIDTH - column % TAB_WIDTH;
+                    cleaned.push_str(&" ".repeat(spaces));
+                    column += spaces - 1;
+                }
+                _ => cleaned.push(char),
+            }
+            column += 1;
+        }
+        cleaned.push('\n');
+    }
+    cleaned
+}
+
+fn import_ultimate_guitar(source: &str, imported: &mut ImportedSong) {
+    let lines = source
+        .lines()
+        .enumerate()
+        .map(|(index, line)| {
+            let line = UG_CHORD_REGEX.replace_all(line, |caps: &regex::Captures| {
+                let chord = &caps["chord"];
+                if Chord::parse(chord).is_none() {
+                    imported.warnings.push(format!(
+                        "Line {}: could not interpret chord '{}'",
+                        index + 1,
+                        chord
+                    ));
+                }
+                chord.to_owned()
+            });
+            line.replace("[tab]", "").replace("[/tab]", "")
+        })
+        .collect::<Vec<_>>();
+    imported.contents = lines.join("\n");
+}
+
+/// Converts a line with inline chords (`[Am]Hello [G]world`) into a chords line and a lyrics
+/// line, keeping each chord above the character it was attached to.
+fn split_inline_chords(line: &str, line_number: usize, warnings: &mut Vec<String>) -> String {
+    let mut chords = String::new();
+    let mut lyrics = String::new();
+    let mut last_end = 0;
+    for caps in INLINE_CHORD_REGEX.captures_iter(line) {
+        let whole = caps.get(0).unwrap();
+        let chord = &caps["chord"];
+        lyrics.push_str(&line[last_end..whole.start()]);
+        last_end = whole.end();
+        if Chord::parse(chord).is_none() {
+            warnings.push(format!(
+                "Line {}: could not interpret chord '{}'",
+                line_number, chord
+            ));
+        }
+
+        let lyrics_width = lyrics.chars().count();
+        let chords_width = chords.chars().count();
+        let min_column = if chords.is_empty() {
+            0
+        } else {
+            chords_width + 1
+        };
+        if lyrics_width < min_column {
+            let padding = if lyrics.trim().is_empty() { " " } else { "-" };
+            lyrics.push_str(&padding.repeat(min_column - lyrics_width));
+        }
+        let column = lyrics.chars().count();
+        chords.push_str(&" ".repeat(column - chords_width));
+        chords.push_str(chord);
+    }
+    lyrics.push_str(&line[last_end..]);
+
+    if chords.is_empty() {
+        lyrics
+    } else if lyrics.trim().is_empty() {
+        chords
+    } else {
+        format!("{}\n{}", chords, lyrics.trim_end())
+    }
+}
+
+fn import_chordpro(source: &str, imported: &mut ImportedSong) {
+    let mut lines = vec![];
+    for (index, line) in source.lines().enumerate() {
+        let Some(caps) = CHORDPRO_DIRECTIVE_REGEX.captures(line) else {
+            lines.push(split_inline_chords(line, index + 1, &mut imported.warnings));
+            continue;
+        };
+        let value = caps.name("value").map(|v| v.as_str().to_owned());
+        match caps["name"].to_lowercase().as_str() {
+            "title" | "t" => imported.title = value,
+            "artist" | "subtitle" | "st" | "composer" => {
+                if imported.author.is_none() {
+                    imported.author = value
+                }
+            }
+            "comment" | "c" | "comment_italic" | "ci" | "comment_box" | "cb" => {
+                lines.push(value.unwrap_or_default())
+            }
+            "start_of_chorus" | "soc" => lines.push("Chorus:".to_owned()),
+            "start_of_verse" | "sov" => lines.push("Verse:".to_owned()),
+            "start_of_bridge" | "sob" => lines.push("Bridge:".to_owned()),
+            "end_of_chorus" | "eoc" | "end_of_verse" | "eov" | "end_of_bridge" | "eob"
+            | "start_of_tab" | "sot" | "end_of_tab" | "eot" => {}
+            // Kept verbatim, they are read as song metadata
+            "key" | "capo" | "tempo" | "time" | "tuning" | "instrument" => {
+                lines.push(line.trim().to_owned())
+            }
+            name => {
+                imported
+                    .warnings
+                    .push(format!("Line {}: unknown directive '{}'", index + 1, name));
+                lines.push(line.to_owned());
+            }
+        }
+    }
+    imported.contents = lines.join("\n");
+}
+
+fn import_onsong(source: &str, imported: &mut ImportedSong) {
+    let mut lines = vec![];
+    for (index, line) in source.lines().enumerate() {
+        if index < ONSONG_HEADER_LINES {
+            if let Some(caps) = ONSONG_METADATA_REGEX.captures(line) {
+                let value = caps["value"].to_owned();
+                match &caps["name"] {
+                    "Title" => imported.title = Some(value),
+                    "Artist" => imported.author = Some(value),
+                    _ => lines.push(line.trim().to_owned()),
+                }
+                continue;
+            }
+        }
+        lines.push(split_inline_chords(line, index + 1, &mut imported.warnings));
+    }
+    imported.contents = lines.join("\n");
+}
+
+/// Normalizes a song copied from another site or app into the plain chords-over-lyrics text
+/// consumed by `parse_tablature`. If `format` is `None` it is detected from the source.
+pub fn import_song<S: AsRef<str>>(source: S, format: Option<SourceFormat>) -> ImportedSong {
+    let cleaned = clean_text(source.as_ref());
+    let format = format.unwrap_or_else(|| SourceFormat::detect(&cleaned));
+    let mut imported = ImportedSong {
+        format,
+        contents: String::new(),
+        title: None,
+        author: None,
+        warnings: vec![],
+    };
+    match format {
+        SourceFormat::Plain => imported.contents = cleaned.trim_end().to_owned(),
+        SourceFormat::UltimateGuitar => import_ultimate_guitar(&cleaned, &mut imported),
+        SourceFormat::ChordPro => import_chordpro(&cleaned, &mut imported),
+        SourceFormat::OnSong => import_onsong(&cleaned, &mut imported),
+    }
+    imported
+}
+
+pub fn parse_format(format: Option<&str>) -> ChordDbResult<Option<SourceFormat>> {
+    match format {
+        None | Some("auto") => Ok(None),
+        Some(format) => SourceFormat::parse(format)
+            .map(Some)
+            .ok_or_else(|| ChordDbError::BadRequest(format!("Unknown format '{}'", format))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(SourceFormat::detect("Am\nHello"), SourceFormat::Plain);
+        assert_eq!(
+            SourceFormat::detect("[Chorus]\nAm\nHello"),
+            SourceFormat::Plain
+        );
+        assert_eq!(
+            SourceFormat::detect("[tab][ch]Am[/ch]\nHello[/tab]"),
+            SourceFormat::UltimateGuitar
+        );
+        assert_eq!(
+            SourceFormat::detect("{title: Song}\n[Am]Hello"),
+            SourceFormat::ChordPro
+        );
+        assert_eq!(
+            SourceFormat::detect("Title: Song\nArtist: Someone\n\n[Am]Hello"),
+            SourceFormat::OnSong
+        );
+    }
+
+    #[test]
+    fn test_clean_text() {
+        assert_eq!(
+            clean_text("\u{201C}Don\u{2019}t\u{201D}\u{00A0}stop\u{2026}"),
+            "\"Don't\" stop...\n"
+        );
+        assert_eq!(clean_text("Am\tG\r\n"), "Am      G\n");
+    }
+
+    #[test]
+    fn test_import_ultimate_guitar() {
+        let imported = import_song(
+            "[Verse]\n[tab][ch]Am[/ch]     [ch]G[/ch]\nHello world[/tab]\n[ch]Xyz[/ch]",
+            None,
+        );
+        assert_eq!(imported.format, SourceFormat::UltimateGuitar);
+        assert_eq!(imported.contents, "[Verse]\nAm     G\nHello world\nXyz");
+        assert_eq!(
+            imported.warnings,
+            vec!["Line 4: could not interpret chord 'Xyz'".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_import_chordpro() {
+        let imported = import_song(
+            "{title: My Song}\n{artist: Someone}\n{soc}\n[Am]Hello [G]world\n[C][D]Hi\n{eoc}\n{foo}",
+            None,
+        );
+        assert_eq!(imported.format, SourceFormat::ChordPro);
+        assert_eq!(imported.title, Some("My Song".to_string()));
+        assert_eq!(imported.author, Some("Someone".to_string()));
+        assert_eq!(
+            imported.contents,
+            "Chorus:\nAm    G\nHello world\nC D\n  Hi\n{foo}"
+        );
+        assert_eq!(
+            imported.warnings,
+            vec!["Line 7: unknown directive 'foo'".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_import_onsong() {
+        let imported = import_song("Title: My Song\nArtist: Someone\nKey: G\n\n[G]Hi", None);
+        assert_eq!(imported.format, SourceFormat::OnSong);
+        assert_eq!(imported.title, Some("My Song".to_string()));
+        assert_eq!(imported.author, Some("Someone".to_string()));
+        assert_eq!(imported.contents, "Key: G\n\nG\nHi");
+    }
+}
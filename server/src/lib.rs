@@ -4,13 +4,25 @@ extern crate serde;
 
 use clap::Parser;
 
+pub mod account;
+pub mod api_token;
+pub mod archive;
+pub mod band;
 pub mod chord;
+pub mod db;
 pub mod entities;
 pub mod error;
+pub mod export;
+pub mod importer;
 pub mod instrument;
+pub mod mail;
+pub mod oidc;
 pub mod parser;
 pub mod session;
+pub mod setlist;
 pub mod song;
+#[cfg(test)]
+mod testing;
 pub mod user;
 pub mod web;
 
@@ -33,10 +45,105 @@ pub struct Opt {
     /// set the directory where static files are to be found
     #[clap(long = "static-dir", default_value = "./dist")]
     static_dir: String,
+
+    /// where to keep the data. `memory` and `file` run without a database, keeping everything
+    /// but the songs in memory
+    #[clap(long = "storage", value_enum, default_value = "database")]
+    storage: Storage,
+
+    /// set the JSON file with the songs of the `file` storage
+    #[clap(long = "songs-file", default_value = "songs.json")]
+    songs_file: String,
+
+    /// don't apply pending database migrations on startup
+    #[clap(long = "no-migrate")]
+    no_migrate: bool,
+
+    /// who can create an account through the API
+    #[clap(long = "registration", value_enum, default_value = "disabled")]
+    registration: account::Registration,
+
+    /// set the address of the web app, for the links sent by email
+    #[clap(long = "public-url", default_value = "http://localhost:8080")]
+    public_url: String,
+
+    /// how to deliver emails. `smtp` sends them through the server at SMTP_URL
+    #[clap(long = "mailer", value_enum, default_value = "log")]
+    mailer: mail::MailDelivery,
+
+    /// set the file the emails are appended to with the `file` mailer
+    #[clap(long = "mail-file", default_value = "mail.txt")]
+    mail_file: String,
+
+    /// set the sender of the emails
+    #[clap(long = "mail-from", default_value = "ChordDB <chorddb@localhost>")]
+    mail_from: String,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum Command {
+    /// Manage the database schema
+    Db {
+        #[clap(subcommand)]
+        command: db::DbCommand,
+    },
+    /// Manage the users
+    User {
+        #[clap(subcommand)]
+        command: user::UserCommand,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Storage {
+    /// The database at DATABASE_URL
+    Database,
+    /// Nothing is saved, songs are lost when the server stops
+    Memory,
+    /// Songs are saved to a JSON file
+    File,
 }
 
 impl Opt {
     pub fn log_level(&self) -> &str {
         &self.log_level
     }
+
+    pub fn storage(&self) -> Storage {
+        self.storage
+    }
+
+    pub fn songs_file(&self) -> &str {
+        &self.songs_file
+    }
+
+    pub fn migrate(&self) -> bool {
+        !self.no_migrate
+    }
+
+    pub fn account_settings(&self) -> account::AccountSettings {
+        account::AccountSettings {
+            registration: self.registration,
+            public_url: self.public_url.clone(),
+        }
+    }
+
+    pub fn mailer(&self) -> mail::MailDelivery {
+        self.mailer
+    }
+
+    pub fn mail_file(&self) -> &str {
+        &self.mail_file
+    }
+
+    pub fn mail_from(&self) -> &str {
+        &self.mail_from
+    }
+
+    pub fn command(&self) -> Option<&Command> {
+        self.command.as_ref()
+    }
 }
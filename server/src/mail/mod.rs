@@ -0,0 +1,127 @@
+//! Delivery of the emails sent to users, like address verifications and password resets
+
+use std::path::PathBuf;
+
+use axum::async_trait;
+use tokio::io::AsyncWriteExt;
+
+use crate::error::{ChordDbError, ChordDbResult};
+
+mod smtp;
+
+pub use smtp::SmtpMailer;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Email {
+    /// The email as text, with its headers
+    fn to_text(&self, from: &str) -> String {
+        format!(
+            "From: {}\nTo: {}\nSubject: {}\n\n{}\n",
+            from, self.to, self.subject, self.body
+        )
+    }
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> ChordDbResult<()>;
+}
+
+/// How emails are delivered
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MailDelivery {
+    /// Written to the log, for local testing
+    Log,
+    /// Appended to a file, for local testing
+    File,
+    /// Sent through the SMTP server at SMTP_URL
+    Smtp,
+}
+
+/// Writes emails to the log instead of sending them
+pub struct LogMailer {
+    from: String,
+}
+
+impl LogMailer {
+    pub fn new(from: &str) -> Self {
+        Self {
+            from: from.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: &Email) -> ChordDbResult<()> {
+        log::info!("Email not sent:\n{}", email.to_text(&self.from));
+        Ok(())
+    }
+}
+
+/// Appends emails to a file instead of sending them
+pub struct FileMailer {
+    path: PathBuf,
+    from: String,
+}
+
+impl FileMailer {
+    pub fn new(path: impl Into<PathBuf>, from: &str) -> Self {
+        Self {
+            path: path.into(),
+            from: from.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: &Email) -> ChordDbResult<()> {
+        let text = format!("{}\n", email.to_text(&self.from));
+        let result = async {
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .await?;
+            file.write_all(text.as_bytes()).await?;
+            file.flush().await
+        }
+        .await;
+        result.map_err(|err| ChordDbError::Generic(Box::new(err)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test(tokio::test)]
+    async fn test_file_mailer() {
+        let path = std::env::temp_dir().join(format!("chorddb-{}.txt", uuid::Uuid::new_v4()));
+        let mailer = FileMailer::new(&path, "ChordDB <chorddb@localhost>");
+        for subject in ["First", "Second"] {
+            mailer
+                .send(&Email {
+                    to: "user@example.com".to_string(),
+                    subject: subject.to_string(),
+                    body: "Hello".to_string(),
+                })
+                .await
+                .unwrap();
+        }
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(contents.starts_with(
+            "From: ChordDB <chorddb@localhost>\nTo: user@example.com\nSubject: First\n\nHello\n"
+        ));
+        assert!(contents.contains("Subject: Second"));
+    }
+}
@@ -1,15 +1,23 @@
-use std::fs::File;
-use std::path::Path;
 use std::sync::Arc;
 
+use chorddb::account::{MemoryAccounts, SeaOrmAccounts};
+use chorddb::api_token::{MemoryApiTokens, SeaOrmApiTokens};
+use chorddb::band::{Bands, MemoryBands, SeaOrmBands};
+use chorddb::error::ChordDbResult;
 use chorddb::instrument::MemoryInstruments;
-use chorddb::session::SeaOrmSessions;
-use chorddb::song::{CachedChords, FingeringCalculator, SeaOrmSongs};
-use chorddb::user::SeaOrmUsers;
+use chorddb::mail::{FileMailer, LogMailer, MailDelivery, Mailer, SmtpMailer};
+use chorddb::oidc::{OidcClient, OidcConfig};
+use chorddb::session::{MemorySessions, SeaOrmSessions};
+use chorddb::setlist::{MemorySetlists, SeaOrmSetlists};
+use chorddb::song::{
+    CachedChords, FileSongs, FingeringCalculator, MemorySongs, SeaOrmSongs, Songs,
+};
+use chorddb::user::{self, MemoryUsers, SeaOrmUsers, User};
 use chorddb::web::{run_server, AppState};
-use chorddb::Opt;
+use chorddb::{db, Command, Opt, Storage};
 use clap::Parser;
-use sea_orm::Database;
+use sea_orm::DatabaseConnection;
+use uuid::Uuid;
 
 #[tokio::main]
 async fn main() {
@@ -25,34 +33,147 @@ async fn main() {
     // Enable console logging
     tracing_subscriber::fmt::init();
 
+    match opt.command() {
+        Some(Command::Db { command }) => {
+            let result = db::run_command(&connect().await, command).await;
+            exit_on_error(result);
+            return;
+        }
+        Some(Command::User { command }) => {
+            let db = connect().await;
+            db::prepare_schema(&db, opt.migrate())
+                .await
+                .unwrap_or_else(|err| panic!("Could not prepare the database schema: {}", err));
+            let users = SeaOrmUsers::new(db.clone());
+            let sessions = SeaOrmSessions::new(db.clone());
+            let api_tokens = SeaOrmApiTokens::new(db);
+            let result = user::run_command(&users, &sessions, &api_tokens, command).await;
+            exit_on_error(result);
+            return;
+        }
+        None => {}
+    }
+
+    let state = match opt.storage() {
+        Storage::Database => database_state(&opt).await,
+        _ => memory_state(&opt),
+    };
+
+    run_server(opt, state).await;
+}
+
+/// Ends a command line command with an error message instead of a panic
+fn exit_on_error(result: ChordDbResult<()>) {
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+async fn connect() -> DatabaseConnection {
     let url = std::env::var("DATABASE_URL").expect("Must set DATABASE_URL");
+    db::connect(&url)
+        .await
+        .unwrap_or_else(|err| panic!("Could not connect to the database @{}: {}", url, err))
+}
+
+fn mailer(opt: &Opt) -> Arc<dyn Mailer> {
+    match opt.mailer() {
+        MailDelivery::Log => Arc::new(LogMailer::new(opt.mail_from())),
+        MailDelivery::File => {
+            log::info!("Writing emails to {}", opt.mail_file());
+            Arc::new(FileMailer::new(opt.mail_file(), opt.mail_from()))
+        }
+        MailDelivery::Smtp => {
+            let url = std::env::var("SMTP_URL").expect("Must set SMTP_URL to send emails");
+            let mailer = SmtpMailer::new(&url, opt.mail_from())
+                .unwrap_or_else(|err| panic!("Could not set up the SMTP mailer: {}", err));
+            Arc::new(mailer)
+        }
+    }
+}
 
-    create_sqlite_db_if_missing(&url);
+/// Login with the OpenID Connect provider set in the `OIDC_*` variables, if any
+fn oidc(opt: &Opt) -> Option<Arc<OidcClient>> {
+    let config = OidcConfig::from_env(&opt.account_settings())
+        .unwrap_or_else(|err| panic!("Invalid OpenID Connect configuration: {}", err))?;
+    log::info!("Login with the OpenID Connect provider {}", config.issuer);
+    let client = OidcClient::new(config)
+        .unwrap_or_else(|err| panic!("Could not set up the OpenID Connect client: {}", err));
+    Some(Arc::new(client))
+}
 
-    let db = Database::connect(&url)
+async fn database_state(opt: &Opt) -> AppState {
+    let db = connect().await;
+    db::prepare_schema(&db, opt.migrate())
+        .await
+        .unwrap_or_else(|err| panic!("Could not prepare the database schema: {}", err));
+    let songs = SeaOrmSongs::new(db.clone());
+    songs
+        .index_missing_chords()
         .await
-        .unwrap_or_else(|err| panic!("Could not connect to the database @{}: {}", url, err));
-    let state = AppState {
-        songs: Arc::new(SeaOrmSongs::new(db.clone())),
+        .unwrap_or_else(|err| panic!("Could not index song chords: {}", err));
+    AppState {
+        songs: Arc::new(songs),
         users: Arc::new(SeaOrmUsers::new(db.clone())),
+        bands: Arc::new(SeaOrmBands::new(db.clone())),
         sessions: Arc::new(SeaOrmSessions::new(db.clone())),
+        setlists: Arc::new(SeaOrmSetlists::new(db.clone())),
         chords: Arc::new(CachedChords::new(FingeringCalculator {})),
         instruments: Arc::new(MemoryInstruments::new()),
-    };
-
-    run_server(opt, state).await;
+        accounts: Arc::new(SeaOrmAccounts::new(db.clone())),
+        mailer: mailer(opt),
+        account_settings: Arc::new(opt.account_settings()),
+        api_tokens: Arc::new(SeaOrmApiTokens::new(db.clone())),
+        oidc: oidc(opt),
+    }
 }
 
-const SQLITE_PREFIX: &str = "sqlite://";
+/// State for running without a database, with a single admin user to log in with
+fn memory_state(opt: &Opt) -> AppState {
+    let password = std::env::var("DEMO_PASSWORD").unwrap_or_else(|_| "demo".to_string());
+    let demo = User {
+        id: Uuid::from_u128(1),
+        name: "demo".to_string(),
+        email: "demo@localhost".to_string(),
+        password: user::hash_password(&password)
+            .unwrap_or_else(|err| panic!("Could not hash the demo password: {}", err)),
+        is_admin: true,
+        email_verified: true,
+        note_locale: None,
+    };
+    log::info!(
+        "Running without a database ({:?}). Log in as '{}'",
+        opt.storage(),
+        demo.name
+    );
 
-fn create_sqlite_db_if_missing(url: &str) {
-    if !url.starts_with(SQLITE_PREFIX) {
-        return;
-    }
-    let path = Path::new(&url[SQLITE_PREFIX.len()..]);
-    if !path.exists() {
-        log::info!("Creating empty database @{}", url);
-        File::create(path)
-            .unwrap_or_else(|err| panic!("Could not create database file @ {}: {}", url, err));
+    let bands: Arc<dyn Bands> = Arc::new(MemoryBands::new());
+    let songs: Arc<dyn Songs> = if opt.storage() == Storage::File {
+        log::info!("Saving songs to {}", opt.songs_file());
+        let songs = FileSongs::new(opt.songs_file()).unwrap_or_else(|err| {
+            panic!(
+                "Could not load the songs from {}: {}",
+                opt.songs_file(),
+                err
+            )
+        });
+        Arc::new(songs.with_bands(bands.clone()))
+    } else {
+        Arc::new(MemorySongs::new().with_bands(bands.clone()))
+    };
+    AppState {
+        songs,
+        users: Arc::new(MemoryUsers::new([demo])),
+        bands,
+        sessions: Arc::new(MemorySessions::new()),
+        setlists: Arc::new(MemorySetlists::new()),
+        chords: Arc::new(CachedChords::new(FingeringCalculator {})),
+        instruments: Arc::new(MemoryInstruments::new()),
+        accounts: Arc::new(MemoryAccounts::new()),
+        mailer: mailer(opt),
+        account_settings: Arc::new(opt.account_settings()),
+        api_tokens: Arc::new(MemoryApiTokens::new()),
+        oidc: oidc(opt),
     }
 }
@@ -0,0 +1,220 @@
+//! An OpenID Connect provider for the tests. It signs its ID tokens with a test RSA key.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, post},
+    Form, Json, Router,
+};
+use chrono::Utc;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use reqwest::Url;
+use serde_json::{json, Map, Value};
+use tokio::{net::TcpListener, task::JoinHandle};
+
+use super::{pkce_challenge, OidcConfig};
+
+pub const TEST_CLIENT_ID: &str = "chorddb";
+pub const TEST_KEY_ID: &str = "test-key";
+const TEST_REDIRECT_URL: &str = "http://localhost:8080/api/auth/oidc/callback";
+const TEST_KEY: &str = include_str!("test_key.pem");
+/// Modulus of the test key, base64url encoded
+const TEST_KEY_MODULUS: &str = "uyITvB5jMr08JlbxApGxKkFLTrYgU3xUJ973Qu-H18WFNYh1C3M6mIY349cf_F_6IG5sJkXc7YYGgbm4MhHd0IdYMe_VKXxJ6-8JXTTxDxbRS0fOW9yRT770qyFXbT5pdwR6nR8DWstn5Z1ZcuP3x_AH2WeEVemNjf73M7gdn3LSuMBZ17HcM_xNqf3caCouIqamiu_vcUfEREERcqt9FF_ESkqrFZht172qcJ0ehr0OvaA5sLCfeTatOWo_Y9EcYffpuko7So0ykY7jQug0ZM11Vdp0TSPb5bJF2dNs0dBgkH4yxr-KCU6qOKgAc0X1mcgzWV39gjDMvSIwajA4DQ";
+
+/// A code given to a user who logged in, waiting to be exchanged for an ID token
+struct PendingCode {
+    challenge: String,
+    claims: Map<String, Value>,
+}
+
+struct MockState {
+    issuer: String,
+    codes: Mutex<HashMap<String, PendingCode>>,
+    key_fetches: AtomicUsize,
+}
+
+pub struct MockIssuer {
+    pub issuer: String,
+    state: Arc<MockState>,
+    server: JoinHandle<()>,
+}
+
+fn sign(header: &Header, claims: &Map<String, Value>) -> String {
+    let key = EncodingKey::from_rsa_pem(TEST_KEY.as_bytes()).unwrap();
+    jsonwebtoken::encode(header, claims, &key).unwrap()
+}
+
+fn signing_header() -> Header {
+    Header {
+        kid: Some(TEST_KEY_ID.to_string()),
+        ..Header::new(Algorithm::RS256)
+    }
+}
+
+/// Default claims of the ID tokens, replaced by the given ones
+fn claims(issuer: &str, overrides: Value) -> Map<String, Value> {
+    let now = Utc::now().timestamp();
+    let mut claims = json!({
+        "iss": issuer,
+        "aud": TEST_CLIENT_ID,
+        "sub": "user-1",
+        "iat": now,
+        "exp": now + 300,
+        "nonce": "nonce",
+    })
+    .as_object()
+    .unwrap()
+    .clone();
+    claims.extend(overrides.as_object().unwrap().clone());
+    claims
+}
+
+async fn discovery(State(state): State<Arc<MockState>>) -> Json<Value> {
+    Json(json!({
+        "issuer": state.issuer,
+        "authorization_endpoint": format!("{}/auth", state.issuer),
+        "token_endpoint": format!("{}/token", state.issuer),
+        "jwks_uri": format!("{}/jwks", state.issuer),
+    }))
+}
+
+async fn keys(State(state): State<Arc<MockState>>) -> Json<Value> {
+    state.key_fetches.fetch_add(1, Ordering::SeqCst);
+    Json(json!({
+        "keys": [
+            {
+                "kty": "RSA",
+                "use": "sig",
+                "alg": "RS256",
+                "kid": TEST_KEY_ID,
+                "n": TEST_KEY_MODULUS,
+                "e": "AQAB",
+            },
+            // Keys of unknown types are ignored
+            {"kty": "unknown", "kid": "other"},
+        ]
+    }))
+}
+
+async fn token(
+    State(state): State<Arc<MockState>>,
+    Form(form): Form<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let field = |name: &str| form.get(name).map(String::as_str).unwrap_or_default();
+    let pending = state.codes.lock().unwrap().remove(field("code"));
+    match pending {
+        Some(pending)
+            if field("grant_type") == "authorization_code"
+                && field("client_id") == TEST_CLIENT_ID
+                && field("redirect_uri") == TEST_REDIRECT_URL
+                && pkce_challenge(field("code_verifier")) == pending.challenge =>
+        {
+            let id_token = sign(&signing_header(), &pending.claims);
+            (
+                StatusCode::OK,
+                Json(
+                    json!({"access_token": "access", "token_type": "Bearer", "id_token": id_token}),
+                ),
+            )
+        }
+        _ => (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "invalid_grant"})),
+        ),
+    }
+}
+
+impl MockIssuer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        let state = Arc::new(MockState {
+            issuer: issuer.clone(),
+            codes: Mutex::new(HashMap::new()),
+            key_fetches: AtomicUsize::new(0),
+        });
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/jwks", get(keys))
+            .route("/token", post(token))
+            .with_state(state.clone());
+        let server = tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        Self {
+            issuer,
+            state,
+            server,
+        }
+    }
+
+    pub fn config(&self) -> OidcConfig {
+        OidcConfig {
+            issuer: self.issuer.clone(),
+            client_id: TEST_CLIENT_ID.to_string(),
+            client_secret: Some("secret".to_string()),
+            scopes: "openid email".to_string(),
+            user_claim: "email".to_string(),
+            redirect_url: TEST_REDIRECT_URL.to_string(),
+        }
+    }
+
+    /// What the provider does when a user logs in: checks the authorization request and
+    /// returns the code and state sent back, for an ID token with the claims
+    pub fn authorize(&self, url: &str, overrides: Value) -> (String, String) {
+        let url = Url::parse(url).unwrap();
+        assert!(url.as_str().starts_with(&format!("{}/auth?", self.issuer)));
+        let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        assert_eq!(query["response_type"], "code");
+        assert_eq!(query["client_id"], TEST_CLIENT_ID);
+        assert_eq!(query["redirect_uri"], TEST_REDIRECT_URL);
+        assert_eq!(query["code_challenge_method"], "S256");
+        assert!(query["scope"].split(' ').any(|scope| scope == "openid"));
+
+        let mut claims = claims(&self.issuer, json!({"nonce": query["nonce"]}));
+        claims.extend(overrides.as_object().unwrap().clone());
+        let code = uuid::Uuid::new_v4().to_string();
+        self.state.codes.lock().unwrap().insert(
+            code.clone(),
+            PendingCode {
+                challenge: query["code_challenge"].clone(),
+                claims,
+            },
+        );
+        (code, query["state"].clone())
+    }
+
+    /// Claims of an ID token for the "nonce" nonce, replaced by the given ones
+    pub fn claims(&self, overrides: Value) -> Map<String, Value> {
+        claims(&self.issuer, overrides)
+    }
+
+    /// An ID token for the "nonce" nonce, signed with the test key
+    pub fn id_token(&self, overrides: Value) -> String {
+        sign(&signing_header(), &self.claims(overrides))
+    }
+
+    pub fn sign(&self, header: &Header, claims: &Map<String, Value>) -> String {
+        sign(header, claims)
+    }
+
+    /// How many times the keys were fetched
+    pub fn key_fetches(&self) -> usize {
+        self.state.key_fetches.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for MockIssuer {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
@@ -0,0 +1,550 @@
+//! Login with any OpenID Connect provider, like Keycloak: discovery of the provider, the
+//! authorization code flow with PKCE and the verification of the ID tokens it issues
+
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    jwk::{Jwk, JwkSet, PublicKeyUse},
+    Algorithm, DecodingKey, Validation,
+};
+use reqwest::Url;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use tokio::sync::{Mutex, OnceCell};
+
+use crate::{
+    account::AccountSettings,
+    error::{ChordDbError, ChordDbResult},
+    song::new_share_token,
+    user::{User, Users},
+};
+
+#[cfg(test)]
+pub(crate) mod mock;
+
+/// How long the keys of the provider are used before fetching them again
+const JWKS_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// Tokens signed with an unknown key make us fetch the keys again, but at most this often
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(60);
+
+/// The signature algorithms accepted for ID tokens. Symmetric ones would need the client secret
+const ALGORITHMS: [Algorithm; 8] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+];
+
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    /// Identifier of the provider, like `https://keycloak.example.com/realms/chorddb`
+    pub issuer: String,
+    pub client_id: String,
+    /// Confidential clients authenticate with a secret, public ones only use PKCE
+    pub client_secret: Option<String>,
+    /// Scopes requested, separated by spaces
+    pub scopes: String,
+    /// Claim of the ID token with the name or email of the user
+    pub user_claim: String,
+    /// Where the provider sends the users back to
+    pub redirect_url: String,
+}
+
+impl OidcConfig {
+    /// Reads the configuration from the `OIDC_*` environment variables. None if OIDC_ISSUER is
+    /// not set
+    pub fn from_env(settings: &AccountSettings) -> ChordDbResult<Option<Self>> {
+        let Ok(issuer) = dotenv::var("OIDC_ISSUER") else {
+            return Ok(None);
+        };
+        let client_id = dotenv::var("OIDC_CLIENT_ID").map_err(|_| {
+            ChordDbError::InvalidData("OIDC_CLIENT_ID must be set with OIDC_ISSUER".to_string())
+        })?;
+        Ok(Some(OidcConfig {
+            issuer,
+            client_id,
+            client_secret: dotenv::var("OIDC_CLIENT_SECRET").ok(),
+            scopes: dotenv::var("OIDC_SCOPES")
+                .unwrap_or_else(|_| "openid email profile".to_string()),
+            user_claim: dotenv::var("OIDC_USER_CLAIM").unwrap_or_else(|_| "email".to_string()),
+            redirect_url: settings.link("/api/auth/oidc/callback"),
+        }))
+    }
+}
+
+/// The parts of the discovery document of the provider that we use
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+/// A login sent to the provider. Everything but the url is kept until the user comes back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthorizationRequest {
+    pub url: String,
+    pub state: String,
+    pub nonce: String,
+    /// PKCE code verifier, its hash is sent with the request
+    pub verifier: String,
+}
+
+struct CachedKeys {
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+pub struct OidcClient {
+    config: OidcConfig,
+    http: reqwest::Client,
+    metadata: OnceCell<ProviderMetadata>,
+    keys: Mutex<Option<CachedKeys>>,
+    keys_min_refresh: Duration,
+}
+
+fn provider_error(err: impl Display) -> ChordDbError {
+    ChordDbError::InvalidData(format!("OpenID Connect provider: {}", err))
+}
+
+fn invalid_token(err: impl Display) -> ChordDbError {
+    ChordDbError::BadRequest(format!("Invalid ID token: {}", err))
+}
+
+/// PKCE challenge of the verifier, with the S256 method
+pub fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+/// The key with the id, or the only signing key when the token names none
+fn find_key<'a>(keys: &'a JwkSet, kid: Option<&str>) -> Option<&'a Jwk> {
+    match kid {
+        Some(kid) => keys.find(kid),
+        None if keys.keys.len() == 1 => keys.keys.first(),
+        None => None,
+    }
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig) -> ChordDbResult<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(provider_error)?;
+        Ok(Self {
+            config,
+            http,
+            metadata: OnceCell::new(),
+            keys: Mutex::new(None),
+            keys_min_refresh: JWKS_MIN_REFRESH,
+        })
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> ChordDbResult<T> {
+        let response = self.http.get(url).send().await.map_err(provider_error)?;
+        let response = response.error_for_status().map_err(provider_error)?;
+        response.json().await.map_err(provider_error)
+    }
+
+    /// The discovery document, fetched once
+    async fn metadata(&self) -> ChordDbResult<&ProviderMetadata> {
+        self.metadata
+            .get_or_try_init(|| async {
+                let issuer = self.config.issuer.trim_end_matches('/');
+                let url = format!("{}/.well-known/openid-configuration", issuer);
+                let metadata: ProviderMetadata = self.get_json(&url).await?;
+                if metadata.issuer.trim_end_matches('/') != issuer {
+                    return Err(provider_error(format!(
+                        "the discovery document is for '{}', not '{}'",
+                        metadata.issuer, issuer
+                    )));
+                }
+                Ok(metadata)
+            })
+            .await
+    }
+
+    /// The signing keys of the provider, skipping the ones we don't understand
+    async fn fetch_keys(&self) -> ChordDbResult<JwkSet> {
+        #[derive(Deserialize)]
+        struct RawKeys {
+            keys: Vec<Value>,
+        }
+        let metadata = self.metadata().await?;
+        let raw: RawKeys = self.get_json(&metadata.jwks_uri).await?;
+        let keys = raw
+            .keys
+            .into_iter()
+            .filter_map(|key| serde_json::from_value::<Jwk>(key).ok())
+            .filter(|key| key.common.public_key_use != Some(PublicKeyUse::Encryption))
+            .collect();
+        Ok(JwkSet { keys })
+    }
+
+    /// The key that signed a token. The keys are cached, and fetched again when they are old or
+    /// a token is signed with a key we don't know, like after the provider rotated them
+    async fn decoding_key(&self, kid: Option<&str>) -> ChordDbResult<DecodingKey> {
+        let mut cache = self.keys.lock().await;
+        let refresh = match cache.as_ref() {
+            None => true,
+            Some(cached) => {
+                let age = cached.fetched_at.elapsed();
+                age > JWKS_LIFETIME
+                    || (find_key(&cached.keys, kid).is_none() && age >= self.keys_min_refresh)
+            }
+        };
+        if refresh {
+            let keys = self.fetch_keys().await?;
+            *cache = Some(CachedKeys {
+                keys,
+                fetched_at: Instant::now(),
+            });
+        }
+        let key = cache
+            .as_ref()
+            .and_then(|cached| find_key(&cached.keys, kid))
+            .ok_or_else(|| invalid_token(format!("unknown key {:?}", kid)))?;
+        DecodingKey::from_jwk(key).map_err(invalid_token)
+    }
+
+    /// Starts a login, returning where to send the user
+    pub async fn authorization_request(&self) -> ChordDbResult<AuthorizationRequest> {
+        let metadata = self.metadata().await?;
+        let state = new_share_token();
+        let nonce = new_share_token();
+        let verifier = new_share_token();
+        let url = Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            [
+                ("response_type", "code"),
+                ("client_id", &self.config.client_id),
+                ("redirect_uri", &self.config.redirect_url),
+                ("scope", &self.config.scopes),
+                ("state", &state),
+                ("nonce", &nonce),
+                ("code_challenge", &pkce_challenge(&verifier)),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(provider_error)?;
+        Ok(AuthorizationRequest {
+            url: url.to_string(),
+            state,
+            nonce,
+            verifier,
+        })
+    }
+
+    /// Exchanges the code the user came back with for an ID token
+    pub async fn exchange_code(&self, code: &str, verifier: &str) -> ChordDbResult<String> {
+        let metadata = self.metadata().await?;
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.config.redirect_url),
+            ("client_id", &self.config.client_id),
+            ("code_verifier", verifier),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret));
+        }
+        let response = self
+            .http
+            .post(&metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .map_err(provider_error)?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(provider_error(format!(
+                "the code was refused ({}): {}",
+                status, body
+            )));
+        }
+        let tokens: TokenResponse = response.json().await.map_err(provider_error)?;
+        tokens
+            .id_token
+            .ok_or_else(|| provider_error("no ID token in the response"))
+    }
+
+    /// Checks the signature, issuer, audience, expiration and nonce of an ID token, returning
+    /// its claims
+    pub async fn verify_id_token(
+        &self,
+        token: &str,
+        nonce: &str,
+    ) -> ChordDbResult<Map<String, Value>> {
+        let header = jsonwebtoken::decode_header(token).map_err(invalid_token)?;
+        if !ALGORITHMS.contains(&header.alg) {
+            return Err(invalid_token(format!(
+                "unsupported algorithm {:?}",
+                header.alg
+            )));
+        }
+        let key = self.decoding_key(header.kid.as_deref()).await?;
+        let metadata = self.metadata().await?;
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        let claims = jsonwebtoken::decode::<Map<String, Value>>(token, &key, &validation)
+            .map_err(invalid_token)?
+            .claims;
+        if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+            return Err(invalid_token("wrong nonce"));
+        }
+        Ok(claims)
+    }
+
+    /// The user the claims of an ID token belong to, by the configured claim. The `email` claim
+    /// is matched against the emails of the users, and only if the provider verified it. Any
+    /// other claim is matched against their names.
+    pub async fn find_user(
+        &self,
+        users: &dyn Users,
+        claims: &Map<String, Value>,
+    ) -> ChordDbResult<User> {
+        let claim = &self.config.user_claim;
+        let Some(id) = claims.get(claim).and_then(Value::as_str) else {
+            return Err(ChordDbError::BadRequest(format!(
+                "The ID token has no '{}' claim",
+                claim
+            )));
+        };
+        let user = if claim == "email" {
+            if claims.get("email_verified").and_then(Value::as_bool) != Some(true) {
+                return Err(ChordDbError::BadRequest(format!(
+                    "The provider has not verified {}",
+                    id
+                )));
+            }
+            users.get_user_by_email(id).await?
+        } else {
+            users.get_user_by_name(id).await?
+        };
+        user.ok_or_else(|| ChordDbError::BadRequest(format!("User {} not found", id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde_json::json;
+
+    use crate::{testing::user, user::MemoryUsers};
+
+    use super::{mock::MockIssuer, *};
+    use test_log::test;
+
+    /// Logs in with the provider, returning the login and the code it sent back
+    async fn authorize(
+        client: &OidcClient,
+        issuer: &MockIssuer,
+        claims: Value,
+    ) -> (AuthorizationRequest, String) {
+        let request = client.authorization_request().await.unwrap();
+        let (code, state) = issuer.authorize(&request.url, claims);
+        assert_eq!(state, request.state);
+        (request, code)
+    }
+
+    #[test]
+    fn test_pkce_challenge() {
+        // From RFC 7636, appendix B
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_login_flow() {
+        let issuer = MockIssuer::start().await;
+        let client = OidcClient::new(issuer.config()).unwrap();
+
+        let (request, code) =
+            authorize(&client, &issuer, json!({"email": "alice@example.com"})).await;
+        assert!(request
+            .url
+            .starts_with(&format!("{}/auth?response_type=code", issuer.issuer)));
+        assert!(client.exchange_code(&code, "wrong verifier").await.is_err());
+
+        let (request, code) =
+            authorize(&client, &issuer, json!({"email": "alice@example.com"})).await;
+        let token = client
+            .exchange_code(&code, &request.verifier)
+            .await
+            .unwrap();
+        let claims = client
+            .verify_id_token(&token, &request.nonce)
+            .await
+            .unwrap();
+        assert_eq!(claims["email"], "alice@example.com");
+        // Codes can only be used once
+        assert!(client
+            .exchange_code(&code, &request.verifier)
+            .await
+            .is_err());
+    }
+
+    #[test(tokio::test)]
+    async fn test_rejected_tokens() {
+        let issuer = MockIssuer::start().await;
+        let client = OidcClient::new(issuer.config()).unwrap();
+        let verify = |token: String| {
+            let client = &client;
+            async move { client.verify_id_token(&token, "nonce").await }
+        };
+        let now = Utc::now().timestamp();
+
+        assert!(verify(issuer.id_token(json!({}))).await.is_ok());
+        for claims in [
+            json!({"nonce": "other"}),
+            json!({"exp": now - 3600}),
+            json!({"aud": "other client"}),
+            json!({"iss": "http://other.example.com"}),
+        ] {
+            assert!(verify(issuer.id_token(claims.clone())).await.is_err());
+        }
+        let mut claims = issuer.claims(json!({}));
+        claims.remove("exp");
+        let header = Header {
+            kid: Some(mock::TEST_KEY_ID.to_string()),
+            ..Header::new(Algorithm::RS256)
+        };
+        assert!(verify(issuer.sign(&header, &claims)).await.is_err());
+
+        // The payload is changed after signing
+        let token = issuer.id_token(json!({}));
+        let parts: Vec<&str> = token.split('.').collect();
+        let payload = URL_SAFE_NO_PAD.encode(
+            serde_json::to_vec(&issuer.claims(json!({"email": "admin@example.com"}))).unwrap(),
+        );
+        let tampered = format!("{}.{}.{}", parts[0], payload, parts[2]);
+        assert!(verify(tampered).await.is_err());
+
+        // Unsigned tokens, and tokens signed with a secret
+        let none = format!(
+            "{}.{}.",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"none","typ":"JWT"}"#),
+            parts[1]
+        );
+        assert!(verify(none).await.is_err());
+        let hs256 = jsonwebtoken::encode(
+            &Header::new(Algorithm::HS256),
+            &issuer.claims(json!({})),
+            &EncodingKey::from_secret(mock::TEST_CLIENT_ID.as_bytes()),
+        )
+        .unwrap();
+        assert!(verify(hs256).await.is_err());
+    }
+
+    #[test(tokio::test)]
+    async fn test_key_cache() {
+        let issuer = MockIssuer::start().await;
+        let mut client = OidcClient::new(issuer.config()).unwrap();
+        let claims = issuer.claims(json!({}));
+        let unknown_key = Header {
+            kid: Some("rotated".to_string()),
+            ..Header::new(Algorithm::RS256)
+        };
+
+        for _ in 0..2 {
+            let token = issuer.id_token(json!({}));
+            assert!(client.verify_id_token(&token, "nonce").await.is_ok());
+        }
+        assert_eq!(issuer.key_fetches(), 1);
+
+        // Unknown keys are looked for again, but not right after fetching the keys
+        let token = issuer.sign(&unknown_key, &claims);
+        assert!(client.verify_id_token(&token, "nonce").await.is_err());
+        assert_eq!(issuer.key_fetches(), 1);
+        client.keys_min_refresh = Duration::ZERO;
+        assert!(client.verify_id_token(&token, "nonce").await.is_err());
+        assert_eq!(issuer.key_fetches(), 2);
+    }
+
+    #[test(tokio::test)]
+    async fn test_issuer_mismatch() {
+        let issuer = MockIssuer::start().await;
+        let mut config = issuer.config();
+        config.issuer = config.issuer.replace("127.0.0.1", "localhost");
+        let client = OidcClient::new(config).unwrap();
+        assert!(client.authorization_request().await.is_err());
+    }
+
+    #[test(tokio::test)]
+    async fn test_find_user() {
+        let issuer = MockIssuer::start().await;
+        let mut config = issuer.config();
+        let alice = user("alice");
+        let users = MemoryUsers::new([alice.clone()]);
+        let claims = |value: Value| value.as_object().unwrap().clone();
+
+        let client = OidcClient::new(config.clone()).unwrap();
+        let found = client
+            .find_user(
+                &users,
+                &claims(json!({"email": "Alice@Example.com", "email_verified": true})),
+            )
+            .await
+            .unwrap();
+        assert_eq!(found.id, alice.id);
+        for rejected in [
+            json!({"email": "bob@example.com", "email_verified": true}),
+            json!({"email": "alice@example.com", "email_verified": false}),
+            json!({"email": "alice@example.com"}),
+            json!({"email": "alice@example.com", "email_verified": "true"}),
+            // Emails only match emails
+            json!({"email": "alice", "email_verified": true}),
+            json!({"email": alice.id.to_string(), "email_verified": true}),
+            json!({"preferred_username": "alice"}),
+        ] {
+            assert!(client.find_user(&users, &claims(rejected)).await.is_err());
+        }
+
+        config.user_claim = "preferred_username".to_string();
+        let client = OidcClient::new(config.clone()).unwrap();
+        let found = client
+            .find_user(&users, &claims(json!({"preferred_username": "alice"})))
+            .await
+            .unwrap();
+        assert_eq!(found.id, alice.id);
+        // Other claims only match names
+        for rejected in [
+            json!({"preferred_username": "alice@example.com"}),
+            json!({"preferred_username": alice.id.to_string()}),
+            json!({"preferred_username": "Alice"}),
+        ] {
+            assert!(client.find_user(&users, &claims(rejected)).await.is_err());
+        }
+
+        config.user_claim = "sub".to_string();
+        let client = OidcClient::new(config).unwrap();
+        for rejected in [
+            json!({"sub": "alice@example.com", "email": "alice@example.com", "email_verified": true}),
+            json!({"sub": alice.id.to_string()}),
+        ] {
+            assert!(client.find_user(&users, &claims(rejected)).await.is_err());
+        }
+    }
+}
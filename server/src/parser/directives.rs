@@ -0,0 +1,372 @@
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::chord::{Chord, NoteLocale};
+
+/// Musical metadata of a song, usually written as directives inside the song itself
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SongMetadata {
+    pub key: Option<String>,
+    pub capo: Option<u32>,
+    pub tempo: Option<u32>,
+    pub time_signature: Option<String>,
+    pub tuning: Option<String>,
+    pub instrument: Option<String>,
+}
+
+impl SongMetadata {
+    /// Overwrites the fields that are set in `other`
+    pub fn merge(&mut self, other: SongMetadata) {
+        if other.key.is_some() {
+            self.key = other.key;
+        }
+        if other.capo.is_some() {
+            self.capo = other.capo;
+        }
+        if other.tempo.is_some() {
+            self.tempo = other.tempo;
+        }
+        if other.time_signature.is_some() {
+            self.time_signature = other.time_signature;
+        }
+        if other.tuning.is_some() {
+            self.tuning = other.tuning;
+        }
+        if other.instrument.is_some() {
+            self.instrument = other.instrument;
+        }
+    }
+
+    /// Follows a change of the contents of the song, from directives `old` to `new`. Values
+    /// set by directives that were removed are cleared, the rest are kept.
+    pub fn follow_directives(&mut self, old: &SongMetadata, new: SongMetadata) {
+        follow(&mut self.key, &old.key, new.key);
+        follow(&mut self.capo, &old.capo, new.capo);
+        follow(&mut self.tempo, &old.tempo, new.tempo);
+        follow(
+            &mut self.time_signature,
+            &old.time_signature,
+            new.time_signature,
+        );
+        follow(&mut self.tuning, &old.tuning, new.tuning);
+        follow(&mut self.instrument, &old.instrument, new.instrument);
+    }
+
+    /// Checks the values and normalizes the key to its English name. Returns a description of
+    /// the first invalid value.
+    pub fn validate(&mut self, locale: NoteLocale) -> Result<(), String> {
+        if let Some(key) = &self.key {
+            let chord = parse_key(key, locale).ok_or_else(|| format!("Invalid key '{}'", key))?;
+            self.key = Some(chord.text());
+        }
+        if let Some(capo) = self.capo {
+            if capo > MAX_CAPO {
+                return Err(format!("Invalid capo {}", capo));
+            }
+        }
+        if let Some(tempo) = self.tempo {
+            if tempo == 0 || tempo > MAX_TEMPO {
+                return Err(format!("Invalid tempo {}", tempo));
+            }
+        }
+        if let Some(time_signature) = &self.time_signature {
+            if !TIME_SIGNATURE_REGEX.is_match(time_signature) {
+                return Err(format!("Invalid time signature '{}'", time_signature));
+            }
+        }
+        Ok(())
+    }
+
+    /// Semitones the written chord shapes must move so the song sounds `transpose` semitones
+    /// higher when played with the capo at `capo` instead of the written position
+    pub fn shape_shift(&self, transpose: i32, capo: Option<u32>) -> i32 {
+        let written = self.capo.unwrap_or(0) as i32;
+        transpose + written - capo.map_or(written, |capo| capo as i32)
+    }
+
+    /// Metadata of the song when transposed and played with the capo at `capo`
+    pub fn transposed(&self, transpose: i32, capo: Option<u32>) -> SongMetadata {
+        let mut metadata = self.clone();
+        if capo.is_some() {
+            metadata.capo = capo;
+        }
+        if let Some(key) = self.key.as_deref().and_then(Chord::parse) {
+            metadata.key = Some(key.transpose(transpose).text());
+        }
+        metadata
+    }
+
+    fn apply(&mut self, directive: Directive) {
+        match directive {
+            Directive::Key(key) => self.key = Some(key.text()),
+            Directive::Capo(capo) => self.capo = Some(capo),
+            Directive::Tempo(tempo) => self.tempo = Some(tempo),
+            Directive::TimeSignature(time_signature) => self.time_signature = Some(time_signature),
+            Directive::Tuning(tuning) => self.tuning = Some(tuning),
+            Directive::Instrument(instrument) => self.instrument = Some(instrument),
+        }
+    }
+}
+
+fn follow<T: PartialEq>(value: &mut Option<T>, old: &Option<T>, new: Option<T>) {
+    if new.is_some() {
+        *value = new;
+    } else if old.is_some() && value == old {
+        *value = None;
+    }
+}
+
+/// Metadata sent by a client. Missing fields are kept, and `null` clears them
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct MetadataUpdate {
+    #[serde(default, deserialize_with = "nullable")]
+    pub key: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub capo: Option<Option<u32>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub tempo: Option<Option<u32>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub time_signature: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub tuning: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub instrument: Option<Option<String>>,
+}
+
+/// Tells a `null` value, `Some(None)`, from a missing one
+fn nullable<'de, T: Deserialize<'de>, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Option<T>>, D::Error> {
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+impl MetadataUpdate {
+    pub fn is_empty(&self) -> bool {
+        *self == MetadataUpdate::default()
+    }
+
+    pub fn apply(self, metadata: &mut SongMetadata) {
+        if let Some(key) = self.key {
+            metadata.key = key;
+        }
+        if let Some(capo) = self.capo {
+            metadata.capo = capo;
+        }
+        if let Some(tempo) = self.tempo {
+            metadata.tempo = tempo;
+        }
+        if let Some(time_signature) = self.time_signature {
+            metadata.time_signature = time_signature;
+        }
+        if let Some(tuning) = self.tuning {
+            metadata.tuning = tuning;
+        }
+        if let Some(instrument) = self.instrument {
+            metadata.instrument = instrument;
+        }
+    }
+}
+
+const MAX_CAPO: u32 = 24;
+const MAX_TEMPO: u32 = 400;
+/// Transpositions go less than an octave either way
+const MAX_TRANSPOSE: i32 = 11;
+
+/// Checks the transposition and capo a song is asked to be played with, before they are used
+pub fn check_overrides(transpose: i32, capo: Option<u32>) -> Result<(), String> {
+    if !(-MAX_TRANSPOSE..=MAX_TRANSPOSE).contains(&transpose) {
+        return Err(format!("Invalid transpose {}", transpose));
+    }
+    SongMetadata {
+        capo,
+        ..Default::default()
+    }
+    .validate(NoteLocale::English)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Directive {
+    Key(Chord),
+    Capo(u32),
+    Tempo(u32),
+    TimeSignature(String),
+    Tuning(String),
+    Instrument(String),
+}
+
+lazy_static! {
+    static ref BRACED_DIRECTIVE_REGEX: Regex =
+        Regex::new(r"^\s*\{\s*(?<name>[a-zA-Z_ ]+?)\s*(?<colon>:)\s*(?<value>.*?)\s*\}\s*$").unwrap();
+    static ref PLAIN_DIRECTIVE_REGEX: Regex = Regex::new(
+        r"(?i)^\s*(?<name>key|capo|tempo|bpm|time|time signature|tuning|instrument)\s*(?<colon>:)?\s+(?<value>\S.*?)\s*$"
+    )
+    .unwrap();
+    static ref NUMBER_REGEX: Regex =
+        Regex::new(r"(?i)^(?<number>\d+)(?:st|nd|rd|th)?(?:\s+(?:fret|bpm))?$").unwrap();
+    static ref TIME_SIGNATURE_REGEX: Regex = Regex::new(r"^\d{1,2}/\d{1,2}$").unwrap();
+}
+
+fn parse_key(key: &str, locale: NoteLocale) -> Option<Chord> {
+    Chord::parse_in(key, locale).or_else(|| Chord::parse(key))
+}
+
+fn parse_number(value: &str, max: u32) -> Option<u32> {
+    NUMBER_REGEX.captures(value)?["number"]
+        .parse()
+        .ok()
+        .filter(|n| *n <= max)
+}
+
+/// Parses a line consisting of a single metadata directive, like `Capo: 2` or `{key: G}`.
+/// Lines with values that can not be interpreted are not directives. Tunings and instruments
+/// can be anything, so without braces they need a colon to tell them from lyrics.
+pub fn parse_directive<S: AsRef<str>>(line: S, locale: NoteLocale) -> Option<Directive> {
+    let line = line.as_ref();
+    let caps = BRACED_DIRECTIVE_REGEX
+        .captures(line)
+        .or_else(|| PLAIN_DIRECTIVE_REGEX.captures(line))?;
+    let value = &caps["value"];
+    let name = caps["name"].to_lowercase();
+    if matches!(name.as_str(), "tuning" | "instrument") && caps.name("colon").is_none() {
+        return None;
+    }
+    match name.as_str() {
+        "key" => parse_key(value, locale).map(Directive::Key),
+        "capo" => parse_number(value, MAX_CAPO).map(Directive::Capo),
+        "tempo" | "bpm" => parse_number(value, MAX_TEMPO)
+            .filter(|t| *t > 0)
+            .map(Directive::Tempo),
+        "time" | "time signature" | "time_signature" | "meter" => Some(value)
+            .filter(|v| TIME_SIGNATURE_REGEX.is_match(v))
+            .map(|v| Directive::TimeSignature(v.to_owned())),
+        "tuning" => Some(Directive::Tuning(value.to_owned())),
+        "instrument" => Some(Directive::Instrument(value.to_lowercase())),
+        _ => None,
+    }
+}
+
+/// Extracts the metadata directives of a song
+pub fn parse_metadata<S: AsRef<str>>(source: S, locale: NoteLocale) -> SongMetadata {
+    let mut metadata = SongMetadata::default();
+    for line in source.as_ref().lines() {
+        if let Some(directive) = parse_directive(line, locale) {
+            metadata.apply(directive);
+        }
+    }
+    metadata
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chord::{Key, Variant};
+
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_parse_directive() {
+        let english = NoteLocale::English;
+        assert_eq!(
+            parse_directive("{key: G}", english),
+            Some(Directive::Key(Chord::simple(Key::G, Variant::Major)))
+        );
+        assert_eq!(
+            parse_directive("Tempo 96", english),
+            Some(Directive::Tempo(96))
+        );
+        assert_eq!(
+            parse_directive("Capo: 2nd fret", english),
+            Some(Directive::Capo(2))
+        );
+        assert_eq!(parse_directive("{title: Song}", english), None);
+        assert_eq!(parse_directive("Key to my heart", english), None);
+        assert_eq!(parse_directive("Time after time", english), None);
+        assert_eq!(parse_directive("Capo:", english), None);
+        assert_eq!(parse_directive("Tuning in to you", english), None);
+        assert_eq!(parse_directive("Instrument of peace", english), None);
+        assert_eq!(
+            parse_directive("Instrument: Ukulele", english),
+            Some(Directive::Instrument("ukulele".to_owned()))
+        );
+        assert_eq!(
+            parse_directive("{tuning: DADGAD}", english),
+            Some(Directive::Tuning("DADGAD".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_parse_metadata() {
+        let metadata = parse_metadata(
+            "Capo: 2\n{key: Am}\nTempo 96 bpm\n{time: 3/4}\nTuning: Drop D\nInstrument: Mimi\nAm\nlyrics",
+            NoteLocale::English,
+        );
+        assert_eq!(
+            metadata,
+            SongMetadata {
+                key: Some("Am".to_owned()),
+                capo: Some(2),
+                tempo: Some(96),
+                time_signature: Some("3/4".to_owned()),
+                tuning: Some("Drop D".to_owned()),
+                instrument: Some("mimi".to_owned()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_metadata_in_locale() {
+        let metadata = parse_metadata("Key: Rem\nCapo 99", NoteLocale::Solfege);
+        assert_eq!(metadata.key, Some("Dm".to_owned()));
+        assert_eq!(metadata.capo, None);
+    }
+
+    #[test]
+    fn test_check_overrides() {
+        assert_eq!(check_overrides(-11, Some(24)), Ok(()));
+        assert_eq!(check_overrides(11, None), Ok(()));
+        assert!(check_overrides(12, None).is_err());
+        assert!(check_overrides(i32::MIN, None).is_err());
+        assert!(check_overrides(0, Some(25)).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut metadata = SongMetadata {
+            key: Some("Sib".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(metadata.validate(NoteLocale::Solfege), Ok(()));
+        assert_eq!(metadata.key, Some("Bb".to_owned()));
+
+        let mut metadata = SongMetadata {
+            time_signature: Some("three".to_owned()),
+            ..Default::default()
+        };
+        assert!(metadata.validate(NoteLocale::English).is_err());
+    }
+
+    #[test]
+    fn test_shape_shift() {
+        let metadata = SongMetadata {
+            capo: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(metadata.shape_shift(0, None), 0);
+        assert_eq!(metadata.shape_shift(0, Some(0)), 2);
+        assert_eq!(metadata.shape_shift(-1, Some(4)), -3);
+        assert_eq!(SongMetadata::default().shape_shift(3, None), 3);
+    }
+
+    #[test]
+    fn test_transposed() {
+        let metadata = SongMetadata {
+            key: Some("Am".to_owned()),
+            capo: Some(2),
+            ..Default::default()
+        };
+        let transposed = metadata.transposed(3, Some(0));
+        assert_eq!(transposed.key, Some("Cm".to_owned()));
+        assert_eq!(transposed.capo, Some(0));
+        assert_eq!(metadata.transposed(0, None), metadata);
+    }
+}
@@ -0,0 +1,520 @@
+use crate::chord::{Chord, NoteLocale};
+
+mod directives;
+mod tab;
+
+pub use directives::{
+    check_overrides, parse_directive, parse_metadata, Directive, MetadataUpdate, SongMetadata,
+};
+pub use tab::{TabBlock, TabEvent, TabStaff, Technique};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Comp {
+    Text(String),
+    Chord {
+        chord: Chord,
+        original_text: String,
+    },
+    Tab(TabBlock),
+    Directive {
+        directive: Directive,
+        original_text: String,
+    },
+}
+
+/// Symbols written around a chord that do not change its meaning, like `(Am)`, `Am*` or `|Am|`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Decorations {
+    pub parenthesized: bool,
+    pub starred: bool,
+    pub bar_before: bool,
+    pub bar_after: bool,
+}
+
+impl Decorations {
+    pub fn is_empty(&self) -> bool {
+        *self == Decorations::default()
+    }
+
+    /// Splits `text` into the decorations around it and the remaining inner text
+    fn strip(text: &str) -> (Decorations, &str) {
+        let mut decorations = Decorations::default();
+        let mut inner = text;
+        loop {
+            if let Some(rest) = inner.strip_prefix('|') {
+                decorations.bar_before = true;
+                inner = rest;
+            } else if let Some(rest) = inner.strip_prefix('(') {
+                decorations.parenthesized = true;
+                inner = rest;
+            } else {
+                break;
+            }
+        }
+        loop {
+            if let Some(rest) = inner.strip_suffix('|') {
+                decorations.bar_after = true;
+                inner = rest;
+            } else if let Some(rest) = inner.strip_suffix(')') {
+                decorations.parenthesized = true;
+                inner = rest;
+            } else if let Some(rest) = inner.strip_suffix('*') {
+                decorations.starred = true;
+                inner = rest;
+            } else {
+                break;
+            }
+        }
+        (decorations, inner)
+    }
+
+    /// Writes `text` surrounded by these decorations
+    pub fn apply(&self, text: &str) -> String {
+        let mut decorated = String::new();
+        if self.bar_before {
+            decorated.push('|');
+        }
+        if self.parenthesized {
+            decorated.push('(');
+        }
+        decorated.push_str(text);
+        if self.starred {
+            decorated.push('*');
+        }
+        if self.parenthesized {
+            decorated.push(')');
+        }
+        if self.bar_after {
+            decorated.push('|');
+        }
+        decorated
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct LineBit {
+    pub comp: Comp,
+    pub position: usize,
+    pub decorations: Decorations,
+}
+
+pub type Line = Vec<LineBit>;
+
+pub fn parse_tablature<S: AsRef<str>>(source: S) -> Vec<Line> {
+    parse_tablature_in(source, NoteLocale::English)
+}
+
+/// Parses a tablature whose chords are written using the given `NoteLocale`
+pub fn parse_tablature_in<S: AsRef<str>>(source: S, locale: NoteLocale) -> Vec<Line> {
+    let source_lines: Vec<&str> = source.as_ref().lines().collect();
+    let mut lines = Vec::new();
+    let mut index = 0;
+    while index < source_lines.len() {
+        let staff_lines = source_lines[index..]
+            .iter()
+            .take_while(|line| tab::is_staff_line(line))
+            .count();
+        if staff_lines > 0 {
+            let block = &source_lines[index..index + staff_lines];
+            if let Some(tab) = tab::parse_tab_block(block) {
+                lines.push(vec![LineBit {
+                    comp: Comp::Tab(tab),
+                    position: 0,
+                    decorations: Decorations::default(),
+                }]);
+            }
+            index += staff_lines;
+        } else if let Some(directive) = parse_directive(source_lines[index], locale) {
+            let original_text = source_lines[index].trim();
+            lines.push(vec![LineBit {
+                comp: Comp::Directive {
+                    directive,
+                    original_text: original_text.to_owned(),
+                },
+                position: 0,
+                decorations: Decorations::default(),
+            }]);
+            index += 1;
+        } else {
+            lines.push(parse_line(source_lines[index], locale));
+            index += 1;
+        }
+    }
+    lines
+}
+
+/// Moves every chord of the tablature the given number of semitones, rewriting them in `locale`
+pub fn transpose_tablature(tablature: &mut [Line], semitones: i32, locale: NoteLocale) {
+    if semitones.rem_euclid(12) == 0 {
+        return;
+    }
+    for bit in tablature.iter_mut().flatten() {
+        if let Comp::Chord {
+            chord,
+            original_text,
+        } = &mut bit.comp
+        {
+            *chord = chord.transpose(semitones);
+            *original_text = bit.decorations.apply(&chord.text_in(locale));
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Whitespace(String),
+    Text(String),
+}
+
+plex::lexer! {
+    fn next_token(text: 'a) -> Token;
+
+    r#"[ \t\r\n]+"# => Token::Whitespace(text.to_owned()),
+    r#"[^ \t\r\n]+"# => Token::Text(text.to_owned()),
+}
+
+fn tokenize<S: AsRef<str>>(source: S) -> Vec<Token> {
+    let mut remaining = source.as_ref();
+    let mut tokens: Vec<Token> = Vec::new();
+    while let Some((token, new_remaining)) = next_token(remaining) {
+        // let final_token = match &token {
+        //     Token::Text(text) => Chord::parse(text)
+        //         .map(|chord| Token::Chord{chord, original_text: text.to_owned()})
+        //         .unwrap_or(token),
+        //     _ => token,
+
+        // };
+        tokens.push(token);
+        remaining = new_remaining;
+    }
+    tokens
+}
+
+fn parse_line<S: AsRef<str>>(source: S, locale: NoteLocale) -> Line {
+    let mut position = 0;
+    let mut line = Line::new();
+    let mut current_text = String::new();
+    for token in tokenize(source.as_ref()) {
+        match &token {
+            Token::Whitespace(text) => current_text += text,
+            Token::Text(text) => {
+                if let Some((chord, decorations)) = parse_chord_token(text, locale) {
+                    push_text(&mut current_text, &mut line, &mut position);
+
+                    line.push(LineBit {
+                        comp: Comp::Chord {
+                            chord,
+                            original_text: text.clone(),
+                        },
+                        position,
+                        decorations,
+                    });
+                    position += text.chars().count()
+                } else {
+                    current_text += text;
+                }
+            }
+        }
+    }
+    push_text(&mut current_text, &mut line, &mut position);
+
+    // Solfège notes are common words ("La", "Mi", "Si"...), so a lyric starting with one
+    // would read as a chord. Only lines with nothing but chords are chord lines then.
+    if locale == NoteLocale::Solfege && line.iter().any(is_word) {
+        let mut position = 0;
+        let mut line = Line::new();
+        push_text(&mut source.as_ref().to_owned(), &mut line, &mut position);
+        return line;
+    }
+
+    line
+}
+
+fn is_word(bit: &LineBit) -> bool {
+    matches!(&bit.comp, Comp::Text(text) if text.chars().any(char::is_alphabetic))
+}
+
+fn parse_chord_token(text: &str, locale: NoteLocale) -> Option<(Chord, Decorations)> {
+    if let Some(chord) = Chord::parse_in(text, locale) {
+        return Some((chord, Decorations::default()));
+    }
+    let (decorations, inner) = Decorations::strip(text);
+    if decorations.is_empty() || inner.is_empty() {
+        return None;
+    }
+    Chord::parse_in(inner, locale).map(|chord| (chord, decorations))
+}
+
+fn push_text(current_text: &mut String, line: &mut Line, position: &mut usize) {
+    let trimmed = current_text.trim();
+    if !trimmed.is_empty() {
+        let mut starting_whitespace = 0;
+        for char in current_text.chars() {
+            if !char.is_whitespace() {
+                break;
+            }
+            starting_whitespace += 1;
+        }
+        line.push(LineBit {
+            comp: Comp::Text(trimmed.to_owned()),
+            position: *position + starting_whitespace,
+            decorations: Decorations::default(),
+        });
+    }
+    *position += current_text.chars().count();
+    current_text.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chord::{Key, Variant};
+
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(
+            parse_line("I'm a genius", NoteLocale::English),
+            vec![LineBit {
+                comp: Comp::Text("I'm a genius".to_owned()),
+                position: 0,
+                decorations: Decorations::default()
+            }]
+        );
+        assert_eq!(
+            parse_line("I'M A GENIUS", NoteLocale::English),
+            vec![
+                LineBit {
+                    comp: Comp::Text("I'M".to_owned()),
+                    position: 0,
+                    decorations: Decorations::default()
+                },
+                LineBit {
+                    comp: Comp::Chord {
+                        chord: Chord::simple(Key::A, Variant::Major),
+                        original_text: "A".to_owned()
+                    },
+                    position: 4,
+                    decorations: Decorations::default()
+                },
+                LineBit {
+                    comp: Comp::Text("GENIUS".to_owned()),
+                    position: 6,
+                    decorations: Decorations::default()
+                }
+            ]
+        );
+        assert_eq!(
+            parse_line("C#7/G#", NoteLocale::English),
+            vec![LineBit {
+                comp: Comp::Chord {
+                    chord: Chord::new(Key::Db, Variant::Seventh, Key::Ab),
+                    original_text: "C#7/G#".to_owned()
+                },
+                position: 0,
+                decorations: Decorations::default()
+            }]
+        );
+        assert_eq!(
+            parse_line(" trailing A7 whitespace ", NoteLocale::English),
+            vec![
+                LineBit {
+                    comp: Comp::Text("trailing".to_owned()),
+                    position: 1,
+                    decorations: Decorations::default()
+                },
+                LineBit {
+                    comp: Comp::Chord {
+                        chord: Chord::simple(Key::A, Variant::Seventh),
+                        original_text: "A7".to_owned()
+                    },
+                    position: 10,
+                    decorations: Decorations::default()
+                },
+                LineBit {
+                    comp: Comp::Text("whitespace".to_owned()),
+                    position: 13,
+                    decorations: Decorations::default()
+                }
+            ]
+        );
+        // assert_eq!(parsed.len(), 5);
+        // assert_eq!(parsed[0].position, 0);
+        // assert_eq!(parsed[0].comp, Comp::Chord { chord: Chord::parse("A").unwrap() })
+    }
+
+    #[test]
+    fn test_parse_line_in_solfege() {
+        assert_eq!(
+            parse_line("La  Rem/Fa", NoteLocale::Solfege),
+            vec![
+                LineBit {
+                    comp: Comp::Chord {
+                        chord: Chord::simple(Key::A, Variant::Major),
+                        original_text: "La".to_owned()
+                    },
+                    position: 0,
+                    decorations: Decorations::default()
+                },
+                LineBit {
+                    comp: Comp::Chord {
+                        chord: Chord::new(Key::D, Variant::Minor, Key::F),
+                        original_text: "Rem/Fa".to_owned()
+                    },
+                    position: 4,
+                    decorations: Decorations::default()
+                }
+            ]
+        );
+        assert_eq!(
+            parse_line("A la", NoteLocale::Solfege),
+            vec![LineBit {
+                comp: Comp::Text("A la".to_owned()),
+                position: 0,
+                decorations: Decorations::default()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_solfege_lyrics() {
+        for lyrics in [
+            "La bamba se baila así",
+            "Mi corazón late por ti",
+            "Si tú me quieres",
+            "Do you know the way",
+            "La donna è mobile",
+            "Re   y   Mi   cantan",
+        ] {
+            assert_eq!(
+                parse_line(lyrics, NoteLocale::Solfege),
+                vec![LineBit {
+                    comp: Comp::Text(lyrics.to_owned()),
+                    position: 0,
+                    decorations: Decorations::default()
+                }],
+                "{}",
+                lyrics
+            );
+        }
+        for (chords, count) in [
+            ("Do  Sol  Lam  Fa", 4),
+            ("|Lam  Fa  |  Do  Sol|", 4),
+            ("  Mi7   La", 2),
+        ] {
+            let parsed = parse_line(chords, NoteLocale::Solfege);
+            let parsed_chords = parsed
+                .iter()
+                .filter(|bit| matches!(bit.comp, Comp::Chord { .. }))
+                .count();
+            assert_eq!(parsed_chords, count, "{}", chords);
+        }
+        let parsed = parse_tablature_in("Do     Sol\nLa vida es bella", NoteLocale::Solfege);
+        assert!(matches!(parsed[0][0].comp, Comp::Chord { .. }));
+        assert_eq!(parsed[1].len(), 1);
+        assert!(matches!(parsed[1][0].comp, Comp::Text(_)));
+    }
+
+    #[test]
+    fn test_parse_line_with_decorations() {
+        let parsed = parse_line("(Am)  G*  |C♯m|  |  (nope)", NoteLocale::English);
+        let chords = parsed
+            .iter()
+            .filter_map(|bit| match &bit.comp {
+                Comp::Chord { chord, .. } => Some((chord.text(), bit.position, bit.decorations)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            chords,
+            vec![
+                (
+                    "Am".to_owned(),
+                    0,
+                    Decorations {
+                        parenthesized: true,
+                        ..Default::default()
+                    }
+                ),
+                (
+                    "G".to_owned(),
+                    6,
+                    Decorations {
+                        starred: true,
+                        ..Default::default()
+                    }
+                ),
+                (
+                    "Dbm".to_owned(),
+                    10,
+                    Decorations {
+                        bar_before: true,
+                        bar_after: true,
+                        ..Default::default()
+                    }
+                ),
+            ]
+        );
+        assert_eq!(
+            parsed.last().unwrap().comp,
+            Comp::Text("|  (nope)".to_owned())
+        );
+        assert_eq!(parsed.last().unwrap().position, 17);
+    }
+
+    #[test]
+    fn test_parse_tablature_with_tab_block() {
+        let parsed = parse_tablature("Am\nE|---0---|\nA|-2-----|\nD|-------|\nlyrics");
+        assert_eq!(parsed.len(), 3);
+        assert!(matches!(parsed[0][0].comp, Comp::Chord { .. }));
+        let Comp::Tab(tab) = &parsed[1][0].comp else {
+            panic!("Expected a tab block but got {:?}", parsed[1]);
+        };
+        assert_eq!(tab.string_count(), 3);
+        assert_eq!(tab.events.len(), 2);
+        assert_eq!(parsed[2][0].comp, Comp::Text("lyrics".to_owned()));
+    }
+
+    #[test]
+    fn test_parse_tablature_with_directives() {
+        let parsed = parse_tablature("{key: G}\nCapo: 2\nKey to my heart");
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(
+            parsed[0][0].comp,
+            Comp::Directive {
+                directive: Directive::Key(Chord::simple(Key::G, Variant::Major)),
+                original_text: "{key: G}".to_owned(),
+            }
+        );
+        assert!(matches!(
+            parsed[1][0].comp,
+            Comp::Directive {
+                directive: Directive::Capo(2),
+                ..
+            }
+        ));
+        assert_eq!(parsed[2][0].comp, Comp::Text("Key to my heart".to_owned()));
+    }
+
+    #[test]
+    fn test_transpose_tablature() {
+        let mut parsed = parse_tablature_in("(Lam)  Do/Mi\nla la", NoteLocale::Solfege);
+        transpose_tablature(&mut parsed, 2, NoteLocale::Solfege);
+        assert_eq!(
+            parsed[0][0].comp,
+            Comp::Chord {
+                chord: Chord::simple(Key::B, Variant::Minor),
+                original_text: "(Sim)".to_owned(),
+            }
+        );
+        assert_eq!(
+            parsed[0][1].comp,
+            Comp::Chord {
+                chord: Chord::new(Key::D, Variant::Major, Key::Gb),
+                original_text: "Re/Solb".to_owned(),
+            }
+        );
+        assert_eq!(parsed[1][0].comp, Comp::Text("la la".to_owned()));
+    }
+}
@@ -0,0 +1,295 @@
+use regex::Regex;
+
+use crate::chord::finder::StringInstrument;
+
+lazy_static! {
+    static ref STAFF_REGEX: Regex =
+        Regex::new(r"^\s*(?<label>[A-Ga-g][#b♯♭]?)?\s*[|│](?<body>[-–—0-9hpbrx/\\~|│*.()^ ]*)$")
+            .unwrap();
+}
+
+const MIN_STAFF_DASHES: usize = 3;
+
+/// Tabs pasted from word processors and some sites use typographic dashes for the lines
+fn is_dash(char: char) -> bool {
+    matches!(char, '-' | '–' | '—')
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Technique {
+    Pick,
+    HammerOn,
+    PullOff,
+    Bend,
+    Release,
+    SlideUp,
+    SlideDown,
+    Muted,
+}
+
+impl Technique {
+    fn from_connector(connector: char) -> Option<Technique> {
+        match connector {
+            'h' => Some(Technique::HammerOn),
+            'p' => Some(Technique::PullOff),
+            'b' => Some(Technique::Bend),
+            'r' => Some(Technique::Release),
+            '/' => Some(Technique::SlideUp),
+            '\\' => Some(Technique::SlideDown),
+            _ => None,
+        }
+    }
+
+    pub fn text(&self) -> &'static str {
+        match self {
+            Technique::Pick => "pick",
+            Technique::HammerOn => "hammer_on",
+            Technique::PullOff => "pull_off",
+            Technique::Bend => "bend",
+            Technique::Release => "release",
+            Technique::SlideUp => "slide_up",
+            Technique::SlideDown => "slide_down",
+            Technique::Muted => "muted",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TabEvent {
+    /// Column of the event in characters, relative to the start of the staff
+    pub column: usize,
+    /// String index, starting from the lowest (bottom) line of the staff
+    pub string: usize,
+    /// Fret to play. `None` for muted notes
+    pub fret: Option<usize>,
+    pub technique: Technique,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TabStaff {
+    pub label: Option<String>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TabBlock {
+    /// Staff lines, as they appear in the source (highest string first)
+    pub staves: Vec<TabStaff>,
+    /// All the events in the block, ordered by column and then by string
+    pub events: Vec<TabEvent>,
+}
+
+impl TabBlock {
+    pub fn string_count(&self) -> usize {
+        self.staves.len()
+    }
+
+    pub fn validate(&self, instrument: &StringInstrument) -> Vec<String> {
+        let strings = instrument.strings();
+        if strings.len() != self.string_count() {
+            return vec![format!(
+                "Tab has {} strings but {} has {}",
+                self.string_count(),
+                instrument.name(),
+                strings.len()
+            )];
+        }
+        self.events
+            .iter()
+            .filter_map(|event| {
+                let fret = event.fret?;
+                let frets = strings[event.string].frets();
+                if fret > frets {
+                    Some(format!(
+                        "Fret {} on string {} is out of range (max {})",
+                        fret,
+                        self.string_count() - event.string,
+                        frets
+                    ))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+fn parse_staff(line: &str) -> Option<(TabStaff, &str)> {
+    let caps = STAFF_REGEX.captures(line)?;
+    let body = caps.name("body")?;
+    if body.as_str().chars().filter(|char| is_dash(*char)).count() < MIN_STAFF_DASHES {
+        return None;
+    }
+    Some((
+        TabStaff {
+            label: caps.name("label").map(|l| l.as_str().to_owned()),
+            text: line.to_owned(),
+        },
+        body.as_str(),
+    ))
+}
+
+pub fn is_staff_line<S: AsRef<str>>(line: S) -> bool {
+    parse_staff(line.as_ref()).is_some()
+}
+
+fn parse_events(body: &str, string: usize) -> Vec<TabEvent> {
+    let mut events = vec![];
+    let mut pending = Technique::Pick;
+    let mut chars = body.chars().enumerate().peekable();
+    while let Some((column, char)) = chars.next() {
+        if let Some(digit) = char.to_digit(10) {
+            let mut fret = digit as usize;
+            while let Some((_, next)) = chars.peek() {
+                let Some(next_digit) = next.to_digit(10) else {
+                    break;
+                };
+                fret = fret * 10 + next_digit as usize;
+                chars.next();
+            }
+            events.push(TabEvent {
+                column,
+                string,
+                fret: Some(fret),
+                technique: pending,
+            });
+            pending = Technique::Pick;
+        } else if char == 'x' {
+            events.push(TabEvent {
+                column,
+                string,
+                fret: None,
+                technique: Technique::Muted,
+            });
+            pending = Technique::Pick;
+        } else if let Some(technique) = Technique::from_connector(char) {
+            pending = technique;
+        } else {
+            pending = Technique::Pick;
+        }
+    }
+    events
+}
+
+/// Parses consecutive staff lines into a `TabBlock`. Returns `None` if any of the lines
+/// is not a staff line.
+pub fn parse_tab_block<S: AsRef<str>>(lines: &[S]) -> Option<TabBlock> {
+    let parsed = lines
+        .iter()
+        .map(|line| parse_staff(line.as_ref()))
+        .collect::<Option<Vec<_>>>()?;
+    if parsed.is_empty() {
+        return None;
+    }
+    let string_count = parsed.len();
+    let mut events: Vec<TabEvent> = parsed
+        .iter()
+        .enumerate()
+        .flat_map(|(index, (_, body))| parse_events(body, string_count - 1 - index))
+        .collect();
+    events.sort_by_key(|e| (e.column, e.string));
+
+    Some(TabBlock {
+        staves: parsed.into_iter().map(|(staff, _)| staff).collect(),
+        events,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chord::finder::{GUITAR_STANDARD, MIMI};
+
+    use super::*;
+    use test_log::test;
+
+    fn event(column: usize, string: usize, fret: usize, technique: Technique) -> TabEvent {
+        TabEvent {
+            column,
+            string,
+            fret: Some(fret),
+            technique,
+        }
+    }
+
+    #[test]
+    fn test_staff_detection() {
+        assert!(is_staff_line("e|---0---3---|"));
+        assert!(is_staff_line("  D#|--5h7--|"));
+        assert!(is_staff_line("|-----------|"));
+        assert!(!is_staff_line("A|D"));
+        assert!(!is_staff_line("| Am | G |"));
+        assert!(!is_staff_line("A           D"));
+    }
+
+    #[test]
+    fn test_parse_events() {
+        let block = parse_tab_block(&["e|---0--12---|", "B|-1-----x---|"]).unwrap();
+        assert_eq!(block.string_count(), 2);
+        assert_eq!(
+            block.events,
+            vec![
+                event(1, 0, 1, Technique::Pick),
+                event(3, 1, 0, Technique::Pick),
+                event(6, 1, 12, Technique::Pick),
+                TabEvent {
+                    column: 7,
+                    string: 0,
+                    fret: None,
+                    technique: Technique::Muted
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multibyte_columns() {
+        assert!(is_staff_line("E♭│–––0–––│"));
+        let block = parse_tab_block(&["e│–0–––3–│", "B|-1--—5--|"]).unwrap();
+        assert_eq!(
+            block.events,
+            vec![
+                event(1, 0, 1, Technique::Pick),
+                event(1, 1, 0, Technique::Pick),
+                event(5, 0, 5, Technique::Pick),
+                event(5, 1, 3, Technique::Pick),
+            ]
+        );
+        assert_eq!(block.staves[0].label.as_deref(), Some("e"));
+    }
+
+    #[test]
+    fn test_parse_techniques() {
+        let block = parse_tab_block(&["G|-5h7p5-7b9r7-/5-7\\5-|"]).unwrap();
+        assert_eq!(
+            block
+                .events
+                .iter()
+                .map(|e| (e.fret.unwrap(), e.technique))
+                .collect::<Vec<_>>(),
+            vec![
+                (5, Technique::Pick),
+                (7, Technique::HammerOn),
+                (5, Technique::PullOff),
+                (7, Technique::Pick),
+                (9, Technique::Bend),
+                (7, Technique::Release),
+                (5, Technique::SlideUp),
+                (7, Technique::Pick),
+                (5, Technique::SlideDown),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let block = parse_tab_block(&["e|---0---|", "B|---1---|", "G|---7---|"]).unwrap();
+        assert!(block.validate(&MIMI).is_empty());
+        assert_eq!(block.validate(&GUITAR_STANDARD).len(), 1);
+
+        let block = parse_tab_block(&["e|--30---|", "B|---1---|", "G|---0---|"]).unwrap();
+        assert_eq!(
+            block.validate(&MIMI),
+            vec!["Fret 30 on string 1 is out of range (max 16)".to_string()]
+        );
+    }
+}
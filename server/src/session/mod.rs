@@ -1,5 +1,11 @@
+use std::{sync::Arc, time::Duration};
+
 use axum::async_trait;
-use sea_orm::{sea_query::OnConflict, EntityTrait, Iterable};
+use chrono::{NaiveDateTime, Utc};
+use dashmap::DashMap;
+use sea_orm::{sea_query::OnConflict, ColumnTrait, EntityTrait, Iterable, QueryFilter};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
 
 use crate::{entities::session, error::ChordDbResult};
 
@@ -10,6 +16,28 @@ pub trait Sessions: Send + Sync {
     async fn get_session(&self, session_id: &str) -> ChordDbResult<Option<Session>>;
     async fn upsert_session(&self, session: Session) -> ChordDbResult<()>;
     async fn delete_session(&self, session_id: &str) -> ChordDbResult<bool>;
+    /// Logs the user out of every device. Returns the number of sessions deleted
+    async fn delete_user_sessions(&self, user_id: &Uuid) -> ChordDbResult<u64>;
+    /// Deletes the sessions that expired at `now`. Returns the number of sessions deleted
+    async fn delete_expired_sessions(&self, now: NaiveDateTime) -> ChordDbResult<u64>;
+}
+
+/// Deletes the expired sessions every `period`, in the background
+pub fn spawn_session_cleanup(sessions: Arc<dyn Sessions>, period: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            match sessions
+                .delete_expired_sessions(Utc::now().naive_utc())
+                .await
+            {
+                Ok(0) => {}
+                Ok(deleted) => log::info!("Deleted {} expired sessions", deleted),
+                Err(err) => log::warn!("Could not delete the expired sessions: {}", err),
+            }
+        }
+    })
 }
 
 pub struct SeaOrmSessions {
@@ -48,4 +76,146 @@ impl Sessions for SeaOrmSessions {
 
         Ok(delete_result.rows_affected > 0)
     }
+
+    async fn delete_user_sessions(&self, user_id: &Uuid) -> ChordDbResult<u64> {
+        let delete_result = SessionEntity::delete_many()
+            .filter(session::Column::UserId.eq(user_id.to_string()))
+            .exec(&self.db)
+            .await?;
+
+        Ok(delete_result.rows_affected)
+    }
+
+    async fn delete_expired_sessions(&self, now: NaiveDateTime) -> ChordDbResult<u64> {
+        let delete_result = SessionEntity::delete_many()
+            .filter(session::Column::ExpiresAt.lte(now))
+            .exec(&self.db)
+            .await?;
+
+        Ok(delete_result.rows_affected)
+    }
+}
+
+/// Sessions kept in memory, for servers running without a database
+#[derive(Default)]
+pub struct MemorySessions {
+    sessions: DashMap<String, Session>,
+}
+
+impl MemorySessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Sessions for MemorySessions {
+    async fn get_session(&self, session_id: &str) -> ChordDbResult<Option<Session>> {
+        Ok(self
+            .sessions
+            .get(session_id)
+            .map(|session| session.value().clone()))
+    }
+
+    async fn upsert_session(&self, session: Session) -> ChordDbResult<()> {
+        self.sessions.insert(session.id.clone(), session);
+        Ok(())
+    }
+
+    async fn delete_session(&self, session_id: &str) -> ChordDbResult<bool> {
+        Ok(self.sessions.remove(session_id).is_some())
+    }
+
+    async fn delete_user_sessions(&self, user_id: &Uuid) -> ChordDbResult<u64> {
+        let user_id = user_id.to_string();
+        let mut deleted = 0;
+        self.sessions.retain(|_, session| {
+            let keep = session.user_id != user_id;
+            if !keep {
+                deleted += 1;
+            }
+            keep
+        });
+        Ok(deleted)
+    }
+
+    async fn delete_expired_sessions(&self, now: NaiveDateTime) -> ChordDbResult<u64> {
+        let mut deleted = 0;
+        self.sessions.retain(|_, session| {
+            let keep = session.expires_at > now;
+            if !keep {
+                deleted += 1;
+            }
+            keep
+        });
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use crate::testing::test_databases;
+
+    use super::*;
+    use test_log::test;
+
+    async fn test_expired_sessions(sessions: &dyn Sessions, name: &str) {
+        let now = Utc::now().naive_utc();
+        let user_id = Uuid::new_v4();
+        for (id, expires_at) in [
+            ("expired", now - TimeDelta::minutes(1)),
+            ("active", now + TimeDelta::days(1)),
+        ] {
+            sessions
+                .upsert_session(Session {
+                    id: id.to_string(),
+                    user_id: user_id.to_string(),
+                    expires_at,
+                })
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            sessions.delete_expired_sessions(now).await.unwrap(),
+            1,
+            "{}",
+            name
+        );
+        assert!(sessions.get_session("expired").await.unwrap().is_none());
+        assert!(sessions.get_session("active").await.unwrap().is_some());
+        assert_eq!(sessions.delete_user_sessions(&user_id).await.unwrap(), 1);
+    }
+
+    #[test(tokio::test)]
+    async fn test_delete_expired_sessions() {
+        test_expired_sessions(&MemorySessions::new(), "memory").await;
+        for database in test_databases().await {
+            test_expired_sessions(&SeaOrmSessions::new(database.db.clone()), database.name).await;
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_session_cleanup() {
+        let sessions = Arc::new(MemorySessions::new());
+        sessions
+            .upsert_session(Session {
+                id: "expired".to_string(),
+                user_id: Uuid::new_v4().to_string(),
+                expires_at: Utc::now().naive_utc() - TimeDelta::minutes(1),
+            })
+            .await
+            .unwrap();
+        // The first cleanup runs right away
+        let cleanup = spawn_session_cleanup(sessions.clone(), Duration::from_secs(3600));
+        for _ in 0..100 {
+            if sessions.get_session("expired").await.unwrap().is_none() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(sessions.get_session("expired").await.unwrap().is_none());
+        cleanup.abort();
+    }
 }
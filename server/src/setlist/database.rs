@@ -0,0 +1,223 @@
+use axum::async_trait;
+use chrono::Utc;
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+    TransactionTrait,
+};
+use uuid::Uuid;
+
+use crate::{
+    entities::{
+        prelude::{Setlist as SetlistEntity, SetlistEntry as SetlistEntryEntity},
+        setlist, setlist_entry,
+    },
+    error::{ChordDbError, ChordDbResult},
+    song::OwnerKind,
+};
+
+use super::{Setlist, SetlistEntry, SetlistHeader, Setlists};
+
+pub struct SeaOrmSetlists {
+    db: DatabaseConnection,
+}
+
+impl SeaOrmSetlists {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+}
+
+fn parse_uuid(id: &str) -> ChordDbResult<Uuid> {
+    Uuid::parse_str(id)
+        .map_err(|err| ChordDbError::InvalidData(format!("Invalid uuid: '{}'. Err: {}", id, err)))
+}
+
+fn build_header(model: setlist::Model) -> ChordDbResult<SetlistHeader> {
+    Ok(SetlistHeader {
+        id: parse_uuid(&model.id)?,
+        name: model.name,
+        owner_id: parse_uuid(&model.owner)?,
+        owner_kind: OwnerKind::parse(&model.owner_kind).ok_or_else(|| {
+            ChordDbError::InvalidData(format!("Invalid owner kind: '{}'", model.owner_kind))
+        })?,
+        created_at: model.created_at,
+        updated_at: model.updated_at,
+    })
+}
+
+fn build_entry(model: setlist_entry::Model) -> ChordDbResult<SetlistEntry> {
+    Ok(SetlistEntry {
+        song_id: parse_uuid(&model.song_id)?,
+        transpose: model.transpose,
+        capo: model.capo.map(|capo| capo as u32),
+        instrument: model.instrument,
+        notes: model.notes,
+    })
+}
+
+#[async_trait]
+impl Setlists for SeaOrmSetlists {
+    async fn setlists(&self, owners: &[Uuid]) -> ChordDbResult<Vec<SetlistHeader>> {
+        SetlistEntity::find()
+            .filter(setlist::Column::Owner.is_in(owners.iter().map(|id| id.to_string())))
+            .order_by_asc(setlist::Column::Name)
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(build_header)
+            .collect()
+    }
+
+    async fn get_setlist(&self, id: &Uuid) -> ChordDbResult<Option<Setlist>> {
+        let Some(model) = SetlistEntity::find_by_id(id.to_string())
+            .one(&self.db)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let entries = SetlistEntryEntity::find()
+            .filter(setlist_entry::Column::SetlistId.eq(id.to_string()))
+            .order_by_asc(setlist_entry::Column::Position)
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(build_entry)
+            .collect::<ChordDbResult<_>>()?;
+        Ok(Some(Setlist {
+            header: build_header(model)?,
+            entries,
+        }))
+    }
+
+    async fn upsert_setlist(&self, setlist: &Setlist) -> ChordDbResult<()> {
+        let id = setlist.header.id.to_string();
+        let model = setlist::Model {
+            id: id.clone(),
+            name: setlist.header.name.clone(),
+            owner: setlist.header.owner_id.to_string(),
+            owner_kind: setlist.header.owner_kind.id().to_string(),
+            created_at: setlist.header.created_at,
+            updated_at: Utc::now().naive_utc(),
+        };
+        let txn = self.db.begin().await?;
+        SetlistEntryEntity::delete_many()
+            .filter(setlist_entry::Column::SetlistId.eq(id.clone()))
+            .exec(&txn)
+            .await?;
+        SetlistEntity::insert(setlist::ActiveModel::from(model))
+            .on_conflict(
+                OnConflict::column(setlist::Column::Id)
+                    .update_columns([
+                        setlist::Column::Name,
+                        setlist::Column::Owner,
+                        setlist::Column::OwnerKind,
+                        setlist::Column::UpdatedAt,
+                    ])
+                    .to_owned(),
+            )
+            .exec(&txn)
+            .await?;
+        if !setlist.entries.is_empty() {
+            SetlistEntryEntity::insert_many(setlist.entries.iter().enumerate().map(
+                |(position, entry)| {
+                    setlist_entry::ActiveModel::from(setlist_entry::Model {
+                        setlist_id: id.clone(),
+                        position: position as i32,
+                        song_id: entry.song_id.to_string(),
+                        transpose: entry.transpose,
+                        capo: entry.capo.map(|capo| capo as i32),
+                        instrument: entry.instrument.clone(),
+                        notes: entry.notes.clone(),
+                    })
+                },
+            ))
+            .exec(&txn)
+            .await?;
+        }
+        txn.commit().await?;
+        Ok(())
+    }
+
+    async fn delete_setlist(&self, id: &Uuid) -> ChordDbResult<bool> {
+        let result = SetlistEntity::delete_by_id(id.to_string())
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{
+        band::{Bands, SeaOrmBands},
+        song::{SeaOrmSongs, Song, Songs},
+        testing::{test_databases, user},
+        user::{SeaOrmUsers, Users},
+    };
+
+    use super::*;
+    use test_log::test;
+
+    #[test(tokio::test)]
+    async fn test_upsert_setlist() {
+        for database in test_databases().await {
+            let user = user("user");
+            SeaOrmUsers::new(database.db.clone())
+                .upsert_user(&user)
+                .await
+                .unwrap();
+            let band = SeaOrmBands::new(database.db.clone())
+                .create_band("Band", &user.id)
+                .await
+                .unwrap();
+            let songs = SeaOrmSongs::new(database.db.clone());
+            let mut song_ids = vec![];
+            for title in ["First", "Second"] {
+                let song = Song::new(
+                    Uuid::new_v4(),
+                    "Author".to_owned(),
+                    title.to_owned(),
+                    "G\nla".to_owned(),
+                    &user,
+                );
+                song_ids.push(*song.id());
+                songs.upsert_song(song, &user.id, None).await.unwrap();
+            }
+
+            let setlists = SeaOrmSetlists::new(database.db.clone());
+            let now = Utc::now().naive_utc();
+            let entry = |song_id: Uuid, capo: Option<u32>| SetlistEntry {
+                song_id,
+                transpose: None,
+                capo,
+                instrument: None,
+                notes: None,
+            };
+            let mut setlist = Setlist {
+                header: SetlistHeader {
+                    id: Uuid::new_v4(),
+                    name: "Gig".to_owned(),
+                    owner_id: band.id,
+                    owner_kind: OwnerKind::Band,
+                    created_at: now,
+                    updated_at: now,
+                },
+                entries: vec![entry(song_ids[1], Some(2)), entry(song_ids[0], None)],
+            };
+            setlists.upsert_setlist(&setlist).await.unwrap();
+            setlist.entries.pop();
+            setlists.upsert_setlist(&setlist).await.unwrap();
+
+            let stored = setlists
+                .get_setlist(&setlist.header.id)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(stored.entries, setlist.entries, "{}", database.name);
+            let headers = setlists.setlists(&[band.id]).await.unwrap();
+            assert_eq!(headers.len(), 1, "{}", database.name);
+            assert!(setlists.delete_setlist(&setlist.header.id).await.unwrap());
+        }
+    }
+}
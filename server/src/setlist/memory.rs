@@ -0,0 +1,49 @@
+use axum::async_trait;
+use chrono::Utc;
+use dashmap::DashMap;
+use uuid::Uuid;
+
+use crate::error::ChordDbResult;
+
+use super::{Setlist, SetlistHeader, Setlists};
+
+/// Setlists kept in memory, for servers running without a database
+#[derive(Default)]
+pub struct MemorySetlists {
+    setlists: DashMap<Uuid, Setlist>,
+}
+
+impl MemorySetlists {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Setlists for MemorySetlists {
+    async fn setlists(&self, owners: &[Uuid]) -> ChordDbResult<Vec<SetlistHeader>> {
+        let mut setlists: Vec<SetlistHeader> = self
+            .setlists
+            .iter()
+            .filter(|setlist| owners.contains(&setlist.header.owner_id))
+            .map(|setlist| setlist.header.clone())
+            .collect();
+        setlists.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(setlists)
+    }
+
+    async fn get_setlist(&self, id: &Uuid) -> ChordDbResult<Option<Setlist>> {
+        Ok(self.setlists.get(id).map(|setlist| setlist.value().clone()))
+    }
+
+    async fn upsert_setlist(&self, setlist: &Setlist) -> ChordDbResult<()> {
+        let mut setlist = setlist.clone();
+        setlist.header.updated_at = Utc::now().naive_utc();
+        self.setlists.insert(setlist.header.id, setlist);
+        Ok(())
+    }
+
+    async fn delete_setlist(&self, id: &Uuid) -> ChordDbResult<bool> {
+        Ok(self.setlists.remove(id).is_some())
+    }
+}
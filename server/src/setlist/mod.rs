@@ -0,0 +1,106 @@
+use axum::async_trait;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    band::BandRole,
+    error::ChordDbResult,
+    song::{OwnerKind, SongRole},
+    user::User,
+};
+
+mod database;
+mod memory;
+
+pub use database::SeaOrmSetlists;
+pub use memory::MemorySetlists;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SetlistHeader {
+    pub id: Uuid,
+    pub name: String,
+    /// Id of the user or band that owns the setlist
+    pub owner_id: Uuid,
+    pub owner_kind: OwnerKind,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl SetlistHeader {
+    /// Role of `user` on the setlist, given its role in the owner band. Setlists use the same
+    /// roles as songs.
+    pub fn role(&self, user: &User, band_role: Option<BandRole>) -> Option<SongRole> {
+        if user.is_admin || (self.owner_kind == OwnerKind::User && self.owner_id == user.id) {
+            Some(SongRole::Owner)
+        } else if self.owner_kind == OwnerKind::Band {
+            band_role.map(|role| role.song_role())
+        } else {
+            None
+        }
+    }
+}
+
+/// A song in a setlist and how to play it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetlistEntry {
+    pub song_id: Uuid,
+    /// Semitones to transpose the song
+    pub transpose: Option<i32>,
+    /// Capo position to play the song with, instead of the written one
+    pub capo: Option<u32>,
+    pub instrument: Option<String>,
+    /// Notes for the performers, like "start with intro x2"
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Setlist {
+    #[serde(flatten)]
+    pub header: SetlistHeader,
+    pub entries: Vec<SetlistEntry>,
+}
+
+#[async_trait]
+pub trait Setlists: Send + Sync {
+    /// Setlists owned by any of the given users or bands, by name
+    async fn setlists(&self, owners: &[Uuid]) -> ChordDbResult<Vec<SetlistHeader>>;
+    async fn get_setlist(&self, id: &Uuid) -> ChordDbResult<Option<Setlist>>;
+    /// Stores the setlist, replacing all of its entries
+    async fn upsert_setlist(&self, setlist: &Setlist) -> ChordDbResult<()>;
+    async fn delete_setlist(&self, id: &Uuid) -> ChordDbResult<bool>;
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use crate::testing::user;
+
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_setlist_role() {
+        let user = user("user");
+        let mut header = SetlistHeader {
+            id: Uuid::new_v4(),
+            name: "Gig".to_string(),
+            owner_id: user.id,
+            owner_kind: OwnerKind::User,
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+        };
+        assert_eq!(header.role(&user, None), Some(SongRole::Owner));
+
+        header.owner_id = Uuid::new_v4();
+        assert_eq!(header.role(&user, Some(BandRole::Leader)), None);
+
+        header.owner_kind = OwnerKind::Band;
+        assert_eq!(header.role(&user, None), None);
+        assert_eq!(
+            header.role(&user, Some(BandRole::Member)),
+            Some(SongRole::Editor)
+        );
+    }
+}
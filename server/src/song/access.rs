@@ -0,0 +1,163 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::user::User;
+
+use super::SongHeader;
+
+/// What a user can do with a song. Each role can do everything the previous ones can
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SongRole {
+    /// Read the song
+    Viewer,
+    /// Change the song
+    Editor,
+    /// Delete the song and manage who can access it
+    Owner,
+}
+
+impl SongRole {
+    pub fn parse(source: &str) -> Option<SongRole> {
+        match source {
+            "viewer" => Some(SongRole::Viewer),
+            "editor" => Some(SongRole::Editor),
+            "owner" => Some(SongRole::Owner),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            SongRole::Viewer => "viewer",
+            SongRole::Editor => "editor",
+            SongRole::Owner => "owner",
+        }
+    }
+
+    /// Role of `user` on a song given the best role it was granted, directly or through its
+    /// bands, if any
+    pub fn of(user: &User, song: &SongHeader, granted: Option<SongRole>) -> Option<SongRole> {
+        if user.is_admin || (song.owner_kind == OwnerKind::User && user.id == song.owner_id) {
+            Some(SongRole::Owner)
+        } else {
+            granted
+        }
+    }
+}
+
+/// What the owner of a song is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OwnerKind {
+    #[default]
+    User,
+    Band,
+}
+
+impl OwnerKind {
+    pub fn parse(source: &str) -> Option<OwnerKind> {
+        match source {
+            "user" => Some(OwnerKind::User),
+            "band" => Some(OwnerKind::Band),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            OwnerKind::User => "user",
+            OwnerKind::Band => "band",
+        }
+    }
+}
+
+/// Access to a song given to a user other than the owner
+#[derive(Debug, Clone, Serialize)]
+pub struct SongGrant {
+    pub song_id: Uuid,
+    pub user_id: Uuid,
+    pub role: SongRole,
+}
+
+/// Access to a song given to every member of a band
+#[derive(Debug, Clone, Serialize)]
+pub struct BandGrant {
+    pub song_id: Uuid,
+    pub band_id: Uuid,
+    pub role: SongRole,
+}
+
+/// Read-only access to a song for anyone that knows the token
+#[derive(Debug, Clone, Serialize)]
+pub struct ShareLink {
+    pub token: String,
+    pub song_id: Uuid,
+    pub created_by: Uuid,
+    pub created_at: NaiveDateTime,
+}
+
+/// Unguessable token for share links, with 244 random bits
+pub fn new_share_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{song::Song, testing::user};
+
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_roles() {
+        assert!(SongRole::Viewer < SongRole::Editor);
+        assert!(SongRole::Editor < SongRole::Owner);
+        for role in [SongRole::Viewer, SongRole::Editor, SongRole::Owner] {
+            assert_eq!(SongRole::parse(role.id()), Some(role));
+        }
+        assert_eq!(SongRole::parse("admin"), None);
+    }
+
+    #[test]
+    fn test_role_of() {
+        let user = user("user");
+        let mut song = Song::new(
+            Uuid::new_v4(),
+            "Author".to_string(),
+            "Title".to_string(),
+            String::new(),
+            &user,
+        );
+        assert_eq!(
+            SongRole::of(&user, song.header(), None),
+            Some(SongRole::Owner)
+        );
+
+        // A band with the same id as the user does not make it the owner
+        song.header.owner_kind = OwnerKind::Band;
+        assert_eq!(SongRole::of(&user, song.header(), None), None);
+        assert_eq!(
+            SongRole::of(&user, song.header(), Some(SongRole::Editor)),
+            Some(SongRole::Editor)
+        );
+
+        let admin = User {
+            is_admin: true,
+            ..user
+        };
+        assert_eq!(
+            SongRole::of(&admin, song.header(), None),
+            Some(SongRole::Owner)
+        );
+    }
+
+    #[test]
+    fn test_new_share_token() {
+        let token = new_share_token();
+        assert_eq!(token.len(), 64);
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(token, new_share_token());
+    }
+}
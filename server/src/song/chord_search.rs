@@ -0,0 +1,142 @@
+use std::collections::HashSet;
+
+use itertools::Itertools;
+
+use crate::{
+    chord::{Chord, NoteLocale},
+    parser::{parse_tablature_in, Comp},
+};
+
+/// Difficulty assigned to chords without a known fingering
+pub const UNPLAYABLE_DIFFICULTY: usize = 100;
+
+/// Chords of a song in order of appearance, without consecutive repetitions
+pub fn chord_sequence(contents: &str, locale: NoteLocale) -> Vec<Chord> {
+    parse_tablature_in(contents, locale)
+        .into_iter()
+        .flatten()
+        .filter_map(|bit| match bit.comp {
+            Comp::Chord { chord, .. } => Some(chord),
+            _ => None,
+        })
+        .dedup()
+        .collect()
+}
+
+pub fn contains_progression(sequence: &[Chord], progression: &[Chord]) -> bool {
+    progression.is_empty()
+        || sequence
+            .windows(progression.len())
+            .any(|window| window == progression)
+}
+
+/// How a song matches a chord search
+#[derive(Debug, PartialEq, Eq)]
+pub struct ChordMatch {
+    /// Semitones the song has to be transposed
+    pub transposition: i32,
+    /// Chords of the song, once transposed
+    pub chords: Vec<Chord>,
+    pub difficulty: usize,
+}
+
+/// Finds the easiest transposition, among `transpositions`, of a song with the chords in
+/// `sequence` that only uses chords in `allowed` (if given) and contains `progression`
+pub fn best_match<F>(
+    sequence: &[Chord],
+    allowed: Option<&HashSet<Chord>>,
+    progression: &[Chord],
+    transpositions: &[i32],
+    difficulty: F,
+) -> Option<ChordMatch>
+where
+    F: Fn(&Chord) -> usize,
+{
+    let chords: Vec<Chord> = sequence.iter().copied().unique().collect();
+    if chords.is_empty() {
+        return None;
+    }
+    transpositions
+        .iter()
+        .filter_map(|transposition| {
+            let transposed: Vec<Chord> = chords
+                .iter()
+                .map(|chord| chord.transpose(*transposition))
+                .collect();
+            if let Some(allowed) = allowed {
+                if !transposed.iter().all(|chord| allowed.contains(chord)) {
+                    return None;
+                }
+            }
+            let shifted_progression: Vec<Chord> = progression
+                .iter()
+                .map(|chord| chord.transpose(-transposition))
+                .collect();
+            if !contains_progression(sequence, &shifted_progression) {
+                return None;
+            }
+            Some(ChordMatch {
+                transposition: *transposition,
+                difficulty: transposed.iter().map(&difficulty).sum(),
+                chords: transposed,
+            })
+        })
+        .min_by_key(|m| m.difficulty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    fn chords(texts: &[&str]) -> Vec<Chord> {
+        texts.iter().map(|t| Chord::parse(t).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_chord_sequence() {
+        assert_eq!(
+            chord_sequence("G  G\nlyrics\nC D\nD G", NoteLocale::English),
+            chords(&["G", "C", "D", "G"])
+        );
+    }
+
+    #[test]
+    fn test_best_match() {
+        let sequence = chords(&["A", "D", "E", "A"]);
+        let allowed: HashSet<Chord> = chords(&["G", "C", "D", "Em"]).into_iter().collect();
+        let difficulty = |_: &Chord| 1;
+
+        assert_eq!(
+            best_match(&sequence, Some(&allowed), &[], &[0], difficulty),
+            None
+        );
+        assert_eq!(
+            best_match(
+                &sequence,
+                Some(&allowed),
+                &[],
+                &(0..12).collect_vec(),
+                difficulty
+            ),
+            Some(ChordMatch {
+                transposition: -2 + 12,
+                chords: chords(&["G", "C", "D"]),
+                difficulty: 3,
+            })
+        );
+        assert!(best_match(&sequence, None, &chords(&["D", "E"]), &[0], difficulty).is_some());
+        assert!(best_match(&sequence, None, &chords(&["E", "D"]), &[0], difficulty).is_none());
+        assert_eq!(
+            best_match(
+                &sequence,
+                None,
+                &chords(&["C", "D"]),
+                &(0..12).collect_vec(),
+                difficulty
+            )
+            .map(|m| m.transposition),
+            Some(10)
+        );
+    }
+}
@@ -1,12 +1,75 @@
-use sea_orm::{sea_query::OnConflict, DatabaseConnection, EntityTrait, Iterable};
+use std::collections::{HashMap, HashSet};
+
+use axum::async_trait;
+use chrono::Utc;
+use sea_orm::{
+    sea_query::{Expr, Func, LikeExpr, OnConflict, Query, SelectStatement},
+    ActiveModelTrait,
+    ActiveValue::{NotSet, Set},
+    ColumnTrait, Condition, ConnectionTrait, DatabaseBackend, DatabaseConnection, EntityTrait,
+    FromQueryResult, Order, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, SqlErr,
+    Statement, TransactionTrait, Value,
+};
 use uuid::Uuid;
 
 use crate::{
-    entities::{prelude::Song as SongEntity, song},
+    band::BandRole,
+    chord::{Chord, NoteLocale},
+    entities::{
+        band_member,
+        prelude::{
+            BandMember as BandMemberEntity, Song as SongEntity,
+            SongBandGrant as SongBandGrantEntity, SongChord as SongChordEntity,
+            SongGrant as SongGrantEntity, SongRevision as SongRevisionEntity,
+            SongShareLink as SongShareLinkEntity, SongTag as SongTagEntity,
+        },
+        song, song_band_grant, song_chord, song_grant, song_revision, song_share_link, song_tag,
+    },
     error::{ChordDbError, ChordDbResult},
+    parser::SongMetadata,
+    user::User,
 };
 
-use super::{Song, SongHeader};
+use super::{
+    chord_search::chord_sequence,
+    new_share_token, normalize_tags,
+    search::{highlight, match_query, ts_query, RAW_SNIPPET_END, RAW_SNIPPET_START},
+    BandGrant, OwnerKind, RevisionHeader, ShareLink, Song, SongGrant, SongHeader, SongListQuery,
+    SongPage, SongRevision, SongRole, SongSearchResult, SongSort, Songs, SortKey, SortOrder,
+};
+
+fn parse_uuids(ids: Vec<String>) -> ChordDbResult<Vec<Uuid>> {
+    ids.iter()
+        .map(|id| {
+            Uuid::parse_str(id).map_err(|err| {
+                ChordDbError::InvalidData(format!("Invalid uuid: '{}'. Err: {}", id, err))
+            })
+        })
+        .collect()
+}
+
+/// Replaces the indexed chords of a song
+async fn index_chords<C: ConnectionTrait>(
+    db: &C,
+    id: &Uuid,
+    chords: HashSet<Chord>,
+) -> ChordDbResult<()> {
+    SongChordEntity::delete_many()
+        .filter(song_chord::Column::SongId.eq(id.to_string()))
+        .exec(db)
+        .await?;
+    if !chords.is_empty() {
+        SongChordEntity::insert_many(chords.iter().map(|chord| {
+            song_chord::ActiveModel::from(song_chord::Model {
+                song_id: id.to_string(),
+                chord: chord.text(),
+            })
+        }))
+        .exec(db)
+        .await?;
+    }
+    Ok(())
+}
 
 pub struct SeaOrmSongs {
     db: DatabaseConnection,
@@ -25,33 +88,616 @@ fn build_header(model: &song::Model) -> ChordDbResult<SongHeader> {
                 owner_id: Uuid::parse_str(&model.owner).map_err(|err| {
                     ChordDbError::InvalidData(format!("Invalid uuid: '{}'. Err: {}", model.id, err))
                 })?,
+                owner_kind: OwnerKind::parse(&model.owner_kind).ok_or_else(|| {
+                    ChordDbError::InvalidData(format!("Invalid owner kind: '{}'", model.owner_kind))
+                })?,
+                note_locale: NoteLocale::parse(&model.note_locale).ok_or_else(|| {
+                    ChordDbError::InvalidData(format!(
+                        "Invalid note locale: '{}'",
+                        model.note_locale
+                    ))
+                })?,
+                metadata: SongMetadata {
+                    key: model.key.clone(),
+                    capo: model.capo.map(|capo| capo as u32),
+                    tempo: model.tempo.map(|tempo| tempo as u32),
+                    time_signature: model.time_signature.clone(),
+                    tuning: model.tuning.clone(),
+                    instrument: model.instrument.clone(),
+                },
+                tags: vec![],
+                updated_at: model.updated_at,
+                version: model.version,
             })
         })?
 }
 
+/// Bands a user is a member of
+fn member_bands(user_id: &Uuid) -> SelectStatement {
+    Query::select()
+        .column(band_member::Column::BandId)
+        .from(BandMemberEntity)
+        .and_where(band_member::Column::UserId.eq(user_id.to_string()))
+        .to_owned()
+}
+
+/// Songs owned by or shared with a user, directly or through its bands
+fn accessible_by(user_id: &Uuid) -> Condition {
+    Condition::any()
+        .add(song::Column::Owner.eq(user_id.to_string()))
+        .add(song::Column::Owner.in_subquery(member_bands(user_id)))
+        .add(
+            song::Column::Id.in_subquery(
+                Query::select()
+                    .column(song_grant::Column::SongId)
+                    .from(SongGrantEntity)
+                    .and_where(song_grant::Column::UserId.eq(user_id.to_string()))
+                    .to_owned(),
+            ),
+        )
+        .add(
+            song::Column::Id.in_subquery(
+                Query::select()
+                    .column(song_band_grant::Column::SongId)
+                    .from(SongBandGrantEntity)
+                    .and_where(song_band_grant::Column::BandId.in_subquery(member_bands(user_id)))
+                    .to_owned(),
+            ),
+        )
+}
+
+/// SQL version of `accessible_by` for the search queries, where `user_id` is the placeholder of
+/// the user id, or of NULL to match every song
+fn search_access(user_id: &str) -> String {
+    format!(
+        r#"(
+            {user_id} IS NULL
+            OR song.owner = {user_id}
+            OR song.owner IN (SELECT band_id FROM band_member WHERE user_id = {user_id})
+            OR song.id IN (SELECT song_id FROM song_grant WHERE user_id = {user_id})
+            OR song.id IN (
+                SELECT song_id FROM song_band_grant
+                WHERE band_id IN (SELECT band_id FROM band_member WHERE user_id = {user_id})
+            )
+        )"#
+    )
+}
+
+fn parse_song_role(role: &str) -> ChordDbResult<SongRole> {
+    SongRole::parse(role)
+        .ok_or_else(|| ChordDbError::InvalidData(format!("Invalid song role: '{}'", role)))
+}
+
+fn build_share_link(model: song_share_link::Model) -> ChordDbResult<ShareLink> {
+    let ids = parse_uuids(vec![model.song_id, model.created_by])?;
+    Ok(ShareLink {
+        token: model.token,
+        song_id: ids[0],
+        created_by: ids[1],
+        created_at: model.created_at,
+    })
+}
+
+fn build_revision(model: song_revision::Model) -> ChordDbResult<SongRevision> {
+    let parse = |id: &str| {
+        Uuid::parse_str(id).map_err(|err| {
+            ChordDbError::InvalidData(format!("Invalid uuid: '{}'. Err: {}", id, err))
+        })
+    };
+    Ok(SongRevision {
+        header: RevisionHeader {
+            id: model.id,
+            song_id: parse(&model.song_id)?,
+            editor_id: parse(&model.editor_id)?,
+            created_at: model.created_at,
+            note: model.note,
+            author: model.author,
+            title: model.title,
+        },
+        contents: model.tablature,
+    })
+}
+
 impl SeaOrmSongs {
     pub fn new(db: DatabaseConnection) -> Self {
         Self { db }
     }
 
-    pub async fn all_songs(&self) -> ChordDbResult<Vec<SongHeader>> {
+    /// Songs matching `condition` the user can see, or all of them if `user_id` is `None`, by
+    /// title
+    async fn find_songs(
+        &self,
+        condition: Condition,
+        user_id: Option<&Uuid>,
+    ) -> ChordDbResult<Vec<Song>> {
+        let mut select = SongEntity::find().filter(condition);
+        if let Some(user_id) = user_id {
+            select = select.filter(accessible_by(user_id));
+        }
+        let models = select
+            .order_by_asc(song::Column::Title)
+            .order_by_asc(song::Column::Id)
+            .all(&self.db)
+            .await?;
+        let headers = models
+            .iter()
+            .map(build_header)
+            .collect::<ChordDbResult<_>>()?;
+        let headers = self.with_tags(headers).await?;
+        Ok(headers
+            .into_iter()
+            .zip(models)
+            .map(|(header, model)| Song {
+                header,
+                contents: model.tablature,
+            })
+            .collect())
+    }
+
+    /// Fills in the tags of the given songs
+    async fn with_tags(&self, mut headers: Vec<SongHeader>) -> ChordDbResult<Vec<SongHeader>> {
+        let ids = headers.iter().map(|header| header.id.to_string());
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+        for model in SongTagEntity::find()
+            .filter(song_tag::Column::SongId.is_in(ids))
+            .order_by_asc(song_tag::Column::Tag)
+            .all(&self.db)
+            .await?
+        {
+            tags.entry(model.song_id).or_default().push(model.tag);
+        }
+        for header in headers.iter_mut() {
+            header.tags = tags.remove(&header.id.to_string()).unwrap_or_default();
+        }
+        Ok(headers)
+    }
+}
+
+/// A LIKE pattern for the text anywhere, with its wildcards escaped
+fn contains(text: &str) -> LikeExpr {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    LikeExpr::new(format!("%{}%", escaped)).escape('\\')
+}
+
+#[async_trait]
+impl Songs for SeaOrmSongs {
+    async fn all_songs(&self) -> ChordDbResult<Vec<SongHeader>> {
         let entities = SongEntity::find().all(&self.db).await?;
-        entities.iter().map(build_header).collect()
+        let headers = entities
+            .iter()
+            .map(build_header)
+            .collect::<ChordDbResult<_>>()?;
+        self.with_tags(headers).await
     }
 
-    pub async fn upsert_song(&self, song: Song) -> ChordDbResult<()> {
+    async fn list_songs(&self, query: &SongListQuery) -> ChordDbResult<SongPage> {
+        let filter = &query.filter;
+        let mut select = SongEntity::find();
+        if let Some(author) = &filter.author {
+            select = select.filter(
+                Expr::expr(Func::lower(Expr::col(song::Column::Author)))
+                    .like(contains(&author.to_lowercase())),
+            );
+        }
+        if let Some(owner) = &filter.owner {
+            select = select.filter(song::Column::Owner.eq(owner.to_string()));
+        }
+        if let Some(tag) = &filter.tag {
+            select = select.filter(
+                song::Column::Id.in_subquery(
+                    Query::select()
+                        .column(song_tag::Column::SongId)
+                        .from(SongTagEntity)
+                        .and_where(song_tag::Column::Tag.eq(tag))
+                        .to_owned(),
+                ),
+            );
+        }
+        if let Some(key) = &filter.key {
+            select = select.filter(song::Column::Key.eq(key));
+        }
+        if let Some(user_id) = &filter.accessible_by {
+            select = select.filter(accessible_by(user_id));
+        }
+        let total = select.clone().count(&self.db).await?;
+
+        let column = match query.sort {
+            SongSort::Title => song::Column::Title,
+            SongSort::Author => song::Column::Author,
+            SongSort::Updated => song::Column::UpdatedAt,
+        };
+        if let Some(cursor) = query.cursor()? {
+            let value = match &cursor.key {
+                SortKey::Text(text) => Value::from(text.clone()),
+                SortKey::Time(time) => Value::from(*time),
+            };
+            let id = cursor.id.to_string();
+            // Keyset pagination, with the id breaking ties
+            select = select.filter(match query.order {
+                SortOrder::Asc => Condition::any().add(column.gt(value.clone())).add(
+                    Condition::all()
+                        .add(column.eq(value))
+                        .add(song::Column::Id.gt(id)),
+                ),
+                SortOrder::Desc => Condition::any().add(column.lt(value.clone())).add(
+                    Condition::all()
+                        .add(column.eq(value))
+                        .add(song::Column::Id.lt(id)),
+                ),
+            });
+        }
+        let order = match query.order {
+            SortOrder::Asc => Order::Asc,
+            SortOrder::Desc => Order::Desc,
+        };
+        let headers = select
+            .order_by(column, order.clone())
+            .order_by(song::Column::Id, order)
+            .limit(query.limit + 1)
+            .all(&self.db)
+            .await?
+            .iter()
+            .map(build_header)
+            .collect::<ChordDbResult<_>>()?;
+        let songs = self.with_tags(headers).await?;
+        Ok(SongPage::new(songs, total, query))
+    }
+
+    async fn upsert_song(
+        &self,
+        song: Song,
+        editor: &Uuid,
+        note: Option<String>,
+    ) -> ChordDbResult<i32> {
+        let chords = chord_sequence(song.contents(), song.note_locale())
+            .into_iter()
+            .collect();
+        let id = *song.id();
+        let tags = normalize_tags(&song.header.tags);
         let model = song::Model {
             id: song.id().to_string(),
             author: song.author().to_string(),
             title: song.title().to_string(),
             owner: song.owner_id().to_string(),
+            owner_kind: song.header.owner_kind.id().to_string(),
+            note_locale: song.note_locale().id().to_string(),
+            key: song.metadata().key.clone(),
+            capo: song.metadata().capo.map(|capo| capo as i32),
+            tempo: song.metadata().tempo.map(|tempo| tempo as i32),
+            time_signature: song.metadata().time_signature.clone(),
+            tuning: song.metadata().tuning.clone(),
+            instrument: song.metadata().instrument.clone(),
+            updated_at: Utc::now().naive_utc(),
+            version: song.header.version + 1,
             tablature: song.contents,
         };
+        let revision = song_revision::ActiveModel {
+            id: NotSet,
+            song_id: Set(model.id.clone()),
+            editor_id: Set(editor.to_string()),
+            created_at: Set(model.updated_at),
+            note: Set(note),
+            author: Set(model.author.clone()),
+            title: Set(model.title.clone()),
+            tablature: Set(model.tablature.clone()),
+        };
+
+        let txn = self.db.begin().await?;
+        let version = model.version;
+        // The version is checked by the write itself, so concurrent saves of the same version
+        // can't both succeed
+        if version == 1 {
+            let inserted = SongEntity::insert(song::ActiveModel::from(model))
+                .exec_without_returning(&txn)
+                .await;
+            match inserted {
+                Err(err) if matches!(err.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) => {
+                    return Err(ChordDbError::Conflict(serde_json::Value::Null));
+                }
+                inserted => inserted?,
+            };
+        } else {
+            let updated = SongEntity::update_many()
+                .set(song::ActiveModel::from(model).reset_all())
+                .filter(song::Column::Id.eq(id.to_string()))
+                .filter(song::Column::Version.eq(version - 1))
+                .exec(&txn)
+                .await?;
+            if updated.rows_affected == 0 {
+                return Err(ChordDbError::Conflict(serde_json::Value::Null));
+            }
+        }
+        index_chords(&txn, &id, chords).await?;
+        SongTagEntity::delete_many()
+            .filter(song_tag::Column::SongId.eq(id.to_string()))
+            .exec(&txn)
+            .await?;
+        if !tags.is_empty() {
+            SongTagEntity::insert_many(tags.into_iter().map(|tag| {
+                song_tag::ActiveModel::from(song_tag::Model {
+                    song_id: id.to_string(),
+                    tag,
+                })
+            }))
+            .exec(&txn)
+            .await?;
+        }
+        SongRevisionEntity::insert(revision).exec(&txn).await?;
+        txn.commit().await?;
+        Ok(version)
+    }
+
+    async fn revisions(&self, song_id: &Uuid) -> ChordDbResult<Vec<RevisionHeader>> {
+        SongRevisionEntity::find()
+            .filter(song_revision::Column::SongId.eq(song_id.to_string()))
+            .order_by_desc(song_revision::Column::Id)
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|model| build_revision(model).map(|revision| revision.header))
+            .collect()
+    }
+
+    async fn get_revision(
+        &self,
+        song_id: &Uuid,
+        revision_id: i32,
+    ) -> ChordDbResult<Option<SongRevision>> {
+        SongRevisionEntity::find_by_id(revision_id)
+            .filter(song_revision::Column::SongId.eq(song_id.to_string()))
+            .one(&self.db)
+            .await?
+            .map(build_revision)
+            .transpose()
+    }
+
+    async fn index_missing_chords(&self) -> ChordDbResult<()> {
+        let models = SongEntity::find()
+            .filter(
+                song::Column::Id.not_in_subquery(
+                    Query::select()
+                        .column(song_chord::Column::SongId)
+                        .from(SongChordEntity)
+                        .to_owned(),
+                ),
+            )
+            .all(&self.db)
+            .await?;
+        for model in models {
+            let header = build_header(&model)?;
+            let chords = chord_sequence(&model.tablature, header.note_locale)
+                .into_iter()
+                .collect();
+            index_chords(&self.db, &header.id, chords).await?;
+        }
+        Ok(())
+    }
+
+    async fn songs_with_chords_within(&self, chords: &[Chord]) -> ChordDbResult<Vec<Uuid>> {
+        let texts = chords.iter().map(Chord::text);
+        let ids = SongChordEntity::find()
+            .select_only()
+            .column(song_chord::Column::SongId)
+            .group_by(song_chord::Column::SongId)
+            .having(
+                Expr::expr(Func::sum(
+                    Expr::case(Expr::col(song_chord::Column::Chord).is_in(texts), 0).finally(1),
+                ))
+                .eq(0),
+            )
+            .into_tuple()
+            .all(&self.db)
+            .await?;
+        parse_uuids(ids)
+    }
+
+    async fn songs_with_all_chords(&self, chords: &[Chord]) -> ChordDbResult<Vec<Uuid>> {
+        let texts: HashSet<String> = chords.iter().map(Chord::text).collect();
+        let count = texts.len() as i32;
+        let ids = SongChordEntity::find()
+            .select_only()
+            .column(song_chord::Column::SongId)
+            .filter(song_chord::Column::Chord.is_in(texts))
+            .group_by(song_chord::Column::SongId)
+            .having(Expr::col(song_chord::Column::Chord).count().eq(count))
+            .into_tuple()
+            .all(&self.db)
+            .await?;
+        parse_uuids(ids)
+    }
+
+    async fn get_song(&self, id: &Uuid) -> ChordDbResult<Option<Song>> {
+        let Some(model) = SongEntity::find_by_id(*id).one(&self.db).await? else {
+            return Ok(None);
+        };
+        let header = build_header(&model)?;
+        let mut headers = self.with_tags(vec![header]).await?;
+        Ok(headers.pop().map(|header| Song {
+            header,
+            contents: model.tablature,
+        }))
+    }
+
+    async fn accessible_songs(&self, user_id: Option<&Uuid>) -> ChordDbResult<Vec<Song>> {
+        self.find_songs(Condition::all(), user_id).await
+    }
+
+    async fn get_songs(
+        &self,
+        ids: &[Uuid],
+        accessible_by: Option<&Uuid>,
+    ) -> ChordDbResult<Vec<Song>> {
+        if ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let ids = Condition::all().add(song::Column::Id.is_in(ids.iter().map(Uuid::to_string)));
+        self.find_songs(ids, accessible_by).await
+    }
+
+    async fn search_songs(
+        &self,
+        query: &str,
+        accessible_by: Option<&Uuid>,
+        limit: u64,
+    ) -> ChordDbResult<Vec<SongSearchResult>> {
+        let backend = self.db.get_database_backend();
+        let accessible_by = accessible_by.map(|user_id| user_id.to_string());
+        let statement = if backend == DatabaseBackend::Postgres {
+            let Some(ts_query) = ts_query(query) else {
+                return Ok(vec![]);
+            };
+            let options = format!(
+                "StartSel={}, StopSel={}, MaxWords=16, MinWords=4",
+                RAW_SNIPPET_START, RAW_SNIPPET_END
+            );
+            Statement::from_sql_and_values(
+                backend,
+                format!(
+                    r#"
+                    SELECT song.*,
+                        ts_headline(
+                            'simple_unaccent',
+                            song.title || E'\n' || song.author || E'\n' || song.tablature,
+                            query,
+                            $1
+                        ) AS snippet,
+                        -ts_rank('{{0.1, 0.0, 0.5, 1.0}}', song_search.document, query)::FLOAT8
+                            AS rank
+                    FROM song_search
+                    JOIN song ON song.id = song_search.song_id,
+                        to_tsquery('simple', unaccent($2)) AS query
+                    WHERE song_search.document @@ query AND {}
+                    ORDER BY rank
+                    LIMIT $4
+                    "#,
+                    search_access("$3::TEXT")
+                ),
+                [
+                    options.into(),
+                    ts_query.into(),
+                    accessible_by.into(),
+                    (limit as i64).into(),
+                ],
+            )
+        } else {
+            let Some(match_query) = match_query(query) else {
+                return Ok(vec![]);
+            };
+            Statement::from_sql_and_values(
+                backend,
+                format!(
+                    r#"
+                    SELECT song.*,
+                        snippet(song_search, -1, ?1, ?2, '…', 16) AS snippet,
+                        bm25(song_search, 10.0, 5.0, 1.0) AS rank
+                    FROM song_search
+                    JOIN song ON song.rowid = song_search.rowid
+                    WHERE song_search MATCH ?3 AND {}
+                    ORDER BY rank
+                    LIMIT ?5
+                    "#,
+                    search_access("?4")
+                ),
+                [
+                    RAW_SNIPPET_START.into(),
+                    RAW_SNIPPET_END.into(),
+                    match_query.into(),
+                    accessible_by.into(),
+                    (limit as i64).into(),
+                ],
+            )
+        };
+        let results: Vec<SongSearchResult> = self
+            .db
+            .query_all(statement)
+            .await?
+            .iter()
+            .map(|row| {
+                let model = song::Model::from_query_result(row, "")?;
+                Ok(SongSearchResult {
+                    header: build_header(&model)?,
+                    snippet: highlight(&row.try_get::<String>("", "snippet")?),
+                    rank: row.try_get("", "rank")?,
+                })
+            })
+            .collect::<ChordDbResult<_>>()?;
+        let headers = self
+            .with_tags(results.iter().map(|r| r.header.clone()).collect())
+            .await?;
+        Ok(results
+            .into_iter()
+            .zip(headers)
+            .map(|(result, header)| SongSearchResult { header, ..result })
+            .collect())
+    }
 
-        SongEntity::insert(song::ActiveModel::from(model))
+    async fn song_role(&self, user: &User, song: &SongHeader) -> ChordDbResult<Option<SongRole>> {
+        let mut granted = vec![];
+        if let Some(grant) = SongGrantEntity::find_by_id((song.id.to_string(), user.id.to_string()))
+            .one(&self.db)
+            .await?
+        {
+            granted.push(parse_song_role(&grant.role)?);
+        }
+
+        let bands: HashMap<String, String> = BandMemberEntity::find()
+            .filter(band_member::Column::UserId.eq(user.id.to_string()))
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|member| (member.band_id, member.role))
+            .collect();
+        if song.owner_kind == OwnerKind::Band {
+            if let Some(role) = bands.get(&song.owner_id.to_string()) {
+                let role = BandRole::parse(role).ok_or_else(|| {
+                    ChordDbError::InvalidData(format!("Invalid band role: '{}'", role))
+                })?;
+                granted.push(role.song_role());
+            }
+        }
+        if !bands.is_empty() {
+            for grant in SongBandGrantEntity::find()
+                .filter(song_band_grant::Column::SongId.eq(song.id.to_string()))
+                .filter(song_band_grant::Column::BandId.is_in(bands.keys()))
+                .all(&self.db)
+                .await?
+            {
+                granted.push(parse_song_role(&grant.role)?);
+            }
+        }
+
+        Ok(SongRole::of(user, song, granted.into_iter().max()))
+    }
+
+    async fn grants(&self, song_id: &Uuid) -> ChordDbResult<Vec<SongGrant>> {
+        SongGrantEntity::find()
+            .filter(song_grant::Column::SongId.eq(song_id.to_string()))
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|model| {
+                Ok(SongGrant {
+                    song_id: *song_id,
+                    user_id: parse_uuids(vec![model.user_id])?[0],
+                    role: parse_song_role(&model.role)?,
+                })
+            })
+            .collect()
+    }
+
+    async fn upsert_grant(&self, grant: &SongGrant) -> ChordDbResult<()> {
+        let model = song_grant::Model {
+            song_id: grant.song_id.to_string(),
+            user_id: grant.user_id.to_string(),
+            role: grant.role.id().to_string(),
+        };
+        SongGrantEntity::insert(song_grant::ActiveModel::from(model))
             .on_conflict(
-                OnConflict::column(song::Column::Id)
-                    .update_columns(song::Column::iter())
+                OnConflict::columns([song_grant::Column::SongId, song_grant::Column::UserId])
+                    .update_column(song_grant::Column::Role)
                     .to_owned(),
             )
             .exec(&self.db)
@@ -59,19 +705,456 @@ impl SeaOrmSongs {
         Ok(())
     }
 
-    pub async fn get_song(&self, id: &Uuid) -> ChordDbResult<Option<Song>> {
-        let song = SongEntity::find_by_id(*id).one(&self.db).await?;
-        Ok(match song {
-            Some(model) => Some(Song {
-                header: build_header(&model)?,
-                contents: model.tablature,
-            }),
-            None => None,
-        })
+    async fn delete_grant(&self, song_id: &Uuid, user_id: &Uuid) -> ChordDbResult<()> {
+        SongGrantEntity::delete_by_id((song_id.to_string(), user_id.to_string()))
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    async fn band_grants(&self, song_id: &Uuid) -> ChordDbResult<Vec<BandGrant>> {
+        SongBandGrantEntity::find()
+            .filter(song_band_grant::Column::SongId.eq(song_id.to_string()))
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|model| {
+                Ok(BandGrant {
+                    song_id: *song_id,
+                    band_id: parse_uuids(vec![model.band_id])?[0],
+                    role: parse_song_role(&model.role)?,
+                })
+            })
+            .collect()
+    }
+
+    async fn upsert_band_grant(&self, grant: &BandGrant) -> ChordDbResult<()> {
+        let model = song_band_grant::Model {
+            song_id: grant.song_id.to_string(),
+            band_id: grant.band_id.to_string(),
+            role: grant.role.id().to_string(),
+        };
+        SongBandGrantEntity::insert(song_band_grant::ActiveModel::from(model))
+            .on_conflict(
+                OnConflict::columns([
+                    song_band_grant::Column::SongId,
+                    song_band_grant::Column::BandId,
+                ])
+                .update_column(song_band_grant::Column::Role)
+                .to_owned(),
+            )
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_band_grant(&self, song_id: &Uuid, band_id: &Uuid) -> ChordDbResult<()> {
+        SongBandGrantEntity::delete_by_id((song_id.to_string(), band_id.to_string()))
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    async fn count_owned_songs(&self, owner_id: &Uuid) -> ChordDbResult<u64> {
+        Ok(SongEntity::find()
+            .filter(song::Column::Owner.eq(owner_id.to_string()))
+            .count(&self.db)
+            .await?)
+    }
+
+    async fn share_links(&self, song_id: &Uuid) -> ChordDbResult<Vec<ShareLink>> {
+        SongShareLinkEntity::find()
+            .filter(song_share_link::Column::SongId.eq(song_id.to_string()))
+            .order_by_asc(song_share_link::Column::CreatedAt)
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(build_share_link)
+            .collect()
+    }
+
+    async fn create_share_link(
+        &self,
+        song_id: &Uuid,
+        created_by: &Uuid,
+    ) -> ChordDbResult<ShareLink> {
+        let model = song_share_link::Model {
+            token: new_share_token(),
+            song_id: song_id.to_string(),
+            created_by: created_by.to_string(),
+            created_at: Utc::now().naive_utc(),
+        };
+        SongShareLinkEntity::insert(song_share_link::ActiveModel::from(model.clone()))
+            .exec(&self.db)
+            .await?;
+        build_share_link(model)
+    }
+
+    async fn delete_share_link(&self, song_id: &Uuid, token: &str) -> ChordDbResult<bool> {
+        let result = SongShareLinkEntity::delete_many()
+            .filter(song_share_link::Column::SongId.eq(song_id.to_string()))
+            .filter(song_share_link::Column::Token.eq(token))
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    async fn get_shared_song(&self, token: &str) -> ChordDbResult<Option<Song>> {
+        let Some(link) = SongShareLinkEntity::find_by_id(token).one(&self.db).await? else {
+            return Ok(None);
+        };
+        let link = build_share_link(link)?;
+        self.get_song(&link.song_id).await
     }
 
-    pub async fn delete_song(&self, id: &Uuid) -> ChordDbResult<()> {
-        SongEntity::delete_by_id(*id).exec(&self.db).await?;
+    async fn delete_song(&self, id: &Uuid, version: i32) -> ChordDbResult<()> {
+        let result = SongEntity::delete_many()
+            .filter(song::Column::Id.eq(id.to_string()))
+            .filter(song::Column::Version.eq(version))
+            .exec(&self.db)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(ChordDbError::Conflict(serde_json::Value::Null));
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser::parse_metadata,
+        song::{SongCursor, SongFilter},
+        testing::{test_databases, user},
+    };
+
+    use super::*;
+    use test_log::test;
+
+    fn song(author: &str, title: &str, contents: &str, owner: &User) -> Song {
+        let mut song = Song::new(
+            Uuid::new_v4(),
+            author.to_owned(),
+            title.to_owned(),
+            contents.to_owned(),
+            owner,
+        );
+        song.header.metadata = parse_metadata(contents, song.note_locale());
+        song
+    }
+
+    #[test(tokio::test)]
+    async fn test_upsert_song() {
+        for database in test_databases().await {
+            let songs = SeaOrmSongs::new(database.db.clone());
+            let owner = user("owner");
+            let mut song = song("Author", "Title", "{capo: 2}\nAm    C\nla la", &owner);
+            song.header.tags = vec!["rock".to_owned()];
+            let id = *song.id();
+
+            assert_eq!(
+                songs
+                    .upsert_song(song.clone(), &owner.id, None)
+                    .await
+                    .unwrap(),
+                1
+            );
+            let stored = songs.get_song(&id).await.unwrap().unwrap();
+            assert_eq!(stored.title(), "Title", "{}", database.name);
+            assert_eq!(stored.contents(), song.contents());
+            assert_eq!(stored.metadata().capo, Some(2));
+            assert_eq!(stored.header.tags, vec!["rock".to_owned()]);
+
+            let mut stale = song.clone();
+            stale.header.title = "Stale".to_owned();
+            assert!(matches!(
+                songs.upsert_song(stale, &owner.id, None).await,
+                Err(ChordDbError::Conflict(_))
+            ));
+            let mut edited = stored;
+            edited.header.title = "Edited".to_owned();
+            assert_eq!(songs.upsert_song(edited, &owner.id, None).await.unwrap(), 2);
+            assert_eq!(songs.revisions(&id).await.unwrap().len(), 2);
+
+            assert!(matches!(
+                songs.delete_song(&id, 1).await,
+                Err(ChordDbError::Conflict(_))
+            ));
+            songs.delete_song(&id, 2).await.unwrap();
+            assert!(songs.get_song(&id).await.unwrap().is_none());
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_concurrent_saves() {
+        for database in test_databases().await {
+            let songs = SeaOrmSongs::new(database.db.clone());
+            let owner = user("owner");
+            let new = song("Author", "Title", "Am\nla la", &owner);
+            let id = *new.id();
+            let (first, second) = tokio::join!(
+                songs.upsert_song(new.clone(), &owner.id, None),
+                songs.upsert_song(new.clone(), &owner.id, None)
+            );
+            let results = [first, second];
+            assert_eq!(
+                results.iter().filter(|result| result.is_ok()).count(),
+                1,
+                "{}",
+                database.name
+            );
+            assert!(results
+                .iter()
+                .any(|result| matches!(result, Err(ChordDbError::Conflict(_)))));
+
+            let stored = songs.get_song(&id).await.unwrap().unwrap();
+            let mut mine = stored.clone();
+            mine.header.title = "Mine".to_owned();
+            let mut theirs = stored;
+            theirs.header.title = "Theirs".to_owned();
+            let (first, second) = tokio::join!(
+                songs.upsert_song(mine, &owner.id, None),
+                songs.upsert_song(theirs, &owner.id, None)
+            );
+            let results = [first, second];
+            assert_eq!(
+                results.iter().filter(|result| result.is_ok()).count(),
+                1,
+                "{}",
+                database.name
+            );
+            assert!(results
+                .iter()
+                .any(|result| matches!(result, Err(ChordDbError::Conflict(_)))));
+            let saved = if results[0].is_ok() { "Mine" } else { "Theirs" };
+            let stored = songs.get_song(&id).await.unwrap().unwrap();
+            assert_eq!(stored.title(), saved, "{}", database.name);
+            assert_eq!(stored.header.version, 2);
+            assert_eq!(songs.revisions(&id).await.unwrap().len(), 2);
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_list_songs() {
+        for database in test_databases().await {
+            let songs = SeaOrmSongs::new(database.db.clone());
+            let owner = user("owner");
+            for (author, title) in [("The Beatles", "Help"), ("Queen", "Bohemian Rhapsody")] {
+                let song = song(author, title, "G\nla", &owner);
+                songs.upsert_song(song, &owner.id, None).await.unwrap();
+            }
+
+            let page = songs
+                .list_songs(&SongListQuery {
+                    filter: SongFilter {
+                        author: Some("beatles".to_owned()),
+                        ..Default::default()
+                    },
+                    sort: SongSort::Title,
+                    order: SortOrder::Asc,
+                    cursor: None,
+                    limit: 10,
+                })
+                .await
+                .unwrap();
+            let titles: Vec<&str> = page.songs.iter().map(|song| song.title.as_str()).collect();
+            assert_eq!(titles, vec!["Help"], "{}", database.name);
+
+            for wildcard in ["_", "%", "\\"] {
+                let page = songs
+                    .list_songs(&SongListQuery {
+                        filter: SongFilter {
+                            author: Some(wildcard.to_owned()),
+                            ..Default::default()
+                        },
+                        sort: SongSort::Title,
+                        order: SortOrder::Asc,
+                        cursor: None,
+                        limit: 10,
+                    })
+                    .await
+                    .unwrap();
+                assert!(page.songs.is_empty(), "{}: {}", database.name, wildcard);
+            }
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_list_songs_after_deleted_cursor() {
+        for database in test_databases().await {
+            let songs = SeaOrmSongs::new(database.db.clone());
+            let owner = user("owner");
+            for title in ["A", "B", "C"] {
+                let song = song("Someone", title, "G\nla", &owner);
+                songs.upsert_song(song, &owner.id, None).await.unwrap();
+            }
+            for sort in [SongSort::Title, SongSort::Updated] {
+                let mut query = SongListQuery {
+                    filter: SongFilter::default(),
+                    sort,
+                    order: sort.default_order(),
+                    cursor: None,
+                    limit: 1,
+                };
+                let first = songs.list_songs(&query).await.unwrap();
+                let cursor = first.next_cursor.unwrap();
+                query.cursor = Some(SongCursor::decode(&cursor).unwrap());
+                let second = songs.list_songs(&query).await.unwrap();
+                assert_eq!(second.songs.len(), 1, "{}", database.name);
+                assert_ne!(second.songs[0].id, first.songs[0].id);
+
+                // The next page starts in the same place without the last song
+                let last = &first.songs[0];
+                songs.delete_song(&last.id, last.version).await.unwrap();
+                let page = songs.list_songs(&query).await.unwrap();
+                assert_eq!(page.songs[0].id, second.songs[0].id, "{}", database.name);
+                let song = song("Someone", &first.songs[0].title, "G\nla", &owner);
+                songs.upsert_song(song, &owner.id, None).await.unwrap();
+            }
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_search_songs() {
+        for database in test_databases().await {
+            let songs = SeaOrmSongs::new(database.db.clone());
+            let owner = user("owner");
+            let by_title = song("Someone", "Canción del sol", "Am\nla la", &owner);
+            let by_contents = song("Someone", "Other", "Am\nEl sol de la mañana", &owner);
+            for song in [&by_title, &by_contents] {
+                songs
+                    .upsert_song(song.clone(), &owner.id, None)
+                    .await
+                    .unwrap();
+            }
+
+            let results = songs.search_songs("SOL", None, 10).await.unwrap();
+            let ids: Vec<Uuid> = results.iter().map(|result| result.header.id).collect();
+            assert_eq!(
+                ids,
+                vec![*by_title.id(), *by_contents.id()],
+                "{}",
+                database.name
+            );
+            assert!(results[0].rank < results[1].rank);
+            assert!(results[1].snippet.contains("<mark>sol</mark>"));
+
+            let results = songs.search_songs("cancion", None, 10).await.unwrap();
+            assert_eq!(results.len(), 1, "{}", database.name);
+            assert!(
+                results[0].snippet.contains("<mark>Canción</mark>"),
+                "{}: {}",
+                database.name,
+                results[0].snippet
+            );
+            let results = songs.search_songs("manan sol", None, 10).await.unwrap();
+            assert_eq!(results.len(), 1, "{}", database.name);
+            assert!(songs.search_songs("&!", None, 10).await.unwrap().is_empty());
+
+            let other = user("other");
+            assert!(songs
+                .search_songs("sol", Some(&other.id), 10)
+                .await
+                .unwrap()
+                .is_empty());
+            songs
+                .upsert_grant(&SongGrant {
+                    song_id: *by_contents.id(),
+                    user_id: other.id,
+                    role: SongRole::Viewer,
+                })
+                .await
+                .unwrap();
+            let results = songs
+                .search_songs("sol", Some(&other.id), 10)
+                .await
+                .unwrap();
+            assert_eq!(results.len(), 1, "{}", database.name);
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_search_index_follows_songs() {
+        for database in test_databases().await {
+            let songs = SeaOrmSongs::new(database.db.clone());
+            let owner = user("owner");
+            let song = song("Someone", "Title", "Am\n<b>Verano</b> & sol", &owner);
+            let id = *song.id();
+            songs
+                .upsert_song(song.clone(), &owner.id, None)
+                .await
+                .unwrap();
+            let results = songs.search_songs("verano", None, 10).await.unwrap();
+            assert_eq!(results.len(), 1, "{}", database.name);
+            let snippet = &results[0].snippet;
+            assert!(
+                snippet.contains("<mark>Verano</mark>") && snippet.contains("&amp; sol"),
+                "{}: {}",
+                database.name,
+                snippet
+            );
+            assert!(!snippet.contains("<b>"), "{}: {}", database.name, snippet);
+
+            let mut edited = songs.get_song(&id).await.unwrap().unwrap();
+            edited.contents = "Am\nInvierno".to_owned();
+            songs.upsert_song(edited, &owner.id, None).await.unwrap();
+            assert!(songs
+                .search_songs("verano", None, 10)
+                .await
+                .unwrap()
+                .is_empty());
+            let results = songs.search_songs("invierno", None, 10).await.unwrap();
+            assert_eq!(results.len(), 1, "{}", database.name);
+
+            songs.delete_song(&id, 2).await.unwrap();
+            assert!(songs
+                .search_songs("invierno", None, 10)
+                .await
+                .unwrap()
+                .is_empty());
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_songs_with_chords() {
+        for database in test_databases().await {
+            let songs = SeaOrmSongs::new(database.db.clone());
+            let owner = user("owner");
+            let simple = song("Someone", "Simple", "Am    C\nla la", &owner);
+            let harder = song("Someone", "Harder", "Am    F#m7\nla la", &owner);
+            for song in [&simple, &harder] {
+                songs
+                    .upsert_song(song.clone(), &owner.id, None)
+                    .await
+                    .unwrap();
+            }
+            let chords = |names: &[&str]| -> Vec<Chord> {
+                names
+                    .iter()
+                    .map(|name| Chord::parse(name).unwrap())
+                    .collect()
+            };
+
+            let within = songs
+                .songs_with_chords_within(&chords(&["Am", "C", "G"]))
+                .await
+                .unwrap();
+            assert_eq!(within, vec![*simple.id()], "{}", database.name);
+            let mut with_all = songs.songs_with_all_chords(&chords(&["Am"])).await.unwrap();
+            with_all.sort();
+            let mut expected = vec![*simple.id(), *harder.id()];
+            expected.sort();
+            assert_eq!(with_all, expected, "{}", database.name);
+
+            let found = songs.get_songs(&with_all, None).await.unwrap();
+            let titles: Vec<&str> = found.iter().map(|song| song.title()).collect();
+            assert_eq!(titles, vec!["Harder", "Simple"], "{}", database.name);
+            let other = user("other");
+            assert!(songs
+                .get_songs(&with_all, Some(&other.id))
+                .await
+                .unwrap()
+                .is_empty());
+        }
+    }
+}
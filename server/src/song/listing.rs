@@ -0,0 +1,201 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::{ChordDbError, ChordDbResult};
+
+use super::SongHeader;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SongSort {
+    Title,
+    Author,
+    Updated,
+}
+
+impl SongSort {
+    /// Most recently updated songs go first, everything else is alphabetical
+    pub fn default_order(&self) -> SortOrder {
+        match self {
+            SongSort::Title | SongSort::Author => SortOrder::Asc,
+            SongSort::Updated => SortOrder::Desc,
+        }
+    }
+
+    /// The value of the song songs are sorted by
+    pub fn key(&self, song: &SongHeader) -> SortKey {
+        match self {
+            SongSort::Title => SortKey::Text(song.title.clone()),
+            SongSort::Author => SortKey::Text(song.author.clone()),
+            SongSort::Updated => SortKey::Time(song.updated_at),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    Text(String),
+    Time(NaiveDateTime),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SongFilter {
+    /// Part of the author name
+    pub author: Option<String>,
+    pub owner: Option<Uuid>,
+    pub tag: Option<String>,
+    pub key: Option<String>,
+    /// Only songs this user can see. `None` means every song
+    pub accessible_by: Option<Uuid>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SongListQuery {
+    pub filter: SongFilter,
+    pub sort: SongSort,
+    pub order: SortOrder,
+    pub cursor: Option<SongCursor>,
+    pub limit: u64,
+}
+
+impl SongListQuery {
+    /// The cursor, if it was made for this sort
+    pub fn cursor(&self) -> ChordDbResult<Option<&SongCursor>> {
+        match (&self.cursor, self.sort) {
+            (None, _)
+            | (
+                Some(SongCursor {
+                    key: SortKey::Text(_),
+                    ..
+                }),
+                SongSort::Title | SongSort::Author,
+            )
+            | (
+                Some(SongCursor {
+                    key: SortKey::Time(_),
+                    ..
+                }),
+                SongSort::Updated,
+            ) => Ok(self.cursor.as_ref()),
+            _ => Err(ChordDbError::BadRequest(
+                "The cursor is for another sort".to_string(),
+            )),
+        }
+    }
+}
+
+/// Where a page ends: the sort key and id of its last song. The next page starts after them,
+/// even if that song was deleted in between.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SongCursor {
+    pub key: SortKey,
+    pub id: Uuid,
+}
+
+impl SongCursor {
+    pub fn after(song: &SongHeader, sort: SongSort) -> Self {
+        SongCursor {
+            key: sort.key(song),
+            id: song.id,
+        }
+    }
+
+    /// Cursors are opaque to clients
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(cursor: &str) -> ChordDbResult<Self> {
+        URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .ok_or_else(|| ChordDbError::BadRequest(format!("Invalid cursor '{}'", cursor)))
+    }
+}
+
+#[derive(Serialize)]
+pub struct SongPage {
+    pub songs: Vec<SongHeader>,
+    /// Number of songs matching the filter, across all pages
+    pub total: u64,
+    pub next_cursor: Option<String>,
+}
+
+impl SongPage {
+    /// The page with the first `limit` songs of `songs`, which may have one more
+    pub fn new(mut songs: Vec<SongHeader>, total: u64, query: &SongListQuery) -> Self {
+        let has_more = songs.len() as u64 > query.limit;
+        songs.truncate(query.limit as usize);
+        SongPage {
+            next_cursor: songs
+                .last()
+                .filter(|_| has_more)
+                .map(|song| SongCursor::after(song, query.sort).encode()),
+            songs,
+            total,
+        }
+    }
+}
+
+/// Tags are compared case-insensitively and without surrounding whitespace
+pub fn normalize_tags<I, S>(tags: I) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut normalized: Vec<String> = tags
+        .into_iter()
+        .map(|tag| tag.as_ref().trim().to_lowercase())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    normalized.sort();
+    normalized.dedup();
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_normalize_tags() {
+        assert_eq!(
+            normalize_tags(["Folk", " rock ", "", "folk"]),
+            vec!["folk".to_owned(), "rock".to_owned()]
+        );
+    }
+
+    #[test]
+    fn test_cursor() {
+        let cursor = SongCursor {
+            key: SortKey::Text("Help".to_owned()),
+            id: Uuid::new_v4(),
+        };
+        let decoded = SongCursor::decode(&cursor.encode()).unwrap();
+        assert_eq!(decoded, cursor);
+        assert!(SongCursor::decode("help").is_err());
+
+        let mut query = SongListQuery {
+            filter: SongFilter::default(),
+            sort: SongSort::Author,
+            order: SortOrder::Asc,
+            cursor: Some(cursor),
+            limit: 10,
+        };
+        assert!(query.cursor().unwrap().is_some());
+        query.sort = SongSort::Updated;
+        assert!(query.cursor().is_err());
+    }
+}
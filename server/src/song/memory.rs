@@ -0,0 +1,718 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use axum::async_trait;
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::{
+    band::{BandRole, Bands},
+    chord::Chord,
+    error::{ChordDbError, ChordDbResult},
+    user::User,
+};
+
+use super::{
+    chord_sequence, new_share_token, normalize_tags, search::match_text, BandGrant, OwnerKind,
+    RevisionHeader, ShareLink, Song, SongCursor, SongGrant, SongHeader, SongListQuery, SongPage,
+    SongRevision, SongRole, SongSearchResult, SongSort, Songs, SortOrder,
+};
+
+#[derive(Default)]
+struct Library {
+    songs: HashMap<Uuid, Song>,
+    revisions: Vec<SongRevision>,
+    grants: Vec<SongGrant>,
+    band_grants: Vec<BandGrant>,
+    links: Vec<ShareLink>,
+}
+
+impl Library {
+    /// Whether the user, member of `bands`, can see the song
+    fn is_accessible(&self, song: &SongHeader, user_id: &Uuid, bands: &HashSet<Uuid>) -> bool {
+        song.owner_id == *user_id
+            || bands.contains(&song.owner_id)
+            || self
+                .grants
+                .iter()
+                .any(|grant| grant.song_id == song.id && grant.user_id == *user_id)
+            || self
+                .band_grants
+                .iter()
+                .any(|grant| grant.song_id == song.id && bands.contains(&grant.band_id))
+    }
+}
+
+/// Songs kept in memory, for tests and demos. Band songs are only accessible to their members
+/// when created `with_bands`.
+pub struct MemorySongs {
+    library: RwLock<Library>,
+    bands: Option<Arc<dyn Bands>>,
+}
+
+impl Default for MemorySongs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemorySongs {
+    pub fn new() -> Self {
+        MemorySongs {
+            library: RwLock::default(),
+            bands: None,
+        }
+    }
+
+    /// Resolves band membership through `bands`
+    pub fn with_bands(mut self, bands: Arc<dyn Bands>) -> Self {
+        self.bands = Some(bands);
+        self
+    }
+
+    fn read_library(&self) -> RwLockReadGuard<'_, Library> {
+        self.library.read().unwrap()
+    }
+
+    fn write_library(&self) -> RwLockWriteGuard<'_, Library> {
+        self.library.write().unwrap()
+    }
+
+    /// Adds songs as they are, without recording revisions
+    pub fn add_songs<I: IntoIterator<Item = Song>>(&self, songs: I) {
+        let mut library = self.write_library();
+        for song in songs {
+            library.songs.insert(*song.id(), song);
+        }
+    }
+
+    async fn user_bands(&self, user_id: &Uuid) -> ChordDbResult<HashMap<Uuid, BandRole>> {
+        let Some(bands) = &self.bands else {
+            return Ok(HashMap::new());
+        };
+        Ok(bands
+            .user_bands(user_id)
+            .await?
+            .into_iter()
+            .map(|membership| (membership.band.id, membership.role))
+            .collect())
+    }
+
+    /// Bands of the user, if the songs have to be filtered for them
+    async fn filter_bands(&self, user_id: Option<&Uuid>) -> ChordDbResult<HashSet<Uuid>> {
+        match user_id {
+            Some(user_id) => Ok(self.user_bands(user_id).await?.into_keys().collect()),
+            None => Ok(HashSet::new()),
+        }
+    }
+
+    fn all(&self) -> Vec<Song> {
+        self.read_library().songs.values().cloned().collect()
+    }
+}
+
+fn compare(a: &SongHeader, b: &SongHeader, sort: SongSort) -> Ordering {
+    compare_to(a, &SongCursor::after(b, sort), sort)
+}
+
+/// How the song sorts against the position of a cursor
+fn compare_to(song: &SongHeader, cursor: &SongCursor, sort: SongSort) -> Ordering {
+    sort.key(song)
+        .cmp(&cursor.key)
+        .then_with(|| song.id.cmp(&cursor.id))
+}
+
+#[async_trait]
+impl Songs for MemorySongs {
+    async fn all_songs(&self) -> ChordDbResult<Vec<SongHeader>> {
+        Ok(self
+            .read_library()
+            .songs
+            .values()
+            .map(|song| song.header.clone())
+            .collect())
+    }
+
+    async fn list_songs(&self, query: &SongListQuery) -> ChordDbResult<SongPage> {
+        let filter = &query.filter;
+        let bands = self.filter_bands(filter.accessible_by.as_ref()).await?;
+        let library = self.read_library();
+        let mut headers: Vec<SongHeader> = library
+            .songs
+            .values()
+            .map(|song| &song.header)
+            .filter(|header| {
+                filter.author.as_ref().is_none_or(|author| {
+                    header
+                        .author
+                        .to_lowercase()
+                        .contains(&author.to_lowercase())
+                }) && filter.owner.is_none_or(|owner| header.owner_id == owner)
+                    && filter
+                        .tag
+                        .as_ref()
+                        .is_none_or(|tag| header.tags.contains(tag))
+                    && filter
+                        .key
+                        .as_ref()
+                        .is_none_or(|key| header.metadata.key.as_ref() == Some(key))
+                    && filter
+                        .accessible_by
+                        .is_none_or(|user_id| library.is_accessible(header, &user_id, &bands))
+            })
+            .cloned()
+            .collect();
+        let total = headers.len() as u64;
+
+        headers.sort_by(|a, b| match query.order {
+            SortOrder::Asc => compare(a, b, query.sort),
+            SortOrder::Desc => compare(b, a, query.sort),
+        });
+        if let Some(cursor) = query.cursor()? {
+            headers.retain(|header| match query.order {
+                SortOrder::Asc => compare_to(header, cursor, query.sort).is_gt(),
+                SortOrder::Desc => compare_to(header, cursor, query.sort).is_lt(),
+            });
+        }
+        headers.truncate(query.limit as usize + 1);
+        Ok(SongPage::new(headers, total, query))
+    }
+
+    async fn accessible_songs(&self, user_id: Option<&Uuid>) -> ChordDbResult<Vec<Song>> {
+        let bands = self.filter_bands(user_id).await?;
+        let library = self.read_library();
+        let mut songs: Vec<Song> = library
+            .songs
+            .values()
+            .filter(|song| {
+                user_id.is_none_or(|user_id| library.is_accessible(&song.header, user_id, &bands))
+            })
+            .cloned()
+            .collect();
+        songs.sort_by(|a, b| compare(&a.header, &b.header, SongSort::Title));
+        Ok(songs)
+    }
+
+    async fn get_song(&self, id: &Uuid) -> ChordDbResult<Option<Song>> {
+        Ok(self.read_library().songs.get(id).cloned())
+    }
+
+    async fn get_songs(
+        &self,
+        ids: &[Uuid],
+        accessible_by: Option<&Uuid>,
+    ) -> ChordDbResult<Vec<Song>> {
+        let bands = self.filter_bands(accessible_by).await?;
+        let library = self.read_library();
+        let mut songs: Vec<Song> = ids
+            .iter()
+            .filter_map(|id| library.songs.get(id))
+            .filter(|song| {
+                accessible_by
+                    .is_none_or(|user_id| library.is_accessible(&song.header, user_id, &bands))
+            })
+            .cloned()
+            .collect();
+        songs.sort_by(|a, b| compare(&a.header, &b.header, SongSort::Title));
+        Ok(songs)
+    }
+
+    async fn upsert_song(
+        &self,
+        mut song: Song,
+        editor: &Uuid,
+        note: Option<String>,
+    ) -> ChordDbResult<i32> {
+        let mut library = self.write_library();
+        let current_version = library.songs.get(song.id()).map(|song| song.header.version);
+        if current_version.unwrap_or(0) != song.header.version {
+            return Err(ChordDbError::Conflict(serde_json::Value::Null));
+        }
+        song.header.version += 1;
+        song.header.updated_at = Utc::now().naive_utc();
+        song.header.tags = normalize_tags(&song.header.tags);
+
+        let revision = SongRevision {
+            header: RevisionHeader {
+                id: library.revisions.len() as i32 + 1,
+                song_id: *song.id(),
+                editor_id: *editor,
+                created_at: song.header.updated_at,
+                note,
+                author: song.author().to_owned(),
+                title: song.title().to_owned(),
+            },
+            contents: song.contents().to_owned(),
+        };
+        library.revisions.push(revision);
+        let version = song.header.version;
+        library.songs.insert(*song.id(), song);
+        Ok(version)
+    }
+
+    async fn delete_song(&self, id: &Uuid, version: i32) -> ChordDbResult<()> {
+        let mut library = self.write_library();
+        if library.songs.get(id).map(|song| song.header.version) != Some(version) {
+            return Err(ChordDbError::Conflict(serde_json::Value::Null));
+        }
+        library.songs.remove(id);
+        library
+            .revisions
+            .retain(|revision| revision.header.song_id != *id);
+        library.grants.retain(|grant| grant.song_id != *id);
+        library.band_grants.retain(|grant| grant.song_id != *id);
+        library.links.retain(|link| link.song_id != *id);
+        Ok(())
+    }
+
+    async fn revisions(&self, song_id: &Uuid) -> ChordDbResult<Vec<RevisionHeader>> {
+        Ok(self
+            .read_library()
+            .revisions
+            .iter()
+            .rev()
+            .filter(|revision| revision.header.song_id == *song_id)
+            .map(|revision| revision.header.clone())
+            .collect())
+    }
+
+    async fn get_revision(
+        &self,
+        song_id: &Uuid,
+        revision_id: i32,
+    ) -> ChordDbResult<Option<SongRevision>> {
+        Ok(self
+            .read_library()
+            .revisions
+            .iter()
+            .find(|revision| {
+                revision.header.id == revision_id && revision.header.song_id == *song_id
+            })
+            .cloned())
+    }
+
+    // Chords are read from the songs on every search
+    async fn index_missing_chords(&self) -> ChordDbResult<()> {
+        Ok(())
+    }
+
+    async fn songs_with_chords_within(&self, chords: &[Chord]) -> ChordDbResult<Vec<Uuid>> {
+        Ok(self
+            .all()
+            .iter()
+            .filter(|song| {
+                let sequence = chord_sequence(song.contents(), song.note_locale());
+                !sequence.is_empty() && sequence.iter().all(|chord| chords.contains(chord))
+            })
+            .map(|song| *song.id())
+            .collect())
+    }
+
+    async fn songs_with_all_chords(&self, chords: &[Chord]) -> ChordDbResult<Vec<Uuid>> {
+        Ok(self
+            .all()
+            .iter()
+            .filter(|song| {
+                let sequence = chord_sequence(song.contents(), song.note_locale());
+                chords.iter().all(|chord| sequence.contains(chord))
+            })
+            .map(|song| *song.id())
+            .collect())
+    }
+
+    async fn search_songs(
+        &self,
+        query: &str,
+        accessible_by: Option<&Uuid>,
+        limit: u64,
+    ) -> ChordDbResult<Vec<SongSearchResult>> {
+        let bands = self.filter_bands(accessible_by).await?;
+        let library = self.read_library();
+        let mut results: Vec<SongSearchResult> = library
+            .songs
+            .values()
+            .filter(|song| {
+                accessible_by
+                    .is_none_or(|user_id| library.is_accessible(&song.header, user_id, &bands))
+            })
+            .filter_map(|song| {
+                let columns = [
+                    (song.title(), 10.0),
+                    (song.author(), 5.0),
+                    (song.contents(), 1.0),
+                ];
+                match_text(query, &columns).map(|(snippet, rank)| SongSearchResult {
+                    header: song.header.clone(),
+                    snippet,
+                    rank,
+                })
+            })
+            .collect();
+        results.sort_by(|a, b| a.rank.total_cmp(&b.rank));
+        results.truncate(limit as usize);
+        Ok(results)
+    }
+
+    async fn song_role(&self, user: &User, song: &SongHeader) -> ChordDbResult<Option<SongRole>> {
+        let bands = self.user_bands(&user.id).await?;
+        let library = self.read_library();
+        let mut granted: Vec<SongRole> = library
+            .grants
+            .iter()
+            .filter(|grant| grant.song_id == song.id && grant.user_id == user.id)
+            .map(|grant| grant.role)
+            .collect();
+        if song.owner_kind == OwnerKind::Band {
+            if let Some(role) = bands.get(&song.owner_id) {
+                granted.push(role.song_role());
+            }
+        }
+        granted.extend(
+            library
+                .band_grants
+                .iter()
+                .filter(|grant| grant.song_id == song.id && bands.contains_key(&grant.band_id))
+                .map(|grant| grant.role),
+        );
+        Ok(SongRole::of(user, song, granted.into_iter().max()))
+    }
+
+    async fn grants(&self, song_id: &Uuid) -> ChordDbResult<Vec<SongGrant>> {
+        Ok(self
+            .read_library()
+            .grants
+            .iter()
+            .filter(|grant| grant.song_id == *song_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn upsert_grant(&self, grant: &SongGrant) -> ChordDbResult<()> {
+        let mut library = self.write_library();
+        library
+            .grants
+            .retain(|other| other.song_id != grant.song_id || other.user_id != grant.user_id);
+        library.grants.push(grant.clone());
+        Ok(())
+    }
+
+    async fn delete_grant(&self, song_id: &Uuid, user_id: &Uuid) -> ChordDbResult<()> {
+        self.write_library()
+            .grants
+            .retain(|grant| grant.song_id != *song_id || grant.user_id != *user_id);
+        Ok(())
+    }
+
+    async fn band_grants(&self, song_id: &Uuid) -> ChordDbResult<Vec<BandGrant>> {
+        Ok(self
+            .read_library()
+            .band_grants
+            .iter()
+            .filter(|grant| grant.song_id == *song_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn upsert_band_grant(&self, grant: &BandGrant) -> ChordDbResult<()> {
+        let mut library = self.write_library();
+        library
+            .band_grants
+            .retain(|other| other.song_id != grant.song_id || other.band_id != grant.band_id);
+        library.band_grants.push(grant.clone());
+        Ok(())
+    }
+
+    async fn delete_band_grant(&self, song_id: &Uuid, band_id: &Uuid) -> ChordDbResult<()> {
+        self.write_library()
+            .band_grants
+            .retain(|grant| grant.song_id != *song_id || grant.band_id != *band_id);
+        Ok(())
+    }
+
+    async fn count_owned_songs(&self, owner_id: &Uuid) -> ChordDbResult<u64> {
+        Ok(self
+            .read_library()
+            .songs
+            .values()
+            .filter(|song| song.owner_id() == owner_id)
+            .count() as u64)
+    }
+
+    async fn share_links(&self, song_id: &Uuid) -> ChordDbResult<Vec<ShareLink>> {
+        Ok(self
+            .read_library()
+            .links
+            .iter()
+            .filter(|link| link.song_id == *song_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn create_share_link(
+        &self,
+        song_id: &Uuid,
+        created_by: &Uuid,
+    ) -> ChordDbResult<ShareLink> {
+        let link = ShareLink {
+            token: new_share_token(),
+            song_id: *song_id,
+            created_by: *created_by,
+            created_at: Utc::now().naive_utc(),
+        };
+        self.write_library().links.push(link.clone());
+        Ok(link)
+    }
+
+    async fn delete_share_link(&self, song_id: &Uuid, token: &str) -> ChordDbResult<bool> {
+        let mut library = self.write_library();
+        let count = library.links.len();
+        library
+            .links
+            .retain(|link| link.song_id != *song_id || link.token != token);
+        Ok(library.links.len() < count)
+    }
+
+    async fn get_shared_song(&self, token: &str) -> ChordDbResult<Option<Song>> {
+        let library = self.read_library();
+        Ok(library
+            .links
+            .iter()
+            .find(|link| link.token == token)
+            .and_then(|link| library.songs.get(&link.song_id))
+            .cloned())
+    }
+}
+
+/// Songs kept in memory and saved to a JSON file after every change. Only the songs are saved:
+/// revisions, grants and share links are lost on restart.
+pub struct FileSongs {
+    path: String,
+    cache: MemorySongs,
+}
+
+/// Loads the songs saved in `path`. A missing file is an empty library, but any other error
+/// fails: saving over a file that could not be read would lose its songs.
+fn load_cache(path: &str) -> ChordDbResult<MemorySongs> {
+    let songs = MemorySongs::new();
+    match std::fs::read_to_string(path) {
+        Ok(data) => {
+            let data: Vec<Song> = serde_json::from_str(&data).map_err(|err| {
+                ChordDbError::InvalidData(format!("Invalid songs file {}: {}", path, err))
+            })?;
+            songs.add_songs(data);
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(ChordDbError::Generic(Box::new(err))),
+    }
+    Ok(songs)
+}
+
+impl FileSongs {
+    pub fn new(path: &str) -> ChordDbResult<Self> {
+        Ok(FileSongs {
+            path: path.to_owned(),
+            cache: load_cache(path)?,
+        })
+    }
+
+    /// Resolves band membership through `bands`
+    pub fn with_bands(mut self, bands: Arc<dyn Bands>) -> Self {
+        self.cache = self.cache.with_bands(bands);
+        self
+    }
+
+    fn save_cache(&self) -> ChordDbResult<()> {
+        let mut songs = self.cache.all();
+        songs.sort_by(|a, b| compare(&a.header, &b.header, SongSort::Title));
+        let data = serde_json::to_string_pretty(&songs)
+            .map_err(|err| ChordDbError::Generic(Box::new(err)))?;
+        std::fs::write(&self.path, data).map_err(|err| ChordDbError::Generic(Box::new(err)))
+    }
+}
+
+#[async_trait]
+impl Songs for FileSongs {
+    async fn all_songs(&self) -> ChordDbResult<Vec<SongHeader>> {
+        self.cache.all_songs().await
+    }
+
+    async fn list_songs(&self, query: &SongListQuery) -> ChordDbResult<SongPage> {
+        self.cache.list_songs(query).await
+    }
+
+    async fn accessible_songs(&self, user_id: Option<&Uuid>) -> ChordDbResult<Vec<Song>> {
+        self.cache.accessible_songs(user_id).await
+    }
+
+    async fn get_song(&self, id: &Uuid) -> ChordDbResult<Option<Song>> {
+        self.cache.get_song(id).await
+    }
+
+    async fn get_songs(
+        &self,
+        ids: &[Uuid],
+        accessible_by: Option<&Uuid>,
+    ) -> ChordDbResult<Vec<Song>> {
+        self.cache.get_songs(ids, accessible_by).await
+    }
+
+    async fn upsert_song(
+        &self,
+        song: Song,
+        editor: &Uuid,
+        note: Option<String>,
+    ) -> ChordDbResult<i32> {
+        let version = self.cache.upsert_song(song, editor, note).await?;
+        self.save_cache()?;
+        Ok(version)
+    }
+
+    async fn delete_song(&self, id: &Uuid, version: i32) -> ChordDbResult<()> {
+        self.cache.delete_song(id, version).await?;
+        self.save_cache()
+    }
+
+    async fn revisions(&self, song_id: &Uuid) -> ChordDbResult<Vec<RevisionHeader>> {
+        self.cache.revisions(song_id).await
+    }
+
+    async fn get_revision(
+        &self,
+        song_id: &Uuid,
+        revision_id: i32,
+    ) -> ChordDbResult<Option<SongRevision>> {
+        self.cache.get_revision(song_id, revision_id).await
+    }
+
+    async fn index_missing_chords(&self) -> ChordDbResult<()> {
+        self.cache.index_missing_chords().await
+    }
+
+    async fn songs_with_chords_within(&self, chords: &[Chord]) -> ChordDbResult<Vec<Uuid>> {
+        self.cache.songs_with_chords_within(chords).await
+    }
+
+    async fn songs_with_all_chords(&self, chords: &[Chord]) -> ChordDbResult<Vec<Uuid>> {
+        self.cache.songs_with_all_chords(chords).await
+    }
+
+    async fn search_songs(
+        &self,
+        query: &str,
+        accessible_by: Option<&Uuid>,
+        limit: u64,
+    ) -> ChordDbResult<Vec<SongSearchResult>> {
+        self.cache.search_songs(query, accessible_by, limit).await
+    }
+
+    async fn song_role(&self, user: &User, song: &SongHeader) -> ChordDbResult<Option<SongRole>> {
+        self.cache.song_role(user, song).await
+    }
+
+    async fn grants(&self, song_id: &Uuid) -> ChordDbResult<Vec<SongGrant>> {
+        self.cache.grants(song_id).await
+    }
+
+    async fn upsert_grant(&self, grant: &SongGrant) -> ChordDbResult<()> {
+        self.cache.upsert_grant(grant).await
+    }
+
+    async fn delete_grant(&self, song_id: &Uuid, user_id: &Uuid) -> ChordDbResult<()> {
+        self.cache.delete_grant(song_id, user_id).await
+    }
+
+    async fn band_grants(&self, song_id: &Uuid) -> ChordDbResult<Vec<BandGrant>> {
+        self.cache.band_grants(song_id).await
+    }
+
+    async fn upsert_band_grant(&self, grant: &BandGrant) -> ChordDbResult<()> {
+        self.cache.upsert_band_grant(grant).await
+    }
+
+    async fn delete_band_grant(&self, song_id: &Uuid, band_id: &Uuid) -> ChordDbResult<()> {
+        self.cache.delete_band_grant(song_id, band_id).await
+    }
+
+    async fn count_owned_songs(&self, owner_id: &Uuid) -> ChordDbResult<u64> {
+        self.cache.count_owned_songs(owner_id).await
+    }
+
+    async fn share_links(&self, song_id: &Uuid) -> ChordDbResult<Vec<ShareLink>> {
+        self.cache.share_links(song_id).await
+    }
+
+    async fn create_share_link(
+        &self,
+        song_id: &Uuid,
+        created_by: &Uuid,
+    ) -> ChordDbResult<ShareLink> {
+        self.cache.create_share_link(song_id, created_by).await
+    }
+
+    async fn delete_share_link(&self, song_id: &Uuid, token: &str) -> ChordDbResult<bool> {
+        self.cache.delete_share_link(song_id, token).await
+    }
+
+    async fn get_shared_song(&self, token: &str) -> ChordDbResult<Option<Song>> {
+        self.cache.get_shared_song(token).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+
+    use super::*;
+    use test_log::test;
+
+    fn songs_file() -> String {
+        std::env::temp_dir()
+            .join(format!("chorddb-{}.json", Uuid::new_v4()))
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test(tokio::test)]
+    async fn test_missing_songs_file() {
+        let path = songs_file();
+        let songs = FileSongs::new(&path).unwrap();
+        assert!(songs.all_songs().await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_corrupt_songs_file() {
+        let path = songs_file();
+        std::fs::write(&path, "[{\"header\": ").unwrap();
+        assert!(FileSongs::new(&path).is_err());
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents, "[{\"header\": ");
+    }
+
+    #[test(tokio::test)]
+    async fn test_load_old_songs_file() {
+        let path = songs_file();
+        let id = Uuid::new_v4();
+        let song = serde_json::json!([{
+            "header": {
+                "id": id,
+                "author": "Author",
+                "title": "Title",
+                "owner_id": Uuid::new_v4(),
+            },
+            "contents": "[C]Hello",
+        }]);
+        std::fs::write(&path, song.to_string()).unwrap();
+        let songs = FileSongs::new(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let song = songs.unwrap().get_song(&id).await.unwrap().unwrap();
+        assert_eq!(song.title(), "Title");
+        assert_eq!(song.header.updated_at, NaiveDateTime::default());
+        assert_eq!(song.header.version, 0);
+    }
+}
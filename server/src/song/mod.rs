@@ -1,9 +1,7 @@
-use std::{
-    collections::HashMap,
-    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
-    time::SystemTime,
-};
+use std::{collections::HashMap, time::SystemTime};
 
+use axum::async_trait;
+use chrono::{NaiveDateTime, Utc};
 use dashmap::DashMap;
 use itertools::Itertools;
 use sea_orm::Iterable;
@@ -13,21 +11,53 @@ use uuid::Uuid;
 use crate::{
     chord::{
         finder::{find_fingerings, Fingering, StringInstrument},
-        Chord, Key, Variant,
+        Chord, Key, NoteLocale, Variant,
     },
+    error::ChordDbResult,
+    parser::SongMetadata,
     user::User,
 };
 
+mod access;
+mod chord_search;
 mod database;
+mod listing;
+mod memory;
+mod revision;
+mod search;
 
+pub use access::{new_share_token, BandGrant, OwnerKind, ShareLink, SongGrant, SongRole};
+pub use chord_search::{best_match, chord_sequence, ChordMatch, UNPLAYABLE_DIFFICULTY};
 pub use database::SeaOrmSongs;
+pub use listing::{
+    normalize_tags, SongCursor, SongFilter, SongListQuery, SongPage, SongSort, SortKey, SortOrder,
+};
+pub use memory::{FileSongs, MemorySongs};
+pub use revision::{diff_lines, DiffLine, DiffOp, RevisionHeader, SongRevision};
+pub use search::SongSearchResult;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct SongHeader {
     pub id: Uuid,
     pub author: String,
     pub title: String,
+    /// Id of the user or band that owns the song
     pub owner_id: Uuid,
+    #[serde(default)]
+    pub owner_kind: OwnerKind,
+    /// How the chords in the song are written
+    #[serde(default)]
+    pub note_locale: NoteLocale,
+    #[serde(flatten)]
+    pub metadata: SongMetadata,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Songs saved before this was tracked were last updated at the Unix epoch
+    #[serde(default)]
+    pub updated_at: NaiveDateTime,
+    /// Incremented on every save. Songs that were never saved have version 0
+    #[serde(default)]
+    pub version: i32,
 }
 
 impl SongHeader {
@@ -58,6 +88,12 @@ impl Song {
                 author,
                 title,
                 owner_id: owner.id,
+                owner_kind: OwnerKind::User,
+                note_locale: NoteLocale::default(),
+                metadata: SongMetadata::default(),
+                tags: vec![],
+                updated_at: Utc::now().naive_utc(),
+                version: 0,
             },
             contents,
         }
@@ -79,116 +115,105 @@ impl Song {
         &self.header.owner_id
     }
 
-    pub fn header(&self) -> &SongHeader {
-        &self.header
-    }
-
-    pub fn contents(&self) -> &str {
-        &self.contents
-    }
-}
-
-pub trait SongRepository {
-    fn all_songs(&self) -> Vec<SongHeader>;
-    fn add_song(&self, song: Song);
-    fn get_song(&self, id: &Uuid) -> Option<Song>;
-}
-
-pub struct MemorySongs {
-    songs: RwLock<HashMap<Uuid, Song>>,
-}
-
-impl Default for MemorySongs {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-impl MemorySongs {
-    pub fn new() -> Self {
-        MemorySongs {
-            songs: HashMap::new().into(),
-        }
-    }
-
-    fn read_songs(&self) -> RwLockReadGuard<'_, HashMap<Uuid, Song>> {
-        return self.songs.read().unwrap();
-    }
-
-    fn write_songs(&self) -> RwLockWriteGuard<'_, HashMap<Uuid, Song>> {
-        return self.songs.write().unwrap();
+    /// Moves the song to a band library
+    pub fn set_band(&mut self, band_id: Uuid) {
+        self.header.owner_id = band_id;
+        self.header.owner_kind = OwnerKind::Band;
     }
-}
 
-impl SongRepository for MemorySongs {
-    fn all_songs(&self) -> Vec<SongHeader> {
-        let songs = self.read_songs();
-        songs.values().map(|song| song.header()).cloned().collect()
+    pub fn set_user(&mut self, user_id: Uuid) {
+        self.header.owner_id = user_id;
+        self.header.owner_kind = OwnerKind::User;
     }
 
-    fn get_song(&self, id: &Uuid) -> Option<Song> {
-        let songs = self.read_songs();
-        return songs.get(id).cloned();
+    pub fn note_locale(&self) -> NoteLocale {
+        self.header.note_locale
     }
 
-    fn add_song(&self, song: Song) {
-        let mut songs = self.write_songs();
-        songs.insert(*song.id(), song);
+    pub fn metadata(&self) -> &SongMetadata {
+        &self.header.metadata
     }
-}
-
-pub struct FileSongs {
-    path: String,
-    cache: MemorySongs,
-}
-
-fn load_cache(path: &str) -> MemorySongs {
-    let data: Vec<Song> = std::fs::read_to_string(path)
-        .ok()
-        .and_then(|data| serde_json::from_str(&data).ok())
-        .unwrap_or_default();
-
-    let songs = MemorySongs::new();
-
-    for song in data {
-        songs.add_song(song)
-    }
-
-    songs
-}
 
-impl FileSongs {
-    pub fn new(path: &str) -> Self {
-        FileSongs {
-            path: path.to_owned(),
-            cache: load_cache(path),
-        }
+    pub fn header(&self) -> &SongHeader {
+        &self.header
     }
 
-    fn save_cache(&self) {
-        let songs: Vec<Song> = self
-            .cache
-            .all_songs()
-            .iter()
-            .filter_map(|header| self.cache.get_song(&header.id))
-            .collect();
-        std::fs::write(&self.path, serde_json::to_string_pretty(&songs).unwrap()).unwrap();
+    pub fn contents(&self) -> &str {
+        &self.contents
     }
 }
 
-impl SongRepository for FileSongs {
-    fn all_songs(&self) -> Vec<SongHeader> {
-        self.cache.all_songs()
-    }
-
-    fn add_song(&self, song: Song) {
-        self.cache.add_song(song);
-        self.save_cache();
-    }
-
-    fn get_song(&self, id: &Uuid) -> Option<Song> {
-        self.cache.get_song(id)
-    }
+#[async_trait]
+pub trait Songs: Send + Sync {
+    async fn all_songs(&self) -> ChordDbResult<Vec<SongHeader>>;
+    async fn list_songs(&self, query: &SongListQuery) -> ChordDbResult<SongPage>;
+    /// Songs the user can see, or every song if `user_id` is `None`, by title
+    async fn accessible_songs(&self, user_id: Option<&Uuid>) -> ChordDbResult<Vec<Song>>;
+    async fn get_song(&self, id: &Uuid) -> ChordDbResult<Option<Song>>;
+    /// The songs with these ids the user can see, or all of them if `accessible_by` is `None`,
+    /// by title
+    async fn get_songs(
+        &self,
+        ids: &[Uuid],
+        accessible_by: Option<&Uuid>,
+    ) -> ChordDbResult<Vec<Song>>;
+    /// Saves the song, recording the new state as a revision by `editor`. Fails with
+    /// `ChordDbError::Conflict` if the song was saved by someone else since it was loaded.
+    /// Returns the new version.
+    async fn upsert_song(
+        &self,
+        song: Song,
+        editor: &Uuid,
+        note: Option<String>,
+    ) -> ChordDbResult<i32>;
+    /// Deletes the song if it is still at `version`. Fails with `ChordDbError::Conflict` if it
+    /// was saved since, or is gone.
+    async fn delete_song(&self, id: &Uuid, version: i32) -> ChordDbResult<()>;
+
+    /// Revisions of a song, newest first
+    async fn revisions(&self, song_id: &Uuid) -> ChordDbResult<Vec<RevisionHeader>>;
+    async fn get_revision(
+        &self,
+        song_id: &Uuid,
+        revision_id: i32,
+    ) -> ChordDbResult<Option<SongRevision>>;
+
+    /// Indexes the chords of the songs that were never indexed, like the ones saved before the
+    /// chord index existed
+    async fn index_missing_chords(&self) -> ChordDbResult<()>;
+    /// Songs whose chords are all in `chords`
+    async fn songs_with_chords_within(&self, chords: &[Chord]) -> ChordDbResult<Vec<Uuid>>;
+    /// Songs that use every chord in `chords`
+    async fn songs_with_all_chords(&self, chords: &[Chord]) -> ChordDbResult<Vec<Uuid>>;
+    /// Full text search over title, author and contents, best matches first. When
+    /// `accessible_by` is given only the songs that user can see are returned.
+    async fn search_songs(
+        &self,
+        query: &str,
+        accessible_by: Option<&Uuid>,
+        limit: u64,
+    ) -> ChordDbResult<Vec<SongSearchResult>>;
+
+    /// Role of `user` on the song
+    async fn song_role(&self, user: &User, song: &SongHeader) -> ChordDbResult<Option<SongRole>>;
+    async fn grants(&self, song_id: &Uuid) -> ChordDbResult<Vec<SongGrant>>;
+    async fn upsert_grant(&self, grant: &SongGrant) -> ChordDbResult<()>;
+    async fn delete_grant(&self, song_id: &Uuid, user_id: &Uuid) -> ChordDbResult<()>;
+    async fn band_grants(&self, song_id: &Uuid) -> ChordDbResult<Vec<BandGrant>>;
+    async fn upsert_band_grant(&self, grant: &BandGrant) -> ChordDbResult<()>;
+    async fn delete_band_grant(&self, song_id: &Uuid, band_id: &Uuid) -> ChordDbResult<()>;
+    /// Number of songs owned by a user or band
+    async fn count_owned_songs(&self, owner_id: &Uuid) -> ChordDbResult<u64>;
+
+    async fn share_links(&self, song_id: &Uuid) -> ChordDbResult<Vec<ShareLink>>;
+    async fn create_share_link(
+        &self,
+        song_id: &Uuid,
+        created_by: &Uuid,
+    ) -> ChordDbResult<ShareLink>;
+    async fn delete_share_link(&self, song_id: &Uuid, token: &str) -> ChordDbResult<bool>;
+    /// Song shared through the link with the given token
+    async fn get_shared_song(&self, token: &str) -> ChordDbResult<Option<Song>>;
 }
 
 pub trait ChordRepository: Send + Sync {
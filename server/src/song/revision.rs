@@ -0,0 +1,94 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+use uuid::Uuid;
+
+/// A saved state of a song, without its contents
+#[derive(Debug, Clone, Serialize)]
+pub struct RevisionHeader {
+    pub id: i32,
+    pub song_id: Uuid,
+    /// User that saved the revision
+    pub editor_id: Uuid,
+    pub created_at: NaiveDateTime,
+    pub note: Option<String>,
+    pub author: String,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SongRevision {
+    #[serde(flatten)]
+    pub header: RevisionHeader,
+    pub contents: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct DiffLine {
+    pub op: DiffOp,
+    pub text: String,
+    /// Line number in the old text, starting at 1
+    pub old_line: Option<usize>,
+    /// Line number in the new text, starting at 1
+    pub new_line: Option<usize>,
+}
+
+fn with_final_newline(text: &str) -> String {
+    if text.is_empty() || text.ends_with('\n') {
+        text.to_owned()
+    } else {
+        format!("{}\n", text)
+    }
+}
+
+/// Line by line differences between two versions of a song. A missing newline at the end is
+/// not a difference.
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let old = with_final_newline(old);
+    let new = with_final_newline(new);
+    TextDiff::from_lines(&old, &new)
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            op: match change.tag() {
+                ChangeTag::Equal => DiffOp::Equal,
+                ChangeTag::Insert => DiffOp::Insert,
+                ChangeTag::Delete => DiffOp::Delete,
+            },
+            text: change.value().trim_end_matches(['\r', '\n']).to_owned(),
+            old_line: change.old_index().map(|index| index + 1),
+            new_line: change.new_index().map(|index| index + 1),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_diff_lines() {
+        let diff = diff_lines("Am   G\nhello\nworld", "Am   C\nhello\nworld\n");
+        let ops: Vec<(DiffOp, &str)> = diff.iter().map(|l| (l.op, l.text.as_str())).collect();
+        assert_eq!(
+            ops,
+            vec![
+                (DiffOp::Delete, "Am   G"),
+                (DiffOp::Insert, "Am   C"),
+                (DiffOp::Equal, "hello"),
+                (DiffOp::Equal, "world"),
+            ]
+        );
+        assert_eq!(diff[2].old_line, Some(2));
+        assert_eq!(diff[2].new_line, Some(2));
+        assert_eq!(diff[0].new_line, None);
+    }
+}
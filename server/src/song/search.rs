@@ -0,0 +1,209 @@
+use serde::Serialize;
+
+use super::SongHeader;
+
+pub const SNIPPET_START: &str = "<mark>";
+pub const SNIPPET_END: &str = "</mark>";
+
+/// Placeholders for the snippet markers in the database queries, replaced by `highlight` once
+/// the text is escaped. Private use characters, so they can't be confused with song contents.
+pub const RAW_SNIPPET_START: &str = "\u{E000}";
+pub const RAW_SNIPPET_END: &str = "\u{E001}";
+
+#[derive(Clone, Serialize)]
+pub struct SongSearchResult {
+    pub header: SongHeader,
+    /// HTML-escaped fragment of the best matching column, with the matches surrounded by
+    /// `SNIPPET_START` and `SNIPPET_END`
+    pub snippet: String,
+    /// Lower is better
+    pub rank: f64,
+}
+
+/// Builds an FTS5 query from user input, matching songs that contain all the words, each as a
+/// prefix. Returns `None` if there is nothing to search for.
+pub fn match_query(input: &str) -> Option<String> {
+    let terms: Vec<String> = input
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{}\"*", word))
+        .collect();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// Builds a Postgres `tsquery` from user input, with the same meaning as `match_query`
+pub fn ts_query(input: &str) -> Option<String> {
+    let terms: Vec<String> = words(input)
+        .into_iter()
+        .map(|(start, end)| format!("'{}':*", &input[start..end]))
+        .collect();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" & "))
+    }
+}
+
+/// Escapes a fragment of song text so it can be shown as HTML
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Turns a snippet built by the database with `RAW_SNIPPET_START` and `RAW_SNIPPET_END` around
+/// the matches into an escaped one, marked with `SNIPPET_START` and `SNIPPET_END`
+pub fn highlight(raw: &str) -> String {
+    escape(raw)
+        .replace(RAW_SNIPPET_START, SNIPPET_START)
+        .replace(RAW_SNIPPET_END, SNIPPET_END)
+}
+
+/// Byte ranges of the words in `text`, split like `match_query` does
+fn words(text: &str) -> Vec<(usize, usize)> {
+    let mut words = vec![];
+    let mut start = None;
+    for (index, c) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+    {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(index),
+            (false, Some(begin)) => {
+                words.push((begin, index));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
+
+/// Matches text without the full text index, for stores that do not have one. Every word of
+/// the query has to be the prefix of a word in one of the weighted `columns`. Returns a snippet
+/// of the first matching line and the rank of the match.
+pub fn match_text(input: &str, columns: &[(&str, f64)]) -> Option<(String, f64)> {
+    let terms: Vec<String> = words(input)
+        .into_iter()
+        .map(|(start, end)| input[start..end].to_lowercase())
+        .collect();
+    if terms.is_empty() {
+        return None;
+    }
+    let matches = |word: &str| {
+        let word = word.to_lowercase();
+        terms.iter().any(|term| word.starts_with(term.as_str()))
+    };
+
+    let mut score = 0.0;
+    for term in &terms {
+        let mut found = false;
+        for (text, weight) in columns {
+            let hits = words(text)
+                .into_iter()
+                .filter(|(start, end)| text[*start..*end].to_lowercase().starts_with(term.as_str()))
+                .count();
+            if hits > 0 {
+                found = true;
+                score += weight * hits as f64;
+            }
+        }
+        if !found {
+            return None;
+        }
+    }
+
+    let line = columns
+        .iter()
+        .flat_map(|(text, _)| text.lines())
+        .find(|line| {
+            words(line)
+                .into_iter()
+                .any(|(start, end)| matches(&line[start..end]))
+        })
+        .unwrap_or_default();
+    let mut snippet = String::new();
+    let mut last = 0;
+    for (start, end) in words(line) {
+        if matches(&line[start..end]) {
+            snippet.push_str(&escape(&line[last..start]));
+            snippet.push_str(SNIPPET_START);
+            snippet.push_str(&escape(&line[start..end]));
+            snippet.push_str(SNIPPET_END);
+            last = end;
+        }
+    }
+    snippet.push_str(&escape(&line[last..]));
+    Some((snippet.trim().to_owned(), -score))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_match_query() {
+        assert_eq!(match_query("  "), None);
+        assert_eq!(match_query("\"*()"), None);
+        assert_eq!(match_query("hello"), Some("\"hello\"*".to_owned()));
+        assert_eq!(
+            match_query("Canción del \"mar\" OR x"),
+            Some("\"Canción\"* \"del\"* \"mar\"* \"OR\"* \"x\"*".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_ts_query() {
+        assert_eq!(ts_query("\"*()"), None);
+        assert_eq!(
+            ts_query("Canción & 'mar' | !x"),
+            Some("'Canción':* & 'mar':* & 'x':*".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_match_text() {
+        let columns = [("Canción del mar", 10.0), ("Am\nEl mar, el mar azul", 1.0)];
+        assert_eq!(match_text("  ", &columns), None);
+        assert_eq!(match_text("mar sol", &columns), None);
+        assert_eq!(
+            match_text("MAR", &columns),
+            Some(("Canción del <mark>mar</mark>".to_owned(), -12.0))
+        );
+        assert_eq!(
+            match_text("az", &columns),
+            Some(("El mar, el mar <mark>azul</mark>".to_owned(), -1.0))
+        );
+
+        let columns = [("<b>Tom & Jerry</b>", 1.0)];
+        assert_eq!(
+            match_text("jerry", &columns),
+            Some((
+                "&lt;b&gt;Tom &amp; <mark>Jerry</mark>&lt;/b&gt;".to_owned(),
+                -1.0
+            ))
+        );
+    }
+
+    #[test]
+    fn test_highlight() {
+        assert_eq!(
+            highlight("<script>alert('\u{E000}hi\u{E001}')</script>"),
+            "&lt;script&gt;alert(&#39;<mark>hi</mark>&#39;)&lt;/script&gt;"
+        );
+    }
+}
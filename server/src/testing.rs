@@ -0,0 +1,222 @@
+//! Databases for the tests of the SeaORM stores. Every test gets a fresh SQLite database in
+//! memory and, when the Postgres binaries are available, a throwaway Postgres cluster, so the
+//! same test runs against both backends.
+//!
+//! Also has the in-memory state for the tests of the web handlers, and users to fill it with.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use axum::async_trait;
+use migration::{Migrator, MigratorTrait};
+use sea_orm::{Database, DatabaseConnection};
+use uuid::Uuid;
+
+use crate::{
+    account::{AccountSettings, MemoryAccounts, Registration},
+    api_token::MemoryApiTokens,
+    band::{Bands, MemoryBands},
+    error::{ChordDbError, ChordDbResult},
+    instrument::MemoryInstruments,
+    mail::{Email, Mailer},
+    session::MemorySessions,
+    setlist::MemorySetlists,
+    song::{CachedChords, FingeringCalculator, MemorySongs},
+    user::{MemoryUsers, User},
+    web::AppState,
+};
+
+pub struct TestDatabase {
+    pub name: &'static str,
+    pub db: DatabaseConnection,
+    _cluster: Option<PostgresCluster>,
+}
+
+/// A Postgres cluster in a temporary directory, only listening on a socket in that directory.
+/// It is stopped and removed when dropped.
+struct PostgresCluster {
+    dir: PathBuf,
+}
+
+/// Postgres binaries are looked up in `PG_BIN`, if set, or in the `PATH`
+fn pg_command(name: &str) -> Command {
+    match std::env::var_os("PG_BIN") {
+        Some(dir) => Command::new(Path::new(&dir).join(name)),
+        None => Command::new(name),
+    }
+}
+
+fn run(command: &mut Command) -> Result<(), String> {
+    let output = command
+        .output()
+        .map_err(|err| format!("{:?}: {}", command.get_program(), err))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{:?}: {}",
+            command.get_program(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+impl PostgresCluster {
+    fn start() -> Result<Self, String> {
+        let dir = std::env::temp_dir().join(format!("chorddb-{}", Uuid::new_v4().simple()));
+        std::fs::create_dir(&dir).map_err(|err| err.to_string())?;
+        let cluster = PostgresCluster { dir };
+        let data = cluster.dir.join("data");
+        run(pg_command("initdb")
+            .arg("--pgdata")
+            .arg(&data)
+            .args(["--username=postgres", "--auth=trust", "--no-sync"])
+            .args(["--encoding=UTF8", "--locale=C"]))?;
+        run(pg_command("pg_ctl")
+            .arg("--pgdata")
+            .arg(&data)
+            .arg("--log")
+            .arg(cluster.dir.join("postgres.log"))
+            .arg("--options")
+            .arg(format!(
+                "-k {} -c listen_addresses='' -c fsync=off",
+                cluster.dir.display()
+            ))
+            .args(["--wait", "start"]))?;
+        Ok(cluster)
+    }
+
+    fn url(&self) -> String {
+        format!(
+            "postgres://postgres@localhost/postgres?host={}",
+            self.dir.display()
+        )
+    }
+}
+
+impl Drop for PostgresCluster {
+    fn drop(&mut self) {
+        let _ = run(pg_command("pg_ctl")
+            .arg("--pgdata")
+            .arg(self.dir.join("data"))
+            .args(["--mode=immediate", "stop"]));
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+async fn connect(name: &'static str, url: &str, cluster: Option<PostgresCluster>) -> TestDatabase {
+    let db = Database::connect(url)
+        .await
+        .unwrap_or_else(|err| panic!("Could not connect to {}: {}", url, err));
+    Migrator::up(&db, None)
+        .await
+        .unwrap_or_else(|err| panic!("Could not migrate the {} database: {}", name, err));
+    TestDatabase {
+        name,
+        db,
+        _cluster: cluster,
+    }
+}
+
+/// Set to `1` to fail the database tests when Postgres can't be started, instead of skipping it
+const REQUIRE_POSTGRES_VAR: &str = "CHORDDB_TEST_POSTGRES";
+
+/// Fresh, migrated databases of every backend available
+pub async fn test_databases() -> Vec<TestDatabase> {
+    let mut databases = vec![connect("sqlite", "sqlite::memory:", None).await];
+    match PostgresCluster::start() {
+        Ok(cluster) => {
+            let url = cluster.url();
+            databases.push(connect("postgres", &url, Some(cluster)).await);
+        }
+        Err(err) if std::env::var(REQUIRE_POSTGRES_VAR).is_ok_and(|value| value == "1") => {
+            panic!(
+                "Postgres is required by {} but could not be started: {}",
+                REQUIRE_POSTGRES_VAR, err
+            )
+        }
+        Err(err) => log::warn!(
+            "Skipping the Postgres tests, it could not be started: {}",
+            err
+        ),
+    }
+    databases
+}
+
+/// Keeps the emails instead of sending them
+#[derive(Default)]
+pub struct TestMailer {
+    pub sent: Mutex<Vec<Email>>,
+    /// Fails to send emails while set
+    pub failing: AtomicBool,
+}
+
+#[async_trait]
+impl Mailer for TestMailer {
+    async fn send(&self, email: &Email) -> ChordDbResult<()> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Err(ChordDbError::Generic("The mail server is down".into()));
+        }
+        self.sent.lock().unwrap().push(email.clone());
+        Ok(())
+    }
+}
+
+/// State of a server without a database or users, with open registration
+pub fn memory_state() -> AppState {
+    let bands: Arc<dyn Bands> = Arc::new(MemoryBands::new());
+    AppState {
+        songs: Arc::new(MemorySongs::new().with_bands(bands.clone())),
+        users: Arc::new(MemoryUsers::default()),
+        bands,
+        sessions: Arc::new(MemorySessions::new()),
+        setlists: Arc::new(MemorySetlists::new()),
+        chords: Arc::new(CachedChords::new(FingeringCalculator {})),
+        instruments: Arc::new(MemoryInstruments::new()),
+        accounts: Arc::new(MemoryAccounts::new()),
+        mailer: Arc::new(TestMailer::default()),
+        account_settings: Arc::new(AccountSettings {
+            registration: Registration::Open,
+            public_url: "http://localhost:8080".to_string(),
+        }),
+        api_tokens: Arc::new(MemoryApiTokens::new()),
+        oidc: None,
+    }
+}
+
+/// A verified user without a password, with the email `<name>@example.com`
+pub fn user(name: &str) -> User {
+    User {
+        id: Uuid::new_v4(),
+        name: name.to_string(),
+        email: format!("{}@example.com", name),
+        password: String::new(),
+        is_admin: false,
+        email_verified: true,
+        note_locale: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test(tokio::test)]
+    async fn test_migrations_round_trip() {
+        for database in test_databases().await {
+            Migrator::down(&database.db, None)
+                .await
+                .unwrap_or_else(|err| panic!("{}: {}", database.name, err));
+            Migrator::up(&database.db, None)
+                .await
+                .unwrap_or_else(|err| panic!("{}: {}", database.name, err));
+        }
+    }
+}
@@ -0,0 +1,332 @@
+use uuid::Uuid;
+
+use crate::{
+    api_token::ApiTokens,
+    error::{ChordDbError, ChordDbResult},
+    session::Sessions,
+};
+
+use super::{check_name_and_email, hash_password, is_hashed, normalize_email, User, Users};
+
+#[derive(clap::Subcommand, Debug)]
+pub enum UserCommand {
+    /// Create a user
+    Add {
+        name: String,
+        email: String,
+        /// password of the user. A random one is generated and printed if not given
+        #[clap(long = "password")]
+        password: Option<String>,
+        /// make the user an admin
+        #[clap(long = "admin")]
+        admin: bool,
+    },
+    /// List every user
+    List,
+    /// Change the password of a user, logging it out and revoking its API tokens
+    SetPassword {
+        /// id, name or email of the user
+        user: String,
+        /// new password. A random one is generated and printed if not given
+        #[clap(long = "password")]
+        password: Option<String>,
+    },
+    /// Make a user an admin
+    Promote {
+        /// id, name or email of the user
+        user: String,
+    },
+    /// Take the admin rights from a user
+    Demote {
+        /// id, name or email of the user
+        user: String,
+    },
+    /// Delete a user, log it out everywhere and revoke its API tokens. Its songs are kept
+    Delete {
+        /// id, name or email of the user
+        user: String,
+    },
+    /// Hash the passwords still stored in plaintext
+    HashPasswords,
+    /// Manage the sessions of a user
+    Sessions {
+        #[clap(subcommand)]
+        command: SessionCommand,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum SessionCommand {
+    /// Log a user out of every device and revoke its API tokens
+    Revoke {
+        /// id, name or email of the user
+        user: String,
+    },
+}
+
+fn generate_password() -> String {
+    Uuid::new_v4().simple().to_string()[..20].to_owned()
+}
+
+/// Hash of the given password, or of a random one that is printed so it can be handed to the
+/// user
+fn password_or_generated(password: &Option<String>) -> ChordDbResult<String> {
+    let password = password.clone().unwrap_or_else(|| {
+        let password = generate_password();
+        println!("Password: {}", password);
+        password
+    });
+    hash_password(&password)
+}
+
+async fn find_user(users: &dyn Users, id: &str) -> ChordDbResult<User> {
+    users
+        .get_user(id)
+        .await?
+        .ok_or_else(|| ChordDbError::BadRequest(format!("Unknown user '{}'", id)))
+}
+
+pub async fn run_command(
+    users: &dyn Users,
+    sessions: &dyn Sessions,
+    api_tokens: &dyn ApiTokens,
+    command: &UserCommand,
+) -> ChordDbResult<()> {
+    match command {
+        UserCommand::Add {
+            name,
+            email,
+            password,
+            admin,
+        } => {
+            let name = name.trim();
+            let email = normalize_email(email);
+            check_name_and_email(name, &email)?;
+            for id in [name, &email] {
+                if users.get_user(id).await?.is_some() {
+                    return Err(ChordDbError::BadRequest(format!(
+                        "There is already a user '{}'",
+                        id
+                    )));
+                }
+            }
+            let user = User {
+                id: Uuid::new_v4(),
+                name: name.to_string(),
+                email,
+                password: password_or_generated(password)?,
+                is_admin: *admin,
+                email_verified: true,
+                note_locale: None,
+            };
+            users.upsert_user(&user).await?;
+            println!("Created user {} ({})", user.name, user.id);
+        }
+        UserCommand::List => {
+            for user in users.list_users().await? {
+                let line = format!(
+                    "{}  {:<20} {:<30} {}",
+                    user.id,
+                    user.name,
+                    user.email,
+                    if user.is_admin { "admin" } else { "" }
+                );
+                println!("{}", line.trim_end());
+            }
+        }
+        UserCommand::SetPassword { user, password } => {
+            let mut user = find_user(users, user).await?;
+            user.password = password_or_generated(password)?;
+            users.upsert_user(&user).await?;
+            sessions.delete_user_sessions(&user.id).await?;
+            api_tokens.delete_user_tokens(&user.id).await?;
+            println!("Changed the password of {}", user.name);
+        }
+        UserCommand::Promote { user } | UserCommand::Demote { user } => {
+            let mut user = find_user(users, user).await?;
+            user.is_admin = matches!(command, UserCommand::Promote { .. });
+            users.upsert_user(&user).await?;
+            println!(
+                "{} is {} an admin",
+                user.name,
+                if user.is_admin { "now" } else { "no longer" }
+            );
+        }
+        UserCommand::Delete { user } => {
+            let user = find_user(users, user).await?;
+            sessions.delete_user_sessions(&user.id).await?;
+            api_tokens.delete_user_tokens(&user.id).await?;
+            users.delete_user(&user.id).await?;
+            println!("Deleted user {}", user.name);
+        }
+        UserCommand::HashPasswords => {
+            let mut hashed = 0;
+            for mut user in users.list_users().await? {
+                if user.password.is_empty() || is_hashed(&user.password) {
+                    continue;
+                }
+                user.password = hash_password(&user.password)?;
+                users.upsert_user(&user).await?;
+                hashed += 1;
+            }
+            println!("Hashed {} passwords", hashed);
+        }
+        UserCommand::Sessions {
+            command: SessionCommand::Revoke { user },
+        } => {
+            let user = find_user(users, user).await?;
+            let deleted = sessions.delete_user_sessions(&user.id).await?;
+            let tokens = api_tokens.delete_user_tokens(&user.id).await?;
+            println!(
+                "Revoked {} sessions and {} API tokens of {}",
+                deleted, tokens, user.name
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, Utc};
+
+    use crate::{
+        api_token::{ApiToken, MemoryApiTokens, TokenScope},
+        session::{MemorySessions, Session},
+        user::{check_password, MemoryUsers, PasswordCheck},
+    };
+
+    use super::*;
+    use test_log::test;
+
+    #[test(tokio::test)]
+    async fn test_user_commands() {
+        let users = MemoryUsers::default();
+        let sessions = MemorySessions::new();
+        let api_tokens = MemoryApiTokens::new();
+        let run = |command| {
+            let users = &users;
+            let sessions = &sessions;
+            let api_tokens = &api_tokens;
+            async move { run_command(users, sessions, api_tokens, &command).await }
+        };
+
+        run(UserCommand::Add {
+            name: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            password: Some("secret".to_string()),
+            admin: false,
+        })
+        .await
+        .unwrap();
+        let duplicate = run(UserCommand::Add {
+            name: "other".to_string(),
+            email: "alice@example.com".to_string(),
+            password: None,
+            admin: false,
+        })
+        .await;
+        assert!(matches!(duplicate, Err(ChordDbError::BadRequest(_))));
+        // Names can not shadow the emails or ids of other users
+        let alice = users.get_user("alice").await.unwrap().unwrap();
+        for (name, email) in [
+            ("bob@example.com", "bob@example.com"),
+            (alice.id.to_string().as_str(), "bob@example.com"),
+            ("bob", "bob"),
+            ("", "bob@example.com"),
+        ] {
+            let invalid = run(UserCommand::Add {
+                name: name.to_string(),
+                email: email.to_string(),
+                password: None,
+                admin: false,
+            })
+            .await;
+            assert!(matches!(invalid, Err(ChordDbError::BadRequest(_))));
+        }
+        assert_eq!(users.list_users().await.unwrap().len(), 1);
+        assert_eq!(
+            check_password("secret", &alice.password),
+            PasswordCheck::Valid
+        );
+        assert!(!alice.is_admin);
+
+        run(UserCommand::Promote {
+            user: "alice@example.com".to_string(),
+        })
+        .await
+        .unwrap();
+        assert!(users.get_user("alice").await.unwrap().unwrap().is_admin);
+        run(UserCommand::Demote {
+            user: alice.id.to_string(),
+        })
+        .await
+        .unwrap();
+        assert!(!users.get_user("alice").await.unwrap().unwrap().is_admin);
+
+        let log_in = |id: &'static str| {
+            let sessions = &sessions;
+            let api_tokens = &api_tokens;
+            async move {
+                sessions
+                    .upsert_session(Session {
+                        id: id.to_string(),
+                        user_id: alice.id.to_string(),
+                        expires_at: Utc::now().naive_utc(),
+                    })
+                    .await
+                    .unwrap();
+                let (token, _) =
+                    ApiToken::generate(alice.id, id, TokenScope::Write, TimeDelta::days(1));
+                api_tokens.insert_token(&token).await.unwrap();
+            }
+        };
+        let api_token_count = || async { api_tokens.user_tokens(&alice.id).await.unwrap().len() };
+        log_in("a").await;
+        run(UserCommand::SetPassword {
+            user: "alice".to_string(),
+            password: None,
+        })
+        .await
+        .unwrap();
+        let password = users.get_user("alice").await.unwrap().unwrap().password;
+        assert_eq!(check_password("secret", &password), PasswordCheck::Invalid);
+        assert!(sessions.get_session("a").await.unwrap().is_none());
+        assert_eq!(api_token_count().await, 0);
+
+        let mut legacy = alice.clone();
+        legacy.password = "plaintext".to_string();
+        users.upsert_user(&legacy).await.unwrap();
+        run(UserCommand::HashPasswords).await.unwrap();
+        let password = users.get_user("alice").await.unwrap().unwrap().password;
+        assert_eq!(check_password("plaintext", &password), PasswordCheck::Valid);
+
+        for id in ["a", "b"] {
+            log_in(id).await;
+        }
+        run(UserCommand::Sessions {
+            command: SessionCommand::Revoke {
+                user: "alice".to_string(),
+            },
+        })
+        .await
+        .unwrap();
+        assert!(sessions.get_session("a").await.unwrap().is_none());
+        assert_eq!(api_token_count().await, 0);
+
+        log_in("c").await;
+        run(UserCommand::Delete {
+            user: "alice".to_string(),
+        })
+        .await
+        .unwrap();
+        assert!(sessions.get_session("c").await.unwrap().is_none());
+        assert_eq!(api_token_count().await, 0);
+        assert!(users.list_users().await.unwrap().is_empty());
+        assert!(run(UserCommand::Delete {
+            user: "alice".to_string(),
+        })
+        .await
+        .is_err());
+    }
+}
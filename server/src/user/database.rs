@@ -1,13 +1,17 @@
 use axum::async_trait;
-use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use sea_orm::{
+    sea_query::{Expr, Func, OnConflict},
+    ColumnTrait, DatabaseConnection, EntityTrait, Iterable, QueryFilter, QueryOrder,
+};
 use uuid::Uuid;
 
 use crate::{
+    chord::NoteLocale,
     entities::{prelude::User as UserEntity, user},
-    error::ChordDbResult,
+    error::{ChordDbError, ChordDbResult},
 };
 
-use super::{User, Users};
+use super::{normalize_email, User, Users};
 
 pub struct SeaOrmUsers {
     db: DatabaseConnection,
@@ -20,33 +24,136 @@ impl SeaOrmUsers {
 
     async fn get_user_by_id(&self, id: &str) -> ChordDbResult<Option<user::Model>> {
         if let Some(user) = UserEntity::find_by_id(id).one(&self.db).await? {
-            Ok(Some(user))
-        } else if let Some(user) = UserEntity::find()
-            .filter(user::Column::Name.eq(id))
-            .one(&self.db)
-            .await?
-        {
-            Ok(Some(user))
-        } else {
-            Ok(UserEntity::find()
-                .filter(user::Column::Email.eq(id))
-                .one(&self.db)
-                .await?)
+            return Ok(Some(user));
+        }
+        let user = self.find_by_name(id).await?;
+        if user.is_some() {
+            return Ok(user);
         }
+        self.find_by_email(id).await
     }
+
+    async fn find_by_name(&self, name: &str) -> ChordDbResult<Option<user::Model>> {
+        Ok(UserEntity::find()
+            .filter(user::Column::Name.eq(name))
+            .one(&self.db)
+            .await?)
+    }
+
+    async fn find_by_email(&self, email: &str) -> ChordDbResult<Option<user::Model>> {
+        // Older versions stored emails as they were written
+        Ok(UserEntity::find()
+            .filter(
+                Expr::expr(Func::lower(Expr::col(user::Column::Email))).eq(normalize_email(email)),
+            )
+            .one(&self.db)
+            .await?)
+    }
+}
+
+fn build_user(model: user::Model) -> ChordDbResult<User> {
+    Ok(User {
+        id: Uuid::parse_str(&model.id).map_err(|err| {
+            ChordDbError::InvalidData(format!("Invalid uuid: '{}'. Err: {}", model.id, err))
+        })?,
+        name: model.name,
+        email: model.email,
+        password: model.password,
+        is_admin: model.is_admin,
+        email_verified: model.email_verified,
+        note_locale: model.note_locale.as_deref().and_then(NoteLocale::parse),
+    })
 }
 
 #[async_trait]
 impl Users for SeaOrmUsers {
     async fn get_user(&self, id: &str) -> ChordDbResult<Option<User>> {
-        let entity = self.get_user_by_id(id).await?;
-
-        Ok(entity.map(|model| User {
-            id: Uuid::parse_str(&model.id).unwrap(),
-            name: model.name,
-            email: model.email,
-            password: model.password,
-            is_admin: model.is_admin,
-        }))
+        self.get_user_by_id(id).await?.map(build_user).transpose()
+    }
+
+    async fn get_user_by_name(&self, name: &str) -> ChordDbResult<Option<User>> {
+        self.find_by_name(name).await?.map(build_user).transpose()
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> ChordDbResult<Option<User>> {
+        self.find_by_email(email).await?.map(build_user).transpose()
+    }
+
+    async fn list_users(&self) -> ChordDbResult<Vec<User>> {
+        UserEntity::find()
+            .order_by_asc(user::Column::Name)
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(build_user)
+            .collect()
+    }
+
+    async fn upsert_user(&self, user: &User) -> ChordDbResult<()> {
+        let model = user::Model {
+            id: user.id.to_string(),
+            name: user.name.clone(),
+            email: user.email.clone(),
+            password: user.password.clone(),
+            is_admin: user.is_admin,
+            email_verified: user.email_verified,
+            note_locale: user.note_locale.map(|locale| locale.id().to_string()),
+        };
+        UserEntity::insert(user::ActiveModel::from(model))
+            .on_conflict(
+                OnConflict::column(user::Column::Id)
+                    .update_columns(user::Column::iter())
+                    .to_owned(),
+            )
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_user(&self, id: &Uuid) -> ChordDbResult<bool> {
+        let result = UserEntity::delete_by_id(id.to_string())
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{test_databases, user};
+
+    use super::*;
+    use test_log::test;
+
+    #[test(tokio::test)]
+    async fn test_upsert_user() {
+        for database in test_databases().await {
+            let users = SeaOrmUsers::new(database.db.clone());
+            let mut user = User {
+                password: "secret".to_string(),
+                note_locale: Some(NoteLocale::Solfege),
+                ..user("user")
+            };
+            users.upsert_user(&user).await.unwrap();
+            user.is_admin = true;
+            users.upsert_user(&user).await.unwrap();
+
+            let ids = [
+                user.id.to_string(),
+                user.name.clone(),
+                user.email.clone(),
+                "User@Example.com".to_string(),
+            ];
+            for id in ids {
+                let stored = users.get_user(&id).await.unwrap().unwrap();
+                assert_eq!(stored.id, user.id, "{}", database.name);
+                assert!(stored.is_admin);
+                assert_eq!(stored.note_locale, Some(NoteLocale::Solfege));
+            }
+            assert!(users.get_user("USER").await.unwrap().is_none());
+            assert_eq!(users.list_users().await.unwrap().len(), 1);
+            assert!(users.delete_user(&user.id).await.unwrap());
+            assert!(users.get_user(&user.name).await.unwrap().is_none());
+        }
     }
 }
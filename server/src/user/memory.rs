@@ -0,0 +1,70 @@
+use axum::async_trait;
+use dashmap::DashMap;
+use uuid::Uuid;
+
+use crate::error::ChordDbResult;
+
+use super::{normalize_email, User, Users};
+
+/// Users kept in memory, for servers running without a database
+#[derive(Default)]
+pub struct MemoryUsers {
+    users: DashMap<Uuid, User>,
+}
+
+impl MemoryUsers {
+    pub fn new(users: impl IntoIterator<Item = User>) -> Self {
+        Self {
+            users: users.into_iter().map(|user| (user.id, user)).collect(),
+        }
+    }
+}
+
+impl MemoryUsers {
+    fn find(&self, matches: impl Fn(&User) -> bool) -> Option<User> {
+        self.users
+            .iter()
+            .find(|user| matches(user.value()))
+            .map(|user| user.value().clone())
+    }
+}
+
+#[async_trait]
+impl Users for MemoryUsers {
+    async fn get_user(&self, id: &str) -> ChordDbResult<Option<User>> {
+        if let Some(user) = Uuid::parse_str(id)
+            .ok()
+            .and_then(|uuid| self.users.get(&uuid))
+        {
+            return Ok(Some(user.value().clone()));
+        }
+        if let Some(user) = self.get_user_by_name(id).await? {
+            return Ok(Some(user));
+        }
+        self.get_user_by_email(id).await
+    }
+
+    async fn get_user_by_name(&self, name: &str) -> ChordDbResult<Option<User>> {
+        Ok(self.find(|user| user.name == name))
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> ChordDbResult<Option<User>> {
+        let email = normalize_email(email);
+        Ok(self.find(|user| normalize_email(&user.email) == email))
+    }
+
+    async fn list_users(&self) -> ChordDbResult<Vec<User>> {
+        let mut users: Vec<User> = self.users.iter().map(|user| user.value().clone()).collect();
+        users.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(users)
+    }
+
+    async fn upsert_user(&self, user: &User) -> ChordDbResult<()> {
+        self.users.insert(user.id, user.clone());
+        Ok(())
+    }
+
+    async fn delete_user(&self, id: &Uuid) -> ChordDbResult<bool> {
+        Ok(self.users.remove(id).is_some())
+    }
+}
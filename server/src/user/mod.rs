@@ -1,11 +1,20 @@
 use axum::async_trait;
 use uuid::Uuid;
 
-use crate::error::ChordDbResult;
+use crate::{
+    chord::NoteLocale,
+    error::{ChordDbError, ChordDbResult},
+};
 
+mod command;
 mod database;
+mod memory;
+mod password;
 
+pub use command::{run_command, SessionCommand, UserCommand};
 pub use database::SeaOrmUsers;
+pub use memory::MemoryUsers;
+pub use password::{check_password, hash_password, is_hashed, PasswordCheck};
 
 #[derive(Debug, Clone)]
 pub struct User {
@@ -14,9 +23,44 @@ pub struct User {
     pub email: String,
     pub password: String,
     pub is_admin: bool,
+    /// Users that registered themselves can not log in until they verify their email
+    pub email_verified: bool,
+    pub note_locale: Option<NoteLocale>,
+}
+
+/// Emails are stored in lowercase, so they are unique regardless of case
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Users are found by id, name or email, so names can not look like the others
+pub fn check_name_and_email(name: &str, email: &str) -> ChordDbResult<()> {
+    if name.is_empty() || name.contains('@') || Uuid::parse_str(name).is_ok() {
+        return Err(ChordDbError::BadRequest(format!("Invalid name '{}'", name)));
+    }
+    let valid_email = email
+        .split_once('@')
+        .is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.'))
+        && !email.contains(char::is_whitespace);
+    if !valid_email {
+        return Err(ChordDbError::BadRequest(format!(
+            "Invalid email address '{}'",
+            email
+        )));
+    }
+    Ok(())
 }
 
 #[async_trait]
 pub trait Users: Send + Sync {
+    /// Finds a user by id, name or email. Emails match regardless of case.
     async fn get_user(&self, id: &str) -> ChordDbResult<Option<User>>;
+    async fn get_user_by_name(&self, name: &str) -> ChordDbResult<Option<User>>;
+    /// Finds a user by email, regardless of case
+    async fn get_user_by_email(&self, email: &str) -> ChordDbResult<Option<User>>;
+    /// Every user, by name
+    async fn list_users(&self) -> ChordDbResult<Vec<User>>;
+    /// Creates the user or replaces the one with the same id
+    async fn upsert_user(&self, user: &User) -> ChordDbResult<()>;
+    async fn delete_user(&self, id: &Uuid) -> ChordDbResult<bool>;
 }
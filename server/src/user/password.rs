@@ -0,0 +1,109 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+use subtle::ConstantTimeEq;
+
+use crate::error::{ChordDbError, ChordDbResult};
+
+/// Result of checking a submitted password against the stored one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordCheck {
+    Invalid,
+    Valid,
+    /// Valid, but stored in plaintext or hashed with outdated parameters. It should be hashed
+    /// again and stored.
+    Outdated,
+}
+
+fn hasher(params: Params) -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+}
+
+/// Hashes a password with argon2id, as a PHC string with the parameters and salt
+pub fn hash_password(password: &str) -> ChordDbResult<String> {
+    hash_with_params(password, Params::default())
+}
+
+fn hash_with_params(password: &str, params: Params) -> ChordDbResult<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    hasher(params)
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|err| ChordDbError::Generic(Box::new(err)))
+}
+
+/// Whether the stored password is a hash. Anything else is a legacy plaintext password
+pub fn is_hashed(stored: &str) -> bool {
+    PasswordHash::new(stored).is_ok()
+}
+
+fn has_current_params(hash: &PasswordHash) -> bool {
+    let current = Params::default();
+    hash.algorithm == Algorithm::Argon2id.ident()
+        && hash.version == Some(Version::V0x13.into())
+        && Params::try_from(hash).is_ok_and(|params| {
+            params.m_cost() == current.m_cost()
+                && params.t_cost() == current.t_cost()
+                && params.p_cost() == current.p_cost()
+        })
+}
+
+/// Checks a submitted password in constant time. Users without a password can not log in with
+/// one.
+pub fn check_password(submitted: &str, stored: &str) -> PasswordCheck {
+    if stored.is_empty() {
+        return PasswordCheck::Invalid;
+    }
+    match PasswordHash::new(stored) {
+        Ok(hash) => {
+            // Verifies with the algorithm and parameters of the stored hash
+            if Argon2::default()
+                .verify_password(submitted.as_bytes(), &hash)
+                .is_err()
+            {
+                PasswordCheck::Invalid
+            } else if has_current_params(&hash) {
+                PasswordCheck::Valid
+            } else {
+                PasswordCheck::Outdated
+            }
+        }
+        Err(_) => {
+            if bool::from(submitted.as_bytes().ct_eq(stored.as_bytes())) {
+                PasswordCheck::Outdated
+            } else {
+                PasswordCheck::Invalid
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_check_password() {
+        let hash = hash_password("secret").unwrap();
+        assert!(hash.starts_with("$argon2id$v=19$"));
+        assert!(is_hashed(&hash));
+        assert_ne!(hash, hash_password("secret").unwrap());
+        assert_eq!(check_password("secret", &hash), PasswordCheck::Valid);
+        assert_eq!(check_password("Secret", &hash), PasswordCheck::Invalid);
+        assert_eq!(check_password("", &hash), PasswordCheck::Invalid);
+    }
+
+    #[test]
+    fn test_check_legacy_password() {
+        assert!(!is_hashed("secret"));
+        assert_eq!(check_password("secret", "secret"), PasswordCheck::Outdated);
+        assert_eq!(check_password("secre", "secret"), PasswordCheck::Invalid);
+        assert_eq!(check_password("", ""), PasswordCheck::Invalid);
+
+        let weak = hash_with_params("secret", Params::new(1024, 1, 1, None).unwrap()).unwrap();
+        assert_eq!(check_password("secret", &weak), PasswordCheck::Outdated);
+        assert_eq!(check_password("other", &weak), PasswordCheck::Invalid);
+    }
+}
@@ -0,0 +1,775 @@
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use tower_cookies::Cookies;
+use uuid::Uuid;
+
+use crate::{
+    account::{issue_token, redeem_token, Invite, Registration, TokenPurpose},
+    error::{ChordDbError, ChordDbResult},
+    mail::Email,
+    user::{
+        check_name_and_email, check_password, hash_password, normalize_email, PasswordCheck, User,
+    },
+};
+
+use super::{api::SimpleApiResult, auth::initialize_session, song::parse_note_locale, AppState};
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+fn check_new_password(password: &str) -> ChordDbResult<()> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(ChordDbError::BadRequest(format!(
+            "The password must have at least {} characters",
+            MIN_PASSWORD_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+fn invalid_token() -> ChordDbError {
+    ChordDbError::BadRequest("The link is invalid or has expired".to_string())
+}
+
+async fn send_verification(state: &AppState, user: &User) -> ChordDbResult<()> {
+    let token = issue_token(state.accounts.as_ref(), &user.id, TokenPurpose::VerifyEmail).await?;
+    let link = state
+        .account_settings
+        .link(&format!("/verify_email?token={}", token));
+    state
+        .mailer
+        .send(&Email {
+            to: user.email.clone(),
+            subject: "Verify your email address".to_string(),
+            body: format!(
+                "Hi {},\n\nOpen this link to verify your email address and start using ChordDB:\n\n{}\n\nThe link expires in {} hours.",
+                user.name,
+                link,
+                TokenPurpose::VerifyEmail.lifetime().num_hours()
+            ),
+        })
+        .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterPayload {
+    name: String,
+    email: String,
+    password: String,
+    invite: Option<String>,
+}
+
+pub async fn register(
+    State(state): State<AppState>,
+    Json(payload): Json<RegisterPayload>,
+) -> ChordDbResult<Json<SimpleApiResult>> {
+    let now = Utc::now().naive_utc();
+    let invite = match state.account_settings.registration {
+        Registration::Disabled => {
+            return Err(ChordDbError::BadRequest(
+                "Registration is disabled".to_string(),
+            ))
+        }
+        Registration::Invite => {
+            let invite = match payload.invite.as_deref() {
+                Some(code) => state.accounts.get_invite(code).await?,
+                None => None,
+            };
+            match invite {
+                Some(invite) if invite.is_usable(now) => Some(invite),
+                _ => {
+                    return Err(ChordDbError::BadRequest(
+                        "A valid invite code is required to register".to_string(),
+                    ))
+                }
+            }
+        }
+        Registration::Open => None,
+    };
+
+    let name = payload.name.trim();
+    let email = normalize_email(&payload.email);
+    let email = email.as_str();
+    check_name_and_email(name, email)?;
+    check_new_password(&payload.password)?;
+    for id in [name, email] {
+        if state.users.get_user(id).await?.is_some() {
+            return Err(ChordDbError::BadRequest(format!(
+                "There is already a user '{}'",
+                id
+            )));
+        }
+    }
+
+    let user = User {
+        id: Uuid::new_v4(),
+        name: name.to_string(),
+        email: email.to_string(),
+        password: hash_password(&payload.password)?,
+        is_admin: false,
+        email_verified: false,
+        note_locale: None,
+    };
+    state.users.upsert_user(&user).await?;
+    if let Some(invite) = &invite {
+        if !state
+            .accounts
+            .redeem_invite(&invite.code, &user.id, now)
+            .await?
+        {
+            state.users.delete_user(&user.id).await?;
+            return Err(ChordDbError::BadRequest(
+                "The invite code has already been used".to_string(),
+            ));
+        }
+    }
+    let sent = send_verification(&state, &user).await.map_err(|err| {
+        log::warn!(
+            "Could not send the verification email to {}: {}",
+            user.email,
+            err
+        );
+    });
+    if sent.is_err() {
+        // Undo the registration, so it can be tried again
+        if let Some(invite) = invite {
+            state
+                .accounts
+                .release_invite(&invite.code, &user.id)
+                .await?;
+        }
+        state.users.delete_user(&user.id).await?;
+        return Err(ChordDbError::Generic(
+            "The verification email could not be sent".into(),
+        ));
+    }
+    log::info!("Registered user {}", user.name);
+
+    Ok(Json(SimpleApiResult::simple_success(
+        "Check your email to verify your address",
+    )))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenPayload {
+    token: String,
+}
+
+pub async fn verify_email(
+    State(state): State<AppState>,
+    Json(payload): Json<TokenPayload>,
+) -> ChordDbResult<Json<SimpleApiResult>> {
+    let Some(user_id) = redeem_token(
+        state.accounts.as_ref(),
+        &payload.token,
+        TokenPurpose::VerifyEmail,
+    )
+    .await?
+    else {
+        return Err(invalid_token());
+    };
+    let Some(mut user) = state.users.get_user(&user_id.to_string()).await? else {
+        return Err(invalid_token());
+    };
+    user.email_verified = true;
+    state.users.upsert_user(&user).await?;
+
+    Ok(Json(SimpleApiResult::simple_success("Email verified")))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AccountPayload {
+    /// name or email of the user
+    user: String,
+}
+
+/// Sends a new verification email. Answers the same whether the user exists or not.
+pub async fn resend_verification(
+    State(state): State<AppState>,
+    Json(payload): Json<AccountPayload>,
+) -> ChordDbResult<Json<SimpleApiResult>> {
+    let user = state.users.get_user(&payload.user).await?;
+    if let Some(user) = user {
+        if !user.email_verified {
+            // Failures are only logged, or they would tell that the account exists
+            if let Err(err) = send_verification(&state, &user).await {
+                log::warn!(
+                    "Could not send the verification email to {}: {}",
+                    user.email,
+                    err
+                );
+            }
+        }
+    }
+    Ok(Json(SimpleApiResult::simple_success(
+        "If the account needs it, a verification email has been sent",
+    )))
+}
+
+/// Emails a password reset link. Answers the same whether the user exists or not.
+pub async fn forgot_password(
+    State(state): State<AppState>,
+    Json(payload): Json<AccountPayload>,
+) -> ChordDbResult<Json<SimpleApiResult>> {
+    let user = state.users.get_user(&payload.user).await?;
+    if let Some(user) = user {
+        let token = issue_token(
+            state.accounts.as_ref(),
+            &user.id,
+            TokenPurpose::ResetPassword,
+        )
+        .await?;
+        let link = state
+            .account_settings
+            .link(&format!("/reset_password?token={}", token));
+        let sent = state
+            .mailer
+            .send(&Email {
+                to: user.email.clone(),
+                subject: "Reset your password".to_string(),
+                body: format!(
+                    "Hi {},\n\nOpen this link to choose a new password:\n\n{}\n\nThe link expires in {} minutes. If you did not ask for it, you can ignore this email.",
+                    user.name,
+                    link,
+                    TokenPurpose::ResetPassword.lifetime().num_minutes()
+                ),
+            })
+            .await;
+        // Failures are only logged, or they would tell that the account exists
+        if let Err(err) = sent {
+            log::warn!(
+                "Could not send the password reset email to {}: {}",
+                user.email,
+                err
+            );
+        }
+    }
+    Ok(Json(SimpleApiResult::simple_success(
+        "If the account exists, a password reset email has been sent",
+    )))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetPasswordPayload {
+    token: String,
+    password: String,
+}
+
+/// Sets a new password with a reset token, logs the user out everywhere and revokes their API
+/// tokens
+pub async fn reset_password(
+    State(state): State<AppState>,
+    Json(payload): Json<ResetPasswordPayload>,
+) -> ChordDbResult<Json<SimpleApiResult>> {
+    check_new_password(&payload.password)?;
+    let Some(user_id) = redeem_token(
+        state.accounts.as_ref(),
+        &payload.token,
+        TokenPurpose::ResetPassword,
+    )
+    .await?
+    else {
+        return Err(invalid_token());
+    };
+    let Some(mut user) = state.users.get_user(&user_id.to_string()).await? else {
+        return Err(invalid_token());
+    };
+    user.password = hash_password(&payload.password)?;
+    // The reset link was sent to the address
+    user.email_verified = true;
+    state.users.upsert_user(&user).await?;
+    state.sessions.delete_user_sessions(&user.id).await?;
+    state.api_tokens.delete_user_tokens(&user.id).await?;
+    log::info!("Reset the password of {}", user.name);
+
+    Ok(Json(SimpleApiResult::simple_success("Password changed")))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordPayload {
+    current_password: String,
+    new_password: String,
+}
+
+/// Changes the password of the logged in user, logging it out of the other devices and revoking
+/// its API tokens
+pub async fn change_password(
+    State(state): State<AppState>,
+    Extension(mut user): Extension<User>,
+    cookies: Cookies,
+    Json(payload): Json<ChangePasswordPayload>,
+) -> ChordDbResult<Json<SimpleApiResult>> {
+    if check_password(&payload.current_password, &user.password) == PasswordCheck::Invalid {
+        return Err(ChordDbError::BadRequest("Invalid password".to_string()));
+    }
+    check_new_password(&payload.new_password)?;
+    user.password = hash_password(&payload.new_password)?;
+    state.users.upsert_user(&user).await?;
+    state.sessions.delete_user_sessions(&user.id).await?;
+    state.api_tokens.delete_user_tokens(&user.id).await?;
+    initialize_session(&user, state.sessions.as_ref(), &cookies).await?;
+
+    Ok(Json(SimpleApiResult::simple_success("Password changed")))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NoteLocalePayload {
+    /// `null` to go back to the default of the songs
+    note_locale: Option<String>,
+}
+
+/// Sets the locale of the songs added by the logged in user, when they don't choose one
+pub async fn set_note_locale(
+    State(state): State<AppState>,
+    Extension(mut user): Extension<User>,
+    Json(payload): Json<NoteLocalePayload>,
+) -> ChordDbResult<Json<SimpleApiResult>> {
+    user.note_locale = parse_note_locale(payload.note_locale.as_deref())?;
+    state.users.upsert_user(&user).await?;
+
+    Ok(Json(SimpleApiResult::simple_success("Note locale changed")))
+}
+
+fn check_admin(user: &User) -> ChordDbResult<()> {
+    if user.is_admin {
+        Ok(())
+    } else {
+        Err(ChordDbError::Forbidden)
+    }
+}
+
+pub async fn invites(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+) -> ChordDbResult<Json<Vec<Invite>>> {
+    check_admin(&user)?;
+    Ok(Json(state.accounts.list_invites().await?))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewInvite {
+    /// the invite never expires if not set
+    expires_in_days: Option<u32>,
+}
+
+pub async fn create_invite(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(payload): Json<NewInvite>,
+) -> ChordDbResult<Json<Invite>> {
+    check_admin(&user)?;
+    let expires_at = payload
+        .expires_in_days
+        .map(|days| Utc::now().naive_utc() + Duration::days(days.into()));
+    let invite = Invite::new(user.id, expires_at);
+    state.accounts.insert_invite(&invite).await?;
+    Ok(Json(invite))
+}
+
+pub async fn delete_invite(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(code): Path<String>,
+) -> ChordDbResult<Json<SimpleApiResult>> {
+    check_admin(&user)?;
+    if !state.accounts.delete_invite(&code).await? {
+        return Err(ChordDbError::HttpNotFound);
+    }
+    Ok(Json(SimpleApiResult::simple_success("Invite deleted")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::Ordering, Arc};
+
+    use chrono::TimeDelta;
+
+    use crate::{
+        account::AccountSettings,
+        api_token::{ApiToken, TokenScope},
+        chord::NoteLocale,
+        session::Session,
+        testing::{memory_state, user, TestMailer},
+        web::auth::{login, LoginPayload},
+    };
+
+    use super::*;
+    use test_log::test;
+
+    fn state(registration: Registration) -> (AppState, Arc<TestMailer>) {
+        let mailer = Arc::new(TestMailer::default());
+        let mut state = memory_state();
+        state.mailer = mailer.clone();
+        state.account_settings = Arc::new(AccountSettings {
+            registration,
+            public_url: "https://chorddb.example.com/".to_string(),
+        });
+        (state, mailer)
+    }
+
+    /// Token of the link in the last email sent
+    fn sent_token(mailer: &TestMailer) -> String {
+        let sent = mailer.sent.lock().unwrap();
+        let body = &sent.last().unwrap().body;
+        let start = body.find("token=").unwrap() + "token=".len();
+        body[start..].split_whitespace().next().unwrap().to_string()
+    }
+
+    async fn register_user(
+        state: &AppState,
+        name: &str,
+        invite: Option<&str>,
+    ) -> ChordDbResult<()> {
+        register(
+            State(state.clone()),
+            Json(RegisterPayload {
+                name: name.to_string(),
+                email: format!("{}@example.com", name),
+                password: "a long password".to_string(),
+                invite: invite.map(str::to_string),
+            }),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    async fn log_in(state: &AppState, user: &str, password: &str) -> ChordDbResult<()> {
+        login(
+            State(state.clone()),
+            Cookies::default(),
+            Json(LoginPayload {
+                user: user.to_string(),
+                password: password.to_string(),
+            }),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    #[test(tokio::test)]
+    async fn test_register_and_verify_email() {
+        let (state, mailer) = state(Registration::Open);
+        register_user(&state, "alice", None).await.unwrap();
+        assert!(matches!(
+            register_user(&state, "alice", None).await,
+            Err(ChordDbError::BadRequest(_))
+        ));
+        for (name, email) in [
+            ("", "bob@example.com"),
+            ("bob@example.com", "bob@example.com"),
+            ("bob", "bob"),
+            ("bob", "bob @example.com"),
+        ] {
+            let result = register(
+                State(state.clone()),
+                Json(RegisterPayload {
+                    name: name.to_string(),
+                    email: email.to_string(),
+                    password: "a long password".to_string(),
+                    invite: None,
+                }),
+            )
+            .await;
+            assert!(result.is_err(), "{} {}", name, email);
+        }
+
+        let email = mailer.sent.lock().unwrap().last().unwrap().clone();
+        assert_eq!(email.to, "alice@example.com");
+        assert!(email
+            .body
+            .contains("https://chorddb.example.com/verify_email?token="));
+        let alice = state.users.get_user("alice").await.unwrap().unwrap();
+        assert!(!alice.email_verified);
+        assert!(log_in(&state, "alice", "a long password").await.is_err());
+
+        resend_verification(
+            State(state.clone()),
+            Json(AccountPayload {
+                user: "alice@example.com".to_string(),
+            }),
+        )
+        .await
+        .map(|_| ())
+        .unwrap();
+        assert_eq!(mailer.sent.lock().unwrap().len(), 2);
+        let token = sent_token(&mailer);
+        let verify = |token: String| {
+            let state = state.clone();
+            async move {
+                verify_email(State(state), Json(TokenPayload { token }))
+                    .await
+                    .map(|_| ())
+            }
+        };
+        verify(token.clone()).await.unwrap();
+        assert!(verify(token).await.is_err());
+        assert!(
+            state
+                .users
+                .get_user("alice")
+                .await
+                .unwrap()
+                .unwrap()
+                .email_verified
+        );
+        log_in(&state, "alice", "a long password").await.unwrap();
+
+        let (disabled, _) = self::state(Registration::Disabled);
+        assert!(register_user(&disabled, "alice", None).await.is_err());
+    }
+
+    #[test(tokio::test)]
+    async fn test_register_normalizes_email() {
+        let (state, mailer) = state(Registration::Open);
+        let register_email = |name: &str, email: &str| {
+            let state = state.clone();
+            let payload = RegisterPayload {
+                name: name.to_string(),
+                email: email.to_string(),
+                password: "a long password".to_string(),
+                invite: None,
+            };
+            async move { register(State(state), Json(payload)).await.map(|_| ()) }
+        };
+        register_email("alice", " Alice@Example.COM ")
+            .await
+            .unwrap();
+        assert_eq!(mailer.sent.lock().unwrap()[0].to, "alice@example.com");
+        let alice = state.users.get_user("ALICE@example.com").await.unwrap();
+        assert_eq!(alice.unwrap().email, "alice@example.com");
+        assert!(matches!(
+            register_email("bob", "alice@EXAMPLE.com").await,
+            Err(ChordDbError::BadRequest(_))
+        ));
+    }
+
+    #[test(tokio::test)]
+    async fn test_register_without_email() {
+        let (state, mailer) = state(Registration::Invite);
+        let admin = Uuid::new_v4();
+        let invite = Invite::new(admin, None);
+        state.accounts.insert_invite(&invite).await.unwrap();
+
+        mailer.failing.store(true, Ordering::SeqCst);
+        assert!(register_user(&state, "alice", Some(&invite.code))
+            .await
+            .is_err());
+        assert!(state.users.get_user("alice").await.unwrap().is_none());
+        let invite = state.accounts.get_invite(&invite.code).await.unwrap();
+        assert!(invite.as_ref().unwrap().used_by.is_none());
+
+        mailer.failing.store(false, Ordering::SeqCst);
+        register_user(&state, "alice", Some(&invite.unwrap().code))
+            .await
+            .unwrap();
+        assert!(state.users.get_user("alice").await.unwrap().is_some());
+    }
+
+    #[test(tokio::test)]
+    async fn test_same_answer_when_email_fails() {
+        let (state, mailer) = state(Registration::Open);
+        register_user(&state, "alice", None).await.unwrap();
+
+        mailer.failing.store(true, Ordering::SeqCst);
+        for user in ["alice", "nobody"] {
+            let payload = || AccountPayload {
+                user: user.to_string(),
+            };
+            forgot_password(State(state.clone()), Json(payload()))
+                .await
+                .map(|_| ())
+                .unwrap();
+            resend_verification(State(state.clone()), Json(payload()))
+                .await
+                .map(|_| ())
+                .unwrap();
+        }
+        assert_eq!(mailer.sent.lock().unwrap().len(), 1);
+    }
+
+    #[test(tokio::test)]
+    async fn test_invites() {
+        let (state, _) = state(Registration::Invite);
+        let admin = User {
+            is_admin: true,
+            ..user("admin")
+        };
+        let user = User {
+            is_admin: false,
+            ..admin.clone()
+        };
+        assert!(matches!(
+            invites(State(state.clone()), Extension(user.clone())).await,
+            Err(ChordDbError::Forbidden)
+        ));
+
+        assert!(register_user(&state, "alice", None).await.is_err());
+        assert!(register_user(&state, "alice", Some("unknown"))
+            .await
+            .is_err());
+        let Json(invite) = create_invite(
+            State(state.clone()),
+            Extension(admin.clone()),
+            Json(NewInvite {
+                expires_in_days: Some(7),
+            }),
+        )
+        .await
+        .unwrap();
+        register_user(&state, "alice", Some(&invite.code))
+            .await
+            .unwrap();
+        assert!(register_user(&state, "bob", Some(&invite.code))
+            .await
+            .is_err());
+        assert!(state.users.get_user("bob").await.unwrap().is_none());
+
+        let Json(listed) = invites(State(state.clone()), Extension(admin.clone()))
+            .await
+            .unwrap();
+        let alice = state.users.get_user("alice").await.unwrap().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].used_by, Some(alice.id));
+
+        delete_invite(
+            State(state.clone()),
+            Extension(admin.clone()),
+            Path(invite.code.clone()),
+        )
+        .await
+        .map(|_| ())
+        .unwrap();
+        assert!(matches!(
+            delete_invite(State(state.clone()), Extension(admin), Path(invite.code)).await,
+            Err(ChordDbError::HttpNotFound)
+        ));
+    }
+
+    #[test(tokio::test)]
+    async fn test_reset_and_change_password() {
+        let (state, mailer) = state(Registration::Disabled);
+        let user = User {
+            password: hash_password("old password").unwrap(),
+            ..user("alice")
+        };
+        state.users.upsert_user(&user).await.unwrap();
+        state
+            .sessions
+            .upsert_session(Session {
+                id: "session".to_string(),
+                user_id: user.id.to_string(),
+                expires_at: Utc::now().naive_utc(),
+            })
+            .await
+            .unwrap();
+        let add_api_token = || async {
+            let (token, _) =
+                ApiToken::generate(user.id, "script", TokenScope::Read, TimeDelta::days(1));
+            state.api_tokens.insert_token(&token).await.unwrap();
+        };
+        let api_tokens = || async { state.api_tokens.user_tokens(&user.id).await.unwrap().len() };
+        add_api_token().await;
+
+        let forgot = |user: &str| {
+            let payload = AccountPayload {
+                user: user.to_string(),
+            };
+            let state = state.clone();
+            async move {
+                forgot_password(State(state), Json(payload))
+                    .await
+                    .map(|_| ())
+            }
+        };
+        forgot("nobody").await.unwrap();
+        assert!(mailer.sent.lock().unwrap().is_empty());
+        forgot("alice@example.com").await.unwrap();
+        let token = sent_token(&mailer);
+
+        let reset = |token: &str, password: &str| {
+            let payload = ResetPasswordPayload {
+                token: token.to_string(),
+                password: password.to_string(),
+            };
+            let state = state.clone();
+            async move {
+                reset_password(State(state), Json(payload))
+                    .await
+                    .map(|_| ())
+            }
+        };
+        assert!(reset(&token, "short").await.is_err());
+        assert!(reset("unknown", "new password").await.is_err());
+        reset(&token, "new password").await.unwrap();
+        assert!(reset(&token, "other password").await.is_err());
+        assert!(state
+            .sessions
+            .get_session("session")
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(api_tokens().await, 0);
+        assert!(log_in(&state, "alice", "old password").await.is_err());
+        log_in(&state, "alice", "new password").await.unwrap();
+
+        let user = state.users.get_user("alice").await.unwrap().unwrap();
+        let change = |current: &str, new: &str| {
+            let payload = ChangePasswordPayload {
+                current_password: current.to_string(),
+                new_password: new.to_string(),
+            };
+            let (state, user) = (state.clone(), user.clone());
+            async move {
+                change_password(
+                    State(state),
+                    Extension(user),
+                    Cookies::default(),
+                    Json(payload),
+                )
+                .await
+                .map(|_| ())
+            }
+        };
+        add_api_token().await;
+        assert!(change("wrong password", "changed password").await.is_err());
+        assert_eq!(api_tokens().await, 1);
+        change("new password", "changed password").await.unwrap();
+        assert_eq!(api_tokens().await, 0);
+        log_in(&state, "alice", "changed password").await.unwrap();
+    }
+
+    #[test(tokio::test)]
+    async fn test_set_note_locale() {
+        let (state, _) = state(Registration::Open);
+        register_user(&state, "alice", None).await.unwrap();
+        let set = |note_locale: Option<&str>| {
+            let payload = NoteLocalePayload {
+                note_locale: note_locale.map(str::to_string),
+            };
+            let state = state.clone();
+            async move {
+                let user = state.users.get_user("alice").await.unwrap().unwrap();
+                set_note_locale(State(state), Extension(user), Json(payload))
+                    .await
+                    .map(|_| ())
+            }
+        };
+        let note_locale = || async {
+            let user = state.users.get_user("alice").await.unwrap().unwrap();
+            user.note_locale
+        };
+
+        set(Some("solfege")).await.unwrap();
+        assert_eq!(note_locale().await, Some(NoteLocale::Solfege));
+        assert!(matches!(
+            set(Some("klingon")).await,
+            Err(ChordDbError::BadRequest(_))
+        ));
+        assert_eq!(note_locale().await, Some(NoteLocale::Solfege));
+        set(None).await.unwrap();
+        assert_eq!(note_locale().await, None);
+    }
+}
@@ -0,0 +1,152 @@
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use chrono::TimeDelta;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    api_token::{ApiToken, TokenScope},
+    error::{ChordDbError, ChordDbResult},
+    user::User,
+};
+
+use super::{api::SimpleApiResult, AppState};
+
+const DEFAULT_TOKEN_DAYS: u32 = 30;
+const MAX_TOKEN_DAYS: u32 = 365;
+
+pub async fn api_tokens(
+    State(AppState { api_tokens, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+) -> ChordDbResult<Json<Vec<ApiToken>>> {
+    Ok(Json(api_tokens.user_tokens(&user.id).await?))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewApiToken {
+    name: String,
+    scope: TokenScope,
+    /// 30 days if not set
+    expires_in_days: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedApiToken {
+    #[serde(flatten)]
+    token: ApiToken,
+    /// The secret to send as `Authorization: Bearer <token>`. It is only shown once.
+    secret: String,
+}
+
+pub async fn create_api_token(
+    State(AppState { api_tokens, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(payload): Json<NewApiToken>,
+) -> ChordDbResult<Json<CreatedApiToken>> {
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err(ChordDbError::BadRequest(
+            "Token name can not be empty".to_string(),
+        ));
+    }
+    let days = payload.expires_in_days.unwrap_or(DEFAULT_TOKEN_DAYS);
+    if days == 0 || days > MAX_TOKEN_DAYS {
+        return Err(ChordDbError::BadRequest(format!(
+            "Tokens must expire within 1 to {} days",
+            MAX_TOKEN_DAYS
+        )));
+    }
+    let (token, secret) =
+        ApiToken::generate(user.id, name, payload.scope, TimeDelta::days(days.into()));
+    api_tokens.insert_token(&token).await?;
+    log::info!("{} created the API token '{}'", user.name, token.name);
+    Ok(Json(CreatedApiToken { token, secret }))
+}
+
+pub async fn delete_api_token(
+    State(AppState { api_tokens, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+) -> ChordDbResult<Json<SimpleApiResult>> {
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return Err(ChordDbError::HttpNotFound);
+    };
+    if !api_tokens.delete_token(&user.id, &id).await? {
+        return Err(ChordDbError::HttpNotFound);
+    }
+    Ok(Json(SimpleApiResult::simple_success("Token revoked")))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{memory_state, user};
+
+    use super::*;
+    use test_log::test;
+
+    async fn create(
+        state: &AppState,
+        user: &User,
+        payload: serde_json::Value,
+    ) -> ChordDbResult<CreatedApiToken> {
+        create_api_token(
+            State(state.clone()),
+            Extension(user.clone()),
+            Json(serde_json::from_value(payload).unwrap()),
+        )
+        .await
+        .map(|Json(created)| created)
+    }
+
+    #[test(tokio::test)]
+    async fn test_api_tokens() {
+        let state = memory_state();
+        let alice = user("alice");
+        let created = create(
+            &state,
+            &alice,
+            serde_json::json!({"name": "Song display", "scope": "read"}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(created.token.scope, TokenScope::Read);
+        assert_eq!(
+            created.token.expires_at - created.token.created_at,
+            TimeDelta::days(DEFAULT_TOKEN_DAYS.into())
+        );
+        let json = serde_json::to_value(&created).unwrap();
+        assert_eq!(json["secret"], created.secret);
+        assert!(json.get("token_hash").is_none());
+
+        for payload in [
+            serde_json::json!({"name": " ", "scope": "write"}),
+            serde_json::json!({"name": "Import", "scope": "write", "expires_in_days": 0}),
+            serde_json::json!({"name": "Import", "scope": "write", "expires_in_days": 366}),
+        ] {
+            assert!(create(&state, &alice, payload).await.is_err());
+        }
+
+        let Json(tokens) = api_tokens(State(state.clone()), Extension(alice.clone()))
+            .await
+            .unwrap();
+        assert_eq!(tokens.len(), 1);
+
+        let delete = |user: &User, id: String| {
+            let (state, user) = (state.clone(), user.clone());
+            async move {
+                delete_api_token(State(state), Extension(user), Path(id))
+                    .await
+                    .map(|_| ())
+            }
+        };
+        let id = created.token.id.to_string();
+        assert!(matches!(
+            delete(&user("bob"), id.clone()).await,
+            Err(ChordDbError::HttpNotFound)
+        ));
+        delete(&alice, id.clone()).await.unwrap();
+        assert!(delete(&alice, id).await.is_err());
+    }
+}
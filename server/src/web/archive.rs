@@ -0,0 +1,108 @@
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::IntoResponse,
+    Extension, Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    archive::{plan_import, read_archive, write_archive, ImportItem, ImportStatus},
+    error::ChordDbResult,
+    user::User,
+};
+
+use super::{song::save_song, AppState};
+
+/// Downloads every song the user can see as a tar archive
+pub async fn export_library(
+    State(AppState { songs, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+) -> ChordDbResult<impl IntoResponse> {
+    let accessible_by = if user.is_admin { None } else { Some(&user.id) };
+    let library = songs.accessible_songs(accessible_by).await?;
+    let archive = write_archive(&library, &user.name)?;
+    let disposition = format!(
+        "attachment; filename=\"chorddb-{}.tar\"",
+        Utc::now().format("%Y-%m-%d")
+    );
+    Ok((
+        [
+            (CONTENT_TYPE, "application/x-tar".to_string()),
+            (CONTENT_DISPOSITION, disposition),
+        ],
+        archive,
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct ImportQueryString {
+    /// Reports what would be imported without saving anything
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Serialize)]
+pub struct ImportReport {
+    dry_run: bool,
+    created: usize,
+    duplicates: usize,
+    conflicts: usize,
+    failed: usize,
+    songs: Vec<ImportItem>,
+    warnings: Vec<String>,
+}
+
+/// Adds the songs of an archive made by `export_library` to the user library. Songs already in
+/// the library are skipped and reported, and so are songs that could not be saved.
+pub async fn import_library(
+    State(AppState { songs, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Query(query_string): Query<ImportQueryString>,
+    body: Bytes,
+) -> ChordDbResult<Json<ImportReport>> {
+    let mut contents = read_archive(&body, &user)?;
+    let accessible_by = if user.is_admin { None } else { Some(&user.id) };
+    let library = songs.accessible_songs(accessible_by).await?;
+
+    // Songs with the id of one the user cannot see are imported as copies
+    for song in contents.songs.iter_mut() {
+        if library.iter().all(|other| other.id() != song.id())
+            && songs.get_song(song.id()).await?.is_some()
+        {
+            song.header.id = Uuid::new_v4();
+        }
+    }
+
+    let mut plan = plan_import(&contents.songs, &library);
+    let mut warnings = contents.warnings;
+    if !query_string.dry_run {
+        for (song, item) in contents.songs.into_iter().zip(plan.iter_mut()) {
+            if item.status != ImportStatus::Created {
+                continue;
+            }
+            // Keep going, so the report covers the songs already saved
+            let saved = save_song(songs.as_ref(), song, &user, Some("Imported".to_string()))
+                .await
+                .map_err(|err| err.to_string());
+            if let Err(err) = saved {
+                warnings.push(format!("{}: {}", item.title, err));
+                item.status = ImportStatus::Failed;
+            }
+        }
+    }
+
+    let count = |status| plan.iter().filter(|item| item.status == status).count();
+    Ok(Json(ImportReport {
+        dry_run: query_string.dry_run,
+        created: count(ImportStatus::Created),
+        duplicates: count(ImportStatus::Duplicate),
+        conflicts: count(ImportStatus::Conflict),
+        failed: count(ImportStatus::Failed),
+        songs: plan,
+        warnings,
+    }))
+}
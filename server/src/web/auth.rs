@@ -1,9 +1,9 @@
 use axum::{
     extract::{Query, State},
     response::Redirect,
-    Form, Json,
+    Extension, Form, Json,
 };
-use chrono::Utc;
+use chrono::{TimeDelta, Utc};
 use jsonwebtoken_google::Parser;
 use serde::{Deserialize, Serialize};
 use tower_cookies::{
@@ -13,12 +13,14 @@ use tower_cookies::{
 use uuid::Uuid;
 
 use crate::{
+    api_token::{find_token, ApiTokens, TokenScope},
+    chord::NoteLocale,
     error::{ChordDbError, ChordDbResult},
     session::{Session, Sessions},
-    user::{User, Users},
+    user::{check_password, hash_password, PasswordCheck, User, Users},
 };
 
-use super::AppState;
+use super::{api::SimpleApiResult, AppState};
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -26,6 +28,9 @@ pub struct UserData {
     pub logged_in: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     email: Option<String>,
+    /// Default locale of the songs added by the user
+    #[serde(skip_serializing_if = "Option::is_none")]
+    note_locale: Option<NoteLocale>,
 }
 
 impl UserData {
@@ -33,19 +38,27 @@ impl UserData {
         Self {
             logged_in: false,
             email: None,
+            note_locale: None,
         }
     }
 
-    fn user(email: String) -> Self {
+    fn user(email: String, note_locale: Option<NoteLocale>) -> Self {
         Self {
             logged_in: true,
             email: Some(email),
+            note_locale,
         }
     }
 }
 
 const SESSION_COOKIE_NAME: &str = "session_chorddb";
 
+/// Sessions expire after this long without being used
+const SESSION_LIFETIME: TimeDelta = TimeDelta::days(7);
+
+/// Sessions in use are extended at most this often, to avoid writing them on every request
+const SESSION_RENEWAL_INTERVAL: TimeDelta = TimeDelta::hours(1);
+
 lazy_static! {
     static ref GOOGLE_CLIENT_ID: Option<String> = dotenv::var("GOOGLE_CLIENT_ID").ok();
 }
@@ -57,7 +70,7 @@ pub async fn get_user_data(
 ) -> ChordDbResult<UserData> {
     Ok(get_authenticated_user(cookies, users, sessions)
         .await?
-        .map(|user| UserData::user(user.email))
+        .map(|user| UserData::user(user.email, user.note_locale))
         .unwrap_or_else(UserData::anonymous))
 }
 
@@ -70,18 +83,47 @@ pub async fn get_authenticated_user(
         log::debug!("no cookie");
         return Ok(None);
     };
-    let Some(session) = sessions.get_session(cookie.value()).await? else {
+    let Some(mut session) = sessions.get_session(cookie.value()).await? else {
         log::debug!("no session for cookie {:?}", cookie);
         return Ok(None);
     };
+    let now = Utc::now().naive_utc();
+    if session.expires_at <= now {
+        log::debug!("session of user {} expired", session.user_id);
+        sessions.delete_session(&session.id).await?;
+        cookies.remove(Cookie::new(SESSION_COOKIE_NAME, ""));
+        return Ok(None);
+    }
     let user = users.get_user(&session.user_id).await?;
     if user.is_none() {
         log::debug!("no user for id {}", session.user_id);
         return Ok(None);
     };
+    // Sliding expiration: sessions last as long as they are used once a week
+    if session.expires_at - now < SESSION_LIFETIME - SESSION_RENEWAL_INTERVAL {
+        session.expires_at = now + SESSION_LIFETIME;
+        sessions.upsert_session(session.clone()).await?;
+        cookies.add(session_cookie(session.id));
+    }
     Ok(user)
 }
 
+/// User of the API token sent by a script, with the scope of the token. Like logins, tokens only
+/// work for users that verified their email.
+pub async fn get_token_user(
+    secret: &str,
+    users: &dyn Users,
+    api_tokens: &dyn ApiTokens,
+) -> ChordDbResult<Option<(User, TokenScope)>> {
+    let Some(token) = find_token(api_tokens, secret).await? else {
+        return Ok(None);
+    };
+    let user = users.get_user(&token.user_id.to_string()).await?;
+    Ok(user
+        .filter(|user| user.email_verified)
+        .map(|user| (user, token.scope)))
+}
+
 pub(super) async fn user_data(
     State(AppState {
         users, sessions, ..
@@ -110,15 +152,29 @@ pub(super) async fn login(
         return Err(ChordDbError::BadRequest("Invalid user".to_string()));
     };
 
-    if !verify_password(&payload.password, &user.password) {
-        return Err(ChordDbError::BadRequest("Invalid password".to_string()));
+    match check_password(&payload.password, &user.password) {
+        PasswordCheck::Invalid => {
+            return Err(ChordDbError::BadRequest("Invalid password".to_string()));
+        }
+        _ if !user.email_verified => {
+            return Err(ChordDbError::BadRequest(
+                "Verify your email address before logging in".to_string(),
+            ));
+        }
+        PasswordCheck::Valid => {}
+        PasswordCheck::Outdated => {
+            let mut user = user.clone();
+            user.password = hash_password(&payload.password)?;
+            users.upsert_user(&user).await?;
+            log::info!("Rehashed the password of {}", user.name);
+        }
     }
     initialize_session(&user, sessions.as_ref(), &cookies).await?;
 
-    Ok(Json(UserData::user(payload.user)))
+    Ok(Json(UserData::user(payload.user, user.note_locale)))
 }
 
-async fn initialize_session(
+pub(super) async fn initialize_session(
     user: &User,
     sessions: &dyn Sessions,
     cookies: &Cookies,
@@ -128,25 +184,24 @@ async fn initialize_session(
         .upsert_session(Session {
             id: session.clone(),
             user_id: user.id.to_string(),
-            expires_at: (Utc::now() + chrono::Duration::days(7)).naive_utc(),
+            expires_at: (Utc::now() + SESSION_LIFETIME).naive_utc(),
         })
         .await?;
 
-    cookies.add(
-        Cookie::build(Cookie::new(SESSION_COOKIE_NAME, session))
-            .max_age(Duration::days(7))
-            .same_site(SameSite::Strict)
-            .path("/")
-            .secure(true)
-            .http_only(true)
-            .into(),
-    );
+    cookies.add(session_cookie(session));
 
     Ok(())
 }
 
-fn verify_password(submitted: &str, database: &str) -> bool {
-    submitted == database
+/// The cookie lasts as long as the session
+fn session_cookie(session_id: String) -> Cookie<'static> {
+    Cookie::build(Cookie::new(SESSION_COOKIE_NAME, session_id))
+        .max_age(Duration::seconds(SESSION_LIFETIME.num_seconds()))
+        .same_site(SameSite::Strict)
+        .path("/")
+        .secure(true)
+        .http_only(true)
+        .into()
 }
 
 pub(super) async fn logout(
@@ -161,6 +216,25 @@ pub(super) async fn logout(
     Ok(Redirect::to("/"))
 }
 
+/// Logs the user out of every device, this one included, and revokes their API tokens
+pub(super) async fn logout_everywhere(
+    State(AppState {
+        sessions,
+        api_tokens,
+        ..
+    }): State<AppState>,
+    Extension(user): Extension<User>,
+    cookies: Cookies,
+) -> ChordDbResult<Json<SimpleApiResult>> {
+    let deleted = sessions.delete_user_sessions(&user.id).await?;
+    let revoked = api_tokens.delete_user_tokens(&user.id).await?;
+    cookies.remove(Cookie::new(SESSION_COOKIE_NAME, ""));
+    Ok(Json(SimpleApiResult::simple_success(format!(
+        "Logged out of {} sessions and revoked {} API tokens",
+        deleted, revoked
+    ))))
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
 #[allow(dead_code)]
@@ -177,17 +251,40 @@ pub struct GoogleLoginPayload {
 
 #[derive(Debug, Deserialize)]
 pub struct LoginQueryString {
-    redirect: Option<String>,
+    pub(super) redirect: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TokenClaims {
     pub email: String,
+    #[serde(default)]
+    pub email_verified: bool,
     pub aud: String,
     pub iss: String,
     pub exp: u64,
 }
 
+impl TokenClaims {
+    /// Whether the token is expired at `now`, in seconds since the epoch
+    fn is_expired(&self, now: i64) -> bool {
+        i64::try_from(self.exp).is_ok_and(|exp| exp <= now)
+    }
+
+    /// The user with the email of the token, if Google verified it
+    async fn find_user(&self, users: &dyn Users) -> ChordDbResult<User> {
+        if !self.email_verified {
+            return Err(ChordDbError::BadRequest(format!(
+                "Google has not verified {}",
+                self.email
+            )));
+        }
+        users
+            .get_user_by_email(&self.email)
+            .await?
+            .ok_or_else(|| ChordDbError::BadRequest(format!("User {} not found", self.email)))
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct GoogleLoginResult {
     user: UserData,
@@ -226,16 +323,172 @@ pub async fn login_google(
         }
     };
 
-    // TODO: check expiration
-
-    let Some(user) = users.get_user(&claims.email).await? else {
-        return Err(ChordDbError::BadRequest(format!(
-            "User {} not found",
-            claims.email
-        )));
-    };
+    if claims.is_expired(Utc::now().timestamp()) {
+        return Err(ChordDbError::BadRequest("Expired token".to_string()));
+    }
 
+    let user = claims.find_user(users.as_ref()).await?;
     initialize_session(&user, sessions.as_ref(), &cookies).await?;
 
     Ok(Redirect::to(&query.redirect.unwrap_or("/".to_string())))
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        api_token::ApiToken,
+        testing::{memory_state, user},
+    };
+
+    use super::*;
+    use test_log::test;
+
+    async fn logged_in(state: &AppState, expires_in: TimeDelta) -> (User, Cookies) {
+        let user = user("alice");
+        state.users.upsert_user(&user).await.unwrap();
+        state
+            .sessions
+            .upsert_session(Session {
+                id: "session".to_string(),
+                user_id: user.id.to_string(),
+                expires_at: Utc::now().naive_utc() + expires_in,
+            })
+            .await
+            .unwrap();
+        let cookies = Cookies::default();
+        cookies.add(Cookie::new(SESSION_COOKIE_NAME, "session"));
+        (user, cookies)
+    }
+
+    async fn authenticated(state: &AppState, cookies: &Cookies) -> Option<User> {
+        get_authenticated_user(cookies, state.users.as_ref(), state.sessions.as_ref())
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn test_google_token_expiration() {
+        let claims = google_claims("alice@example.com", true);
+        assert!(!claims.is_expired(1_699_999_999));
+        assert!(claims.is_expired(1_700_000_000));
+        assert!(claims.is_expired(1_700_000_001));
+    }
+
+    fn google_claims(email: &str, email_verified: bool) -> TokenClaims {
+        TokenClaims {
+            email: email.to_string(),
+            email_verified,
+            aud: "client".to_string(),
+            iss: "https://accounts.google.com".to_string(),
+            exp: 1_700_000_000,
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_google_user() {
+        let state = memory_state();
+        let (alice, _) = logged_in(&state, TimeDelta::days(1)).await;
+        let users = state.users.as_ref();
+        let found = google_claims("Alice@Example.com", true)
+            .find_user(users)
+            .await
+            .unwrap();
+        assert_eq!(found.id, alice.id);
+
+        // Only verified emails match, and only emails
+        let id = alice.id.to_string();
+        for (email, verified) in [
+            ("alice@example.com", false),
+            ("alice", true),
+            (id.as_str(), true),
+            ("bob@example.com", true),
+        ] {
+            let claims = google_claims(email, verified);
+            assert!(claims.find_user(users).await.is_err(), "{}", email);
+        }
+        let claims: TokenClaims = serde_json::from_value(serde_json::json!({
+            "email": "alice@example.com",
+            "aud": "client",
+            "iss": "https://accounts.google.com",
+            "exp": 1_700_000_000,
+        }))
+        .unwrap();
+        assert!(claims.find_user(users).await.is_err());
+    }
+
+    #[test(tokio::test)]
+    async fn test_expired_session() {
+        let state = memory_state();
+        let (_, cookies) = logged_in(&state, TimeDelta::seconds(-1)).await;
+        assert!(authenticated(&state, &cookies).await.is_none());
+        assert!(state
+            .sessions
+            .get_session("session")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(cookies.get(SESSION_COOKIE_NAME).is_none());
+    }
+
+    #[test(tokio::test)]
+    async fn test_session_renewal() {
+        let state = memory_state();
+        let expires_at = |state: AppState| async move {
+            let session = state.sessions.get_session("session").await.unwrap();
+            session.unwrap().expires_at
+        };
+
+        // Not renewed within SESSION_RENEWAL_INTERVAL of logging in
+        let (user, cookies) = logged_in(&state, SESSION_LIFETIME - TimeDelta::minutes(1)).await;
+        let inserted = expires_at(state.clone()).await;
+        assert_eq!(authenticated(&state, &cookies).await.unwrap().id, user.id);
+        assert_eq!(expires_at(state.clone()).await, inserted);
+        assert!(cookies
+            .get(SESSION_COOKIE_NAME)
+            .unwrap()
+            .max_age()
+            .is_none());
+
+        let (_, cookies) = logged_in(&state, TimeDelta::days(1)).await;
+        let inserted = expires_at(state.clone()).await;
+        assert!(authenticated(&state, &cookies).await.is_some());
+        let renewed = expires_at(state.clone()).await;
+        assert!(renewed > inserted);
+        assert!(renewed > Utc::now().naive_utc() + TimeDelta::days(6));
+        let cookie = cookies.get(SESSION_COOKIE_NAME).unwrap();
+        assert_eq!(
+            cookie.max_age(),
+            Some(Duration::seconds(SESSION_LIFETIME.num_seconds()))
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_logout_everywhere() {
+        let state = memory_state();
+        let (user, cookies) = logged_in(&state, TimeDelta::days(1)).await;
+        initialize_session(&user, state.sessions.as_ref(), &Cookies::default())
+            .await
+            .unwrap();
+        let (token, _) =
+            ApiToken::generate(user.id, "script", TokenScope::Read, TimeDelta::days(1));
+        state.api_tokens.insert_token(&token).await.unwrap();
+        let Json(_) = logout_everywhere(
+            State(state.clone()),
+            Extension(user.clone()),
+            cookies.clone(),
+        )
+        .await
+        .unwrap();
+        assert!(authenticated(&state, &cookies).await.is_none());
+        assert_eq!(
+            state.sessions.delete_user_sessions(&user.id).await.unwrap(),
+            0
+        );
+        assert!(state
+            .api_tokens
+            .user_tokens(&user.id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,164 @@
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    band::{Band, BandMember, BandRole, Bands, Membership},
+    error::{ChordDbError, ChordDbResult},
+    user::{User, Users},
+};
+
+use super::{api::SimpleApiResult, AppState};
+
+/// Loads a band the user has at least the `required` role in. Admins can manage every band.
+pub(super) async fn load_band(
+    id: &str,
+    user: &User,
+    bands: &dyn Bands,
+    required: BandRole,
+) -> ChordDbResult<Band> {
+    let Some(uuid) = Uuid::parse_str(id).ok() else {
+        return Err(ChordDbError::HttpNotFound);
+    };
+    let Some(band) = bands.get_band(&uuid).await? else {
+        return Err(ChordDbError::HttpNotFound);
+    };
+    if user.is_admin {
+        return Ok(band);
+    }
+    match bands.member_role(&band.id, &user.id).await? {
+        Some(role) if role >= required => Ok(band),
+        _ => Err(ChordDbError::Forbidden),
+    }
+}
+
+async fn find_user(users: &dyn Users, id: &str) -> ChordDbResult<User> {
+    users.get_user(id).await?.ok_or(ChordDbError::HttpNotFound)
+}
+
+/// Fails if `user_id` is the only leader of the band
+async fn check_other_leader(bands: &dyn Bands, band: &Band, user_id: &Uuid) -> ChordDbResult<()> {
+    let has_other_leader = bands
+        .members(&band.id)
+        .await?
+        .iter()
+        .any(|member| member.role == BandRole::Leader && member.user_id != *user_id);
+    if has_other_leader {
+        Ok(())
+    } else {
+        Err(ChordDbError::BadRequest(
+            "Bands must have at least one leader".to_string(),
+        ))
+    }
+}
+
+pub async fn bands(
+    State(AppState { bands, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+) -> ChordDbResult<Json<Vec<Membership>>> {
+    Ok(Json(bands.user_bands(&user.id).await?))
+}
+
+#[derive(Deserialize)]
+pub struct NewBand {
+    name: String,
+}
+
+pub async fn create_band(
+    State(AppState { bands, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(payload): Json<NewBand>,
+) -> ChordDbResult<Json<Band>> {
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err(ChordDbError::BadRequest(
+            "Band name can not be empty".to_string(),
+        ));
+    }
+    Ok(Json(bands.create_band(name, &user.id).await?))
+}
+
+#[derive(Serialize)]
+pub struct BandDetails {
+    #[serde(flatten)]
+    band: Band,
+    members: Vec<BandMember>,
+}
+
+pub async fn band(
+    State(AppState { bands, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+) -> ChordDbResult<Json<BandDetails>> {
+    let band = load_band(&id, &user, bands.as_ref(), BandRole::Member).await?;
+    let members = bands.members(&band.id).await?;
+    Ok(Json(BandDetails { band, members }))
+}
+
+pub async fn delete_band(
+    State(AppState { bands, songs, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+) -> ChordDbResult<Json<SimpleApiResult>> {
+    let band = load_band(&id, &user, bands.as_ref(), BandRole::Leader).await?;
+    let owned = songs.count_owned_songs(&band.id).await?;
+    if owned > 0 {
+        return Err(ChordDbError::BadRequest(format!(
+            "The band still owns {} songs",
+            owned
+        )));
+    }
+
+    bands.delete_band(&band.id).await?;
+    Ok(Json(SimpleApiResult::simple_success("Delete successful")))
+}
+
+#[derive(Deserialize)]
+pub struct MemberPayload {
+    role: BandRole,
+}
+
+pub async fn set_member(
+    State(AppState { bands, users, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path((id, member)): Path<(String, String)>,
+    Json(payload): Json<MemberPayload>,
+) -> ChordDbResult<Json<BandMember>> {
+    let band = load_band(&id, &user, bands.as_ref(), BandRole::Leader).await?;
+    let member = find_user(users.as_ref(), &member).await?;
+    if payload.role != BandRole::Leader {
+        check_other_leader(bands.as_ref(), &band, &member.id).await?;
+    }
+
+    let member = BandMember {
+        band_id: band.id,
+        user_id: member.id,
+        role: payload.role,
+    };
+    bands.upsert_member(&member).await?;
+    Ok(Json(member))
+}
+
+/// Removes a member from the band. Members can always leave on their own.
+pub async fn delete_member(
+    State(AppState { bands, users, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path((id, member)): Path<(String, String)>,
+) -> ChordDbResult<Json<SimpleApiResult>> {
+    let member = find_user(users.as_ref(), &member).await?;
+    let required = if member.id == user.id {
+        BandRole::Member
+    } else {
+        BandRole::Leader
+    };
+    let band = load_band(&id, &user, bands.as_ref(), required).await?;
+    check_other_leader(bands.as_ref(), &band, &member.id).await?;
+
+    if !bands.delete_member(&band.id, &member.id).await? {
+        return Err(ChordDbError::HttpNotFound);
+    }
+    Ok(Json(SimpleApiResult::simple_success("Member removed")))
+}
@@ -1,30 +1,41 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     Json,
 };
+use serde::Deserialize;
 
-use crate::chord::{finder::Fingering, Chord};
+use crate::{
+    chord::{finder::Fingering, Chord},
+    error::ChordDbResult,
+};
+
+use super::{song::parse_note_locale, AppState};
 
-use super::AppState;
+#[derive(Deserialize)]
+pub struct ChordQueryString {
+    note_locale: Option<String>,
+}
 
 pub async fn chords(
     Path((instrument, chord)): Path<(String, String)>,
+    Query(query_string): Query<ChordQueryString>,
     State(AppState {
         chords,
         instruments,
         ..
     }): State<AppState>,
-) -> Json<Vec<String>> {
-    let Some(chord) = Chord::parse(chord) else {
-        return Json(vec![]);
+) -> ChordDbResult<Json<Vec<String>>> {
+    let locale = parse_note_locale(query_string.note_locale.as_deref())?.unwrap_or_default();
+    let Some(chord) = Chord::parse_in(chord, locale) else {
+        return Ok(Json(vec![]));
     };
     let Some(instrument) = instruments.get_instrument(&instrument).await else {
-        return Json(vec![]);
+        return Ok(Json(vec![]));
     };
     let response = chords
         .get_fingerings(&instrument, &chord)
         .iter()
         .map(Fingering::to_str)
         .collect();
-    Json::<Vec<_>>(response)
+    Ok(Json::<Vec<_>>(response))
 }
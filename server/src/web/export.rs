@@ -0,0 +1,217 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::IntoResponse,
+    Extension,
+};
+use serde::Deserialize;
+
+use crate::{
+    chord::NoteLocale,
+    error::{ChordDbError, ChordDbResult},
+    export::{
+        build_sheet, render_chordpro, render_html, render_pdf, render_text, PdfOptions,
+        SheetOptions, SongSheet,
+    },
+    parser::check_overrides,
+    song::{Song, SongRole},
+    user::User,
+};
+
+use super::{
+    setlist::load_setlist,
+    song::{get_instrument, load_song, parse_note_locale},
+    AppState,
+};
+
+#[derive(Deserialize)]
+pub struct ExportQueryString {
+    /// `pdf`, `html`, `text` or `chordpro`
+    format: Option<String>,
+    /// Text columns per page of PDF exports
+    columns: Option<usize>,
+    instrument: Option<String>,
+    note_locale: Option<String>,
+    transpose: Option<i32>,
+    capo: Option<u32>,
+}
+
+enum ExportFormat {
+    Pdf,
+    Html,
+    Text,
+    ChordPro,
+}
+
+impl ExportFormat {
+    fn parse(format: Option<&str>) -> ChordDbResult<Self> {
+        match format.unwrap_or("pdf") {
+            "pdf" => Ok(ExportFormat::Pdf),
+            "html" => Ok(ExportFormat::Html),
+            "text" => Ok(ExportFormat::Text),
+            "chordpro" => Ok(ExportFormat::ChordPro),
+            format => Err(ChordDbError::BadRequest(format!(
+                "Unknown export format '{}'",
+                format
+            ))),
+        }
+    }
+}
+
+/// Lays out a song with the fingerings of the instrument it is played on
+async fn song_sheet(
+    AppState {
+        chords,
+        instruments,
+        ..
+    }: &AppState,
+    song: &Song,
+    instrument: Option<String>,
+    options: SheetOptions,
+) -> ChordDbResult<SongSheet> {
+    check_overrides(options.transpose, options.capo).map_err(ChordDbError::BadRequest)?;
+    let instrument_id = instrument.or_else(|| song.metadata().instrument.clone());
+    let instrument = get_instrument(instruments.as_ref(), instrument_id).await;
+    let mut sheet = build_sheet(song, &options, |chord| {
+        chords.get_fingerings(&instrument, chord).into_iter().next()
+    });
+    sheet
+        .metadata
+        .validate(NoteLocale::English)
+        .map_err(ChordDbError::BadRequest)?;
+    Ok(sheet)
+}
+
+/// Keeps the name of the downloaded file to safe characters
+fn file_name(title: &str, extension: &str) -> String {
+    let name: String = title
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("{}.{}", name.trim_matches('_'), extension)
+}
+
+fn export(
+    sheets: &[SongSheet],
+    title: &str,
+    format: ExportFormat,
+    columns: Option<usize>,
+) -> ChordDbResult<impl IntoResponse> {
+    let (contents, content_type, extension) = match format {
+        ExportFormat::Pdf => {
+            let options = PdfOptions {
+                title: title.to_string(),
+                columns: columns.unwrap_or(1),
+            };
+            (render_pdf(sheets, &options)?, "application/pdf", "pdf")
+        }
+        ExportFormat::Html => (
+            render_html(sheets, title).into_bytes(),
+            "text/html; charset=utf-8",
+            "html",
+        ),
+        ExportFormat::Text => (
+            render_text(sheets).into_bytes(),
+            "text/plain; charset=utf-8",
+            "txt",
+        ),
+        ExportFormat::ChordPro => (
+            sheets
+                .iter()
+                .map(render_chordpro)
+                .collect::<Vec<_>>()
+                .join("\n")
+                .into_bytes(),
+            "text/plain; charset=utf-8",
+            "cho",
+        ),
+    };
+    let disposition = format!("attachment; filename=\"{}\"", file_name(title, extension));
+    Ok((
+        [
+            (CONTENT_TYPE, content_type.to_string()),
+            (CONTENT_DISPOSITION, disposition),
+        ],
+        contents,
+    ))
+}
+
+pub async fn export_song(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+    Query(query_string): Query<ExportQueryString>,
+) -> ChordDbResult<impl IntoResponse> {
+    let song = load_song(&id, &user, state.songs.as_ref(), SongRole::Viewer).await?;
+    export_single(&state, song, query_string, user.note_locale).await
+}
+
+/// Exports a song through a share link. Does not require logging in.
+pub async fn export_shared_song(
+    State(state): State<AppState>,
+    user: Option<Extension<User>>,
+    Path(token): Path<String>,
+    Query(query_string): Query<ExportQueryString>,
+) -> ChordDbResult<impl IntoResponse> {
+    let Some(song) = state.songs.get_shared_song(&token).await? else {
+        return Err(ChordDbError::HttpNotFound);
+    };
+    let user_locale = user.and_then(|Extension(user)| user.note_locale);
+    export_single(&state, song, query_string, user_locale).await
+}
+
+async fn export_single(
+    state: &AppState,
+    song: Song,
+    query_string: ExportQueryString,
+    user_locale: Option<NoteLocale>,
+) -> ChordDbResult<impl IntoResponse> {
+    let format = ExportFormat::parse(query_string.format.as_deref())?;
+    let options = SheetOptions {
+        note_locale: parse_note_locale(query_string.note_locale.as_deref())?.or(user_locale),
+        transpose: query_string.transpose.unwrap_or(0),
+        capo: query_string.capo,
+    };
+    let sheet = song_sheet(state, &song, query_string.instrument, options).await?;
+
+    export(&[sheet], song.title(), format, query_string.columns)
+}
+
+/// Exports the songs of a setlist as a songbook, with the overrides of each entry. Songs the
+/// user cannot read are left out.
+pub async fn export_setlist(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+    Query(query_string): Query<ExportQueryString>,
+) -> ChordDbResult<impl IntoResponse> {
+    let format = ExportFormat::parse(query_string.format.as_deref())?;
+    let note_locale = parse_note_locale(query_string.note_locale.as_deref())?.or(user.note_locale);
+    let setlist = load_setlist(
+        &id,
+        &user,
+        state.setlists.as_ref(),
+        state.bands.as_ref(),
+        SongRole::Viewer,
+    )
+    .await?;
+
+    let mut sheets = vec![];
+    for entry in setlist.entries {
+        let Some(song) = state.songs.get_song(&entry.song_id).await? else {
+            continue;
+        };
+        if state.songs.song_role(&user, song.header()).await?.is_none() {
+            continue;
+        }
+        let options = SheetOptions {
+            note_locale,
+            transpose: entry.transpose.unwrap_or(0),
+            capo: entry.capo,
+        };
+        let instrument = entry.instrument.or_else(|| query_string.instrument.clone());
+        sheets.push(song_sheet(&state, &song, instrument, options).await?);
+    }
+
+    export(&sheets, &setlist.header.name, format, query_string.columns)
+}
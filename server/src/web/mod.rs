@@ -3,14 +3,15 @@ use std::{
     net::{IpAddr, Ipv6Addr, SocketAddr},
     str::FromStr,
     sync::Arc,
+    time::Duration,
 };
 
 use axum::{
-    extract::{Request, State},
-    http::StatusCode,
+    extract::{DefaultBodyLimit, Request, State},
+    http::{header::AUTHORIZATION, Method, StatusCode},
     middleware::{self, Next},
     response::IntoResponse,
-    routing::{delete, get, patch, post},
+    routing::{delete, get, patch, post, put},
     Json, RequestExt, Router,
 };
 
@@ -19,27 +20,50 @@ use tower_cookies::{CookieManagerLayer, Cookies};
 use tower_http::{services::ServeDir, trace::TraceLayer};
 
 use crate::{
-    error::ChordDbResult,
+    account::{AccountSettings, Accounts},
+    api_token::{ApiTokens, TokenScope},
+    band::Bands,
+    error::{ChordDbError, ChordDbResult},
     instrument::Instruments,
-    session::Sessions,
-    song::{ChordRepository, SeaOrmSongs},
+    mail::Mailer,
+    oidc::OidcClient,
+    session::{self, Sessions},
+    setlist::Setlists,
+    song::{ChordRepository, Songs},
     user::Users,
     Opt,
 };
 
+mod account;
 mod api;
+mod api_token;
+mod archive;
 mod auth;
+mod band;
 mod chord;
+mod export;
 mod instrument;
+mod oidc;
+mod revision;
+mod setlist;
+mod share;
 mod song;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub songs: Arc<SeaOrmSongs>,
+    pub songs: Arc<dyn Songs>,
     pub users: Arc<dyn Users>,
+    pub bands: Arc<dyn Bands>,
     pub sessions: Arc<dyn Sessions>,
+    pub setlists: Arc<dyn Setlists>,
     pub chords: Arc<dyn ChordRepository>,
     pub instruments: Arc<dyn Instruments>,
+    pub accounts: Arc<dyn Accounts>,
+    pub mailer: Arc<dyn Mailer>,
+    pub account_settings: Arc<AccountSettings>,
+    pub api_tokens: Arc<dyn ApiTokens>,
+    /// Login with an OpenID Connect provider, when configured
+    pub oidc: Option<Arc<OidcClient>>,
 }
 
 async fn not_found() -> StatusCode {
@@ -52,18 +76,76 @@ lazy_static::lazy_static! {
         urls.insert("/api/auth/user");
         urls.insert("/api/auth/login");
         urls.insert("/api/auth/login/google");
+        urls.insert("/api/auth/oidc/login");
+        urls.insert("/api/auth/oidc/callback");
+        urls.insert("/api/auth/register");
+        urls.insert("/api/auth/verify_email");
+        urls.insert("/api/auth/resend_verification");
+        urls.insert("/api/auth/forgot_password");
+        urls.insert("/api/auth/reset_password");
 
         urls
     };
+    /// Prefixes of the paths that can be accessed without logging in
+    static ref ANONYMOUS_PREFIXES: Vec<&'static str> = vec!["/api/shared/"];
+    /// Prefixes of the paths that need a session. API tokens can not manage accounts or tokens
+    static ref SESSION_ONLY_PREFIXES: Vec<&'static str> =
+        vec!["/api/auth/", "/api/tokens", "/api/invites"];
+}
+
+fn is_anonymous(path: &str) -> bool {
+    ANONYMOUS_URIS.contains(path)
+        || ANONYMOUS_PREFIXES
+            .iter()
+            .any(|prefix| path.starts_with(prefix))
+}
+
+/// Whether an API token with the scope can make the request
+fn token_allows(scope: TokenScope, method: &Method, path: &str) -> bool {
+    if SESSION_ONLY_PREFIXES
+        .iter()
+        .any(|prefix| path.starts_with(prefix))
+    {
+        return false;
+    }
+    scope == TokenScope::Write || method == Method::GET || method == Method::HEAD
+}
+
+fn bearer_token(request: &Request) -> Option<&str> {
+    request
+        .headers()
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
 }
 
 async fn auth_middleware(
     State(AppState {
-        users, sessions, ..
+        users,
+        sessions,
+        api_tokens,
+        ..
     }): State<AppState>,
     mut request: Request,
     next: Next,
 ) -> ChordDbResult<impl IntoResponse> {
+    // Scripts authenticate with an API token instead of a session cookie
+    if let Some(secret) = bearer_token(&request) {
+        let Some((user, scope)) =
+            auth::get_token_user(secret, users.as_ref(), api_tokens.as_ref()).await?
+        else {
+            log::info!("Invalid API token: {:?}", request.uri());
+            return Ok((StatusCode::UNAUTHORIZED, Json("Unauthorized".to_string())).into_response());
+        };
+        if !token_allows(scope, request.method(), request.uri().path()) {
+            return Err(ChordDbError::Forbidden);
+        }
+        request.extensions_mut().insert(user);
+        return Ok(next.run(request).await);
+    }
+
     let cookies: Cookies = request
         .extract_parts()
         .await
@@ -73,7 +155,7 @@ async fn auth_middleware(
         auth::get_authenticated_user(&cookies, users.as_ref(), sessions.as_ref()).await?
     {
         request.extensions_mut().insert(user);
-    } else if !ANONYMOUS_URIS.contains(request.uri().path()) {
+    } else if !is_anonymous(request.uri().path()) {
         log::info!("Unauthorized request: {:?}", request.uri());
         return Ok((StatusCode::UNAUTHORIZED, Json("Unauthorized".to_string())).into_response());
     }
@@ -81,18 +163,96 @@ async fn auth_middleware(
     Ok(next.run(request).await)
 }
 
+/// How often expired sessions are deleted
+const SESSION_CLEANUP_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+/// Largest library archive accepted by `/api/import`
+const MAX_ARCHIVE_SIZE: usize = 64 * 1024 * 1024;
+
 pub async fn run_server(opt: Opt, state: AppState) {
+    session::spawn_session_cleanup(state.sessions.clone(), SESSION_CLEANUP_PERIOD);
+
     let app = Router::new()
         .route("/api/auth/user", get(auth::user_data))
         .route("/api/auth/login", post(auth::login))
         .route("/api/auth/login/google", post(auth::login_google))
+        .route("/api/auth/oidc/login", get(oidc::oidc_login))
+        .route("/api/auth/oidc/callback", get(oidc::oidc_callback))
         .route("/api/auth/logout", get(auth::logout))
+        .route("/api/auth/logout_everywhere", post(auth::logout_everywhere))
+        .route("/api/auth/register", post(account::register))
+        .route("/api/auth/verify_email", post(account::verify_email))
+        .route(
+            "/api/auth/resend_verification",
+            post(account::resend_verification),
+        )
+        .route("/api/auth/forgot_password", post(account::forgot_password))
+        .route("/api/auth/reset_password", post(account::reset_password))
+        .route("/api/auth/password", post(account::change_password))
+        .route("/api/auth/note_locale", put(account::set_note_locale))
+        .route("/api/invites", get(account::invites))
+        .route("/api/invites", post(account::create_invite))
+        .route("/api/invites/:code", delete(account::delete_invite))
+        .route("/api/tokens", get(api_token::api_tokens))
+        .route("/api/tokens", post(api_token::create_api_token))
+        .route("/api/tokens/:id", delete(api_token::delete_api_token))
         .route("/api/chords/:instrument/:chord", get(chord::chords))
+        .route("/api/export", get(archive::export_library))
+        .route(
+            "/api/import",
+            post(archive::import_library).layer(DefaultBodyLimit::max(MAX_ARCHIVE_SIZE)),
+        )
         .route("/api/songs", get(song::songs))
+        .route("/api/songs/search", get(song::search_songs))
+        .route("/api/songs/by_chords", get(song::search_songs_by_chords))
         .route("/api/songs/:id", get(song::api_song))
         .route("/api/songs/:id", patch(song::patch_song))
         .route("/api/songs/:id", delete(song::delete_song))
+        .route("/api/songs/:id/export", get(export::export_song))
+        .route("/api/songs/:id/revisions", get(revision::revisions))
+        .route(
+            "/api/songs/:id/revisions/:revision",
+            get(revision::revision),
+        )
+        .route(
+            "/api/songs/:id/revisions/:revision/restore",
+            post(revision::restore),
+        )
+        .route("/api/songs/:id/diff", get(revision::diff))
+        .route("/api/songs/:id/grants", get(share::grants))
+        .route("/api/songs/:id/grants/:user", put(share::set_grant))
+        .route("/api/songs/:id/grants/:user", delete(share::delete_grant))
+        .route("/api/songs/:id/band_grants", get(share::band_grants))
+        .route(
+            "/api/songs/:id/band_grants/:band",
+            put(share::set_band_grant),
+        )
+        .route(
+            "/api/songs/:id/band_grants/:band",
+            delete(share::delete_band_grant),
+        )
+        .route("/api/songs/:id/links", get(share::share_links))
+        .route("/api/songs/:id/links", post(share::create_share_link))
+        .route(
+            "/api/songs/:id/links/:token",
+            delete(share::delete_share_link),
+        )
+        .route("/api/shared/:token", get(share::shared_song))
+        .route("/api/shared/:token/export", get(export::export_shared_song))
         .route("/api/add_song", post(song::api_add_song))
+        .route("/api/bands", get(band::bands))
+        .route("/api/bands", post(band::create_band))
+        .route("/api/bands/:id", get(band::band))
+        .route("/api/bands/:id", delete(band::delete_band))
+        .route("/api/bands/:id/members/:user", put(band::set_member))
+        .route("/api/bands/:id/members/:user", delete(band::delete_member))
+        .route("/api/setlists", get(setlist::setlists))
+        .route("/api/setlists", post(setlist::create_setlist))
+        .route("/api/setlists/:id", get(setlist::setlist))
+        .route("/api/setlists/:id", put(setlist::update_setlist))
+        .route("/api/setlists/:id", delete(setlist::delete_setlist))
+        .route("/api/setlists/:id/render", get(setlist::render_setlist))
+        .route("/api/setlists/:id/export", get(export::export_setlist))
         .route("/api/instruments", get(instrument::get_instruments))
         .nest_service("/static", ServeDir::new(opt.static_dir))
         .fallback(not_found)
@@ -117,3 +277,126 @@ pub async fn run_server(opt: Opt, state: AppState) {
         .await
         .expect("Unable to start server");
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use chrono::TimeDelta;
+    use tower::ServiceExt;
+
+    use crate::{
+        api_token::ApiToken,
+        testing::{memory_state, user},
+        user::User,
+    };
+
+    use super::*;
+    use test_log::test;
+
+    async fn user_name(axum::Extension(user): axum::Extension<User>) -> String {
+        user.name
+    }
+
+    fn app(state: AppState) -> Router {
+        Router::new()
+            .route("/api/songs", get(user_name).post(user_name))
+            .route("/api/tokens", get(user_name))
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            ))
+            .layer(CookieManagerLayer::new())
+            .with_state(state)
+    }
+
+    async fn status(state: &AppState, method: Method, path: &str, token: &str) -> StatusCode {
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .header(AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+        app(state.clone()).oneshot(request).await.unwrap().status()
+    }
+
+    #[test]
+    fn test_token_allows() {
+        assert!(token_allows(TokenScope::Read, &Method::GET, "/api/songs"));
+        assert!(!token_allows(
+            TokenScope::Read,
+            &Method::PATCH,
+            "/api/songs/1"
+        ));
+        assert!(token_allows(
+            TokenScope::Write,
+            &Method::PATCH,
+            "/api/songs/1"
+        ));
+        for path in ["/api/tokens", "/api/auth/password", "/api/invites"] {
+            assert!(!token_allows(TokenScope::Write, &Method::GET, path));
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_api_token_auth() {
+        let state = memory_state();
+        let user = user("alice");
+        state.users.upsert_user(&user).await.unwrap();
+        let mut secrets = vec![];
+        for (scope, lifetime) in [
+            (TokenScope::Read, TimeDelta::days(1)),
+            (TokenScope::Write, TimeDelta::days(1)),
+            (TokenScope::Write, TimeDelta::seconds(-1)),
+        ] {
+            let (token, secret) = ApiToken::generate(user.id, "script", scope, lifetime);
+            state.api_tokens.insert_token(&token).await.unwrap();
+            secrets.push((token, secret));
+        }
+        let [(read, read_secret), (_, write_secret), (_, expired_secret)] = &secrets[..] else {
+            unreachable!()
+        };
+
+        assert_eq!(
+            status(&state, Method::GET, "/api/songs", read_secret).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(&state, Method::POST, "/api/songs", read_secret).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(&state, Method::POST, "/api/songs", write_secret).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(&state, Method::GET, "/api/tokens", write_secret).await,
+            StatusCode::FORBIDDEN
+        );
+        for secret in [expired_secret.as_str(), "chorddb_unknown", ""] {
+            assert_eq!(
+                status(&state, Method::GET, "/api/songs", secret).await,
+                StatusCode::UNAUTHORIZED
+            );
+        }
+
+        state
+            .api_tokens
+            .delete_token(&user.id, &read.id)
+            .await
+            .unwrap();
+        assert_eq!(
+            status(&state, Method::GET, "/api/songs", read_secret).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        let unverified = User {
+            email_verified: false,
+            ..user
+        };
+        state.users.upsert_user(&unverified).await.unwrap();
+        assert_eq!(
+            status(&state, Method::GET, "/api/songs", write_secret).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+}
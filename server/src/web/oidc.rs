@@ -0,0 +1,344 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    response::Redirect,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::Deserialize;
+use tower_cookies::{
+    cookie::{time::Duration, SameSite},
+    Cookie, Cookies,
+};
+
+use crate::{
+    error::{ChordDbError, ChordDbResult},
+    oidc::{AuthorizationRequest, OidcClient},
+    user::User,
+};
+
+use super::{
+    auth::{initialize_session, LoginQueryString},
+    AppState,
+};
+
+/// Keeps the login in progress while the user is with the provider
+const OIDC_COOKIE_NAME: &str = "oidc_chorddb";
+const OIDC_COOKIE_PATH: &str = "/api/auth/oidc";
+
+/// Time the users have to log in with the provider
+const LOGIN_TIMEOUT: Duration = Duration::minutes(10);
+
+/// A login sent to the provider, kept in a cookie until the user comes back
+#[derive(Debug, PartialEq, Eq)]
+struct PendingLogin {
+    state: String,
+    nonce: String,
+    verifier: String,
+    redirect: String,
+}
+
+impl PendingLogin {
+    fn new(request: AuthorizationRequest, redirect: String) -> Self {
+        Self {
+            state: request.state,
+            nonce: request.nonce,
+            verifier: request.verifier,
+            redirect,
+        }
+    }
+
+    fn to_cookie(&self) -> Cookie<'static> {
+        let value = format!(
+            "{}:{}:{}:{}",
+            self.state,
+            self.nonce,
+            self.verifier,
+            URL_SAFE_NO_PAD.encode(&self.redirect)
+        );
+        Cookie::build(Cookie::new(OIDC_COOKIE_NAME, value))
+            .max_age(LOGIN_TIMEOUT)
+            // The provider sends the user back with a cross-site navigation
+            .same_site(SameSite::Lax)
+            .path(OIDC_COOKIE_PATH)
+            .secure(true)
+            .http_only(true)
+            .into()
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.split(':');
+        let login = PendingLogin {
+            state: parts.next()?.to_string(),
+            nonce: parts.next()?.to_string(),
+            verifier: parts.next()?.to_string(),
+            redirect: String::from_utf8(URL_SAFE_NO_PAD.decode(parts.next()?).ok()?).ok()?,
+        };
+        parts.next().is_none().then_some(login)
+    }
+}
+
+/// Only local paths are followed after logging in, so the login can't be used to send users to
+/// another site
+fn safe_redirect(redirect: Option<String>) -> String {
+    redirect
+        .filter(|path| path.starts_with('/') && !path.starts_with("//") && !path.contains('\\'))
+        .unwrap_or_else(|| "/".to_string())
+}
+
+fn enabled(oidc: Option<Arc<OidcClient>>) -> ChordDbResult<Arc<OidcClient>> {
+    oidc.ok_or_else(|| {
+        ChordDbError::BadRequest("OpenID Connect login is not supported".to_string())
+    })
+}
+
+/// Sends the user to log in with the provider
+pub(super) async fn oidc_login(
+    State(AppState { oidc, .. }): State<AppState>,
+    Query(query): Query<LoginQueryString>,
+    cookies: Cookies,
+) -> ChordDbResult<Redirect> {
+    let oidc = enabled(oidc)?;
+    let request = oidc.authorization_request().await;
+    let request = request.map_err(|err| {
+        log::warn!("Could not start an OpenID Connect login: {}", err);
+        ChordDbError::BadRequest("The OpenID Connect provider is not available".to_string())
+    })?;
+    let url = request.url.clone();
+    cookies.add(PendingLogin::new(request, safe_redirect(query.redirect)).to_cookie());
+    Ok(Redirect::to(&url))
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+/// The user of a login the provider sent back
+async fn complete_login(
+    oidc: &OidcClient,
+    state: &AppState,
+    login: &PendingLogin,
+    code: &str,
+) -> ChordDbResult<User> {
+    let token = oidc.exchange_code(code, &login.verifier).await?;
+    let claims = oidc.verify_id_token(&token, &login.nonce).await?;
+    oidc.find_user(state.users.as_ref(), &claims).await
+}
+
+/// Where the provider sends the user back to, with a code to exchange for an ID token
+pub(super) async fn oidc_callback(
+    State(state): State<AppState>,
+    Query(query): Query<CallbackQuery>,
+    cookies: Cookies,
+) -> ChordDbResult<Redirect> {
+    let oidc = enabled(state.oidc.clone())?;
+    let login = cookies
+        .get(OIDC_COOKIE_NAME)
+        .and_then(|cookie| PendingLogin::parse(cookie.value()));
+    cookies.remove(
+        Cookie::build(Cookie::new(OIDC_COOKIE_NAME, ""))
+            .path(OIDC_COOKIE_PATH)
+            .into(),
+    );
+    let Some(login) = login else {
+        return Err(ChordDbError::BadRequest(
+            "No OpenID Connect login in progress".to_string(),
+        ));
+    };
+    if let Some(error) = query.error {
+        log::info!(
+            "The OpenID Connect provider refused the login: {} {}",
+            error,
+            query.error_description.unwrap_or_default()
+        );
+        return Err(ChordDbError::BadRequest(
+            "The login was refused by the provider".to_string(),
+        ));
+    }
+    let (Some(code), Some(returned_state)) = (query.code, query.state) else {
+        return Err(ChordDbError::BadRequest(
+            "Missing code or state".to_string(),
+        ));
+    };
+    if returned_state != login.state {
+        return Err(ChordDbError::BadRequest(
+            "The state does not match the login".to_string(),
+        ));
+    }
+
+    let user = complete_login(&oidc, &state, &login, &code).await;
+    let user = user.map_err(|err| {
+        log::warn!("OpenID Connect login failed: {}", err);
+        ChordDbError::BadRequest("OpenID Connect login failed".to_string())
+    })?;
+    initialize_session(&user, state.sessions.as_ref(), &cookies).await?;
+
+    Ok(Redirect::to(&login.redirect))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{http::header::LOCATION, response::IntoResponse};
+    use serde_json::{json, Value};
+
+    use crate::{
+        oidc::mock::MockIssuer,
+        testing::{memory_state, user},
+        web::auth::get_authenticated_user,
+    };
+
+    use super::*;
+    use test_log::test;
+
+    fn location(redirect: Redirect) -> String {
+        let response = redirect.into_response();
+        response.headers()[LOCATION].to_str().unwrap().to_string()
+    }
+
+    async fn oidc_state(issuer: &MockIssuer) -> AppState {
+        let mut state = memory_state();
+        state.oidc = Some(Arc::new(OidcClient::new(issuer.config()).unwrap()));
+        state.users.upsert_user(&user("alice")).await.unwrap();
+        state
+    }
+
+    /// Starts a login and logs in with the provider, returning the callback query
+    async fn log_in_with_provider(
+        state: &AppState,
+        issuer: &MockIssuer,
+        cookies: &Cookies,
+        claims: Value,
+    ) -> CallbackQuery {
+        let redirect = oidc_login(
+            State(state.clone()),
+            Query(LoginQueryString {
+                redirect: Some("/songs/1".to_string()),
+            }),
+            cookies.clone(),
+        )
+        .await
+        .unwrap();
+        let (code, returned_state) = issuer.authorize(&location(redirect), claims);
+        CallbackQuery {
+            code: Some(code),
+            state: Some(returned_state),
+            error: None,
+            error_description: None,
+        }
+    }
+
+    async fn logged_in(state: &AppState, cookies: &Cookies) -> bool {
+        get_authenticated_user(cookies, state.users.as_ref(), state.sessions.as_ref())
+            .await
+            .unwrap()
+            .is_some()
+    }
+
+    #[test]
+    fn test_safe_redirect() {
+        assert_eq!(
+            safe_redirect(Some("/songs?id=1".to_string())),
+            "/songs?id=1"
+        );
+        for unsafe_redirect in ["https://evil.example.com", "//evil.example.com", "/\\evil"] {
+            assert_eq!(safe_redirect(Some(unsafe_redirect.to_string())), "/");
+        }
+        assert_eq!(safe_redirect(None), "/");
+    }
+
+    #[test]
+    fn test_pending_login_cookie() {
+        let login = PendingLogin {
+            state: "state".to_string(),
+            nonce: "nonce".to_string(),
+            verifier: "verifier".to_string(),
+            redirect: "/songs?a=1;b=2".to_string(),
+        };
+        let cookie = login.to_cookie();
+        assert_eq!(PendingLogin::parse(cookie.value()), Some(login));
+        assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+        assert_eq!(PendingLogin::parse("state:nonce"), None);
+    }
+
+    #[test(tokio::test)]
+    async fn test_oidc_login() {
+        let issuer = MockIssuer::start().await;
+        let state = oidc_state(&issuer).await;
+        let cookies = Cookies::default();
+        let query = log_in_with_provider(
+            &state,
+            &issuer,
+            &cookies,
+            json!({"email": "alice@example.com", "email_verified": true}),
+        )
+        .await;
+        assert!(cookies.get(OIDC_COOKIE_NAME).is_some());
+
+        let redirect = oidc_callback(State(state.clone()), Query(query), cookies.clone())
+            .await
+            .unwrap();
+        assert_eq!(location(redirect), "/songs/1");
+        assert!(logged_in(&state, &cookies).await);
+        assert!(cookies.get(OIDC_COOKIE_NAME).is_none());
+    }
+
+    #[test(tokio::test)]
+    async fn test_oidc_login_rejected() {
+        let issuer = MockIssuer::start().await;
+        let state = oidc_state(&issuer).await;
+
+        // Another login's state
+        let cookies = Cookies::default();
+        let mut query = log_in_with_provider(
+            &state,
+            &issuer,
+            &cookies,
+            json!({"email": "alice@example.com", "email_verified": true}),
+        )
+        .await;
+        query.state = Some("other".to_string());
+        let result = oidc_callback(State(state.clone()), Query(query), cookies.clone()).await;
+        assert!(result.is_err());
+        assert!(!logged_in(&state, &cookies).await);
+
+        // Unknown user
+        let cookies = Cookies::default();
+        let query = log_in_with_provider(
+            &state,
+            &issuer,
+            &cookies,
+            json!({"email": "bob@example.com", "email_verified": true}),
+        )
+        .await;
+        let result = oidc_callback(State(state.clone()), Query(query), cookies.clone()).await;
+        assert!(result.is_err());
+        assert!(!logged_in(&state, &cookies).await);
+
+        // No login started
+        let query = CallbackQuery {
+            code: Some("code".to_string()),
+            state: Some("state".to_string()),
+            error: None,
+            error_description: None,
+        };
+        let result = oidc_callback(State(state.clone()), Query(query), Cookies::default()).await;
+        assert!(result.is_err());
+
+        let result = oidc_callback(
+            State(memory_state()),
+            Query(CallbackQuery {
+                code: None,
+                state: None,
+                error: None,
+                error_description: None,
+            }),
+            Cookies::default(),
+        )
+        .await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use axum::{
+    extract::{Path, Query, State},
+    Extension, Json,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    error::{ChordDbError, ChordDbResult},
+    parser::MetadataUpdate,
+    song::{diff_lines, DiffLine, RevisionHeader, SongRevision, SongRole, Songs},
+    user::User,
+};
+
+use super::{
+    api::SimpleApiResult,
+    song::{load_song, save_song, update_metadata},
+    AppState,
+};
+
+async fn load_revision(
+    songs: &dyn Songs,
+    song_id: &Uuid,
+    revision_id: i32,
+) -> ChordDbResult<SongRevision> {
+    songs
+        .get_revision(song_id, revision_id)
+        .await?
+        .ok_or(ChordDbError::HttpNotFound)
+}
+
+pub async fn revisions(
+    State(AppState { songs, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+) -> ChordDbResult<Json<Vec<RevisionHeader>>> {
+    let song = load_song(&id, &user, songs.as_ref(), SongRole::Viewer).await?;
+    Ok(Json(songs.revisions(song.id()).await?))
+}
+
+pub async fn revision(
+    State(AppState { songs, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path((id, revision_id)): Path<(String, i32)>,
+) -> ChordDbResult<Json<SongRevision>> {
+    let song = load_song(&id, &user, songs.as_ref(), SongRole::Viewer).await?;
+    Ok(Json(
+        load_revision(songs.as_ref(), song.id(), revision_id).await?,
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct DiffQueryString {
+    from: i32,
+    /// Defaults to the current version of the song
+    to: Option<i32>,
+}
+
+pub async fn diff(
+    State(AppState { songs, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+    Query(query_string): Query<DiffQueryString>,
+) -> ChordDbResult<Json<Vec<DiffLine>>> {
+    let song = load_song(&id, &user, songs.as_ref(), SongRole::Viewer).await?;
+    let from = load_revision(songs.as_ref(), song.id(), query_string.from).await?;
+    let to = match query_string.to {
+        Some(to) => load_revision(songs.as_ref(), song.id(), to).await?.contents,
+        None => song.contents,
+    };
+    Ok(Json(diff_lines(&from.contents, &to)))
+}
+
+pub async fn restore(
+    State(AppState { songs, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path((id, revision_id)): Path<(String, i32)>,
+) -> ChordDbResult<Json<SimpleApiResult>> {
+    let mut song = load_song(&id, &user, songs.as_ref(), SongRole::Editor).await?;
+    let revision = load_revision(songs.as_ref(), song.id(), revision_id).await?;
+
+    song.header.author = revision.header.author;
+    song.header.title = revision.header.title;
+    update_metadata(
+        &mut song,
+        Some(&revision.contents),
+        MetadataUpdate::default(),
+    )?;
+    song.contents = revision.contents;
+
+    let note = format!("Restored revision {}", revision_id);
+    save_song(songs.as_ref(), song, &user, Some(note)).await?;
+
+    Ok(Json(SimpleApiResult::simple_success("Restore successful")))
+}
@@ -0,0 +1,300 @@
+use axum::{
+    extract::{Path, Query, State},
+    Extension, Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    band::Bands,
+    error::{ChordDbError, ChordDbResult},
+    parser::check_overrides,
+    setlist::{Setlist, SetlistEntry, SetlistHeader, Setlists},
+    song::{OwnerKind, SongRole, Songs},
+    user::User,
+};
+
+use super::{
+    api::SimpleApiResult,
+    song::{check_band_member, render_song, SongModel, SongQueryString},
+    AppState,
+};
+
+/// Loads a setlist the user has at least the `required` role on
+pub(super) async fn load_setlist(
+    id: &str,
+    user: &User,
+    setlists: &dyn Setlists,
+    bands: &dyn Bands,
+    required: SongRole,
+) -> ChordDbResult<Setlist> {
+    let Some(uuid) = Uuid::parse_str(id).ok() else {
+        return Err(ChordDbError::HttpNotFound);
+    };
+    let Some(setlist) = setlists.get_setlist(&uuid).await? else {
+        return Err(ChordDbError::HttpNotFound);
+    };
+    let band_role = if setlist.header.owner_kind == OwnerKind::Band {
+        bands
+            .member_role(&setlist.header.owner_id, &user.id)
+            .await?
+    } else {
+        None
+    };
+    match setlist.header.role(user, band_role) {
+        Some(role) if role >= required => Ok(setlist),
+        _ => Err(ChordDbError::Forbidden),
+    }
+}
+
+/// Checks that the entries point to songs the user can read and that their overrides are valid
+async fn check_entries(
+    songs: &dyn Songs,
+    user: &User,
+    entries: &[SetlistEntry],
+) -> ChordDbResult<()> {
+    for entry in entries {
+        let song = songs.get_song(&entry.song_id).await?;
+        let role = match &song {
+            Some(song) => songs.song_role(user, song.header()).await?,
+            None => None,
+        };
+        if role.is_none() {
+            return Err(ChordDbError::BadRequest(format!(
+                "Unknown song {}",
+                entry.song_id
+            )));
+        }
+        check_overrides(entry.transpose.unwrap_or(0), entry.capo)
+            .map_err(ChordDbError::BadRequest)?;
+    }
+    Ok(())
+}
+
+pub async fn setlists(
+    State(AppState {
+        setlists, bands, ..
+    }): State<AppState>,
+    Extension(user): Extension<User>,
+) -> ChordDbResult<Json<Vec<SetlistHeader>>> {
+    let mut owners = vec![user.id];
+    owners.extend(
+        bands
+            .user_bands(&user.id)
+            .await?
+            .into_iter()
+            .map(|membership| membership.band.id),
+    );
+    Ok(Json(setlists.setlists(&owners).await?))
+}
+
+#[derive(Deserialize)]
+pub struct SetlistDetails {
+    name: String,
+    /// Band that owns the setlist
+    band: Option<Uuid>,
+    #[serde(default)]
+    entries: Vec<SetlistEntry>,
+}
+
+impl SetlistDetails {
+    fn name(&self) -> ChordDbResult<String> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err(ChordDbError::BadRequest(
+                "Setlist name can not be empty".to_string(),
+            ));
+        }
+        Ok(name.to_string())
+    }
+}
+
+pub async fn create_setlist(
+    State(AppState {
+        songs,
+        setlists,
+        bands,
+        ..
+    }): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(payload): Json<SetlistDetails>,
+) -> ChordDbResult<Json<Setlist>> {
+    let name = payload.name()?;
+    check_entries(songs.as_ref(), &user, &payload.entries).await?;
+    let (owner_id, owner_kind) = match payload.band {
+        Some(band_id) => {
+            check_band_member(bands.as_ref(), &band_id, &user).await?;
+            (band_id, OwnerKind::Band)
+        }
+        None => (user.id, OwnerKind::User),
+    };
+
+    let now = Utc::now().naive_utc();
+    let setlist = Setlist {
+        header: SetlistHeader {
+            id: Uuid::new_v4(),
+            name,
+            owner_id,
+            owner_kind,
+            created_at: now,
+            updated_at: now,
+        },
+        entries: payload.entries,
+    };
+    setlists.upsert_setlist(&setlist).await?;
+    Ok(Json(setlist))
+}
+
+pub async fn setlist(
+    State(AppState {
+        setlists, bands, ..
+    }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+) -> ChordDbResult<Json<Setlist>> {
+    Ok(Json(
+        load_setlist(
+            &id,
+            &user,
+            setlists.as_ref(),
+            bands.as_ref(),
+            SongRole::Viewer,
+        )
+        .await?,
+    ))
+}
+
+pub async fn update_setlist(
+    State(AppState {
+        songs,
+        setlists,
+        bands,
+        ..
+    }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+    Json(payload): Json<SetlistDetails>,
+) -> ChordDbResult<Json<Setlist>> {
+    let mut setlist = load_setlist(
+        &id,
+        &user,
+        setlists.as_ref(),
+        bands.as_ref(),
+        SongRole::Editor,
+    )
+    .await?;
+    setlist.header.name = payload.name()?;
+    check_entries(songs.as_ref(), &user, &payload.entries).await?;
+    if let Some(band_id) = payload.band {
+        let band_role = bands
+            .member_role(&setlist.header.owner_id, &user.id)
+            .await?;
+        if setlist.header.role(&user, band_role) != Some(SongRole::Owner) {
+            return Err(ChordDbError::Forbidden);
+        }
+        check_band_member(bands.as_ref(), &band_id, &user).await?;
+        setlist.header.owner_id = band_id;
+        setlist.header.owner_kind = OwnerKind::Band;
+    }
+    setlist.entries = payload.entries;
+
+    setlists.upsert_setlist(&setlist).await?;
+    setlist.header.updated_at = Utc::now().naive_utc();
+    Ok(Json(setlist))
+}
+
+pub async fn delete_setlist(
+    State(AppState {
+        setlists, bands, ..
+    }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+) -> ChordDbResult<Json<SimpleApiResult>> {
+    let setlist = load_setlist(
+        &id,
+        &user,
+        setlists.as_ref(),
+        bands.as_ref(),
+        SongRole::Owner,
+    )
+    .await?;
+
+    setlists.delete_setlist(&setlist.header.id).await?;
+    Ok(Json(SimpleApiResult::simple_success("Delete successful")))
+}
+
+#[derive(Deserialize)]
+pub struct SetlistQueryString {
+    /// Used for the entries that do not choose an instrument
+    instrument: Option<String>,
+    note_locale: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct RenderedEntry {
+    #[serde(flatten)]
+    entry: SetlistEntry,
+    /// Missing when the user can no longer read the song
+    song: Option<SongModel>,
+}
+
+#[derive(Serialize)]
+pub struct RenderedSetlist {
+    #[serde(flatten)]
+    header: SetlistHeader,
+    entries: Vec<RenderedEntry>,
+}
+
+/// Renders every song of the setlist with the overrides of its entry
+pub async fn render_setlist(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+    Query(query_string): Query<SetlistQueryString>,
+) -> ChordDbResult<Json<RenderedSetlist>> {
+    let setlist = load_setlist(
+        &id,
+        &user,
+        state.setlists.as_ref(),
+        state.bands.as_ref(),
+        SongRole::Viewer,
+    )
+    .await?;
+
+    let mut entries = vec![];
+    for entry in setlist.entries {
+        let mut song = state.songs.get_song(&entry.song_id).await?;
+        if let Some(found) = &song {
+            if state
+                .songs
+                .song_role(&user, found.header())
+                .await?
+                .is_none()
+            {
+                song = None;
+            }
+        }
+        let song = match song {
+            Some(song) => {
+                let options = SongQueryString {
+                    instrument: entry
+                        .instrument
+                        .clone()
+                        .or_else(|| query_string.instrument.clone()),
+                    note_locale: query_string.note_locale.clone(),
+                    transpose: entry.transpose,
+                    capo: entry.capo,
+                };
+                Some(render_song(&state, song, options, user.note_locale).await?)
+            }
+            None => None,
+        };
+        entries.push(RenderedEntry { entry, song });
+    }
+
+    Ok(Json(RenderedSetlist {
+        header: setlist.header,
+        entries,
+    }))
+}
@@ -0,0 +1,183 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    band::{Band, Bands},
+    error::{ChordDbError, ChordDbResult},
+    song::{BandGrant, OwnerKind, ShareLink, SongGrant, SongRole},
+    user::{User, Users},
+};
+
+use super::{
+    api::SimpleApiResult,
+    song::{load_song, render_song, SongQueryString},
+    AppState,
+};
+
+async fn find_user(users: &dyn Users, id: &str) -> ChordDbResult<User> {
+    users.get_user(id).await?.ok_or(ChordDbError::HttpNotFound)
+}
+
+pub async fn grants(
+    State(AppState { songs, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+) -> ChordDbResult<Json<Vec<SongGrant>>> {
+    let song = load_song(&id, &user, songs.as_ref(), SongRole::Owner).await?;
+    Ok(Json(songs.grants(song.id()).await?))
+}
+
+#[derive(Deserialize)]
+pub struct GrantPayload {
+    role: SongRole,
+}
+
+pub async fn set_grant(
+    State(AppState { songs, users, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path((id, grantee)): Path<(String, String)>,
+    Json(payload): Json<GrantPayload>,
+) -> ChordDbResult<Json<SongGrant>> {
+    let song = load_song(&id, &user, songs.as_ref(), SongRole::Owner).await?;
+    if payload.role == SongRole::Owner {
+        return Err(ChordDbError::BadRequest(
+            "Songs can only be shared with viewers and editors".to_string(),
+        ));
+    }
+    let grantee = find_user(users.as_ref(), &grantee).await?;
+    if song.header.owner_kind == OwnerKind::User && grantee.id == song.header.owner_id {
+        return Err(ChordDbError::BadRequest(
+            "The owner already has access to the song".to_string(),
+        ));
+    }
+
+    let grant = SongGrant {
+        song_id: *song.id(),
+        user_id: grantee.id,
+        role: payload.role,
+    };
+    songs.upsert_grant(&grant).await?;
+    Ok(Json(grant))
+}
+
+pub async fn delete_grant(
+    State(AppState { songs, users, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path((id, grantee)): Path<(String, String)>,
+) -> ChordDbResult<Json<SimpleApiResult>> {
+    let song = load_song(&id, &user, songs.as_ref(), SongRole::Owner).await?;
+    let grantee = find_user(users.as_ref(), &grantee).await?;
+
+    songs.delete_grant(song.id(), &grantee.id).await?;
+    Ok(Json(SimpleApiResult::simple_success("Access revoked")))
+}
+
+pub async fn band_grants(
+    State(AppState { songs, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+) -> ChordDbResult<Json<Vec<BandGrant>>> {
+    let song = load_song(&id, &user, songs.as_ref(), SongRole::Owner).await?;
+    Ok(Json(songs.band_grants(song.id()).await?))
+}
+
+async fn find_band(bands: &dyn Bands, id: &str) -> ChordDbResult<Band> {
+    let Some(uuid) = Uuid::parse_str(id).ok() else {
+        return Err(ChordDbError::HttpNotFound);
+    };
+    bands
+        .get_band(&uuid)
+        .await?
+        .ok_or(ChordDbError::HttpNotFound)
+}
+
+pub async fn set_band_grant(
+    State(AppState { songs, bands, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path((id, band)): Path<(String, String)>,
+    Json(payload): Json<GrantPayload>,
+) -> ChordDbResult<Json<BandGrant>> {
+    let song = load_song(&id, &user, songs.as_ref(), SongRole::Owner).await?;
+    if payload.role == SongRole::Owner {
+        return Err(ChordDbError::BadRequest(
+            "Songs can only be shared with viewers and editors".to_string(),
+        ));
+    }
+    let band = find_band(bands.as_ref(), &band).await?;
+    if song.header.owner_kind == OwnerKind::Band && band.id == song.header.owner_id {
+        return Err(ChordDbError::BadRequest(
+            "The owner already has access to the song".to_string(),
+        ));
+    }
+
+    let grant = BandGrant {
+        song_id: *song.id(),
+        band_id: band.id,
+        role: payload.role,
+    };
+    songs.upsert_band_grant(&grant).await?;
+    Ok(Json(grant))
+}
+
+pub async fn delete_band_grant(
+    State(AppState { songs, bands, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path((id, band)): Path<(String, String)>,
+) -> ChordDbResult<Json<SimpleApiResult>> {
+    let song = load_song(&id, &user, songs.as_ref(), SongRole::Owner).await?;
+    let band = find_band(bands.as_ref(), &band).await?;
+
+    songs.delete_band_grant(song.id(), &band.id).await?;
+    Ok(Json(SimpleApiResult::simple_success("Access revoked")))
+}
+
+pub async fn share_links(
+    State(AppState { songs, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+) -> ChordDbResult<Json<Vec<ShareLink>>> {
+    let song = load_song(&id, &user, songs.as_ref(), SongRole::Owner).await?;
+    Ok(Json(songs.share_links(song.id()).await?))
+}
+
+pub async fn create_share_link(
+    State(AppState { songs, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+) -> ChordDbResult<Json<ShareLink>> {
+    let song = load_song(&id, &user, songs.as_ref(), SongRole::Owner).await?;
+    Ok(Json(songs.create_share_link(song.id(), &user.id).await?))
+}
+
+pub async fn delete_share_link(
+    State(AppState { songs, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Path((id, token)): Path<(String, String)>,
+) -> ChordDbResult<Json<SimpleApiResult>> {
+    let song = load_song(&id, &user, songs.as_ref(), SongRole::Owner).await?;
+    if !songs.delete_share_link(song.id(), &token).await? {
+        return Err(ChordDbError::HttpNotFound);
+    }
+    Ok(Json(SimpleApiResult::simple_success("Link revoked")))
+}
+
+/// Read-only view of a song through a share link. Does not require logging in.
+pub async fn shared_song(
+    State(state): State<AppState>,
+    user: Option<Extension<User>>,
+    Path(token): Path<String>,
+    Query(query_string): Query<SongQueryString>,
+) -> ChordDbResult<impl IntoResponse> {
+    let Some(song) = state.songs.get_shared_song(&token).await? else {
+        return Err(ChordDbError::HttpNotFound);
+    };
+    let user_locale = user.and_then(|Extension(user)| user.note_locale);
+    Ok(Json(
+        render_song(&state, song, query_string, user_locale).await?,
+    ))
+}
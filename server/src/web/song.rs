@@ -2,17 +2,35 @@ use std::collections::{HashMap, HashSet};
 
 use axum::{
     extract::{Path, Query, State},
+    http::{
+        header::{ETAG, IF_MATCH},
+        HeaderMap,
+    },
     response::IntoResponse,
     Extension, Json,
 };
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::{
-    chord::{finder::GUITAR_STANDARD, Chord},
+    band::Bands,
+    chord::{
+        finder::{StringInstrument, GUITAR_STANDARD},
+        Chord, NoteLocale,
+    },
     error::{ChordDbError, ChordDbResult},
-    parser::{parse_tablature, Comp, Line, LineBit},
-    song::{SeaOrmSongs, Song, SongHeader},
+    importer::{self, ImportedSong},
+    instrument::Instruments,
+    parser::{
+        check_overrides, parse_metadata, parse_tablature_in, transpose_tablature, Comp,
+        Decorations, Line, LineBit, MetadataUpdate, TabBlock, TabEvent,
+    },
+    song::{
+        best_match, chord_sequence, normalize_tags, Song, SongCursor, SongFilter, SongHeader,
+        SongListQuery, SongPage, SongRole, SongSearchResult, SongSort, Songs, SortOrder,
+        UNPLAYABLE_DIFFICULTY,
+    },
     user::User,
 };
 
@@ -25,6 +43,58 @@ struct LineBitModel {
     position: usize,
     text: String,
     chord: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tab: Option<TabModel>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    decorations: Vec<&'static str>,
+}
+
+fn serialize_decorations(decorations: &Decorations) -> Vec<&'static str> {
+    [
+        (decorations.parenthesized, "parenthesized"),
+        (decorations.starred, "starred"),
+        (decorations.bar_before, "bar_before"),
+        (decorations.bar_after, "bar_after"),
+    ]
+    .into_iter()
+    .filter_map(|(present, name)| present.then_some(name))
+    .collect()
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct TabModel {
+    strings: usize,
+    events: Vec<TabEventModel>,
+    errors: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct TabEventModel {
+    column: usize,
+    string: usize,
+    fret: Option<usize>,
+    technique: &'static str,
+}
+
+impl TabEventModel {
+    fn new(event: &TabEvent) -> Self {
+        Self {
+            column: event.column,
+            string: event.string,
+            fret: event.fret,
+            technique: event.technique.text(),
+        }
+    }
+}
+
+impl TabModel {
+    fn new(tab: &TabBlock, instrument: &StringInstrument) -> Self {
+        Self {
+            strings: tab.string_count(),
+            events: tab.events.iter().map(TabEventModel::new).collect(),
+            errors: tab.validate(instrument),
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -32,25 +102,114 @@ pub struct AddSong {
     author: String,
     title: String,
     contents: String,
+    format: Option<String>,
+    note_locale: Option<String>,
+    #[serde(flatten)]
+    metadata: MetadataUpdate,
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Note for the first revision
+    note: Option<String>,
+    /// Band whose library the song is added to
+    band: Option<Uuid>,
+}
+
+pub(super) fn parse_note_locale(note_locale: Option<&str>) -> ChordDbResult<Option<NoteLocale>> {
+    note_locale
+        .map(|locale| {
+            NoteLocale::parse(locale).ok_or_else(|| {
+                ChordDbError::BadRequest(format!("Unknown note locale '{}'", locale))
+            })
+        })
+        .transpose()
+}
+
+/// Updates the metadata of `song` with the directives in its new `contents`, if given, and
+/// then with the explicitly set values. Values of directives no longer in the contents are
+/// cleared.
+pub(super) fn update_metadata(
+    song: &mut Song,
+    contents: Option<&str>,
+    explicit: MetadataUpdate,
+) -> ChordDbResult<()> {
+    let locale = song.note_locale();
+    if let Some(contents) = contents {
+        let old = parse_metadata(&song.contents, locale);
+        let new = parse_metadata(contents, locale);
+        song.header.metadata.follow_directives(&old, new);
+    }
+    let metadata = &mut song.header.metadata;
+    explicit.apply(metadata);
+    metadata.validate(locale).map_err(ChordDbError::BadRequest)
+}
+
+/// Checks that the user can add songs to the band library
+pub(super) async fn check_band_member(
+    bands: &dyn Bands,
+    band_id: &Uuid,
+    user: &User,
+) -> ChordDbResult<()> {
+    if bands.get_band(band_id).await?.is_none() {
+        return Err(ChordDbError::BadRequest(format!(
+            "Unknown band {}",
+            band_id
+        )));
+    }
+    if !user.is_admin && bands.member_role(band_id, &user.id).await?.is_none() {
+        return Err(ChordDbError::Forbidden);
+    }
+    Ok(())
 }
 
 pub async fn add_song(
-    AppState { songs, .. }: &AppState,
+    AppState { songs, bands, .. }: &AppState,
     user: &User,
     payload: AddSong,
-) -> ChordDbResult<Uuid> {
+) -> ChordDbResult<AddSongResult> {
+    let note_locale = parse_note_locale(payload.note_locale.as_deref())?;
+    // Songs are stored as they are sent unless the client asks for an import, `auto` included
+    let imported = match payload.format.as_deref() {
+        None => ImportedSong::unchanged(&payload.contents),
+        format => importer::import_song(&payload.contents, importer::parse_format(format)?),
+    };
+    let author = match imported.author {
+        Some(author) if payload.author.trim().is_empty() => author,
+        _ => payload.author,
+    };
+    let title = match imported.title {
+        Some(title) if payload.title.trim().is_empty() => title,
+        _ => payload.title,
+    };
+
     let id = Uuid::new_v4();
-    let song = Song::new(id, payload.author, payload.title, payload.contents, user);
+    let mut song = Song::new(id, author, title, imported.contents, user);
+    if let Some(note_locale) = note_locale.or(user.note_locale) {
+        song.header.note_locale = note_locale;
+    }
+    let contents = song.contents.clone();
+    update_metadata(&mut song, Some(&contents), payload.metadata)?;
+    song.header.tags = normalize_tags(payload.tags);
+    if let Some(band_id) = payload.band {
+        check_band_member(bands.as_ref(), &band_id, user).await?;
+        song.set_band(band_id);
+    }
 
-    songs.upsert_song(song).await?;
+    save_song(songs.as_ref(), song, user, payload.note).await?;
 
-    Ok(id)
+    Ok(AddSongResult {
+        success: true,
+        id,
+        format: imported.format.text(),
+        warnings: imported.warnings,
+    })
 }
 
 #[derive(Serialize)]
 pub struct AddSongResult {
     success: bool,
     id: Uuid,
+    format: &'static str,
+    warnings: Vec<String>,
 }
 
 pub async fn api_add_song(
@@ -58,14 +217,24 @@ pub async fn api_add_song(
     Extension(user): Extension<User>,
     Json(payload): Json<AddSong>,
 ) -> ChordDbResult<Json<AddSongResult>> {
-    add_song(&state, &user, payload)
-        .await
-        .map(|id| AddSongResult { success: true, id })
-        .map(Json)
+    add_song(&state, &user, payload).await.map(Json)
+}
+
+/// Locales used to read and to display a song
+#[derive(Clone, Copy)]
+struct LocalePair {
+    input: NoteLocale,
+    output: NoteLocale,
 }
 
-fn serialize_line(line: &Line) -> Vec<LineBitModel> {
-    line.iter().map(serialize_bit).collect()
+fn serialize_line(
+    line: &Line,
+    instrument: &StringInstrument,
+    locales: LocalePair,
+) -> Vec<LineBitModel> {
+    line.iter()
+        .map(|bit| serialize_bit(bit, instrument, locales))
+        .collect()
 }
 
 lazy_static! {
@@ -112,13 +281,19 @@ lazy_static! {
     .collect();
 }
 
-fn serialize_bit(bit: &LineBit) -> LineBitModel {
+fn serialize_bit(
+    bit: &LineBit,
+    instrument: &StringInstrument,
+    locales: LocalePair,
+) -> LineBitModel {
     match &bit.comp {
         Comp::Text(text) => LineBitModel {
             bit_type: "text".to_owned(),
             position: bit.position,
             text: text.clone(),
             chord: None,
+            tab: None,
+            decorations: vec![],
         },
         Comp::Chord {
             chord,
@@ -126,34 +301,264 @@ fn serialize_bit(bit: &LineBit) -> LineBitModel {
         } => LineBitModel {
             bit_type: "chord".to_owned(),
             position: bit.position,
+            text: if locales.input == locales.output {
+                original_text.clone()
+            } else {
+                bit.decorations.apply(&chord.text_in(locales.output))
+            },
+            chord: Some(chord.text_in(locales.output)),
+            tab: None,
+            decorations: serialize_decorations(&bit.decorations),
+        },
+        Comp::Tab(tab) => LineBitModel {
+            bit_type: "tab".to_owned(),
+            position: bit.position,
+            text: tab.staves.iter().map(|s| s.text.as_str()).join("\n"),
+            chord: None,
+            tab: Some(TabModel::new(tab, instrument)),
+            decorations: vec![],
+        },
+        Comp::Directive { original_text, .. } => LineBitModel {
+            bit_type: "directive".to_owned(),
+            position: bit.position,
             text: original_text.clone(),
-            chord: Some(chord.text()),
+            chord: None,
+            tab: None,
+            decorations: vec![],
         },
     }
 }
 
+const DEFAULT_PAGE_SIZE: u64 = 50;
+const MAX_PAGE_SIZE: u64 = 200;
+
+#[derive(Deserialize)]
+pub struct SongListQueryString {
+    cursor: Option<String>,
+    limit: Option<u64>,
+    sort: Option<SongSort>,
+    order: Option<SortOrder>,
+    author: Option<String>,
+    owner: Option<Uuid>,
+    tag: Option<String>,
+    key: Option<String>,
+}
+
 pub async fn songs(
     State(AppState { songs, .. }): State<AppState>,
     Extension(user): Extension<User>,
-) -> ChordDbResult<Json<Vec<SongHeader>>> {
+    Query(query_string): Query<SongListQueryString>,
+) -> ChordDbResult<Json<SongPage>> {
+    let key = query_string
+        .key
+        .map(|key| {
+            Chord::parse_in(&key, user.note_locale.unwrap_or_default())
+                .or_else(|| Chord::parse(&key))
+                .map(|chord| chord.text())
+                .ok_or_else(|| ChordDbError::BadRequest(format!("Invalid key '{}'", key)))
+        })
+        .transpose()?;
+    let sort = query_string.sort.unwrap_or(SongSort::Title);
+    let query = SongListQuery {
+        filter: SongFilter {
+            author: query_string.author,
+            owner: query_string.owner,
+            tag: query_string.tag.and_then(|tag| normalize_tags([tag]).pop()),
+            key,
+            accessible_by: if user.is_admin { None } else { Some(user.id) },
+        },
+        sort,
+        order: query_string.order.unwrap_or(sort.default_order()),
+        cursor: query_string
+            .cursor
+            .as_deref()
+            .map(SongCursor::decode)
+            .transpose()?,
+        limit: query_string
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE),
+    };
+    Ok(Json(songs.list_songs(&query).await?))
+}
+
+const DEFAULT_SEARCH_LIMIT: u64 = 20;
+const MAX_SEARCH_LIMIT: u64 = 100;
+
+#[derive(Deserialize)]
+pub struct SearchQueryString {
+    q: String,
+    limit: Option<u64>,
+}
+
+pub async fn search_songs(
+    State(AppState { songs, .. }): State<AppState>,
+    Extension(user): Extension<User>,
+    Query(query_string): Query<SearchQueryString>,
+) -> ChordDbResult<Json<Vec<SongSearchResult>>> {
+    let limit = query_string
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .min(MAX_SEARCH_LIMIT);
+    let accessible_by = if user.is_admin { None } else { Some(&user.id) };
     Ok(Json(
         songs
-            .all_songs()
-            .await?
-            .into_iter()
-            .filter(|s| user_can_access(&user, s))
-            .collect(),
+            .search_songs(&query_string.q, accessible_by, limit)
+            .await?,
     ))
 }
 
+#[derive(Deserialize)]
+pub struct ChordSearchQueryString {
+    /// Comma separated chords the songs may use
+    chords: Option<String>,
+    /// Comma separated chords the songs must play in this order
+    progression: Option<String>,
+    transpose: Option<bool>,
+    instrument: Option<String>,
+    limit: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct ChordSearchResult {
+    header: SongHeader,
+    transposition: i32,
+    chords: Vec<String>,
+    difficulty: usize,
+}
+
+fn parse_chord_list(source: Option<&str>, locale: NoteLocale) -> ChordDbResult<Vec<Chord>> {
+    source
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(|text| {
+            Chord::parse_in(text, locale)
+                .ok_or_else(|| ChordDbError::BadRequest(format!("Invalid chord '{}'", text)))
+        })
+        .collect()
+}
+
+pub async fn search_songs_by_chords(
+    State(AppState {
+        songs,
+        chords: chord_repository,
+        instruments,
+        ..
+    }): State<AppState>,
+    Extension(user): Extension<User>,
+    Query(query_string): Query<ChordSearchQueryString>,
+) -> ChordDbResult<Json<Vec<ChordSearchResult>>> {
+    let locale = user.note_locale.unwrap_or_default();
+    let allowed = parse_chord_list(query_string.chords.as_deref(), locale)?;
+    let progression = parse_chord_list(query_string.progression.as_deref(), locale)?;
+    if allowed.is_empty() && progression.is_empty() {
+        return Err(ChordDbError::BadRequest(
+            "Either chords or progression must be given".to_string(),
+        ));
+    }
+    let instrument = get_instrument(instruments.as_ref(), query_string.instrument).await;
+    let transpositions: Vec<i32> = if query_string.transpose.unwrap_or(false) {
+        (0..12).collect()
+    } else {
+        vec![0]
+    };
+
+    let mut candidates = HashSet::new();
+    for transposition in &transpositions {
+        let shift = |chords: &[Chord]| -> Vec<Chord> {
+            chords.iter().map(|c| c.transpose(-transposition)).collect()
+        };
+        let mut ids: Option<HashSet<Uuid>> = None;
+        if !allowed.is_empty() {
+            ids = Some(
+                songs
+                    .songs_with_chords_within(&shift(&allowed))
+                    .await?
+                    .into_iter()
+                    .collect(),
+            );
+        }
+        if !progression.is_empty() {
+            let with_progression: HashSet<Uuid> = songs
+                .songs_with_all_chords(&shift(&progression))
+                .await?
+                .into_iter()
+                .collect();
+            ids = Some(match ids {
+                Some(ids) => &ids & &with_progression,
+                None => with_progression,
+            });
+        }
+        candidates.extend(ids.unwrap_or_default());
+    }
+
+    let allowed: Option<HashSet<Chord>> =
+        Some(allowed.into_iter().collect()).filter(|a: &HashSet<Chord>| !a.is_empty());
+    let difficulty = |chord: &Chord| {
+        chord_repository
+            .get_fingerings(&instrument, chord)
+            .first()
+            .map(|f| f.difficulty())
+            .unwrap_or(UNPLAYABLE_DIFFICULTY)
+    };
+    let limit = query_string
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .min(MAX_SEARCH_LIMIT);
+    let accessible_by = if user.is_admin { None } else { Some(&user.id) };
+    let candidates: Vec<Uuid> = candidates.into_iter().collect();
+    let mut results = vec![];
+    for song in songs.get_songs(&candidates, accessible_by).await? {
+        let sequence = chord_sequence(song.contents(), song.note_locale());
+        if let Some(found) = best_match(
+            &sequence,
+            allowed.as_ref(),
+            &progression,
+            &transpositions,
+            difficulty,
+        ) {
+            results.push(ChordSearchResult {
+                header: song.header,
+                transposition: found.transposition,
+                chords: found.chords.iter().map(|c| c.text_in(locale)).collect(),
+                difficulty: found.difficulty,
+            });
+        }
+    }
+    results.sort_by(|a, b| {
+        a.difficulty
+            .cmp(&b.difficulty)
+            .then_with(|| a.header.title.cmp(&b.header.title))
+    });
+    results.truncate(limit as usize);
+    Ok(Json(results))
+}
+
+pub(super) async fn get_instrument(
+    instruments: &dyn Instruments,
+    id: Option<String>,
+) -> StringInstrument {
+    if let Some(id) = id {
+        instruments
+            .get_instrument(&id)
+            .await
+            .unwrap_or(GUITAR_STANDARD.clone())
+    } else {
+        GUITAR_STANDARD.clone()
+    }
+}
+
 #[derive(Serialize)]
-struct SongModel {
+pub(super) struct SongModel {
     header: SongHeader,
     contents: String,
     tablature: Vec<Vec<LineBitModel>>,
     fingerings: HashMap<String, String>,
     original: String,
     instrument: String,
+    note_locale: NoteLocale,
 }
 
 fn extract_chords(tablature: Vec<Vec<LineBit>>) -> HashSet<Chord> {
@@ -167,34 +572,65 @@ fn extract_chords(tablature: Vec<Vec<LineBit>>) -> HashSet<Chord> {
         .collect()
 }
 
-#[derive(Deserialize)]
+#[derive(Default, Deserialize)]
 pub struct SongQueryString {
-    instrument: Option<String>,
+    pub(super) instrument: Option<String>,
+    pub(super) note_locale: Option<String>,
+    /// Semitones to transpose the song
+    pub(super) transpose: Option<i32>,
+    /// Capo position to play the song with, instead of the written one
+    pub(super) capo: Option<u32>,
 }
 
 pub async fn api_song(
     Path(id): Path<String>,
     Query(query_string): Query<SongQueryString>,
     Extension(user): Extension<User>,
-    State(AppState {
-        songs,
+    State(state): State<AppState>,
+) -> ChordDbResult<impl IntoResponse> {
+    let song = load_song(&id, &user, state.songs.as_ref(), SongRole::Viewer).await?;
+    let etag = song_etag(song.header.version);
+    let model = render_song(&state, song, query_string, user.note_locale).await?;
+
+    Ok(([(ETAG, etag)], Json(model)))
+}
+
+/// Renders a song for display, parsing its chords and picking a fingering for each of them
+pub(super) async fn render_song(
+    AppState {
         chords,
         instruments,
         ..
-    }): State<AppState>,
-) -> ChordDbResult<impl IntoResponse> {
-    let song = load_song(&id, &user, &songs).await?;
-
-    let instrument = if let Some(instrument_id) = query_string.instrument {
-        instruments
-            .get_instrument(&instrument_id)
-            .await
-            .unwrap_or(GUITAR_STANDARD.clone())
-    } else {
-        GUITAR_STANDARD.clone()
+    }: &AppState,
+    mut song: Song,
+    query_string: SongQueryString,
+    user_locale: Option<NoteLocale>,
+) -> ChordDbResult<SongModel> {
+    let instrument_id = query_string
+        .instrument
+        .or_else(|| song.metadata().instrument.clone());
+    let instrument = get_instrument(instruments.as_ref(), instrument_id).await;
+    let locales = LocalePair {
+        input: song.note_locale(),
+        output: parse_note_locale(query_string.note_locale.as_deref())?
+            .or(user_locale)
+            .unwrap_or(song.note_locale()),
     };
-    let tab = parse_tablature(song.contents());
-    let serialized_tab = tab.iter().map(serialize_line).collect();
+    let mut tab = parse_tablature_in(song.contents(), locales.input);
+    let transpose = query_string.transpose.unwrap_or(0);
+    check_overrides(transpose, query_string.capo).map_err(ChordDbError::BadRequest)?;
+    let shift = song.metadata().shape_shift(transpose, query_string.capo);
+    transpose_tablature(&mut tab, shift, locales.input);
+    song.header.metadata = song.metadata().transposed(transpose, query_string.capo);
+    song.header
+        .metadata
+        .validate(NoteLocale::English)
+        .map_err(ChordDbError::BadRequest)?;
+
+    let serialized_tab = tab
+        .iter()
+        .map(|line| serialize_line(line, &instrument, locales))
+        .collect();
 
     let fingerings: HashMap<String, String> = extract_chords(tab)
         .iter()
@@ -202,20 +638,19 @@ pub async fn api_song(
             chords
                 .get_fingerings(&instrument, c)
                 .first()
-                .map(|f| (c.text(), f.to_str()))
+                .map(|f| (c.text_in(locales.output), f.to_str()))
         })
         .collect();
 
-    let model = SongModel {
+    Ok(SongModel {
         header: song.header().clone(),
         contents: song.contents().into(),
         tablature: serialized_tab,
         fingerings,
         original: song.contents().to_string(),
         instrument: instrument.id().to_string(),
-    };
-
-    Ok(Json(model))
+        note_locale: locales.output,
+    })
 }
 
 #[derive(Deserialize)]
@@ -223,52 +658,149 @@ pub struct SongDetails {
     author: Option<String>,
     title: Option<String>,
     contents: Option<String>,
+    note_locale: Option<String>,
+    #[serde(flatten)]
+    metadata: MetadataUpdate,
+    tags: Option<Vec<String>>,
+    /// Describes the change in the song history
+    note: Option<String>,
+    /// Version the changes are based on
+    version: Option<i32>,
+    /// Moves the song to the library of this band
+    band: Option<Uuid>,
+    /// Moves the song to the library of this user, who has to be the one making the change
+    user: Option<Uuid>,
 }
 
 impl SongDetails {
     fn is_empty(&self) -> bool {
-        self.author.is_none() && self.title.is_none() && self.contents.is_none()
+        self.author.is_none()
+            && self.title.is_none()
+            && self.contents.is_none()
+            && self.note_locale.is_none()
+            && self.metadata.is_empty()
+            && self.tags.is_none()
+            && self.band.is_none()
+            && self.user.is_none()
     }
 }
 
-async fn load_song(id: &str, user: &User, songs: &SeaOrmSongs) -> ChordDbResult<Song> {
+/// Loads a song the user has at least the `required` role on
+pub(super) async fn load_song(
+    id: &str,
+    user: &User,
+    songs: &dyn Songs,
+    required: SongRole,
+) -> ChordDbResult<Song> {
     let Some(uuid) = Uuid::parse_str(id).ok() else {
         return Err(ChordDbError::HttpNotFound);
     };
     let Some(song) = songs.get_song(&uuid).await? else {
         return Err(ChordDbError::HttpNotFound);
     };
-    if !user_can_access(user, &song.header) {
-        return Err(ChordDbError::Forbidden);
+    match songs.song_role(user, &song.header).await? {
+        Some(role) if role >= required => {}
+        _ => return Err(ChordDbError::Forbidden),
     }
 
     Ok(song)
 }
 
+/// Entity tag of the stored version of a song
+fn song_etag(version: i32) -> String {
+    format!("\"{}\"", version)
+}
+
+fn server_copy(song: &Song) -> serde_json::Value {
+    serde_json::to_value(song).unwrap_or_default()
+}
+
+/// Checks the `If-Match` header and the version the client based its changes on against the
+/// stored song
+fn check_version(headers: &HeaderMap, version: Option<i32>, song: &Song) -> ChordDbResult<()> {
+    if let Some(if_match) = headers.get(IF_MATCH) {
+        let if_match = if_match
+            .to_str()
+            .map_err(|_| ChordDbError::BadRequest("Invalid If-Match header".to_string()))?;
+        let etag = song_etag(song.header.version);
+        if !if_match
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+        {
+            return Err(ChordDbError::PreconditionFailed(server_copy(song)));
+        }
+    }
+    match version {
+        Some(version) if version != song.header.version => {
+            Err(ChordDbError::Conflict(server_copy(song)))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Saves the song, answering version conflicts with the current copy. Returns the new version.
+pub(super) async fn save_song(
+    songs: &dyn Songs,
+    song: Song,
+    editor: &User,
+    note: Option<String>,
+) -> ChordDbResult<i32> {
+    let id = *song.id();
+    match songs.upsert_song(song, &editor.id, note).await {
+        Err(ChordDbError::Conflict(_)) => {}
+        result => return result,
+    }
+    let current = songs.get_song(&id).await?;
+    Err(ChordDbError::Conflict(
+        current.map(|song| server_copy(&song)).unwrap_or_default(),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct VersionQueryString {
+    version: Option<i32>,
+}
+
 pub async fn delete_song(
     State(AppState { songs, .. }): State<AppState>,
     Extension(user): Extension<User>,
     Path(id): Path<String>,
+    Query(query_string): Query<VersionQueryString>,
+    headers: HeaderMap,
 ) -> ChordDbResult<Json<SimpleApiResult>> {
-    let song = load_song(&id, &user, &songs).await?;
+    let song = load_song(&id, &user, songs.as_ref(), SongRole::Owner).await?;
+    check_version(&headers, query_string.version, &song)?;
 
-    songs.delete_song(song.id()).await?;
+    // The song may have been saved since it was loaded
+    let conflict = match songs.delete_song(song.id(), song.header.version).await {
+        Err(ChordDbError::Conflict(_)) => true,
+        result => result.map(|_| false)?,
+    };
+    if conflict {
+        let current = songs.get_song(song.id()).await?;
+        return Err(ChordDbError::Conflict(
+            current.map(|song| server_copy(&song)).unwrap_or_default(),
+        ));
+    }
 
     Ok(Json(SimpleApiResult::simple_success("Delete successful")))
 }
 
 pub async fn patch_song(
-    State(AppState { songs, .. }): State<AppState>,
+    State(AppState { songs, bands, .. }): State<AppState>,
     Extension(user): Extension<User>,
     Path(id): Path<String>,
+    headers: HeaderMap,
     Json(payload): Json<SongDetails>,
-) -> ChordDbResult<Json<SimpleApiResult>> {
-    let mut song = load_song(&id, &user, &songs).await?;
+) -> ChordDbResult<impl IntoResponse> {
+    let mut song = load_song(&id, &user, songs.as_ref(), SongRole::Editor).await?;
     if payload.is_empty() {
         return Err(ChordDbError::BadRequest(
             "All song fields where empty".to_string(),
         ));
     }
+    check_version(&headers, payload.version, &song)?;
 
     if let Some(author) = payload.author {
         song.header.author = author
@@ -276,15 +808,379 @@ pub async fn patch_song(
     if let Some(title) = payload.title {
         song.header.title = title
     }
+    if let Some(note_locale) = parse_note_locale(payload.note_locale.as_deref())? {
+        song.header.note_locale = note_locale
+    }
+    update_metadata(&mut song, payload.contents.as_deref(), payload.metadata)?;
     if let Some(contents) = payload.contents {
         song.contents = contents
     }
+    if let Some(tags) = payload.tags {
+        song.header.tags = normalize_tags(tags)
+    }
+    if payload.band.is_some() || payload.user.is_some() {
+        if songs.song_role(&user, song.header()).await? != Some(SongRole::Owner) {
+            return Err(ChordDbError::Forbidden);
+        }
+        match (payload.band, payload.user) {
+            (Some(_), Some(_)) => {
+                return Err(ChordDbError::BadRequest(
+                    "A song can't be moved to a band and a user at once".to_string(),
+                ));
+            }
+            (Some(band_id), None) => {
+                check_band_member(bands.as_ref(), &band_id, &user).await?;
+                song.set_band(band_id);
+            }
+            (None, Some(user_id)) if user_id == user.id => song.set_user(user_id),
+            (None, _) => return Err(ChordDbError::Forbidden),
+        }
+    }
 
-    songs.upsert_song(song).await?;
+    let version = save_song(songs.as_ref(), song, &user, payload.note).await?;
 
-    Ok(Json(SimpleApiResult::simple_success("Patch successful")))
+    Ok((
+        [(ETAG, song_etag(version))],
+        Json(SimpleApiResult::simple_success("Patch successful")),
+    ))
 }
 
-fn user_can_access(user: &User, song: &SongHeader) -> bool {
-    user.id == song.owner_id || user.is_admin
+#[cfg(test)]
+mod tests {
+    use crate::{
+        band::{BandMember, BandRole},
+        song::OwnerKind,
+        testing::{memory_state, user},
+    };
+
+    use super::*;
+    use test_log::test;
+
+    async fn add(state: &AppState, user: &User, payload: serde_json::Value) -> Uuid {
+        let payload = serde_json::from_value(payload).unwrap();
+        add_song(state, user, payload).await.unwrap().id
+    }
+
+    async fn patch(
+        state: &AppState,
+        user: &User,
+        id: &Uuid,
+        payload: serde_json::Value,
+    ) -> ChordDbResult<()> {
+        patch_song(
+            State(state.clone()),
+            Extension(user.clone()),
+            Path(id.to_string()),
+            HeaderMap::new(),
+            Json(serde_json::from_value(payload).unwrap()),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    #[test(tokio::test)]
+    async fn test_add_and_patch_song() {
+        let state = memory_state();
+        let owner = user("owner");
+        let id = add(
+            &state,
+            &owner,
+            serde_json::json!({
+                "author": "Author",
+                "title": "Title",
+                "contents": "{key: Am}\nAm    C\nHello world",
+            }),
+        )
+        .await;
+
+        let song = load_song(
+            &id.to_string(),
+            &owner,
+            state.songs.as_ref(),
+            SongRole::Owner,
+        )
+        .await
+        .unwrap();
+        assert_eq!(song.title(), "Title");
+        assert_eq!(song.metadata().key.as_deref(), Some("Am"));
+        let version = song.header.version;
+
+        patch(
+            &state,
+            &owner,
+            &id,
+            serde_json::json!({"title": "New title", "version": version}),
+        )
+        .await
+        .unwrap();
+        let result = patch(
+            &state,
+            &owner,
+            &id,
+            serde_json::json!({"title": "Stale", "version": version}),
+        )
+        .await;
+        assert!(matches!(result, Err(ChordDbError::Conflict(_))));
+
+        let song = state.songs.get_song(&id).await.unwrap().unwrap();
+        assert_eq!(song.title(), "New title");
+        assert_eq!(state.songs.revisions(&id).await.unwrap().len(), 2);
+    }
+
+    #[test(tokio::test)]
+    async fn test_patch_metadata() {
+        let state = memory_state();
+        let owner = user("owner");
+        let id = add(
+            &state,
+            &owner,
+            serde_json::json!({
+                "author": "Author",
+                "title": "Title",
+                "contents": "{key: Am}\n{capo: 2}\nAm    C\nHello world",
+                "tempo": 120,
+            }),
+        )
+        .await;
+        let metadata = || async {
+            let song = state.songs.get_song(&id).await.unwrap().unwrap();
+            song.metadata().clone()
+        };
+
+        // Removing a directive clears its value, values set through the API are kept
+        patch(
+            &state,
+            &owner,
+            &id,
+            serde_json::json!({"contents": "{key: Am}\nAm    C\nHello world"}),
+        )
+        .await
+        .unwrap();
+        let current = metadata().await;
+        assert_eq!(current.key.as_deref(), Some("Am"));
+        assert_eq!(current.capo, None);
+        assert_eq!(current.tempo, Some(120));
+
+        patch(&state, &owner, &id, serde_json::json!({"tempo": null}))
+            .await
+            .unwrap();
+        assert_eq!(metadata().await.tempo, None);
+        assert_eq!(metadata().await.key.as_deref(), Some("Am"));
+        patch(&state, &owner, &id, serde_json::json!({"key": null}))
+            .await
+            .unwrap();
+        assert_eq!(metadata().await.key, None);
+    }
+
+    #[test(tokio::test)]
+    async fn test_add_song_import() {
+        let state = memory_state();
+        let owner = user("owner");
+        let contents = "[A]\n\tAm\tC\nHello world";
+        let id = add(
+            &state,
+            &owner,
+            serde_json::json!({"author": "Author", "title": "Title", "contents": contents}),
+        )
+        .await;
+        let song = state.songs.get_song(&id).await.unwrap().unwrap();
+        assert_eq!(song.contents, contents);
+
+        let id = add(
+            &state,
+            &owner,
+            serde_json::json!({
+                "author": "Author",
+                "title": "Title",
+                "contents": "[Am]Hello [C]world",
+                "format": "auto",
+            }),
+        )
+        .await;
+        let song = state.songs.get_song(&id).await.unwrap().unwrap();
+        assert_eq!(song.contents, "Am    C\nHello world");
+    }
+
+    #[test(tokio::test)]
+    async fn test_song_access() {
+        let state = memory_state();
+        let owner = user("owner");
+        let member = user("member");
+        let stranger = user("stranger");
+        let band = state.bands.create_band("Band", &owner.id).await.unwrap();
+        state
+            .bands
+            .upsert_member(&BandMember {
+                band_id: band.id,
+                user_id: member.id,
+                role: BandRole::Member,
+            })
+            .await
+            .unwrap();
+        let id = add(
+            &state,
+            &owner,
+            serde_json::json!({
+                "author": "Author",
+                "title": "Title",
+                "contents": "G\nla la la",
+                "band": band.id,
+            }),
+        )
+        .await;
+
+        let songs = state.songs.as_ref();
+        let id = id.to_string();
+        assert!(load_song(&id, &member, songs, SongRole::Editor)
+            .await
+            .is_ok());
+        assert!(matches!(
+            load_song(&id, &member, songs, SongRole::Owner).await,
+            Err(ChordDbError::Forbidden)
+        ));
+        assert!(matches!(
+            load_song(&id, &stranger, songs, SongRole::Viewer).await,
+            Err(ChordDbError::Forbidden)
+        ));
+        assert!(matches!(
+            load_song(&Uuid::new_v4().to_string(), &owner, songs, SongRole::Viewer).await,
+            Err(ChordDbError::HttpNotFound)
+        ));
+    }
+
+    #[test(tokio::test)]
+    async fn test_move_song() {
+        let state = memory_state();
+        let owner = user("owner");
+        let member = user("member");
+        let band = state.bands.create_band("Band", &owner.id).await.unwrap();
+        let other_band = state.bands.create_band("Other", &owner.id).await.unwrap();
+        state
+            .bands
+            .upsert_member(&BandMember {
+                band_id: band.id,
+                user_id: member.id,
+                role: BandRole::Member,
+            })
+            .await
+            .unwrap();
+        let id = add(
+            &state,
+            &owner,
+            serde_json::json!({
+                "author": "Author",
+                "title": "Title",
+                "contents": "G\nla la la",
+                "band": band.id,
+            }),
+        )
+        .await;
+        let owned_by = |state: AppState| async move {
+            let song = state.songs.get_song(&id).await.unwrap().unwrap();
+            (song.header.owner_kind, song.header.owner_id)
+        };
+
+        // Only owners move songs, and only to their own library
+        let result = patch(&state, &member, &id, serde_json::json!({"user": member.id})).await;
+        assert!(matches!(result, Err(ChordDbError::Forbidden)));
+        let result = patch(&state, &owner, &id, serde_json::json!({"user": member.id})).await;
+        assert!(matches!(result, Err(ChordDbError::Forbidden)));
+        let result = patch(
+            &state,
+            &owner,
+            &id,
+            serde_json::json!({"user": owner.id, "band": other_band.id}),
+        )
+        .await;
+        assert!(matches!(result, Err(ChordDbError::BadRequest(_))));
+
+        patch(
+            &state,
+            &owner,
+            &id,
+            serde_json::json!({"band": other_band.id}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            owned_by(state.clone()).await,
+            (OwnerKind::Band, other_band.id)
+        );
+        patch(&state, &owner, &id, serde_json::json!({"user": owner.id}))
+            .await
+            .unwrap();
+        assert_eq!(owned_by(state.clone()).await, (OwnerKind::User, owner.id));
+    }
+
+    #[test(tokio::test)]
+    async fn test_search_songs_by_chords() {
+        let state = memory_state();
+        let owner = user("owner");
+        let stranger = user("stranger");
+        for (title, contents) in [("Easy", "G    C\nla"), ("Harder", "G    F#m7\nla")] {
+            add(
+                &state,
+                &owner,
+                serde_json::json!({"author": "Author", "title": title, "contents": contents}),
+            )
+            .await;
+        }
+        let search = |user: &User, limit: Option<u64>| {
+            search_songs_by_chords(
+                State(state.clone()),
+                Extension(user.clone()),
+                Query(ChordSearchQueryString {
+                    chords: None,
+                    progression: Some("G".to_string()),
+                    transpose: None,
+                    instrument: None,
+                    limit,
+                }),
+            )
+        };
+
+        let titles = |results: Vec<ChordSearchResult>| -> Vec<String> {
+            results.into_iter().map(|r| r.header.title).collect()
+        };
+        let results = search(&owner, None).await.unwrap().0;
+        assert_eq!(titles(results), vec!["Easy", "Harder"]);
+        let results = search(&owner, Some(1)).await.unwrap().0;
+        assert_eq!(titles(results), vec!["Easy"]);
+        assert!(search(&stranger, None).await.unwrap().0.is_empty());
+    }
+
+    #[test(tokio::test)]
+    async fn test_render_song_overrides() {
+        let state = memory_state();
+        let owner = user("owner");
+        let id = add(
+            &state,
+            &owner,
+            serde_json::json!({"author": "Author", "title": "Title", "contents": "{key: G}\nG\nla"}),
+        )
+        .await;
+        let song = state.songs.get_song(&id).await.unwrap().unwrap();
+        let render = |transpose: i32, capo: Option<u32>| {
+            let query_string = SongQueryString {
+                transpose: Some(transpose),
+                capo,
+                ..Default::default()
+            };
+            render_song(&state, song.clone(), query_string, None)
+        };
+
+        let model = render(-11, Some(2)).await.unwrap();
+        assert_eq!(model.header.metadata.key.as_deref(), Some("Ab"));
+        for (transpose, capo) in [
+            (i32::MAX, None),
+            (i32::MIN, None),
+            (12, None),
+            (0, Some(99)),
+        ] {
+            assert!(matches!(
+                render(transpose, capo).await,
+                Err(ChordDbError::BadRequest(_))
+            ));
+        }
+    }
 }